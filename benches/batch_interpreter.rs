@@ -0,0 +1,51 @@
+use criterion::{black_box, criterion_group, criterion_main, BenchmarkId, Criterion};
+use itertools::Itertools;
+use lgp::{
+    core::{
+        batch::{run_batch, run_scalar, RowRegisters},
+        engines::generate_engine::{Generate, GenerateEngine},
+        instruction::InstructionGeneratorParametersBuilder,
+        program::{Program, ProgramGeneratorParametersBuilder},
+    },
+    utils::random::update_seed,
+};
+
+fn batch_interpreter_benchmark(c: &mut Criterion) {
+    update_seed(Some(0));
+
+    let instruction_parameters = InstructionGeneratorParametersBuilder::default()
+        .n_actions(3)
+        .n_inputs(4)
+        .build()
+        .unwrap();
+    let program_parameters = ProgramGeneratorParametersBuilder::default()
+        .max_instructions(100)
+        .instruction_generator_parameters(instruction_parameters)
+        .build()
+        .unwrap();
+    let program: Program = GenerateEngine::generate(program_parameters);
+
+    let mut group = c.benchmark_group("interpreter");
+
+    for n_rows in [150, 10_000] {
+        let rows = (0..n_rows)
+            .map(|row| {
+                (0..4)
+                    .map(|col| ((row + col) % 13) as f64 / 13.)
+                    .collect_vec()
+            })
+            .collect_vec();
+
+        group.bench_with_input(BenchmarkId::new("scalar", n_rows), &rows, |b, rows| {
+            b.iter(|| run_scalar(black_box(&program), black_box(rows), RowRegisters::Zeroed))
+        });
+        group.bench_with_input(BenchmarkId::new("batch", n_rows), &rows, |b, rows| {
+            b.iter(|| run_batch(black_box(&program), black_box(rows), RowRegisters::Zeroed))
+        });
+    }
+
+    group.finish();
+}
+
+criterion_group!(benches, batch_interpreter_benchmark);
+criterion_main!(benches);
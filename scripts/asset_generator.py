@@ -1,11 +1,12 @@
 #!/usr/bin/env python
 
 import json
+from dataclasses import dataclass, replace
 from pathlib import Path
 import numpy as np
 import matplotlib.pyplot as plt
 import pandas as pd
-from typing import List, Dict, Any
+from typing import List, Dict, Any, Optional, Tuple
 import argparse
 import glob
 import os
@@ -23,9 +24,92 @@ DEFAULTS = {
     "mountain_car_q": {
         "label": "Mountain Car Q-Learning",
     },
+    "mountain_car_q_aligned_crossover": {
+        "label": "Mountain Car Q-Learning with Aligned Crossover",
+    },
 }
 
 
+def load_artifact(path: Path) -> Any:
+    # Artifacts saved by the crate are wrapped in a format version header; older ones are not.
+    with open(path, "r") as f:
+        artifact = json.load(f)
+
+    if isinstance(artifact, dict) and "format_version" in artifact:
+        return artifact["data"]
+
+    return artifact
+
+
+@dataclass(frozen=True)
+class PlotOptions:
+    """Styling and output settings shared by every plot helper."""
+
+    # Either "png" or "svg".
+    format: str = "png"
+    width: float = 6.4
+    height: float = 4.8
+    dpi: int = 300
+    # Overrides the default title of a plot.
+    title: Optional[str] = None
+    # Computed from the plotted data (with a small margin) when not set.
+    y_range: Optional[Tuple[float, float]] = None
+    log_scale: bool = False
+    # Window of the moving average applied to plotted series (1 disables smoothing).
+    smoothing: int = 1
+
+    def with_format(self, format: str) -> "PlotOptions":
+        return replace(self, format=format)
+
+    def with_size(self, width: float, height: float) -> "PlotOptions":
+        return replace(self, width=width, height=height)
+
+    def with_title(self, title: Optional[str]) -> "PlotOptions":
+        return replace(self, title=title)
+
+    def with_y_range(self, y_range: Optional[Tuple[float, float]]) -> "PlotOptions":
+        return replace(self, y_range=y_range)
+
+    def with_log_scale(self, log_scale: bool) -> "PlotOptions":
+        return replace(self, log_scale=log_scale)
+
+    def with_smoothing(self, smoothing: int) -> "PlotOptions":
+        return replace(self, smoothing=max(1, smoothing))
+
+    def figure(self, ncols: int = 1):
+        return plt.subplots(1, ncols, squeeze=False, figsize=(self.width * ncols, self.height))
+
+    def smooth(self, series: pd.Series) -> pd.Series:
+        if self.smoothing <= 1:
+            return series
+
+        return series.rolling(self.smoothing, min_periods=1).mean()
+
+    def apply(self, ax, values: List[float], default_title: str) -> None:
+        ax.set_title(self.title if self.title is not None else default_title)
+
+        finite = [value for value in values if np.isfinite(value)]
+
+        if self.log_scale:
+            # Fitness is often negative (e.g. rewards as costs), which a plain log scale cannot show.
+            ax.set_yscale("log" if finite and min(finite) > 0 else "symlog")
+
+        if self.y_range is not None:
+            ax.set_ylim(*self.y_range)
+        elif finite:
+            lower, upper = min(finite), max(finite)
+            margin = 0.05 * (upper - lower) if upper > lower else max(abs(upper), 1.0) * 0.05
+            ax.set_ylim(lower - margin, upper + margin)
+
+    def save(self, fig, output_dir: str, stem: str) -> None:
+        fig_path: Path = Path(output_dir)
+        fig_path.mkdir(parents=True, exist_ok=True)
+        fig.savefig(
+            fig_path / f"{stem}.{self.format}", bbox_inches="tight", dpi=self.dpi
+        )
+        plt.close(fig)
+
+
 def generate_tables(
     path: str,
     output_dir: str = "assets/tables",
@@ -33,8 +117,13 @@ def generate_tables(
     # Load programs from JSON file.
     basename: str = Path(path).name
 
-    with open(Path(path) / "population.json", "r") as f:
-        programs: List[List[Dict[str, Any]]] = json.load(f)
+    programs: List[List[Dict[str, Any]]] = load_artifact(Path(path) / "population.json")
+
+    # Populations are ranked best first, according to the objective of the experiment.
+    objective: str = "Maximize"
+    params_path = Path(path) / "params.json"
+    if params_path.exists():
+        objective = load_artifact(params_path).get("objective", objective)
 
     # Extract fitness scores and generation information from programs.
     fitness_scores: List[List[float]] = []
@@ -64,14 +153,23 @@ def generate_tables(
         np.median(generation_fitness) for generation_fitness in fitness_scores
     ]
 
+    best_fitness: List[Any] = min_fitness if objective == "Minimize" else max_fitness
+
     # Create a pandas DataFrame with the statistics.
     data: Dict[str, Any] = {
+        "Best": best_fitness,
         "Max": max_fitness,
         "Mean": mean_fitness,
         "Median": median_fitness,
         "Min": min_fitness,
     }
 
+    # Held-out fitness of the best individual, when the run was validated.
+    validation_path = Path(path) / "validation.json"
+    if validation_path.exists():
+        validation: List[float] = load_artifact(validation_path)
+        data["Validation"] = (validation + [np.nan] * len(generations))[: len(generations)]
+
     df: pd.DataFrame = pd.DataFrame(data)
     df.index.name = "Generation"
 
@@ -82,31 +180,123 @@ def generate_tables(
 
 
 def generate_figures(
-    table_path: str, label: str = "", output_dir: str = "assets/figures"
+    table_path: str,
+    label: str = "",
+    output_dir: str = "assets/figures",
+    options: PlotOptions = PlotOptions(),
 ):
     df = pd.read_csv(table_path, index_col="Generation")
 
-    fig, ax = plt.subplots()
+    fig, axes = options.figure()
+    ax = axes[0][0]
 
     title: str = "Fitness Evolution"
 
     if label != "":
         title = f"{title} ({label})"
 
-    ax.plot(df.index, df["Max Fitness"], label="max")
-    ax.plot(df.index, df["Mean Fitness"], label=r"$\mu$")
-    ax.plot(df.index, df["Median Fitness"], label="median")
-    ax.plot(df.index, df["Min Fitness"], label="min")
+    # Tables name their columns either "Max" or "Max Fitness", depending on when they were generated.
+    series = [
+        ("Best", "best", "--"),
+        ("Max", "max", "-"),
+        ("Mean", r"$\mu$", "-"),
+        ("Median", "median", "-"),
+        ("Min", "min", "-"),
+        ("Validation", "validation", ":"),
+    ]
+
+    plotted: List[float] = []
+    for column, legend, linestyle in series:
+        for name in (column, f"{column} Fitness"):
+            if name in df.columns:
+                values = options.smooth(df[name])
+                ax.plot(df.index, values, label=legend, linestyle=linestyle)
+                plotted.extend(values.tolist())
+                break
 
-    ax.set_title(title)
+    options.apply(ax, plotted, title)
     ax.set_xlabel("Generation")
     ax.set_ylabel("Fitness")
     ax.grid(visible=True, which="both")
     ax.legend(loc="upper left", bbox_to_anchor=(1.02, 1))
 
-    fig_path: Path = Path(output_dir)
-    fig_path.mkdir(parents=True, exist_ok=True)
-    fig.savefig(fig_path / f"{Path(table_path).stem}.png", bbox_inches="tight", dpi=300)
+    options.save(fig, output_dir, Path(table_path).stem)
+
+
+def generate_q_table_figures(
+    path: str, output_dir: str = "assets/figures", options: PlotOptions = PlotOptions()
+) -> None:
+    # Plot the Q-table of the best individual of the first and last generations side by side.
+    basename: str = Path(path).name
+
+    snapshots: List[Dict[str, Any]] = load_artifact(Path(path) / "q_tables.json")
+
+    if not snapshots:
+        return
+
+    selected = [snapshots[0], snapshots[-1]]
+    fig, axes = options.figure(len(selected))
+
+    for ax, snapshot in zip(axes[0], selected):
+        image = ax.imshow(np.array(snapshot["table"]), aspect="auto", cmap="coolwarm")
+        title = f"Generation {snapshot['generation']}"
+        ax.set_title(f"{options.title} ({title})" if options.title else title)
+        ax.set_xlabel("Action")
+        ax.set_ylabel("Register")
+        fig.colorbar(image, ax=ax)
+
+    options.save(fig, output_dir, f"{basename}_q_table")
+
+
+def generate_pareto_figures(
+    path: str, output_dir: str = "assets/figures", options: PlotOptions = PlotOptions()
+) -> None:
+    # Plot the programs no other program beats on both fitness and effective length.
+    basename: str = Path(path).name
+    df = pd.read_csv(Path(path) / "pareto.csv")
+
+    if df.empty:
+        return
+
+    fig, axes = options.figure()
+    ax = axes[0][0]
+    ax.step(df["effective_length"], df["fitness"], where="post", alpha=0.5)
+    ax.scatter(df["effective_length"], df["fitness"])
+    ax.set_xlabel("Effective Length")
+    ax.set_ylabel("Fitness")
+    options.apply(ax, df["fitness"].tolist(), f"{basename} Pareto Front")
+
+    options.save(fig, output_dir, f"{basename}_pareto")
+
+
+def generate_compare_figures(
+    path: str, output_dir: str = "assets/figures", options: PlotOptions = PlotOptions()
+) -> None:
+    # Overlay the best and median fitness of the two runs aligned by `lgp compare`.
+    df = pd.read_csv(Path(path) / "compare.csv")
+
+    if df.empty:
+        return
+
+    fig, axes = options.figure(ncols=2)
+
+    for ax, metric in zip(axes[0], ["best", "median"]):
+        for run in ["a", "b"]:
+            ax.plot(
+                df["generation"],
+                options.smooth(df[f"{metric}_{run}"]),
+                label=f"Run {run.upper()}",
+            )
+        ax.set_xlabel("Generation")
+        ax.set_ylabel("Fitness")
+        ax.legend()
+        options.apply(
+            ax,
+            df[f"{metric}_a"].tolist() + df[f"{metric}_b"].tolist(),
+            f"{metric.capitalize()} Fitness",
+        )
+
+    options.save(fig, output_dir, f"{Path(path).name}_compare")
 
 
 def main():
@@ -123,6 +313,24 @@ def main():
         required=True,
         help="Output directory for tables or figures.",
     )
+    parser.add_argument("--format", choices=["png", "svg"], default="png")
+    parser.add_argument("--width", type=float, default=PlotOptions.width)
+    parser.add_argument("--height", type=float, default=PlotOptions.height)
+    parser.add_argument("--title", help="Overrides the title of every figure.")
+    parser.add_argument(
+        "--y-range",
+        type=float,
+        nargs=2,
+        metavar=("MIN", "MAX"),
+        help="Fixed y-axis range (computed from the data by default).",
+    )
+    parser.add_argument("--log-scale", action="store_true")
+    parser.add_argument(
+        "--smoothing",
+        type=int,
+        default=1,
+        help="Window of the moving average applied to plotted series.",
+    )
     subparsers = parser.add_subparsers(dest="command", required=True)
 
     # Tables subcommand
@@ -131,8 +339,29 @@ def main():
     # Figures subcommand
     subparsers.add_parser("figures", help="Generate figures.")
 
+    # Q-table subcommand
+    subparsers.add_parser("q-tables", help="Generate Q-table heatmaps.")
+
+    # Pareto subcommand
+    subparsers.add_parser("pareto", help="Generate fitness vs size Pareto fronts.")
+
+    # Compare subcommand
+    subparsers.add_parser(
+        "compare", help="Overlay two runs from the compare.csv written by `lgp compare`."
+    )
+
     args = parser.parse_args()
 
+    options = (
+        PlotOptions()
+        .with_format(args.format)
+        .with_size(args.width, args.height)
+        .with_title(args.title)
+        .with_y_range(tuple(args.y_range) if args.y_range else None)
+        .with_log_scale(args.log_scale)
+        .with_smoothing(args.smoothing)
+    )
+
     if args.command == "tables":
         for test in os.listdir(args.input):
             test_base = str(Path(test).stem)
@@ -143,7 +372,18 @@ def main():
         for test in glob.glob(f"{args.input}/*.csv"):
             basename = Path(test).stem
             label = DEFAULTS[basename]["label"]
-            generate_figures(test, label, args.output)
+            generate_figures(test, label, args.output, options)
+
+    elif args.command == "q-tables":
+        for test in glob.glob(f"{args.input}/*/q_tables.json"):
+            generate_q_table_figures(str(Path(test).parent), args.output, options)
+
+    elif args.command == "pareto":
+        for test in glob.glob(f"{args.input}/*/pareto.csv"):
+            generate_pareto_figures(str(Path(test).parent), args.output, options)
+
+    elif args.command == "compare":
+        generate_compare_figures(args.input, args.output, options)
 
 
 if __name__ == "__main__":
@@ -8,9 +8,12 @@ import statistics
 from loguru import logger
 from pathlib import Path
 import time
-from typing import Any, Callable, List
+import math
+from typing import Any, Callable, List, Tuple
 
 import optuna
+from optuna.distributions import distribution_to_json, json_to_distribution
+from optuna.trial import FrozenTrial, TrialState
 from subprocess import Popen, PIPE
 from threading import Lock
 
@@ -23,9 +26,12 @@ ENV = [
     "cart-pole-q",
 ]
 
+HISTORY_DIR = "assets/tuning"
+
 global_best_score = None
 global_hyper_parameters = None
 score_lock = Lock()
+history_lock = Lock()
 
 
 def update_best_hyperparameters(score: float, hyper_parameters: str) -> None:
@@ -48,6 +54,144 @@ def save_best_hyperparameters(study_name: str) -> None:
                 f.write(global_hyper_parameters)
 
 
+def default_history_path(env: str) -> str:
+    return f"{HISTORY_DIR}/{env}.jsonl"
+
+
+def record_trial(history_path: str, study: optuna.Study, trial: FrozenTrial) -> None:
+    # Called by optuna once a trial is over, so an interrupted search only loses its running trials.
+    if trial.state not in (TrialState.COMPLETE, TrialState.PRUNED):
+        return
+
+    entry = {
+        "number": trial.number,
+        "state": trial.state.name,
+        "value": trial.value,
+        "params": trial.params,
+        "distributions": {
+            name: distribution_to_json(distribution)
+            for name, distribution in trial.distributions.items()
+        },
+        "user_attrs": trial.user_attrs,
+    }
+
+    with history_lock:
+        Path(history_path).parent.mkdir(parents=True, exist_ok=True)
+
+        with open(history_path, "a") as f:
+            f.write(json.dumps(entry) + "\n")
+
+
+def load_history(history_path: str) -> List[dict[str, Any]]:
+    if not Path(history_path).exists():
+        return []
+
+    with open(history_path, "r") as f:
+        return [json.loads(line) for line in f if line.strip()]
+
+
+def replay_history(study_name: str, history: List[dict[str, Any]]) -> None:
+    # The TPE sampler only models the finished trials of its study, so replaying them restores its state.
+    study = load_study(study_name)
+
+    for entry in history:
+        state = TrialState[entry["state"]]
+        study.add_trial(
+            optuna.trial.create_trial(
+                state=state,
+                value=entry["value"] if state == TrialState.COMPLETE else None,
+                params=entry["params"],
+                distributions={
+                    name: json_to_distribution(distribution)
+                    for name, distribution in entry["distributions"].items()
+                },
+                user_attrs=entry["user_attrs"],
+            )
+        )
+
+        if "score" in entry["user_attrs"]:
+            update_best_hyperparameters(
+                entry["user_attrs"]["score"], entry["user_attrs"]["hyper_parameters"]
+            )
+
+
+def average_ranks(values: List[float]) -> List[float]:
+    # One-based ranks, ties sharing the average of their ranks.
+    order = sorted(range(len(values)), key=lambda idx: values[idx])
+    ranks = [0.0] * len(values)
+
+    start = 0
+    while start < len(order):
+        end = start + 1
+        while end < len(order) and values[order[end]] == values[order[start]]:
+            end += 1
+
+        for idx in order[start:end]:
+            ranks[idx] = (start + 1 + end) / 2
+
+        start = end
+
+    return ranks
+
+
+def spearman(xs: List[float], ys: List[float]) -> float:
+    x_ranks, y_ranks = average_ranks(xs), average_ranks(ys)
+    x_mean, y_mean = statistics.fmean(x_ranks), statistics.fmean(y_ranks)
+
+    covariance = sum((x - x_mean) * (y - y_mean) for x, y in zip(x_ranks, y_ranks))
+    x_spread = math.sqrt(sum((x - x_mean) ** 2 for x in x_ranks))
+    y_spread = math.sqrt(sum((y - y_mean) ** 2 for y in y_ranks))
+
+    if x_spread == 0 or y_spread == 0:
+        return 0.0
+
+    return covariance / (x_spread * y_spread)
+
+
+def parameter_importance(history: List[dict[str, Any]]) -> List[Tuple[str, float, float]]:
+    # Correlation-based importance: the absolute Spearman correlation of each numeric hyperparameter with
+    # the score, normalized to sum to one. Returns (name, importance, correlation), most important first;
+    # a positive correlation means larger values scored better.
+    scored = [
+        (entry["params"], entry["user_attrs"].get("score", entry["value"]))
+        for entry in history
+    ]
+    scored = [
+        (params, score)
+        for params, score in scored
+        if isinstance(score, (int, float)) and not math.isnan(score)
+    ]
+
+    names = sorted({name for params, _ in scored for name in params})
+    correlations = {}
+
+    for name in names:
+        pairs = [
+            (params[name], score)
+            for params, score in scored
+            if isinstance(params.get(name), (int, float))
+        ]
+
+        if len(pairs) >= 2:
+            correlations[name] = spearman([x for x, _ in pairs], [y for _, y in pairs])
+
+    total = sum(abs(correlation) for correlation in correlations.values())
+
+    return sorted(
+        (
+            (name, abs(correlation) / total if total > 0 else 0.0, correlation)
+            for name, correlation in correlations.items()
+        ),
+        key=lambda importance: importance[1],
+        reverse=True,
+    )
+
+
+def report_importance(history: List[dict[str, Any]]) -> None:
+    for name, importance, correlation in parameter_importance(history):
+        logger.info(f"{name}: importance={importance:.3f}, correlation={correlation:+.3f}")
+
+
 def load_study(study_name: str) -> optuna.Study:
     return optuna.load_study(study_name=study_name, storage=STORAGE)
 
@@ -59,21 +203,44 @@ def create_study(env: str) -> str:
 
 
 def run_optimization(
-    study_name: str, objective: Callable[[optuna.Trial], float], n_trials: int
+    study_name: str,
+    objective: Callable[[optuna.Trial], float],
+    n_trials: int,
+    history_path: str,
 ):
     study = load_study(study_name)
-    study.optimize(objective, n_trials=n_trials)
+    study.optimize(
+        objective, n_trials=n_trials, callbacks=[partial(record_trial, history_path)]
+    )
 
 
-def build_objective(
+def run_in_threads(
     study_name: str,
-    median_trials: int,
-    trial: optuna.Trial,
-    lgp_parameters: dict[str, Any] | None = None,
-) -> float:
+    objective: Callable[[optuna.Trial], float],
+    n_trials: int,
+    n_threads: int,
+    history_path: str,
+) -> None:
+    results: List[Future[Any]] = []
+
+    with ThreadPoolExecutor(max_workers=n_threads) as executor:
+        for _ in range(n_threads if n_trials > 0 else 0):
+            future = executor.submit(
+                run_optimization,
+                study_name=study_name,
+                objective=objective,
+                n_trials=n_trials,
+                history_path=history_path,
+            )
+            results.append(future)
+
+    for future in results:
+        future.result()
 
-    env, _ = study_name.split("_")
 
+def build_command(
+    env: str, trial: optuna.Trial, lgp_parameters: dict[str, Any] | None = None
+) -> List[str]:
     max_instructions = None
     external_factor = None
 
@@ -111,13 +278,16 @@ def build_objective(
 
         base_command.extend(q_cli_parameters)
 
-    champions = []
+    return list(map(str, base_command))
 
-    command = list(map(str, base_command))
-    logger.trace(" ".join(command))
+
+def run_median(commands: List[List[str]]) -> Tuple[float, str]:
+    # Runs every command, returning the median champion along with the hyperparameters it was found with.
     pairings = []
 
-    for _ in range(median_trials):
+    for command in commands:
+        logger.trace(" ".join(command))
+
         # Run the command and capture the output
         process = Popen(command, stdout=PIPE, stderr=PIPE)
         output, error = process.communicate()
@@ -136,9 +306,25 @@ def build_objective(
         pairings.append((champion, hyperparameters))
 
     pairings.sort(key=lambda x: x[0])
-    champion, hyperparameters = pairings[len(pairings) // 2]
+    return pairings[len(pairings) // 2]
+
+
+def build_objective(
+    study_name: str,
+    median_trials: int,
+    trial: optuna.Trial,
+    lgp_parameters: dict[str, Any] | None = None,
+) -> float:
+
+    env, _ = study_name.split("_")
 
-    if champion == float("nan"):
+    command = build_command(env, trial, lgp_parameters)
+    champion, hyperparameters = run_median([command] * median_trials)
+
+    trial.set_user_attr("score", champion)
+    trial.set_user_attr("hyper_parameters", hyperparameters)
+
+    if math.isnan(champion):
         raise optuna.TrialPruned()
 
     prune_thresholds = {"cart": 400, "iris": 0.9, "mountain": -150, "default": 0}
@@ -152,6 +338,95 @@ def build_objective(
     return champion
 
 
+def successive_halving(
+    study_name: str,
+    median_trials: int,
+    n_configurations: int,
+    min_generations: int,
+    reduction_factor: int,
+    max_generations: int | None,
+    n_threads: int,
+    history_path: str,
+    checkpoint_root: str,
+    lgp_parameters: dict[str, Any] | None = None,
+) -> None:
+    # Every rung runs the surviving configurations `reduction_factor` times longer than the previous one,
+    # continuing their runs from the checkpoints of the previous rung, and only promotes the best
+    # `1 / reduction_factor` of them. The last survivors complete, the others are pruned at their rung.
+    env, _ = study_name.split("_")
+    study = load_study(study_name)
+
+    survivors = [study.ask() for _ in range(n_configurations)]
+    commands = {trial.number: build_command(env, trial, lgp_parameters) for trial in survivors}
+    n_generations = min_generations
+    rung = 0
+
+    def evaluate(trial: optuna.Trial) -> float:
+        binary, *arguments = commands[trial.number]
+        runs = []
+
+        for repetition in range(median_trials):
+            checkpoint_dir = Path(checkpoint_root) / study_name / str(trial.number) / str(repetition)
+            options = [f"--checkpoint-dir={checkpoint_dir}"]
+
+            if rung == 0:
+                runs.append([binary, *options, *arguments, f"--n-generations={n_generations}"])
+            else:
+                options += [
+                    f"--resume={checkpoint_dir / 'checkpoint.json'}",
+                    f"--until={n_generations}",
+                ]
+                runs.append([binary, *options, *arguments])
+
+        champion, hyperparameters = run_median(runs)
+
+        trial.report(champion, step=n_generations)
+        trial.set_user_attr("score", champion)
+        trial.set_user_attr("hyper_parameters", hyperparameters)
+        trial.set_user_attr("n_generations", n_generations)
+        update_best_hyperparameters(champion, hyperparameters)
+
+        return champion
+
+    while survivors:
+        with ThreadPoolExecutor(max_workers=n_threads) as executor:
+            scores = list(executor.map(evaluate, survivors))
+
+        logger.info(
+            f"rung {rung}: {len(survivors)} configurations ran for {n_generations} generations"
+        )
+
+        # Best first, failed runs last.
+        ranked = sorted(
+            zip(scores, survivors),
+            key=lambda pair: -math.inf if math.isnan(pair[0]) else pair[0],
+            reverse=True,
+        )
+        n_promoted = len(survivors) // reduction_factor
+        last_rung = n_promoted == 0 or (
+            max_generations is not None and n_generations >= max_generations
+        )
+
+        for idx, (score, trial) in enumerate(ranked):
+            if last_rung and not math.isnan(score):
+                frozen = study.tell(trial, score)
+            elif last_rung or idx >= n_promoted:
+                frozen = study.tell(trial, state=TrialState.PRUNED)
+            else:
+                continue
+
+            record_trial(history_path, study, frozen)
+
+        if last_rung:
+            break
+
+        survivors = [trial for _, trial in ranked[:n_promoted]]
+        n_generations *= reduction_factor
+        if max_generations is not None:
+            n_generations = min(n_generations, max_generations)
+        rung += 1
+
+
 def parse_args() -> argparse.Namespace:
     parser = argparse.ArgumentParser(description="Parameter Searcher")
     parser.add_argument(
@@ -179,16 +454,74 @@ def parse_args() -> argparse.Namespace:
         type=int,
         help="The number of threads to use per study",
     )
+    parser.add_argument(
+        "--history",
+        type=str,
+        help="JSON lines file every finished trial is appended to (assets/tuning/<env>.jsonl by default)",
+    )
+    parser.add_argument(
+        "--resume",
+        action="store_true",
+        help="Replay the trials of the history into the new study and only run the remaining ones",
+    )
+    parser.add_argument(
+        "--importance",
+        action="store_true",
+        help="Only report the importance of each hyperparameter from the history, without searching",
+    )
+    parser.add_argument(
+        "--successive-halving",
+        action="store_true",
+        help="Run n-trials x n-threads configurations for few generations, promoting the best to longer runs",
+    )
+    parser.add_argument(
+        "--min-generations",
+        default=10,
+        type=int,
+        help="Generations of the first rung of successive halving",
+    )
+    parser.add_argument(
+        "--reduction-factor",
+        default=3,
+        type=int,
+        help="Factor by which successive halving cuts configurations and extends runs every rung",
+    )
+    parser.add_argument(
+        "--max-generations",
+        type=int,
+        help="Generations after which successive halving stops promoting",
+    )
+    parser.add_argument(
+        "--checkpoint-root",
+        default="assets/tuning/checkpoints",
+        type=str,
+        help="Directory the runs of successive halving keep their checkpoints in",
+    )
     return parser.parse_args()
 
 
 def main(args: argparse.Namespace) -> None:
+    history_path = args.history or default_history_path(args.env)
+
+    if args.importance:
+        report_importance(load_history(history_path))
+        return
+
+    if not args.resume and Path(history_path).exists():
+        raise SystemExit(
+            f"{history_path} holds an earlier search, resume it with --resume or pick another --history"
+        )
+
     study_name = create_study(args.env)
+    history = load_history(history_path) if args.resume else []
+    replay_history(study_name, history)
 
     env_tokens = args.env.split("-")
     learning_type = env_tokens[-1]
     env_name = "-".join(env_tokens[:-1])
 
+    parameters = None
+
     if learning_type == "q":
         # todo: check for assets/parameters/{env_name}-lgp.json
         # load the parameters and set max_instructions and external_factor
@@ -197,30 +530,35 @@ def main(args: argparse.Namespace) -> None:
         assert Path(lgp_params).exists()
         with open(lgp_params, "r") as file:
             parameters = json.load(file)
-            objective = partial(
-                build_objective,
-                study_name,
-                args.median_trials,
-                lgp_parameters=parameters,
-            )
-    else:
-        objective = partial(build_objective, study_name, args.median_trials)
 
-    n_trials = args.n_trials
-    results: List[Future[Any]] = []
+    objective = partial(
+        build_objective,
+        study_name,
+        args.median_trials,
+        lgp_parameters=parameters,
+    )
 
-    with ThreadPoolExecutor(max_workers=args.n_threads) as executor:
-        for _ in range(args.n_threads):
-            future = executor.submit(
-                run_optimization,
-                study_name=study_name,
-                objective=objective,
-                n_trials=n_trials,
-            )
-            results.append(future)
+    # Every thread runs `n_trials` trials, those of an earlier run counting towards the total.
+    n_remaining = max(0, args.n_trials * args.n_threads - len(history))
+    n_trials = -(-n_remaining // args.n_threads)
+    logger.info(f"replayed {len(history)} trials, running {n_remaining} more")
+
+    if args.successive_halving:
+        successive_halving(
+            study_name,
+            args.median_trials,
+            n_configurations=n_remaining,
+            min_generations=args.min_generations,
+            reduction_factor=args.reduction_factor,
+            max_generations=args.max_generations,
+            n_threads=args.n_threads,
+            history_path=history_path,
+            checkpoint_root=args.checkpoint_root,
+            lgp_parameters=parameters,
+        )
 
-    for future in results:
-        future.result()
+    else:
+        run_in_threads(study_name, objective, n_trials, args.n_threads, history_path)
 
     load_study(study_name)
     save_best_hyperparameters(study_name)
@@ -229,6 +567,7 @@ def main(args: argparse.Namespace) -> None:
     logger.info(
         f"best_score={global_best_score}, best_params={global_hyper_parameters}"
     )
+    report_importance(load_history(history_path))
 
 
 if __name__ == "__main__":
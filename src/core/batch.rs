@@ -0,0 +1,308 @@
+//! Data-parallel interpretation of a single program over many input rows.
+//!
+//! With [`RowRegisters::Zeroed`], rows are processed in packs of [`LANES`]: every register is a packed
+//! SIMD vector holding one value per row of the pack, so each instruction is decoded once and applied to
+//! all rows at once. Input masks and instruction budgets are honoured as by [`Program::run`]; settling
+//! registers is a decision per row, so programs with a numeric policy other than
+//! [`NumericPolicy::Propagate`] run row by row through [`run_scalar`].
+//!
+//! With [`RowRegisters::Carried`], the registers one row leaves are those the next starts from, as
+//! classification fitness ([`Program::accuracy`], [`Program::classification_report`]) evaluates a
+//! dataset. Each row then depends on the previous one, so rows run one by one.
+use std::array;
+
+use clap::ValueEnum;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    engines::reset_engine::{Reset, ResetEngine},
+    environment::State,
+    instruction::{Op, Operand},
+    program::Program,
+    registers::{
+        narrow, ActionRegister, ArgmaxInput, NumericPolicy, RegisterValue, Registers, TieBreak,
+    },
+};
+use crate::utils::telemetry::record_program_execution;
+
+/// Number of rows executed simultaneously, twice as many when registers are `f32` as they fit the same
+/// vector registers.
+#[cfg(not(feature = "f32-registers"))]
+pub const LANES: usize = 4;
+#[cfg(feature = "f32-registers")]
+pub const LANES: usize = 8;
+
+/// A register of every row of a pack.
+#[cfg(not(feature = "f32-registers"))]
+type Lanes = wide::f64x4;
+#[cfg(feature = "f32-registers")]
+type Lanes = wide::f32x8;
+
+fn apply(op: Op, a: Lanes, b: Lanes) -> Lanes {
+    match op {
+        Op::Add => a + b,
+        Op::Mult => a * b,
+        Op::Divide => a / Lanes::splat(2.),
+        Op::Sub => a - b,
+        Op::Load => b,
+    }
+}
+
+/// Which registers each row starts from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ValueEnum)]
+pub enum RowRegisters {
+    /// The registers the previous row left (zeroed for the first row), as classification fitness
+    /// evaluates a dataset; predictions then match those programs were scored on.
+    #[default]
+    Carried,
+    /// Zeroed registers, as regression fitness evaluates its points; rows are independent and run
+    /// [`LANES`] at a time.
+    Zeroed,
+}
+
+/// A single row of features, read as the inputs of a program.
+pub struct Row<'a>(pub &'a [f64]);
+
+impl State for Row<'_> {
+    fn get_value(&self, at_idx: usize) -> f64 {
+        self.0[at_idx]
+    }
+
+    fn execute_action(&mut self, _action: usize) -> f64 {
+        0.
+    }
+
+    fn get(&mut self) -> Option<&mut Self> {
+        Some(self)
+    }
+}
+
+/// Runs `program` over every row, returning the final registers of each row.
+pub fn run_batch(program: &Program, rows: &[Vec<f64>], mode: RowRegisters) -> Vec<Registers> {
+    if mode == RowRegisters::Carried
+        || program.numeric_parameters.numeric_policy != NumericPolicy::Propagate
+    {
+        return run_scalar(program, rows, mode);
+    }
+
+    let input_mask = program.input_mask.as_deref().unwrap_or(&[]);
+    // As `Program::run` does when the budget runs out before the last instruction, every register of
+    // every row is left `NaN`.
+    let out_of_bounds = program
+        .instruction_budget
+        .map_or(false, |budget| program.instructions.len() > budget);
+    let (initial, instructions) = match out_of_bounds {
+        true => (RegisterValue::NAN, &program.instructions[..0]),
+        false => (0., &program.instructions[..]),
+    };
+
+    let n_registers = program.registers.len();
+    let n_inputs = rows.iter().map(Vec::len).max().unwrap_or(0);
+
+    let mut outputs = Vec::with_capacity(rows.len());
+    let mut registers: Vec<Lanes> = vec![Lanes::splat(0.); n_registers];
+    let mut inputs: Vec<[f64; LANES]> = vec![[0.; LANES]; n_inputs];
+
+    for pack in rows.chunks(LANES) {
+        // Transpose the pack so each input holds one value per lane; missing lanes are padded with zeros.
+        for (input_idx, input) in inputs.iter_mut().enumerate() {
+            *input = array::from_fn(|lane| {
+                pack.get(lane)
+                    .and_then(|row| row.get(input_idx))
+                    .copied()
+                    .unwrap_or(0.)
+            });
+        }
+
+        for register in registers.iter_mut() {
+            *register = Lanes::splat(initial);
+        }
+
+        for instruction in instructions {
+            let instruction = match instruction.reads_masked_input(input_mask) {
+                true => instruction.masked(),
+                false => *instruction,
+            };
+
+            let operand = match instruction.src2() {
+                Operand::Input(input) => {
+                    let factor = instruction.external_factor();
+                    let input = inputs[input];
+                    Lanes::new(array::from_fn(|lane| narrow(factor * input[lane])))
+                }
+                Operand::Register(register) => registers[register],
+                Operand::Immediate(value) => Lanes::splat(narrow(value)),
+            };
+
+            let source = registers[instruction.src1()];
+            registers[instruction.dest()] = apply(instruction.op(), source, operand);
+        }
+
+        let lanes = registers
+            .iter()
+            .map(|register| register.to_array())
+            .collect::<Vec<_>>();
+
+        for lane in 0..pack.len() {
+            record_program_execution();
+
+            // Copies the layout (and readout) of the program's registers.
+            let mut output = program.registers.clone();
+            for (value, register) in output.as_mut_slice().iter_mut().zip(lanes.iter()) {
+                *value = register[lane];
+            }
+            outputs.push(output);
+        }
+    }
+
+    outputs
+}
+
+/// The scalar counterpart of [`run_batch`]: runs `program` row by row through [`Program::run`].
+pub fn run_scalar(program: &Program, rows: &[Vec<f64>], mode: RowRegisters) -> Vec<Registers> {
+    let mut program = program.clone();
+    ResetEngine::reset(&mut program.registers);
+
+    rows.iter()
+        .map(|row| {
+            if mode == RowRegisters::Zeroed {
+                ResetEngine::reset(&mut program.registers);
+            }
+            program.run(&Row(row));
+            program.registers.clone()
+        })
+        .collect()
+}
+
+/// Predicts the class of every row as [`Program::select_action`] does, ties failing unless the program
+/// breaks them otherwise; `None` when the action registers overflow or tie.
+pub fn predict_batch(
+    program: &Program,
+    rows: &[Vec<f64>],
+    mode: RowRegisters,
+) -> Vec<Option<usize>> {
+    let tie_break = program.tie_break.unwrap_or(TieBreak::Fail);
+
+    run_batch(program, rows, mode)
+        .iter()
+        .map(|registers| {
+            match registers
+                .argmax(ArgmaxInput::ActionRegisters)
+                .resolve(tie_break, registers.n_actions())
+            {
+                ActionRegister::Value(class) => Some(class),
+                ActionRegister::Overflow => None,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+
+    use super::*;
+    use crate::core::engines::generate_engine::{Generate, GenerateEngine};
+    use crate::core::registers::NumericParameters;
+    use crate::extensions::classification::{ClassificationReport, Dataset};
+    use crate::utils::misc::VoidResultAnyError;
+    use crate::utils::test::program_parameters_of_length;
+
+    #[test]
+    fn given_random_programs_when_run_in_batch_then_registers_match_scalar_path(
+    ) -> VoidResultAnyError {
+        let program_parameters = program_parameters_of_length(4, 3, 50);
+
+        // 10 rows: two full packs and a partial one.
+        let rows = (0..10)
+            .map(|row| (0..4).map(|col| (row * 4 + col) as f64 / 7.).collect_vec())
+            .collect_vec();
+
+        for _ in 0..20 {
+            let program: Program = GenerateEngine::generate(program_parameters);
+
+            let batch = run_batch(&program, &rows, RowRegisters::Zeroed);
+            let scalar = run_scalar(&program, &rows, RowRegisters::Zeroed);
+
+            assert_eq!(batch.len(), rows.len());
+
+            for (batch, scalar) in batch.iter().zip(scalar.iter()) {
+                let batch = batch.iter().copied().collect_vec();
+                let scalar = scalar.iter().copied().collect_vec();
+
+                assert!(batch
+                    .iter()
+                    .zip(scalar.iter())
+                    .all(|(a, b)| a == b || (a.is_nan() && b.is_nan())));
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_masked_budgeted_or_settled_programs_when_run_in_batch_then_registers_match_program_run(
+    ) -> VoidResultAnyError {
+        let program_parameters = program_parameters_of_length(4, 2, 30);
+
+        let rows = (0..6)
+            .map(|row| (0..4).map(|col| (row * 4 + col) as f64 * 1e3).collect_vec())
+            .collect_vec();
+        let same = |a: &Registers, b: &Registers| {
+            a.iter()
+                .zip(b.iter())
+                .all(|(a, b)| a == b || (a.is_nan() && b.is_nan()))
+        };
+
+        for _ in 0..20 {
+            let generated: Program = GenerateEngine::generate(program_parameters);
+
+            let mut masked = generated.clone();
+            masked.input_mask = Some(vec![true, false, true, false]);
+
+            let mut budgeted = generated.clone();
+            budgeted.instruction_budget = Some(generated.instructions.len() / 2);
+
+            let mut clamped = generated.clone();
+            clamped.numeric_parameters = NumericParameters {
+                numeric_policy: NumericPolicy::Clamp,
+                register_bound: 1e3,
+            };
+
+            for program in [masked, budgeted, clamped] {
+                let batch = run_batch(&program, &rows, RowRegisters::Zeroed);
+                let scalar = run_scalar(&program, &rows, RowRegisters::Zeroed);
+
+                assert!(batch.iter().zip(scalar.iter()).all(|(a, b)| same(a, b)));
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_carried_registers_when_rows_are_predicted_then_predictions_match_the_classification_report(
+    ) -> VoidResultAnyError {
+        let program_parameters = program_parameters_of_length(4, 3, 50);
+
+        let rows = (0..12)
+            .map(|row| (0..4).map(|col| ((row * 5 + col) % 9) as f64).collect_vec())
+            .collect_vec();
+        let labels = (0..12).map(|row| row % 3).collect_vec();
+        let dataset = Dataset::new(rows.clone(), labels.clone());
+
+        for _ in 0..20 {
+            let program: Program = GenerateEngine::generate(program_parameters);
+
+            let predictions = predict_batch(&program, &rows, RowRegisters::Carried);
+            let pairs = labels.iter().copied().zip(predictions).collect_vec();
+
+            assert_eq!(
+                ClassificationReport::new(3, &pairs),
+                program.classification_report(&dataset)
+            );
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,430 @@
+//! A compact, pre-decoded form of a program's instructions.
+//!
+//! Compilation resolves each instruction's operand kind and operation once, so executing the program over the
+//! hundreds of states of an episode only dispatches on a single opcode per instruction. Divisions, which
+//! ignore their operand, never read it; loads, which overwrite their register, never read the register.
+use serde::{Deserialize, Serialize};
+
+use super::{
+    environment::State,
+    instruction::{Op, Operand},
+    instructions::Instructions,
+    registers::{narrow, NumericParameters, NumericPolicy, RegisterValue, Registers},
+};
+
+/// How an execution of a program ended.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum ExecOutcome {
+    Completed,
+    /// The numeric policy invalidated the registers.
+    Invalidated,
+    /// The execution ran out of instruction budget; every register is set to `NaN`, so the program is
+    /// marked invalid.
+    OutOfBounds,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Code {
+    AddRegister {
+        dst: u32,
+        src: u32,
+        operand: u32,
+    },
+    SubRegister {
+        dst: u32,
+        src: u32,
+        operand: u32,
+    },
+    MultRegister {
+        dst: u32,
+        src: u32,
+        operand: u32,
+    },
+    AddInput {
+        dst: u32,
+        src: u32,
+        input: u32,
+        factor: f64,
+    },
+    SubInput {
+        dst: u32,
+        src: u32,
+        input: u32,
+        factor: f64,
+    },
+    MultInput {
+        dst: u32,
+        src: u32,
+        input: u32,
+        factor: f64,
+    },
+    AddImmediate {
+        dst: u32,
+        src: u32,
+        value: f64,
+    },
+    SubImmediate {
+        dst: u32,
+        src: u32,
+        value: f64,
+    },
+    MultImmediate {
+        dst: u32,
+        src: u32,
+        value: f64,
+    },
+    Half {
+        dst: u32,
+        src: u32,
+    },
+    LoadRegister {
+        dst: u32,
+        operand: u32,
+    },
+    LoadInput {
+        dst: u32,
+        input: u32,
+        factor: f64,
+    },
+    LoadImmediate {
+        dst: u32,
+        value: f64,
+    },
+}
+
+impl Code {
+    /// The register written by the code.
+    pub fn dst(&self) -> usize {
+        match *self {
+            Code::AddRegister { dst, .. }
+            | Code::SubRegister { dst, .. }
+            | Code::MultRegister { dst, .. }
+            | Code::AddInput { dst, .. }
+            | Code::SubInput { dst, .. }
+            | Code::MultInput { dst, .. }
+            | Code::AddImmediate { dst, .. }
+            | Code::SubImmediate { dst, .. }
+            | Code::MultImmediate { dst, .. }
+            | Code::Half { dst, .. }
+            | Code::LoadRegister { dst, .. }
+            | Code::LoadInput { dst, .. }
+            | Code::LoadImmediate { dst, .. } => dst as usize,
+        }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Bytecode {
+    codes: Vec<Code>,
+}
+
+impl Bytecode {
+    pub fn compile(instructions: &Instructions) -> Self {
+        Self::compile_masked(instructions, None)
+    }
+
+    /// Compiles `instructions`, reading the inputs disabled by `input_mask` as `0`.
+    pub fn compile_masked(instructions: &Instructions, input_mask: Option<&[bool]>) -> Self {
+        let codes = instructions
+            .iter()
+            .map(|instruction| match input_mask {
+                Some(mask) if instruction.reads_masked_input(mask) => instruction.masked(),
+                _ => *instruction,
+            })
+            .map(|instruction| {
+                let dst = instruction.dest() as u32;
+                let src = instruction.src1() as u32;
+                let factor = instruction.external_factor();
+
+                match (instruction.op(), instruction.src2()) {
+                    (Op::Divide, _) => Code::Half { dst, src },
+                    (Op::Add, Operand::Register(operand)) => Code::AddRegister {
+                        dst,
+                        src,
+                        operand: operand as u32,
+                    },
+                    (Op::Sub, Operand::Register(operand)) => Code::SubRegister {
+                        dst,
+                        src,
+                        operand: operand as u32,
+                    },
+                    (Op::Mult, Operand::Register(operand)) => Code::MultRegister {
+                        dst,
+                        src,
+                        operand: operand as u32,
+                    },
+                    (Op::Add, Operand::Input(input)) => Code::AddInput {
+                        dst,
+                        src,
+                        input: input as u32,
+                        factor,
+                    },
+                    (Op::Sub, Operand::Input(input)) => Code::SubInput {
+                        dst,
+                        src,
+                        input: input as u32,
+                        factor,
+                    },
+                    (Op::Mult, Operand::Input(input)) => Code::MultInput {
+                        dst,
+                        src,
+                        input: input as u32,
+                        factor,
+                    },
+                    (Op::Add, Operand::Immediate(value)) => Code::AddImmediate { dst, src, value },
+                    (Op::Sub, Operand::Immediate(value)) => Code::SubImmediate { dst, src, value },
+                    (Op::Mult, Operand::Immediate(value)) => {
+                        Code::MultImmediate { dst, src, value }
+                    }
+                    (Op::Load, Operand::Register(operand)) => Code::LoadRegister {
+                        dst,
+                        operand: operand as u32,
+                    },
+                    (Op::Load, Operand::Input(input)) => Code::LoadInput {
+                        dst,
+                        input: input as u32,
+                        factor,
+                    },
+                    (Op::Load, Operand::Immediate(value)) => Code::LoadImmediate { dst, value },
+                }
+            })
+            .collect();
+
+        Bytecode { codes }
+    }
+
+    pub fn len(&self) -> usize {
+        self.codes.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.codes.is_empty()
+    }
+
+    /// Executes the codes over `input`, aborting once more than `instruction_budget` codes would run.
+    pub fn exec(
+        &self,
+        registers: &mut Registers,
+        input: &impl State,
+        numeric_parameters: NumericParameters,
+        instruction_budget: Option<usize>,
+    ) -> ExecOutcome {
+        let registers = registers.as_mut_slice();
+        let settle = numeric_parameters.numeric_policy != NumericPolicy::Propagate;
+        let instruction_budget = instruction_budget.unwrap_or(usize::MAX);
+
+        for (n_executed, code) in self.codes.iter().enumerate() {
+            if n_executed >= instruction_budget {
+                registers.fill(RegisterValue::NAN);
+                return ExecOutcome::OutOfBounds;
+            }
+
+            match *code {
+                Code::AddRegister { dst, src, operand } => {
+                    registers[dst as usize] = registers[src as usize] + registers[operand as usize]
+                }
+                Code::SubRegister { dst, src, operand } => {
+                    registers[dst as usize] = registers[src as usize] - registers[operand as usize]
+                }
+                Code::MultRegister { dst, src, operand } => {
+                    registers[dst as usize] = registers[src as usize] * registers[operand as usize]
+                }
+                Code::AddInput {
+                    dst,
+                    src,
+                    input: idx,
+                    factor,
+                } => {
+                    registers[dst as usize] =
+                        registers[src as usize] + narrow(factor * input.get_value(idx as usize))
+                }
+                Code::SubInput {
+                    dst,
+                    src,
+                    input: idx,
+                    factor,
+                } => {
+                    registers[dst as usize] =
+                        registers[src as usize] - narrow(factor * input.get_value(idx as usize))
+                }
+                Code::MultInput {
+                    dst,
+                    src,
+                    input: idx,
+                    factor,
+                } => {
+                    registers[dst as usize] =
+                        registers[src as usize] * narrow(factor * input.get_value(idx as usize))
+                }
+                Code::AddImmediate { dst, src, value } => {
+                    registers[dst as usize] = registers[src as usize] + narrow(value)
+                }
+                Code::SubImmediate { dst, src, value } => {
+                    registers[dst as usize] = registers[src as usize] - narrow(value)
+                }
+                Code::MultImmediate { dst, src, value } => {
+                    registers[dst as usize] = registers[src as usize] * narrow(value)
+                }
+                Code::Half { dst, src } => registers[dst as usize] = registers[src as usize] / 2.,
+                Code::LoadRegister { dst, operand } => {
+                    registers[dst as usize] = registers[operand as usize]
+                }
+                Code::LoadInput {
+                    dst,
+                    input: idx,
+                    factor,
+                } => registers[dst as usize] = narrow(factor * input.get_value(idx as usize)),
+                Code::LoadImmediate { dst, value } => registers[dst as usize] = narrow(value),
+            }
+
+            if settle && !numeric_parameters.settle(registers, code.dst()) {
+                return ExecOutcome::Invalidated;
+            }
+        }
+
+        ExecOutcome::Completed
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+
+    use super::*;
+    use crate::core::{
+        batch::Row,
+        engines::{
+            generate_engine::{Generate, GenerateEngine},
+            mutate_engine::{Mutate, MutateEngine},
+        },
+        instruction::Modes,
+        program::Program,
+    };
+    use crate::problems::problem::program_parameters;
+    use crate::utils::misc::VoidResultAnyError;
+    use crate::utils::test::program_parameters_of_length;
+
+    #[test]
+    fn given_random_programs_when_compiled_then_execution_matches_interpreter() -> VoidResultAnyError
+    {
+        let program_parameters = program_parameters_of_length(4, 3, 50);
+
+        let rows = (0..5)
+            .map(|row| (0..4).map(|col| (row * 4 + col) as f64 / 3.).collect_vec())
+            .collect_vec();
+
+        for _ in 0..20 {
+            let mut compiled: Program = GenerateEngine::generate(program_parameters);
+            let mut interpreted = compiled.clone();
+
+            // Registers carry over between states, as they do within an episode.
+            for row in rows.iter() {
+                compiled.run(&Row(row));
+                interpreted.interpret(&Row(row));
+            }
+
+            assert!(compiled
+                .registers
+                .iter()
+                .zip(interpreted.registers.iter())
+                .all(|(a, b)| a == b || (a.is_nan() && b.is_nan())));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_immediate_operands_when_compiled_then_execution_matches_interpreter(
+    ) -> VoidResultAnyError {
+        let mut program_parameters = program_parameters_of_length(2, 2, 30);
+        program_parameters.instruction_generator_parameters.modes = Modes::Immediate;
+
+        let row = vec![0.25, -1.5];
+
+        for _ in 0..20 {
+            let mut compiled: Program = GenerateEngine::generate(program_parameters);
+            let mut interpreted = compiled.clone();
+
+            compiled.run(&Row(&row));
+            interpreted.interpret(&Row(&row));
+
+            assert!(compiled
+                .registers
+                .iter()
+                .zip(interpreted.registers.iter())
+                .all(|(a, b)| a == b || (a.is_nan() && b.is_nan())));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_compiled_program_when_mutated_then_new_instructions_are_executed() -> VoidResultAnyError
+    {
+        let program_parameters = program_parameters_of_length(2, 2, 20);
+
+        let row = vec![0.5, -2.];
+        let mut program: Program = GenerateEngine::generate(program_parameters);
+        program.run(&Row(&row));
+
+        MutateEngine::mutate(&mut program, program_parameters);
+
+        let mut interpreted = program.clone();
+        program.run(&Row(&row));
+        interpreted.interpret(&Row(&row));
+
+        assert!(program
+            .registers
+            .iter()
+            .zip(interpreted.registers.iter())
+            .all(|(a, b)| a == b || (a.is_nan() && b.is_nan())));
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_numeric_policy_when_compiled_then_execution_matches_interpreter() -> VoidResultAnyError
+    {
+        for numeric_policy in [
+            NumericPolicy::Clamp,
+            NumericPolicy::Saturate,
+            NumericPolicy::Invalidate,
+        ] {
+            let mut program_parameters = program_parameters(1, 2);
+            program_parameters.numeric_parameters = NumericParameters {
+                numeric_policy,
+                register_bound: 100.,
+            };
+
+            // r2 overflows after a few multiplications by 10 * 1e300.
+            let mut compiled = Program::parse(
+                "r2 = r2 + in0; r2 = r2 * in0; r2 = r2 * in0; r0 = r0 + r2; r1 = r1 - r2",
+                program_parameters,
+            )?;
+            let mut interpreted = compiled.clone();
+
+            let row = vec![1e300];
+            compiled.run(&Row(&row));
+            interpreted.interpret(&Row(&row));
+
+            assert!(compiled
+                .registers
+                .iter()
+                .zip(interpreted.registers.iter())
+                .all(|(a, b)| a == b || (a.is_nan() && b.is_nan())));
+
+            match numeric_policy {
+                NumericPolicy::Clamp => assert_eq!(compiled.registers[0..2], [100., -100.]),
+                NumericPolicy::Saturate => {
+                    assert_eq!(
+                        compiled.registers[0..2],
+                        [RegisterValue::MAX, RegisterValue::MIN]
+                    )
+                }
+                _ => assert!(compiled.registers.iter().all(|value| value.is_nan())),
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -6,18 +6,88 @@ use std::{
 };
 
 use serde::{de::DeserializeOwned, Serialize};
+use serde_json::Value;
 
 use crate::utils::benchmark_tools::create_path;
 
+/// Version of the layout of the artifacts written by [`Save`]. Bump it, and add the corresponding step
+/// to [`migrate`], whenever a change to a serialized type stops older files from deserializing.
+///
+/// - 1: first versioned layout.
+/// - 2: three-address instructions. JSON still reads the legacy instruction layout, binary
+///   populations of version 1 are rejected.
+pub const FORMAT_VERSION: u64 = 2;
+
+/// Header wrapped around every saved artifact.
+#[derive(Serialize)]
+pub(crate) struct Versioned<'a, T> {
+    pub(crate) format_version: u64,
+    pub(crate) data: &'a T,
+}
+
+/// Upgrades `data`, written with format `version`, to [`FORMAT_VERSION`].
+pub fn migrate(mut data: Value, version: u64) -> Result<Value, Box<dyn Error>> {
+    if version > FORMAT_VERSION {
+        return Err(format!(
+            "artifact has format version {}, but this build only reads up to version {}",
+            version, FORMAT_VERSION
+        )
+        .into());
+    }
+
+    for from in version..FORMAT_VERSION {
+        data = match from {
+            // Artifacts written before formats were versioned hold the data itself, and every field
+            // added since then has a default.
+            0 => data,
+            // Instructions in the legacy layout are recognized by their fields (see `Instruction`'s
+            // `Deserialize`).
+            1 => data,
+            _ => unreachable!("missing migration from format version {}", from),
+        };
+    }
+
+    Ok(data)
+}
+
+/// Deserializes an artifact written by [`Save`], or by a version of the crate which did not version
+/// its artifacts yet.
+pub fn from_versioned_str<T>(contents: &str) -> Result<T, Box<dyn Error>>
+where
+    T: DeserializeOwned,
+{
+    let (data, version) = match serde_json::from_str::<Value>(contents)? {
+        Value::Object(mut object) if object.contains_key("format_version") => {
+            let version = object
+                .remove("format_version")
+                .and_then(|version| version.as_u64())
+                .ok_or("format_version must be a non-negative integer")?;
+            let data = object
+                .remove("data")
+                .ok_or("versioned artifact has no data")?;
+
+            (data, version)
+        }
+        data => (data, 0),
+    };
+
+    Ok(serde_json::from_value(migrate(data, version)?)?)
+}
+
 pub trait Load
 where
     Self: Sized + DeserializeOwned,
 {
-    fn load(path: impl Into<PathBuf>) -> Self {
-        let contents = read_to_string(&path.into()).unwrap();
-        let deserialized: Self = serde_json::from_str(&contents).unwrap();
+    fn try_load(path: impl Into<PathBuf>) -> Result<Self, Box<dyn Error>> {
+        let path = path.into();
+        let contents = read_to_string(&path)?;
 
-        deserialized
+        from_versioned_str(&contents)
+            .map_err(|error| format!("{}: {}", path.display(), error).into())
+    }
+
+    fn load(path: impl Into<PathBuf>) -> Self {
+        Self::try_load(path).unwrap()
     }
 }
 
@@ -28,11 +98,15 @@ where
     fn save(&self, path: &str) -> Result<String, Box<dyn Error>> {
         create_path(path, true)?;
 
-        let serialized = serde_json::to_string_pretty(&self)?;
+        let serialized = serde_json::to_string_pretty(&Versioned {
+            format_version: FORMAT_VERSION,
+            data: self,
+        })?;
 
         let mut file = OpenOptions::new()
             .write(true)
             .create(true)
+            .truncate(true)
             .open(Path::new(path))?;
 
         file.write_all(serialized.as_bytes())?;
@@ -46,3 +120,67 @@ pub trait Reproduce: Load + Save {}
 impl<T> Load for T where T: Sized + DeserializeOwned {}
 impl<T> Save for T where T: Serialize {}
 impl<T> Reproduce for T where T: Load + Save {}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+    use crate::core::engines::status_engine::{Status, StatusEngine};
+    use crate::core::program::Program;
+    use crate::utils::telemetry::RunSummary;
+
+    const FIXTURES: &str = "assets/fixtures/formats";
+
+    #[test]
+    fn given_unversioned_program_when_loaded_then_it_is_migrated() -> Result<(), Box<dyn Error>> {
+        let program = Program::try_load(Path::new(FIXTURES).join("v0-program.json"))?;
+
+        assert_eq!(StatusEngine::get_fitness(&program), 12.5);
+        assert_eq!(program.instructions.len(), 2);
+        assert_eq!(program.frame_skip, 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_unversioned_summary_when_loaded_then_new_fields_are_defaulted(
+    ) -> Result<(), Box<dyn Error>> {
+        let summary = RunSummary::try_load(Path::new(FIXTURES).join("v0-summary.json"))?;
+
+        assert_eq!(summary.n_generations, 2);
+        assert_eq!(summary.total_time(), Duration::from_millis(16));
+        assert_eq!(summary.episodes, 0);
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_newer_format_when_loaded_then_error_names_both_versions() {
+        let error = RunSummary::try_load(Path::new(FIXTURES).join("v99-summary.json"))
+            .unwrap_err()
+            .to_string();
+
+        assert!(error.contains("format version 99"));
+        assert!(error.contains(&format!("up to version {}", FORMAT_VERSION)));
+    }
+
+    #[test]
+    fn given_saved_artifact_when_loaded_then_it_round_trips() -> Result<(), Box<dyn Error>> {
+        let summary = RunSummary {
+            n_generations: 3,
+            episodes: 4,
+            ..Default::default()
+        };
+
+        let serialized = serde_json::to_string(&Versioned {
+            format_version: FORMAT_VERSION,
+            data: &summary,
+        })?;
+
+        assert!(serialized.starts_with(&format!("{{\"format_version\":{}", FORMAT_VERSION)));
+        assert_eq!(from_versioned_str::<RunSummary>(&serialized)?, summary);
+
+        Ok(())
+    }
+}
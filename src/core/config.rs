@@ -1,31 +1,567 @@
+use std::{
+    iter::{once, repeat_with},
+    path::PathBuf,
+};
+
+use crate::core::batch::Row;
+use crate::core::characteristics::{Load, Save};
+use crate::core::engines::core_engine::{Checkpoint, CoreIter};
+use crate::core::engines::freeze_engine::Freeze;
+use crate::core::engines::generate_engine::{Generate, GenerateEngine};
 use crate::core::engines::reset_engine::{Reset, ResetEngine};
 use crate::core::engines::status_engine::{Status, StatusEngine};
+use crate::core::fixed_point::{FixedPointParameters, FixedPointProgram};
+use crate::core::program::{AsProgram, Program};
+use crate::core::registers::TieBreak;
+use crate::extensions::regression::RegressionEngine;
+use crate::utils::{
+    benchmark_tools::create_path,
+    compare::RunComparison,
+    diff::{instruction_diff, load_programs, PopulationDiff},
+    interrupt::install_interrupt_handler,
+    ledger::EvaluationLedger,
+    misc::VoidResultAnyError,
+    random::{standard_normal, update_seed},
+    report::{ReportFormat, RunReport},
+    robustness::robustness,
+    tournament::{load_run, tournament},
+};
 use crate::{
     core::engines::core_engine::HyperParameters,
     problems::{
+        acrobot::{Acrobot, ShapedAcrobot},
+        custom::{CustomEngine, CustomQEngine, Navigation},
+        frozen_lake::OneHotFrozenLake,
         gym::{GymRsEngine, GymRsQEngine},
-        iris::IrisEngine,
+        iris::{IrisEngine, IrisState},
+        presets::override_preset,
+        problem::Problem,
+        symbolic::{Koza1, Nguyen1, Nguyen3, Nguyen4, Nguyen5, Nguyen6, Nguyen7, Nguyen8},
     },
 };
-use clap::Parser;
+use clap::{Args, Parser};
 use config::{Config, Environment, File};
 use gym_rs::envs::classical_control::{cartpole::CartPoleEnv, mountain_car::MountainCarEnv};
 use serde::{Deserialize, Serialize};
+use uuid::Uuid;
 
 use super::engines::core_engine::Core;
 
-// Generate a macro which takes hyperparameters, builds the necessary engine and run its
-// outputting the best score for each generation
-macro_rules! run_actuator {
-    ($engine:ident, $hyperparameters:ident) => {
-        for population in $hyperparameters
-            .build_engine()
-            .take($hyperparameters.population_size)
-        {
-            println!("{}", StatusEngine::get_fitness(population.first().unwrap()));
+#[derive(Args, Debug, Clone, Default, Serialize, Deserialize)]
+pub struct RunOptions {
+    /// Write a checkpoint, the best program and the run metrics to this directory once the run ends.
+    /// On Ctrl-C, the current generation is finished first.
+    #[arg(long, global = true)]
+    pub checkpoint_dir: Option<PathBuf>,
+    /// Resume from the `checkpoint.json` of an earlier run of the same problem.
+    #[arg(long, global = true)]
+    pub resume: Option<PathBuf>,
+    /// When resuming, run until this generation instead of the checkpoint's `n_generations`, e.g. to
+    /// continue a run promoted by successive halving.
+    #[arg(long, global = true, requires = "resume")]
+    pub until: Option<usize>,
+    /// Append every evaluation of the run to this JSON lines ledger, keyed by genome hash.
+    #[arg(long, global = true)]
+    pub ledger: Option<PathBuf>,
+    /// Run with a preset of the problem, `fast` or `thorough`, the hyperparameters given on the command
+    /// line overriding those of the preset.
+    #[arg(long, global = true)]
+    pub preset: Option<String>,
+    /// Show a live dashboard of the run on stderr.
+    #[cfg(feature = "tui")]
+    #[arg(long, global = true)]
+    pub dashboard: bool,
+    /// Trace the best individual of the dashboard over this input (comma-separated values).
+    #[cfg(feature = "tui")]
+    #[arg(long, global = true, value_delimiter = ',', allow_hyphen_values = true)]
+    pub trace_input: Option<Vec<f64>>,
+}
+
+#[derive(Parser)]
+#[command(author, version, about, long_about=None)]
+pub struct Cli {
+    #[command(flatten)]
+    pub options: RunOptions,
+    #[command(subcommand)]
+    pub actuator: Actuator,
+}
+
+impl Cli {
+    pub fn run(&mut self) -> VoidResultAnyError {
+        self.actuator.run_with(&self.options)
+    }
+}
+
+/// Runs `P` from `hyperparameters` (e.g. parsed from the command line) once the parameters dictated by
+/// the problem are applied, printing the best score of each generation followed by the parameters used:
+/// those of the checkpoint when resuming.
+pub fn run_problem<P>(
+    hyperparameters: &mut HyperParameters<P>,
+    options: &RunOptions,
+) -> VoidResultAnyError
+where
+    P: Problem,
+    P::Individual: AsProgram,
+{
+    if let Some(preset) = &options.preset {
+        *hyperparameters = override_preset(P::preset(preset)?, hyperparameters)?;
+    }
+
+    P::build_fitness_parameters(hyperparameters);
+    run_engine(hyperparameters, options)?;
+    println!("{}", serde_json::to_string(hyperparameters)?);
+
+    Ok(())
+}
+
+/// Runs the engine built from `hyperparameters`, or the run resumed from `options.resume`, whose
+/// parameters then replace `hyperparameters`.
+fn run_engine<C>(
+    hyperparameters: &mut HyperParameters<C>,
+    options: &RunOptions,
+) -> VoidResultAnyError
+where
+    C: Core,
+    C::Individual: AsProgram,
+{
+    let mut engine = match &options.resume {
+        Some(path) => {
+            let mut checkpoint = Checkpoint::<C>::try_load(path)?;
+            if let Some(until) = options.until {
+                checkpoint.params.n_generations = until;
+            }
+
+            *hyperparameters = checkpoint.params;
+            update_seed(checkpoint.params.seed);
+            CoreIter::resume(checkpoint)
         }
-        println!("{}", serde_json::to_string(&$hyperparameters).unwrap());
+        None => hyperparameters.build_engine(),
+    };
+
+    engine = engine.with_cost_penalty();
+
+    if options.checkpoint_dir.is_some() {
+        engine = engine.stop_when(install_interrupt_handler()?);
+    }
+
+    #[cfg(feature = "tui")]
+    let mut dashboard = match options.dashboard {
+        true => Some(
+            crate::utils::dashboard::Dashboard::new()?
+                .with_trace_input(options.trace_input.clone()),
+        ),
+        false => None,
     };
+
+    let mut ledger = match &options.ledger {
+        Some(path) => Some(EvaluationLedger::open(path, hyperparameters.objective)?),
+        None => None,
+    };
+    let first_generation = engine.generation();
+
+    let mut best = None;
+
+    for (idx, population) in engine.by_ref().enumerate() {
+        println!("{}", StatusEngine::get_fitness(population.first().unwrap()));
+
+        if let Some(ledger) = ledger.as_mut() {
+            ledger.record_population::<C>(first_generation + idx, &population);
+            ledger.flush()?;
+        }
+
+        #[cfg(feature = "tui")]
+        if let Some(dashboard) = dashboard.as_mut() {
+            dashboard.update::<C>(&population)?;
+        }
+
+        best = population.first().cloned();
+    }
+
+    #[cfg(feature = "tui")]
+    drop(dashboard);
+
+    if let Some(program) = best.as_ref().map(AsProgram::as_program) {
+        if program.input_mask.is_some() {
+            eprintln!("selected features: {:?}", program.selected_features());
+        }
+    }
+
+    // Tests the best program against the noise it should withstand on fresh trials.
+    let noise = hyperparameters.noise.test();
+    if let (false, Some(best)) = (noise.is_clean(), best.as_ref()) {
+        let mut trials = (0..hyperparameters.n_trials.max(1))
+            .map(|_| C::Generate::generate(()))
+            .collect::<Vec<C::State>>();
+        let report = robustness(best, &mut trials, hyperparameters);
+
+        eprintln!("robustness: {}", serde_json::to_string(&report)?);
+
+        if let Some(checkpoint_dir) = &options.checkpoint_dir {
+            report.save(checkpoint_dir.join("robustness.json").to_str().unwrap())?;
+        }
+    }
+
+    if let Some(checkpoint_dir) = &options.checkpoint_dir {
+        let checkpoint_path = checkpoint_dir.join("checkpoint.json");
+        let checkpoint = engine.checkpoint();
+
+        checkpoint.save(checkpoint_path.to_str().unwrap())?;
+        engine
+            .summary()
+            .save(checkpoint_dir.join("summary.json").to_str().unwrap())?;
+
+        if let Some(mut best) = best {
+            C::Freeze::freeze(&mut best);
+            best.save(checkpoint_dir.join("best.json").to_str().unwrap())?;
+        }
+
+        if engine.stopped() {
+            eprintln!(
+                "interrupted before generation {}, resume with `--resume {}`",
+                checkpoint.generation,
+                checkpoint_path.display()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+#[derive(Args, Debug, Clone, Serialize, Deserialize)]
+pub struct EvaluateOptions {
+    /// A program saved by a run, e.g. its `best.json`.
+    #[arg(long)]
+    pub program: PathBuf,
+    /// Number of shuffles each feature's permutation importance is averaged over.
+    #[arg(long, default_value = "10")]
+    pub n_permutations: usize,
+    #[arg(long)]
+    pub seed: Option<u64>,
+    /// Also export the confusion matrix and per-class precision and recall to this directory, e.g. the
+    /// run directory so its report includes them.
+    #[arg(long)]
+    pub report_dir: Option<PathBuf>,
+}
+
+/// Prints the accuracy of a saved Iris classifier, then the importance of each feature as JSON lines.
+fn evaluate_iris(options: &EvaluateOptions) -> VoidResultAnyError {
+    update_seed(options.seed);
+
+    let program = Program::try_load(&options.program)?;
+    let iris: IrisState = GenerateEngine::generate(());
+    let dataset = iris.dataset();
+
+    println!("{}", program.accuracy(&dataset));
+
+    for importance in program.feature_importance(&dataset, options.n_permutations) {
+        println!("{}", serde_json::to_string(&importance)?);
+    }
+
+    if let Some(report_dir) = &options.report_dir {
+        program.classification_report(&dataset).export(report_dir)?;
+    }
+
+    Ok(())
+}
+
+#[derive(Args, Debug, Clone, Serialize, Deserialize)]
+pub struct InspectOptions {
+    /// A program saved by a run, e.g. its `best.json`.
+    #[arg(long)]
+    pub program: PathBuf,
+    /// The input the program runs over, as comma-separated values.
+    #[arg(long, value_delimiter = ',', allow_hyphen_values = true)]
+    pub input: Vec<f64>,
+    /// Traces the program once simplified, checked against the original on the input and on gaussian
+    /// samples.
+    #[arg(long)]
+    pub simplify: bool,
+}
+
+/// Number of inputs a program simplified by `inspect` is checked on, the given input included.
+const SIMPLIFICATION_SAMPLES: usize = 64;
+
+/// Prints every instruction of a saved program along with the registers it leaves behind on the input,
+/// then the action it selects.
+fn inspect_program(options: &InspectOptions) -> VoidResultAnyError {
+    let mut program = Program::try_load(&options.program)?;
+
+    if options.input.len() < program.n_inputs_read() {
+        return Err(format!(
+            "The program reads {} inputs, {} were given.",
+            program.n_inputs_read(),
+            options.input.len()
+        )
+        .into());
+    }
+
+    if options.simplify {
+        let samples = once(options.input.clone())
+            .chain(repeat_with(|| {
+                repeat_with(standard_normal)
+                    .take(options.input.len())
+                    .collect()
+            }))
+            .take(SIMPLIFICATION_SAMPLES)
+            .collect::<Vec<_>>();
+        let simplification = program.simplify(&samples)?;
+
+        eprintln!(
+            "removed {} introns, merged {} instructions",
+            simplification.n_introns, simplification.n_merged
+        );
+        program = simplification.program;
+    }
+
+    ResetEngine::reset(&mut program.registers);
+
+    for step in program.exec_traced(&Row(&options.input)) {
+        println!("{}", step);
+    }
+
+    match program.select_action(TieBreak::First) {
+        Some(action) => println!("action: {}", action),
+        None => println!("action: overflow"),
+    }
+
+    Ok(())
+}
+
+#[derive(Args, Debug, Clone, Serialize, Deserialize)]
+pub struct ExportOptions {
+    /// A program saved by a run, e.g. its `best.json`.
+    #[arg(long)]
+    pub program: PathBuf,
+    /// Where to write the C source, printed when omitted.
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+    /// Name of the generated C function, also prefixing its helpers and constants.
+    #[arg(long, default_value = "lgp_program")]
+    pub name: String,
+    #[command(flatten)]
+    pub fixed_point: FixedPointParameters,
+}
+
+/// Number of gaussian inputs an exported program is checked against the saved program on.
+const EXPORT_SAMPLES: usize = 256;
+
+/// Compiles a saved program to fixed-point C source, reporting how far it strays from the floating
+/// point program on gaussian inputs.
+fn export_program(options: &ExportOptions) -> VoidResultAnyError {
+    let program = Program::try_load(&options.program)?;
+    let fixed = FixedPointProgram::compile(&program, options.fixed_point)?;
+
+    let samples = repeat_with(|| {
+        repeat_with(standard_normal)
+            .take(program.n_inputs_read())
+            .collect()
+    })
+    .take(EXPORT_SAMPLES)
+    .collect::<Vec<_>>();
+    let (max_error, n_disagreements) = fixed.deviation(&program, &samples);
+    eprintln!(
+        "max action register error {}, {} of {} actions differ",
+        max_error, n_disagreements, EXPORT_SAMPLES
+    );
+
+    let source = fixed.to_c(&options.name);
+    match &options.output {
+        Some(output) => std::fs::write(create_path(output.to_str().unwrap(), true)?, source)?,
+        None => print!("{}", source),
+    }
+
+    Ok(())
+}
+
+#[derive(Args, Debug, Clone, Serialize, Deserialize)]
+pub struct CompareOptions {
+    /// Directory of the baseline run (A), as written by `save_experiment`, or holding one such
+    /// directory per seed; at least two seeds are needed.
+    pub run_a: PathBuf,
+    /// Directory of the run compared against the baseline (B), laid out as `run_a`.
+    pub run_b: PathBuf,
+    /// Also write the report to `compare.md`, the aligned generations to `compare.csv` and their
+    /// overlay plot (see [`RunComparison::save_plot`]) in this directory.
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+}
+
+/// Prints a Markdown report of how run B differs from run A.
+fn compare_runs(options: &CompareOptions) -> VoidResultAnyError {
+    let comparison = RunComparison::load(&options.run_a, &options.run_b)?;
+    let report = comparison.to_markdown(
+        &options.run_a.display().to_string(),
+        &options.run_b.display().to_string(),
+    );
+
+    println!("{}", report);
+
+    if let Some(output) = &options.output {
+        let report_path = create_path(output.join("compare.md").to_str().unwrap(), true)?;
+        std::fs::write(report_path, &report)?;
+        comparison.save_plot(output)?;
+    }
+
+    Ok(())
+}
+
+#[derive(Args, Debug, Clone, Serialize, Deserialize)]
+pub struct ReportOptions {
+    /// Directory of the run, as written by `save_experiment`.
+    pub run_dir: PathBuf,
+    #[arg(long, value_enum, default_value = "markdown")]
+    pub format: ReportFormat,
+}
+
+#[derive(Args, Debug, Clone, Serialize, Deserialize)]
+pub struct DiffOptions {
+    /// Population file of the earlier snapshot.
+    pub before: String,
+    /// Population file of the later snapshot.
+    pub after: String,
+    /// Prints the instruction diff of a pair of programs, given as `<id>:<id>`; ids are looked up in
+    /// both snapshots.
+    #[arg(long, value_parser = parse_pair)]
+    pub pair: Vec<(Uuid, Uuid)>,
+}
+
+fn parse_pair(text: &str) -> Result<(Uuid, Uuid), String> {
+    let (first, second) = text
+        .split_once(':')
+        .ok_or_else(|| format!("Expected `<id>:<id>`, got `{}`.", text))?;
+    let parse = |id: &str| Uuid::parse_str(id).map_err(|error| error.to_string());
+
+    Ok((parse(first)?, parse(second)?))
+}
+
+/// Prints what survived, was cloned or bred between two snapshots, then the requested program diffs.
+fn diff_populations(options: &DiffOptions) -> VoidResultAnyError {
+    let before = load_programs(&options.before)?;
+    let after = load_programs(&options.after)?;
+
+    println!("generation {} -> {}", before.generation, after.generation);
+    print!(
+        "{}",
+        PopulationDiff::new(&before.individuals, &after.individuals)
+    );
+
+    let find = |id: Uuid| {
+        before
+            .individuals
+            .iter()
+            .chain(after.individuals.iter())
+            .find(|program| program.id == id)
+            .ok_or_else(|| format!("No program with id {}.", id))
+    };
+
+    for (first, second) in &options.pair {
+        println!("\n{} -> {}", first, second);
+        println!("{}", instruction_diff(find(*first)?, find(*second)?));
+    }
+
+    Ok(())
+}
+
+#[derive(Args, Debug, Clone, Serialize, Deserialize)]
+pub struct TournamentOptions {
+    /// Problem the runs were evolved on, named like its subcommand, e.g. `cart-pole-lgp`.
+    pub problem: String,
+    /// Directories of the runs, holding either a `checkpoint.json` or what `save_experiment` writes.
+    #[arg(required = true, num_args = 2..)]
+    pub runs: Vec<PathBuf>,
+    /// Hyperparameters setting the common fitness (e.g. one of `assets/parameters`), by default
+    /// those of the first run.
+    #[arg(long)]
+    pub params: Option<String>,
+    /// Number of common trials, by default the `n_trials` of the common hyperparameters.
+    #[arg(long)]
+    pub n_trials: Option<usize>,
+    /// Seed of the common trials, by default the seed of the common hyperparameters.
+    #[arg(long)]
+    pub seed: Option<u64>,
+    /// Also write the report to `tournament.md` and `tournament.json` in this directory, along with a
+    /// `checkpoint.json` of the merged population to evolve further with `--resume`.
+    #[arg(long)]
+    pub output: Option<PathBuf>,
+}
+
+/// Prints a Markdown report of how the final populations of the runs fare against one another.
+fn run_tournament(options: &TournamentOptions) -> VoidResultAnyError {
+    let mut actuator = Actuator::try_parse_from([env!("CARGO_PKG_NAME"), &options.problem])
+        .map_err(|_| format!("Unknown problem `{}`.", options.problem))?;
+
+    actuator
+        .with_problem(TournamentTask(options))
+        .unwrap_or_else(|| Err(format!("Unknown problem `{}`.", options.problem).into()))
+}
+
+struct TournamentTask<'a>(&'a TournamentOptions);
+
+impl ProblemTask for TournamentTask<'_> {
+    fn run<P>(self, _hyperparameters: &mut HyperParameters<P>) -> VoidResultAnyError
+    where
+        P: Problem,
+        P::Individual: AsProgram,
+    {
+        tournament_of::<P>(self.0)
+    }
+}
+
+fn tournament_of<P>(options: &TournamentOptions) -> VoidResultAnyError
+where
+    P: Problem,
+{
+    let mut runs = vec![];
+    for run_dir in &options.runs {
+        runs.push((run_dir.display().to_string(), load_run::<P>(run_dir)?));
+    }
+
+    let mut params = match &options.params {
+        Some(path) => load_hyper_parameters::<P>(path)?,
+        None => runs[0].1 .0,
+    };
+    P::build_fitness_parameters(&mut params);
+    params.n_trials = options.n_trials.unwrap_or(params.n_trials);
+    params.seed = options.seed.or(params.seed);
+
+    update_seed(params.seed);
+    let mut trials = (0..params.n_trials.max(1))
+        .map(|_| P::Generate::generate(()))
+        .collect::<Vec<P::State>>();
+
+    let runs = runs
+        .into_iter()
+        .map(|(name, (_, individuals))| (name, individuals))
+        .collect();
+    let (tournament, merged) = tournament(runs, &params, &mut trials)?;
+    let report = tournament.to_markdown();
+
+    println!("{}", report);
+
+    if let Some(output) = &options.output {
+        let report_path = create_path(output.join("tournament.md").to_str().unwrap(), true)?;
+        std::fs::write(report_path, &report)?;
+        tournament.save(output.join("tournament.json").to_str().unwrap())?;
+
+        Checkpoint::<P> {
+            generation: 0,
+            params,
+            population: merged,
+            schedules: vec![],
+        }
+        .save(output.join("checkpoint.json").to_str().unwrap())?;
+    }
+
+    Ok(())
+}
+
+/// Writes the report of a run to its directory, then prints where.
+fn report_run(options: &ReportOptions) -> VoidResultAnyError {
+    let path = RunReport::load(&options.run_dir)?.save(&options.run_dir, options.format)?;
+
+    println!("{}", path.display());
+
+    Ok(())
 }
 
 #[derive(Parser, Deserialize, Serialize)]
@@ -35,85 +571,125 @@ pub enum Actuator {
     CartPoleQ(HyperParameters<GymRsQEngine<CartPoleEnv>>),
     CartPoleLGP(HyperParameters<GymRsEngine<CartPoleEnv>>),
     IrisLgp(HyperParameters<IrisEngine>),
+    NavigationQ(HyperParameters<CustomQEngine<Navigation>>),
+    NavigationLgp(HyperParameters<CustomEngine<Navigation>>),
+    AcrobotLgp(HyperParameters<CustomEngine<Acrobot>>),
+    /// Acrobot with a reward proportional to the height of the tip.
+    AcrobotShapedLgp(HyperParameters<CustomEngine<ShapedAcrobot>>),
+    /// Symbolic regression of `x^4 + x^3 + x^2 + x`.
+    #[command(alias = <RegressionEngine<Koza1> as Problem>::NAME)]
+    Koza1Lgp(HyperParameters<RegressionEngine<Koza1>>),
+    /// Symbolic regression of `x^3 + x^2 + x`.
+    #[command(alias = <RegressionEngine<Nguyen1> as Problem>::NAME)]
+    Nguyen1Lgp(HyperParameters<RegressionEngine<Nguyen1>>),
+    /// Symbolic regression of `x^5 + x^4 + x^3 + x^2 + x`.
+    #[command(alias = <RegressionEngine<Nguyen3> as Problem>::NAME)]
+    Nguyen3Lgp(HyperParameters<RegressionEngine<Nguyen3>>),
+    /// Symbolic regression of `x^6 + x^5 + x^4 + x^3 + x^2 + x`.
+    #[command(alias = <RegressionEngine<Nguyen4> as Problem>::NAME)]
+    Nguyen4Lgp(HyperParameters<RegressionEngine<Nguyen4>>),
+    /// Symbolic regression of `sin(x^2) cos(x) - 1`.
+    #[command(alias = <RegressionEngine<Nguyen5> as Problem>::NAME)]
+    Nguyen5Lgp(HyperParameters<RegressionEngine<Nguyen5>>),
+    /// Symbolic regression of `sin(x) + sin(x + x^2)`.
+    #[command(alias = <RegressionEngine<Nguyen6> as Problem>::NAME)]
+    Nguyen6Lgp(HyperParameters<RegressionEngine<Nguyen6>>),
+    /// Symbolic regression of `ln(x + 1) + ln(x^2 + 1)`.
+    #[command(alias = <RegressionEngine<Nguyen7> as Problem>::NAME)]
+    Nguyen7Lgp(HyperParameters<RegressionEngine<Nguyen7>>),
+    /// Symbolic regression of `sqrt(x)`.
+    #[command(alias = <RegressionEngine<Nguyen8> as Problem>::NAME)]
+    Nguyen8Lgp(HyperParameters<RegressionEngine<Nguyen8>>),
+    /// The 4x4 FrozenLake grid world, observed as a one-hot encoding of the tile.
+    FrozenLakeLgp(HyperParameters<CustomEngine<OneHotFrozenLake>>),
+    FrozenLakeQ(HyperParameters<CustomQEngine<OneHotFrozenLake>>),
+    /// Evaluates a saved Iris classifier and the importance of each of its features.
+    EvaluateIris(EvaluateOptions),
+    /// Compares the metrics of two runs, testing whether their difference is significant.
+    Compare(CompareOptions),
+    /// Traces a saved program over a single input, instruction by instruction.
+    Inspect(InspectOptions),
+    /// Exports a saved program as fixed-point C source, e.g. for microcontrollers.
+    Export(ExportOptions),
+    /// Assembles the configuration, metrics, figures and best program of a run into a single report.
+    Report(ReportOptions),
+    /// Reports how a saved population became another, e.g. between consecutive generations.
+    Diff(DiffOptions),
+    /// Re-evaluates the final populations of several runs on common trials, reporting which run's
+    /// individuals dominate, and merges them.
+    Tournament(TournamentOptions),
 }
 
 impl Actuator {
-    pub fn run(&mut self) {
-        // Use the run engine macro for each branch of the enum
+    pub fn run(&mut self) -> VoidResultAnyError {
+        self.run_with(&RunOptions::default())
+    }
+
+    pub fn run_with(&mut self, options: &RunOptions) -> VoidResultAnyError {
+        if let Some(result) = self.with_problem(RunTask(options)) {
+            return result;
+        }
+
         match self {
-            Actuator::MountainCarQ(hyperparameters) => {
-                ResetEngine::reset(&mut hyperparameters.program_parameters.consts);
-
-                hyperparameters
-                    .program_parameters
-                    .program_parameters
-                    .instruction_generator_parameters
-                    .n_actions = 3;
-                hyperparameters
-                    .program_parameters
-                    .program_parameters
-                    .instruction_generator_parameters
-                    .n_inputs = 2;
-                hyperparameters.default_fitness = -200.0;
-
-                run_actuator!(GymRsQEngine, hyperparameters);
-            }
-            Actuator::MountainCarLGP(hyperparameters) => {
-                hyperparameters
-                    .program_parameters
-                    .instruction_generator_parameters
-                    .n_actions = 3;
-                hyperparameters
-                    .program_parameters
-                    .instruction_generator_parameters
-                    .n_inputs = 2;
-                hyperparameters.default_fitness = -200.0;
-
-                run_actuator!(GymRsEngine, hyperparameters);
-            }
-            Actuator::IrisLgp(hyperparameters) => {
-                hyperparameters
-                    .program_parameters
-                    .instruction_generator_parameters
-                    .n_actions = 3;
-                hyperparameters
-                    .program_parameters
-                    .instruction_generator_parameters
-                    .n_inputs = 4;
-
-                run_actuator!(IrisEngine, hyperparameters);
-            }
-            Actuator::CartPoleQ(hyperparameters) => {
-                ResetEngine::reset(&mut hyperparameters.program_parameters.consts);
-                hyperparameters
-                    .program_parameters
-                    .program_parameters
-                    .instruction_generator_parameters
-                    .n_actions = 2;
-                hyperparameters
-                    .program_parameters
-                    .program_parameters
-                    .instruction_generator_parameters
-                    .n_inputs = 4;
-                hyperparameters.default_fitness = 500.0;
-
-                run_actuator!(GymRsQEngine, hyperparameters);
-            }
-            Actuator::CartPoleLGP(hyperparameters) => {
-                hyperparameters
-                    .program_parameters
-                    .instruction_generator_parameters
-                    .n_actions = 2;
-                hyperparameters
-                    .program_parameters
-                    .instruction_generator_parameters
-                    .n_inputs = 4;
-                hyperparameters.default_fitness = 500.0;
-
-                run_actuator!(GymRsEngine, hyperparameters);
-            }
+            Actuator::EvaluateIris(evaluate_options) => evaluate_iris(evaluate_options),
+            Actuator::Compare(compare_options) => compare_runs(compare_options),
+            Actuator::Inspect(inspect_options) => inspect_program(inspect_options),
+            Actuator::Export(export_options) => export_program(export_options),
+            Actuator::Report(report_options) => report_run(report_options),
+            Actuator::Diff(diff_options) => diff_populations(diff_options),
+            Actuator::Tournament(tournament_options) => run_tournament(tournament_options),
+            _ => unreachable!("problems are ran by `with_problem`"),
         }
     }
+
+    /// Runs `task` over the hyperparameters of the problem this actuator evolves, `None` for the
+    /// actuators which are not problems.
+    fn with_problem(&mut self, task: impl ProblemTask) -> Option<VoidResultAnyError> {
+        let result = match self {
+            Actuator::MountainCarQ(hyperparameters) => task.run(hyperparameters),
+            Actuator::MountainCarLGP(hyperparameters) => task.run(hyperparameters),
+            Actuator::CartPoleQ(hyperparameters) => task.run(hyperparameters),
+            Actuator::CartPoleLGP(hyperparameters) => task.run(hyperparameters),
+            Actuator::IrisLgp(hyperparameters) => task.run(hyperparameters),
+            Actuator::NavigationQ(hyperparameters) => task.run(hyperparameters),
+            Actuator::NavigationLgp(hyperparameters) => task.run(hyperparameters),
+            Actuator::AcrobotLgp(hyperparameters) => task.run(hyperparameters),
+            Actuator::AcrobotShapedLgp(hyperparameters) => task.run(hyperparameters),
+            Actuator::Koza1Lgp(hyperparameters) => task.run(hyperparameters),
+            Actuator::Nguyen1Lgp(hyperparameters) => task.run(hyperparameters),
+            Actuator::Nguyen3Lgp(hyperparameters) => task.run(hyperparameters),
+            Actuator::Nguyen4Lgp(hyperparameters) => task.run(hyperparameters),
+            Actuator::Nguyen5Lgp(hyperparameters) => task.run(hyperparameters),
+            Actuator::Nguyen6Lgp(hyperparameters) => task.run(hyperparameters),
+            Actuator::Nguyen7Lgp(hyperparameters) => task.run(hyperparameters),
+            Actuator::Nguyen8Lgp(hyperparameters) => task.run(hyperparameters),
+            Actuator::FrozenLakeLgp(hyperparameters) => task.run(hyperparameters),
+            Actuator::FrozenLakeQ(hyperparameters) => task.run(hyperparameters),
+            _ => return None,
+        };
+
+        Some(result)
+    }
+}
+
+/// What an [`Actuator`] does with the problem it evolves, whatever the problem.
+trait ProblemTask {
+    fn run<P>(self, hyperparameters: &mut HyperParameters<P>) -> VoidResultAnyError
+    where
+        P: Problem,
+        P::Individual: AsProgram;
+}
+
+struct RunTask<'a>(&'a RunOptions);
+
+impl ProblemTask for RunTask<'_> {
+    fn run<P>(self, hyperparameters: &mut HyperParameters<P>) -> VoidResultAnyError
+    where
+        P: Problem,
+        P::Individual: AsProgram,
+    {
+        run_problem(hyperparameters, self.0)
+    }
 }
 
 pub fn load_hyper_parameters<C>(
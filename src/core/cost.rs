@@ -0,0 +1,187 @@
+//! Execution cost of programs, e.g. the cycles or energy a controller takes on an embedded target. The
+//! cost can be taken off the fitness (see [`CoreIter::with_cost_penalty`]) to evolve cheap programs.
+//!
+//! [`CoreIter::with_cost_penalty`]: super::engines::core_engine::CoreIter::with_cost_penalty
+use clap::{Args, ValueEnum};
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+
+use crate::utils::telemetry::trial_program_executions;
+
+use super::{
+    instruction::{Instruction, Op, Operand},
+    program::Program,
+};
+
+/// What the cost of a program is measured over.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ValueEnum)]
+pub enum CostMeasure {
+    /// A single execution: the sum of the costs of the effective instructions.
+    #[default]
+    Execution,
+    /// Every execution of a trial, e.g. one per step of an RL episode, so policies ending episodes early
+    /// cost less.
+    Trial,
+}
+
+fn default_operator_cost() -> f64 {
+    1.
+}
+
+fn default_mult_cost() -> f64 {
+    4.
+}
+
+/// Cost of every operator, along with the weight of the cost in the fitness.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Args, Builder)]
+pub struct CostParameters {
+    #[builder(default = "1.")]
+    #[arg(long, default_value = "1")]
+    #[serde(default = "default_operator_cost")]
+    pub add_cost: f64,
+    #[builder(default = "1.")]
+    #[arg(long, default_value = "1")]
+    #[serde(default = "default_operator_cost")]
+    pub sub_cost: f64,
+    #[builder(default = "4.")]
+    #[arg(long, default_value = "4")]
+    #[serde(default = "default_mult_cost")]
+    pub mult_cost: f64,
+    /// Halvings are shifts on integer targets.
+    #[builder(default = "1.")]
+    #[arg(long, default_value = "1")]
+    #[serde(default = "default_operator_cost")]
+    pub divide_cost: f64,
+    /// Added to instructions reading an input rather than a register or a constant, e.g. for reading a
+    /// sensor.
+    #[builder(default = "0.")]
+    #[arg(long, default_value = "0")]
+    #[serde(default)]
+    pub input_cost: f64,
+    /// Weight of the cost taken off the fitness (added to it when minimizing), `0` leaves the fitness
+    /// as is.
+    #[builder(default = "0.")]
+    #[arg(long, default_value = "0")]
+    #[serde(default)]
+    pub cost_weight: f64,
+    #[builder(default = "CostMeasure::Execution")]
+    #[arg(long, value_enum, default_value_t = CostMeasure::Execution)]
+    #[serde(default)]
+    pub cost_measure: CostMeasure,
+}
+
+impl Default for CostParameters {
+    fn default() -> Self {
+        CostParametersBuilder::default().build().unwrap()
+    }
+}
+
+impl CostParameters {
+    pub fn operator_cost(&self, op: Op) -> f64 {
+        match op {
+            // Loads, only written by hand, cost as much as additions.
+            Op::Add | Op::Load => self.add_cost,
+            Op::Sub => self.sub_cost,
+            Op::Mult => self.mult_cost,
+            Op::Divide => self.divide_cost,
+        }
+    }
+
+    /// Halvings never read their operand, so never pay for reading an input.
+    pub fn instruction_cost(&self, instruction: &Instruction) -> f64 {
+        match (instruction.op(), instruction.src2()) {
+            (Op::Divide, _) => self.divide_cost,
+            (op, Operand::Input(_)) => self.operator_cost(op) + self.input_cost,
+            (op, _) => self.operator_cost(op),
+        }
+    }
+
+    /// The cost of `program` according to the cost measure, [`CostMeasure::Trial`] reading the number of
+    /// executions of the trial last evaluated on this thread (see [`trial_program_executions`]).
+    pub fn measure(&self, program: &Program) -> f64 {
+        match self.cost_measure {
+            CostMeasure::Execution => program.cost(self),
+            CostMeasure::Trial => program.cost(self) * trial_program_executions() as f64,
+        }
+    }
+}
+
+impl Program {
+    /// The cost of a single execution, summed over the effective instructions as the others can be
+    /// removed before deployment.
+    pub fn cost(&self, parameters: &CostParameters) -> f64 {
+        self.effective_instructions()
+            .iter()
+            .map(|instruction| parameters.instruction_cost(instruction))
+            .sum()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::engines::core_engine::{Core, HyperParametersBuilder};
+    use crate::core::engines::fitness_engine::{FitnessMode, Objective};
+    use crate::core::engines::generate_engine::{Generate, GenerateEngine};
+    use crate::core::engines::status_engine::{Status, StatusEngine};
+    use crate::problems::custom::{CustomEngine, Navigation, Simulation, SimulationInput};
+    use crate::problems::problem::program_parameters;
+    use crate::utils::misc::VoidResultAnyError;
+    use crate::utils::telemetry::take_counters;
+
+    #[test]
+    fn given_cost_table_when_program_is_measured_then_effective_instructions_are_summed(
+    ) -> VoidResultAnyError {
+        let parameters = program_parameters(Navigation::N_INPUTS, Navigation::N_ACTIONS);
+        // The last instruction writes a register nothing reads.
+        let program = Program::parse(
+            "r0 = r0 + 1 * in0; r1 = r1 * r0; r1 = r1 / r1; r2 = r2 - 1 * in1; r3 = r3 * 2",
+            parameters,
+        )?;
+        let cost = CostParametersBuilder::default().input_cost(0.5).build()?;
+
+        assert_eq!(program.cost(&cost), 1.5 + 4. + 1. + 1.5);
+
+        let mut trial: SimulationInput<Navigation> = GenerateEngine::generate(());
+        take_counters();
+        let per_trial = CostParametersBuilder::default()
+            .cost_weight(2.)
+            .cost_measure(CostMeasure::Trial)
+            .build()?;
+        let mut penalized = program.clone();
+
+        CustomEngine::<Navigation>::eval_individual_penalized(
+            &mut penalized,
+            std::slice::from_mut(&mut trial),
+            0.,
+            FitnessMode::CumulativeReward,
+            1.,
+            Objective::Maximize,
+            &[],
+        );
+        let unpenalized = StatusEngine::get_fitness(&penalized);
+        let (_, executions) = take_counters();
+
+        let engine = HyperParametersBuilder::<CustomEngine<Navigation>>::default()
+            .program_parameters(parameters)
+            .cost(per_trial)
+            .build()?
+            .build_engine()
+            .with_cost_penalty();
+        CustomEngine::<Navigation>::eval_individual_penalized(
+            &mut penalized,
+            std::slice::from_mut(&mut trial),
+            0.,
+            FitnessMode::CumulativeReward,
+            1.,
+            Objective::Maximize,
+            engine.penalties(),
+        );
+
+        assert!(executions > 0);
+        let penalty = 2. * program.cost(&per_trial) * executions as f64;
+        assert_eq!(StatusEngine::get_fitness(&penalized), unpenalized - penalty);
+
+        Ok(())
+    }
+}
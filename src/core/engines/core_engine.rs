@@ -1,21 +1,47 @@
-use std::{iter::repeat_with, sync::Arc};
+use std::{
+    collections::{hash_map::DefaultHasher, BTreeMap, HashMap},
+    error::Error,
+    hash::{Hash, Hasher},
+    iter::{repeat, repeat_with},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    time::{Duration, Instant},
+};
 
-use clap::{Args, Parser};
+use clap::{Args, Parser, ValueEnum};
 use derivative::Derivative;
 use itertools::Itertools;
 use rand::{seq::IteratorRandom, Rng};
 
 use crate::{
     core::{
+        cost::CostParameters,
         engines::{breed_engine::Breed, reset_engine::Reset},
-        environment::State,
+        environment::{with_noise, FreshState, GenerationAware, NoiseParameters, State},
+        program::AsProgram,
+    },
+    utils::{
+        misc::parse_duration,
+        random::{generator, in_stream, update_seed, with_seed},
+        telemetry::{
+            environment_steps, program_executions, record_penalties,
+            record_trial_program_executions, take_counters, take_episode_counters, take_penalties,
+            GenerationMetrics, RunSummary,
+        },
     },
-    utils::random::{generator, update_seed},
 };
 
 use super::{
-    fitness_engine::Fitness, freeze_engine::Freeze, generate_engine::Generate,
-    mutate_engine::Mutate, status_engine::Status,
+    fitness_engine::{
+        EvaluationStrategy, Evaluator, Fitness, FitnessMetadata, FitnessMode, Objective, Penalty,
+        PopulationEvaluator, Ranking, SequentialEvaluation, TrialPool,
+    },
+    freeze_engine::Freeze,
+    generate_engine::Generate,
+    mutate_engine::Mutate,
+    status_engine::Status,
 };
 use derive_builder::Builder;
 use serde::{de::DeserializeOwned, Deserialize, Serialize};
@@ -53,10 +79,274 @@ where
     #[builder(default = "None")]
     #[arg(long)]
     pub seed: Option<u64>,
+    #[builder(default = "EvaluationStrategy::Sequential")]
+    #[arg(long, value_enum, default_value_t = EvaluationStrategy::Sequential)]
+    #[serde(default)]
+    pub evaluation_strategy: EvaluationStrategy,
+    #[builder(default = "Evaluator::Sequential")]
+    #[arg(long, value_enum, default_value_t = Evaluator::Sequential)]
+    #[serde(default)]
+    pub evaluator: Evaluator,
+    #[builder(default = "FitnessMode::CumulativeReward")]
+    #[arg(long, value_enum, default_value_t = FitnessMode::CumulativeReward)]
+    #[serde(default)]
+    pub fitness_mode: FitnessMode,
+    /// Number of standard deviations of the trial scores taken off the mean by
+    /// [`FitnessMode::RiskAdjusted`].
+    #[builder(default = "1.")]
+    #[arg(long, default_value = "1.")]
+    #[serde(default = "default_risk_aversion")]
+    pub risk_aversion: f64,
+    #[builder(default = "Objective::Maximize")]
+    #[arg(long, value_enum, default_value_t = Objective::Maximize)]
+    #[serde(default)]
+    pub objective: Objective,
+    #[builder(default = "Ranking::Fitness")]
+    #[arg(long, value_enum, default_value_t = Ranking::Fitness)]
+    #[serde(default)]
+    pub ranking: Ranking,
+    /// Probability that two infeasible neighbours are compared by fitness rather than by violation
+    /// under stochastic ranking.
+    #[builder(default = "0.45")]
+    #[arg(long, default_value = "0.45")]
+    #[serde(default = "default_fitness_comparison_probability")]
+    pub fitness_comparison_probability: f64,
+    /// Number of trials offspring are screened on before being fully evaluated (0 disables screening).
+    #[builder(default = "0")]
+    #[arg(long, default_value = "0")]
+    #[serde(default)]
+    pub surrogate_trials: usize,
+    /// Fraction of screened offspring which are discarded without a full evaluation.
+    #[builder(default = "0.5")]
+    #[arg(long, default_value = "0.5")]
+    #[serde(default = "default_surrogate_discard")]
+    pub surrogate_discard: f64,
+    /// Maximum number of environment steps spent evaluating a single generation.
+    #[builder(default = "None")]
+    #[arg(long)]
+    pub step_budget: Option<usize>,
+    /// Number of hill-climbing mutations tried on every offspring after variation (0 disables it).
+    #[builder(default = "0")]
+    #[arg(long, default_value = "0")]
+    #[serde(default)]
+    pub local_search_steps: usize,
+    /// Number of trials the local search evaluates candidates on.
+    #[builder(default = "1")]
+    #[arg(long, default_value = "1")]
+    #[serde(default = "default_local_search_trials")]
+    pub local_search_trials: usize,
+    /// Wall-clock time after which the run stops at the end of the current generation, in seconds on
+    /// the command line.
+    #[builder(default = "None")]
+    #[arg(long, value_parser = parse_duration)]
+    #[serde(default)]
+    pub max_duration: Option<Duration>,
+    /// Number of held-out states the best individual of every generation is validated on, revealing
+    /// overfitting to the training trials (0 disables validation).
+    #[builder(default = "0")]
+    #[arg(long, default_value = "0")]
+    #[serde(default)]
+    pub validation_trials: usize,
+    #[command(flatten)]
+    #[builder(default)]
+    #[serde(default)]
+    pub stagnation_policy: StagnationPolicy,
+    #[command(flatten)]
+    #[builder(default)]
+    #[serde(default)]
+    pub noise: NoiseParameters,
+    #[command(flatten)]
+    #[builder(default)]
+    #[serde(default)]
+    pub cost: CostParameters,
+    /// Individuals which survived more than this many generations are retired whatever their fitness,
+    /// so no individual takes over the population; unset keeps survivors indefinitely.
+    #[builder(default = "None")]
+    #[arg(long)]
+    #[serde(default)]
+    pub max_age: Option<usize>,
     #[command(flatten)]
     pub program_parameters: C::ProgramParameters,
 }
 
+/// What the iterator does once the best fitness stops improving.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ValueEnum)]
+pub enum StagnationResponse {
+    /// Keep evolving as usual.
+    #[default]
+    Ignore,
+    /// Raise the mutation rate to `hypermutation_percent` for `hypermutation_generations` generations.
+    Hypermutation,
+    /// Keep the `restart_elites` best individuals and regenerate the rest of the population.
+    Restart,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Args, Builder)]
+pub struct StagnationPolicy {
+    #[builder(default = "StagnationResponse::Ignore")]
+    #[arg(long, value_enum, default_value_t = StagnationResponse::Ignore)]
+    pub stagnation_response: StagnationResponse,
+    /// Number of generations without improvement of the best fitness before responding.
+    #[builder(default = "10")]
+    #[arg(long, default_value = "10")]
+    pub stagnation_generations: usize,
+    #[builder(default = "0.9")]
+    #[arg(long, default_value = "0.9")]
+    pub hypermutation_percent: f64,
+    #[builder(default = "5")]
+    #[arg(long, default_value = "5")]
+    pub hypermutation_generations: usize,
+    #[builder(default = "1")]
+    #[arg(long, default_value = "1")]
+    pub restart_elites: usize,
+}
+
+impl Default for StagnationPolicy {
+    fn default() -> Self {
+        StagnationPolicyBuilder::default().build().unwrap()
+    }
+}
+
+/// A value changing with the generation, e.g. to anneal the mutation rate of a run.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum Schedule {
+    /// Interpolates from `start` to `end` over `n_generations`, then stays at `end`.
+    Linear {
+        start: f64,
+        end: f64,
+        n_generations: usize,
+    },
+    /// `start * rate^generation`.
+    Exponential { start: f64, rate: f64 },
+    /// `start`, multiplied by `factor` every `step_size` generations.
+    Step {
+        start: f64,
+        factor: f64,
+        step_size: usize,
+    },
+}
+
+impl Schedule {
+    pub fn value(&self, generation: usize) -> f64 {
+        match *self {
+            Schedule::Linear {
+                start,
+                end,
+                n_generations,
+            } => {
+                let progress = (generation as f64 / n_generations.max(1) as f64).min(1.);
+                start + (end - start) * progress
+            }
+            Schedule::Exponential { start, rate } => start * rate.powi(generation as i32),
+            Schedule::Step {
+                start,
+                factor,
+                step_size,
+            } => start * factor.powi((generation / step_size.max(1)) as i32),
+        }
+    }
+}
+
+/// Selects the hyperparameter a schedule drives, e.g. `|params| &mut params.mutation_percent`.
+pub type ScheduleTarget<C> = fn(&mut HyperParameters<C>) -> &mut f64;
+
+/// A stage of a [`Curriculum`]: the trials individuals are evaluated on for `n_generations`
+/// generations, along with the parameters the task changes.
+pub struct Task<C>
+where
+    C: Core,
+{
+    /// Key of the task in telemetry.
+    pub name: String,
+    pub n_generations: usize,
+    pub trials: Vec<C::State>,
+    /// Applied to the parameters of the run whenever the task starts, e.g. to set its
+    /// `default_fitness`. A parameter set by one task is not undone by the next, so tasks setting a
+    /// parameter should all set it.
+    pub configure: fn(&mut HyperParameters<C>),
+}
+
+impl<C> Task<C>
+where
+    C: Core,
+{
+    pub fn new(name: &str, n_generations: usize, trials: Vec<C::State>) -> Self {
+        Task {
+            name: name.to_string(),
+            n_generations,
+            trials,
+            configure: |_| {},
+        }
+    }
+
+    pub fn with_parameters(mut self, configure: fn(&mut HyperParameters<C>)) -> Self {
+        self.configure = configure;
+        self
+    }
+}
+
+/// A sequence of tasks evolved on one after the other (e.g. short episodes, then long ones), see
+/// [`CoreIter::with_curriculum`].
+pub struct Curriculum<C>
+where
+    C: Core,
+{
+    pub tasks: Vec<Task<C>>,
+    /// Starts over from the first task once the last one is done, instead of staying on the last one.
+    pub cycle: bool,
+}
+
+impl<C> Curriculum<C>
+where
+    C: Core,
+{
+    pub fn new(tasks: Vec<Task<C>>, cycle: bool) -> Self {
+        assert!(!tasks.is_empty());
+
+        Curriculum { tasks, cycle }
+    }
+
+    /// Index of the task evolved on in `generation`; every task lasts at least one generation.
+    pub fn task_at(&self, generation: usize) -> usize {
+        let lengths = self
+            .tasks
+            .iter()
+            .map(|task| task.n_generations.max(1))
+            .collect_vec();
+        let total = lengths.iter().sum::<usize>();
+
+        let mut generation = match self.cycle {
+            true => generation % total,
+            false => generation.min(total - 1),
+        };
+
+        for (idx, length) in lengths.into_iter().enumerate() {
+            if generation < length {
+                return idx;
+            }
+            generation -= length;
+        }
+
+        unreachable!()
+    }
+}
+
+fn default_surrogate_discard() -> f64 {
+    0.5
+}
+
+fn default_local_search_trials() -> usize {
+    1
+}
+
+fn default_risk_aversion() -> f64 {
+    1.
+}
+
+fn default_fitness_comparison_probability() -> f64 {
+    0.45
+}
+
 pub struct CoreIter<C>
 where
     C: Core,
@@ -65,6 +355,50 @@ where
     next_population: Vec<C::Individual>,
     params: HyperParameters<C>,
     trials: Vec<C::State>,
+    /// Copies of `trials` reused by parallel evaluation from one generation to the next.
+    trial_pool: TrialPool<C::State>,
+    validation: Vec<C::State>,
+    validation_history: Vec<f64>,
+    summary: RunSummary,
+    deferred: Vec<C::Individual>,
+    best_fitness: Option<f64>,
+    stagnant_generations: usize,
+    hypermutation_generations: usize,
+    started: Option<Instant>,
+    timed_out: bool,
+    stop: Option<Arc<AtomicBool>>,
+    schedules: Vec<(ScheduleTarget<C>, Box<dyn Fn(usize) -> f64 + Send>)>,
+    /// The schedule behind every entry of `schedules`, recorded in checkpoints.
+    schedule_specs: Vec<Option<Schedule>>,
+    penalties: Vec<Penalty<C::Individual, C::State>>,
+    curriculum: Option<Curriculum<C>>,
+    /// The task of the curriculum the current trials belong to.
+    task: Option<usize>,
+    /// Evaluations of [`EvaluationStrategy::Keyed`] by genome, valid for the trials hashing to
+    /// `cache_regime`.
+    evaluation_cache: HashMap<u64, (f64, Option<FitnessMetadata>)>,
+    cache_regime: Option<u64>,
+}
+
+/// The state needed to resume an interrupted run: the population about to be evaluated and the
+/// parameters of the run.
+///
+/// Trials and the random number generator are not part of the checkpoint, so a resumed run does not
+/// replay the generations an uninterrupted run would have produced.
+#[derive(Serialize, Deserialize, Derivative)]
+#[derivative(Clone)]
+#[serde(bound = "")]
+pub struct Checkpoint<C>
+where
+    C: Core,
+{
+    pub generation: usize,
+    pub params: HyperParameters<C>,
+    pub population: Vec<C::Individual>,
+    /// The schedules of the run in the order they were added, `None` for those given as functions
+    /// (see [`CoreIter::resume_with_schedules`]).
+    #[serde(default)]
+    pub schedules: Vec<Option<Schedule>>,
 }
 
 impl<C> CoreIter<C>
@@ -72,18 +406,368 @@ where
     C: Core,
 {
     pub fn new(hp: HyperParameters<C>) -> Self {
-        let current_population = C::init_population(hp.program_parameters, hp.population_size);
+        Self::with_seeds(hp, vec![])
+    }
+
+    /// Warm-starts the initial population with the provided individuals (e.g. parsed policies),
+    /// generating the remainder randomly.
+    pub fn with_seeds(hp: HyperParameters<C>, seeds: Vec<C::Individual>) -> Self {
+        let mut current_population = seeds;
+        current_population.truncate(hp.population_size);
+
         let trials: Vec<C::State> = repeat_with(|| C::Generate::generate(()))
             .take(hp.n_trials)
             .collect_vec();
 
+        Self::with_trials(hp, current_population, trials)
+    }
+
+    /// Evaluates every generation on `trials` instead of `n_trials` generated states.
+    pub fn with_trials(
+        hp: HyperParameters<C>,
+        seeds: Vec<C::Individual>,
+        trials: Vec<C::State>,
+    ) -> Self {
+        let mut current_population = seeds;
+        current_population.truncate(hp.population_size);
+
+        let n_generated = hp.population_size - current_population.len();
+        current_population.extend(in_stream("generation", || {
+            C::init_population(hp.program_parameters, n_generated)
+        }));
+
+        let validation = repeat_with(|| C::Generate::generate(()))
+            .take(hp.validation_trials)
+            .collect_vec();
+
         Self {
             generation: 0,
             next_population: current_population,
             params: hp,
             trials,
+            trial_pool: TrialPool::default(),
+            validation,
+            validation_history: vec![],
+            summary: RunSummary::default(),
+            deferred: vec![],
+            best_fitness: None,
+            stagnant_generations: 0,
+            hypermutation_generations: 0,
+            started: None,
+            timed_out: false,
+            stop: None,
+            schedules: vec![],
+            schedule_specs: vec![],
+            penalties: vec![],
+            curriculum: None,
+            task: None,
+            evaluation_cache: HashMap::new(),
+            cache_regime: None,
+        }
+    }
+
+    /// Continues a run from `checkpoint`, with freshly generated trials.
+    pub fn resume(checkpoint: Checkpoint<C>) -> Self {
+        let mut iter = Self::with_seeds(checkpoint.params, checkpoint.population);
+        iter.generation = checkpoint.generation;
+
+        iter
+    }
+
+    /// Same as [`CoreIter::resume`], driving the parameters selected by `targets` with the schedules of
+    /// the checkpoint again, from the generation the run stopped at.
+    ///
+    /// Fails unless there is one target per schedule of the checkpoint, since schedules given as
+    /// functions cannot be saved.
+    pub fn resume_with_schedules(
+        checkpoint: Checkpoint<C>,
+        targets: &[ScheduleTarget<C>],
+    ) -> Result<Self, Box<dyn Error>> {
+        if targets.len() != checkpoint.schedules.len() {
+            return Err(format!(
+                "The checkpoint has {} schedules, {} targets were given.",
+                checkpoint.schedules.len(),
+                targets.len()
+            )
+            .into());
+        }
+
+        let schedules = checkpoint
+            .schedules
+            .iter()
+            .map(|schedule| schedule.ok_or("A schedule given as a function cannot be resumed."))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let mut iter = Self::resume(checkpoint);
+        for (target, schedule) in targets.iter().zip(schedules) {
+            iter = iter.with_schedule(*target, schedule);
+        }
+
+        Ok(iter)
+    }
+
+    /// Stops the iterator before the next generation once `stop` is set (e.g. by
+    /// [`install_interrupt_handler`](crate::utils::interrupt::install_interrupt_handler)).
+    pub fn stop_when(mut self, stop: Arc<AtomicBool>) -> Self {
+        self.stop = Some(stop);
+        self
+    }
+
+    /// Validates the best individual of every generation on `validation` (e.g. a held-out split of a
+    /// dataset) instead of `validation_trials` generated states.
+    pub fn with_validation(mut self, validation: Vec<C::State>) -> Self {
+        self.validation = validation;
+        self
+    }
+
+    /// Sets the hyperparameter selected by `target` to `schedule.value(generation)` before every
+    /// generation is evaluated.
+    ///
+    /// Scheduled variation rates are clamped so that mutation and crossover never claim more than
+    /// the offspring to produce, mutation taking precedence.
+    pub fn with_schedule(self, target: ScheduleTarget<C>, schedule: Schedule) -> Self {
+        let mut iter = self.with_schedule_fn(target, move |generation| schedule.value(generation));
+        *iter.schedule_specs.last_mut().unwrap() = Some(schedule);
+
+        iter
+    }
+
+    /// Same as [`CoreIter::with_schedule`] with an arbitrary function of the generation.
+    pub fn with_schedule_fn(
+        mut self,
+        target: ScheduleTarget<C>,
+        schedule: impl Fn(usize) -> f64 + Send + 'static,
+    ) -> Self {
+        self.schedules.push((target, Box::new(schedule)));
+        self.schedule_specs.push(None);
+        self
+    }
+
+    /// Evolves on the tasks of `curriculum` in turn, in place of the trials of the run. Metrics are
+    /// tagged with the task they were measured on, and the stagnation count starts over with every
+    /// task.
+    pub fn with_curriculum(mut self, curriculum: Curriculum<C>) -> Self {
+        self.curriculum = Some(curriculum);
+        self
+    }
+
+    /// Takes `weight` times `measure` of every trial off the fitness of individuals (adds it when
+    /// minimizing). Each penalty is reported separately, averaged over the trials the generation was
+    /// evaluated on.
+    pub fn with_penalty(
+        mut self,
+        name: &str,
+        weight: f64,
+        measure: impl Fn(&C::Individual, &C::State) -> f64 + Send + Sync + 'static,
+    ) -> Self {
+        self.penalties.push(Penalty {
+            name: name.to_string(),
+            weight,
+            measure: Box::new(measure),
+        });
+        self
+    }
+
+    /// Takes the execution cost of individuals off their fitness (see [`CostParameters::measure`]),
+    /// unless its weight is `0`.
+    pub fn with_cost_penalty(self) -> Self
+    where
+        C::Individual: AsProgram,
+    {
+        let cost = self.params.cost;
+
+        match cost.cost_weight == 0. {
+            true => self,
+            false => self.with_penalty("cost", cost.cost_weight, move |individual, _| {
+                cost.measure(individual.as_program())
+            }),
         }
     }
+
+    /// The penalties taken off the fitness of individuals, in the order they were added.
+    pub fn penalties(&self) -> &[Penalty<C::Individual, C::State>] {
+        &self.penalties
+    }
+
+    pub fn checkpoint(&self) -> Checkpoint<C> {
+        let mut population = self.next_population.clone();
+        population.extend(self.deferred.iter().cloned());
+
+        Checkpoint {
+            generation: self.generation,
+            params: self.params.clone(),
+            population,
+            schedules: self.schedule_specs.clone(),
+        }
+    }
+
+    /// The generation the next population will be evaluated in.
+    pub fn generation(&self) -> usize {
+        self.generation
+    }
+
+    /// The states every individual is evaluated on, e.g. to append the samples which arrived since the
+    /// last generation. Evaluations cached by [`EvaluationStrategy::Keyed`] are dropped, as the trials
+    /// may no longer be those they were made on.
+    pub fn trials_mut(&mut self) -> &mut [C::State] {
+        self.cache_regime = None;
+        &mut self.trials
+    }
+
+    pub fn stopped(&self) -> bool {
+        self.stop
+            .as_ref()
+            .map_or(false, |stop| stop.load(Ordering::SeqCst))
+    }
+
+    /// Tracks the best fitness of a ranked population, returning whether it has not improved for
+    /// `stagnation_generations` generations (in which case the count starts over).
+    fn stagnated(&mut self, population: &[C::Individual]) -> bool {
+        let best = match population.first() {
+            Some(best) => C::Status::get_fitness(best),
+            None => return false,
+        };
+
+        match self.best_fitness {
+            Some(previous) if !self.params.objective.is_better(best, previous) => {
+                self.stagnant_generations += 1
+            }
+            _ => {
+                self.best_fitness = Some(best);
+                self.stagnant_generations = 0;
+            }
+        }
+
+        let policy = self.params.stagnation_policy;
+        if policy.stagnation_response == StagnationResponse::Ignore
+            || self.stagnant_generations < policy.stagnation_generations.max(1)
+        {
+            return false;
+        }
+
+        self.stagnant_generations = 0;
+        true
+    }
+
+    pub fn summary(&self) -> &RunSummary {
+        &self.summary
+    }
+
+    /// The validation fitness of the best individual of every generation so far, empty without
+    /// validation states.
+    pub fn validation_history(&self) -> &[f64] {
+        &self.validation_history
+    }
+
+    /// Gives individuals cached on the current trials their evaluation back and marks every other
+    /// individual as unevaluated; the cache is dropped when the trials changed, be it their
+    /// per-generation state or the trials themselves (see [`CoreIter::trials_mut`]).
+    fn apply_evaluation_cache(&mut self, population: &mut [C::Individual]) {
+        let mut hasher = DefaultHasher::new();
+        for trial in self.trials.iter() {
+            trial.regime_hash().hash(&mut hasher);
+        }
+        let regime = hasher.finish();
+
+        if self.cache_regime != Some(regime) {
+            self.evaluation_cache.clear();
+            self.cache_regime = Some(regime);
+        }
+
+        for individual in population.iter_mut() {
+            let cached = C::Status::genome_hash(individual)
+                .and_then(|genome| self.evaluation_cache.get(&genome))
+                .copied();
+
+            match cached {
+                Some((fitness, metadata)) => {
+                    C::Status::set_fitness(individual, fitness);
+                    if let Some(metadata) = metadata {
+                        C::Status::set_metadata(individual, metadata);
+                    }
+                }
+                None => C::Status::set_fitness(individual, f64::NAN),
+            }
+        }
+    }
+
+    /// Caches the individuals evaluated on every trial (not those screened or cut short by a budget).
+    fn fill_evaluation_cache(&mut self, population: &[C::Individual]) {
+        for individual in population {
+            let metadata = C::Status::get_metadata(individual);
+            let complete =
+                metadata.map_or(false, |metadata| metadata.n_trials == self.trials.len());
+
+            if let (Some(genome), true) = (C::Status::genome_hash(individual), complete) {
+                self.evaluation_cache
+                    .insert(genome, (C::Status::get_fitness(individual), metadata));
+            }
+        }
+    }
+
+    /// Switches the trials and parameters to the task of the current generation, returning whether the
+    /// task changed. The trials of the previous task are put back in the curriculum.
+    fn advance_curriculum(&mut self) -> bool {
+        let curriculum = match self.curriculum.as_mut() {
+            Some(curriculum) => curriculum,
+            None => return false,
+        };
+
+        let task = curriculum.task_at(self.generation);
+        if self.task == Some(task) {
+            return false;
+        }
+
+        if let Some(previous) = self.task {
+            std::mem::swap(&mut self.trials, &mut curriculum.tasks[previous].trials);
+        }
+        std::mem::swap(&mut self.trials, &mut curriculum.tasks[task].trials);
+        (curriculum.tasks[task].configure)(&mut self.params);
+        self.cache_regime = None;
+
+        info!(
+            task = serde_json::to_string(&curriculum.tasks[task].name).unwrap(),
+            generation = serde_json::to_string(&self.generation).unwrap()
+        );
+
+        self.task = Some(task);
+        self.best_fitness = None;
+        self.stagnant_generations = 0;
+
+        true
+    }
+
+    /// Fitness of a copy of `best` on the validation states, leaving `best` untouched.
+    fn validate(&mut self, best: Option<&C::Individual>) -> Option<f64> {
+        if self.validation.is_empty() {
+            return None;
+        }
+
+        let mut best = best?.clone();
+        C::eval_individual_penalized(
+            &mut best,
+            &mut self.validation,
+            self.params.default_fitness,
+            self.params.fitness_mode,
+            self.params.risk_aversion,
+            self.params.objective,
+            &self.penalties,
+        );
+
+        let fitness = C::Status::get_fitness(&best);
+        self.validation_history.push(fitness);
+
+        Some(fitness)
+    }
+}
+
+impl<C> Drop for CoreIter<C>
+where
+    C: Core,
+{
+    fn drop(&mut self) {
+        info!(summary = serde_json::to_string(&self.summary).unwrap());
+    }
 }
 
 impl<C> Iterator for CoreIter<C>
@@ -93,18 +777,104 @@ where
     type Item = Vec<C::Individual>;
 
     fn next(&mut self) -> Option<Self::Item> {
-        if self.generation > self.params.n_generations {
+        if self.generation > self.params.n_generations || self.timed_out || self.stopped() {
             return None;
         }
 
+        let started = *self.started.get_or_insert_with(Instant::now);
+
+        for (target, schedule) in self.schedules.iter() {
+            *target(&mut self.params) = schedule(self.generation);
+        }
+
+        if !self.schedules.is_empty() {
+            self.params.mutation_percent = self.params.mutation_percent.clamp(0., 1.);
+            self.params.crossover_percent = self
+                .params
+                .crossover_percent
+                .clamp(0., 1. - self.params.mutation_percent);
+        }
+
+        let task_changed = self.advance_curriculum();
+
         let mut population = self.next_population.clone();
+        population.append(&mut self.deferred);
 
-        C::eval_fitness(
-            &mut population,
-            &mut self.trials,
-            self.params.default_fitness,
-        );
-        C::rank(&mut population);
+        // Fitness measured on the previous task says nothing about this one.
+        if task_changed {
+            for individual in population.iter_mut() {
+                C::Status::set_fitness(individual, f64::NAN);
+            }
+        }
+
+        #[cfg(debug_assertions)]
+        let per_run = self.trials.iter().map(FreshState::per_run).collect_vec();
+
+        for trial in self.trials.iter_mut() {
+            trial.on_generation(self.generation);
+        }
+
+        if self.params.evaluation_strategy == EvaluationStrategy::Keyed {
+            self.apply_evaluation_cache(&mut population);
+        }
+
+        take_counters();
+        take_episode_counters();
+        take_penalties();
+
+        let noise = self.params.noise.training();
+
+        let eval_start = Instant::now();
+        in_stream("evaluation", || {
+            with_noise(noise, || match self.params.step_budget {
+                Some(step_budget) => {
+                    self.deferred = C::evaluate_with_budget(
+                        &mut population,
+                        &mut self.trials,
+                        &self.params,
+                        step_budget,
+                        &self.penalties,
+                    );
+                }
+                None => C::evaluate(
+                    &mut population,
+                    &mut self.trials,
+                    &self.params,
+                    &self.penalties,
+                    &mut self.trial_pool,
+                ),
+            })
+        });
+        let eval_time = eval_start.elapsed();
+
+        if self.params.evaluation_strategy == EvaluationStrategy::Keyed {
+            self.fill_evaluation_cache(&population);
+        }
+
+        #[cfg(debug_assertions)]
+        for (trial, per_run) in self.trials.iter().zip(per_run) {
+            assert_eq!(
+                trial.per_run(),
+                per_run,
+                "A generation changed state which should last the whole run."
+            );
+        }
+        let (environment_steps, program_executions) = take_counters();
+        let (episodes, successes) = take_episode_counters();
+        let incurred_penalties = take_penalties();
+
+        let rank_start = Instant::now();
+        match self.params.ranking {
+            Ranking::Fitness => C::rank_by(&mut population, self.params.objective),
+            Ranking::Stochastic => in_stream("generation", || {
+                C::rank_stochastic(
+                    &mut population,
+                    self.params.objective,
+                    self.params.fitness_comparison_probability,
+                )
+            }),
+        }
+        let rank_time = rank_start.elapsed();
 
         assert!(population.iter().all(C::Status::evaluated));
 
@@ -115,19 +885,126 @@ where
             generation = serde_json::to_string(&self.generation).unwrap()
         );
 
+        // Measured after the counters are taken, so validation does not count towards the run.
+        let validation_fitness = self.validate(population.first());
+
+        if let Some(validation_fitness) = validation_fitness {
+            info!(
+                training =
+                    serde_json::to_string(&population.first().map(C::Status::get_fitness)).unwrap(),
+                validation = serde_json::to_string(&validation_fitness).unwrap(),
+                generation = serde_json::to_string(&self.generation).unwrap()
+            );
+        }
+
+        let policy = self.params.stagnation_policy;
+        let stagnated = self.stagnated(&population);
+
+        if stagnated {
+            info!(
+                stagnation = serde_json::to_string(&policy.stagnation_response).unwrap(),
+                generation = serde_json::to_string(&self.generation).unwrap()
+            );
+        }
+
         let mut new_population = population.clone();
 
+        let survive_start = Instant::now();
         C::survive(&mut new_population, self.params.gap);
-        C::variation(
-            &mut new_population,
-            self.params.crossover_percent,
-            self.params.mutation_percent,
-            self.params.program_parameters,
-        );
+        C::age(&mut new_population, self.params.max_age);
+        let survive_time = survive_start.elapsed();
+
+        let variation_start = Instant::now();
+        if stagnated && policy.stagnation_response == StagnationResponse::Restart {
+            new_population.truncate(policy.restart_elites.max(1));
+            let n_generated = self
+                .params
+                .population_size
+                .saturating_sub(new_population.len());
+            new_population.extend(in_stream("generation", || {
+                C::init_population(self.params.program_parameters, n_generated)
+            }));
+        } else {
+            if stagnated {
+                self.hypermutation_generations = policy.hypermutation_generations;
+            }
+
+            let (crossover_percent, mutation_percent) = if self.hypermutation_generations > 0 {
+                self.hypermutation_generations -= 1;
+                let mutation_percent = policy.hypermutation_percent.clamp(0., 1.);
+                (
+                    self.params.crossover_percent.min(1. - mutation_percent),
+                    mutation_percent,
+                )
+            } else {
+                (self.params.crossover_percent, self.params.mutation_percent)
+            };
+
+            in_stream("variation", || {
+                C::variation(
+                    &mut new_population,
+                    crossover_percent,
+                    mutation_percent,
+                    self.params.program_parameters,
+                )
+            });
+        }
+        in_stream("local_search", || {
+            with_noise(noise, || {
+                C::local_search(
+                    &mut new_population,
+                    &mut self.trials,
+                    &self.params,
+                    &self.penalties,
+                )
+            })
+        });
+        let variation_time = variation_start.elapsed();
+
+        let metrics = GenerationMetrics {
+            generation: self.generation,
+            eval_time,
+            rank_time,
+            survive_time,
+            variation_time,
+            environment_steps,
+            program_executions,
+            episodes,
+            successes,
+            validation_fitness,
+            task: self.task,
+        };
+        self.summary.record(&metrics);
+
+        info!(telemetry = serde_json::to_string(&metrics).unwrap());
+
+        if !self.penalties.is_empty() {
+            let penalties = self
+                .penalties
+                .iter()
+                .map(|penalty| penalty.name.as_str())
+                .zip(incurred_penalties.into_iter().chain(repeat(0.)))
+                .collect::<BTreeMap<_, _>>();
+
+            info!(
+                penalties = serde_json::to_string(&penalties).unwrap(),
+                generation = serde_json::to_string(&self.generation).unwrap()
+            );
+        }
 
         self.next_population = new_population;
         self.generation += 1;
 
+        if let Some(max_duration) = self.params.max_duration {
+            if started.elapsed() >= max_duration {
+                info!(
+                    timed_out = serde_json::to_string(&started.elapsed()).unwrap(),
+                    generation = serde_json::to_string(&self.generation).unwrap()
+                );
+                self.timed_out = true;
+            }
+        }
+
         return Some(population);
     }
 }
@@ -140,12 +1017,17 @@ where
         update_seed(self.seed);
         CoreIter::new(self.clone())
     }
+
+    pub fn build_engine_with_seeds(&self, seeds: Vec<T::Individual>) -> CoreIter<T> {
+        update_seed(self.seed);
+        CoreIter::with_seeds(self.clone(), seeds)
+    }
 }
 
 pub trait Core {
     type Individual: Ord + Clone + Send + Sync + Serialize + DeserializeOwned;
     type ProgramParameters: Copy + Send + Sync + Clone + Serialize + DeserializeOwned + Args;
-    type State: State;
+    type State: State + GenerationAware + FreshState;
     type FitnessMarker;
     type Generate: Generate<Self::ProgramParameters, Self::Individual> + Generate<(), Self::State>;
     type Fitness: Fitness<Self::Individual, Self::State, Self::FitnessMarker>;
@@ -166,31 +1048,355 @@ pub trait Core {
         population
     }
 
+    fn eval_individual(
+        individual: &mut Self::Individual,
+        trials: &mut [Self::State],
+        default_fitness: f64,
+    ) {
+        Self::eval_individual_with(
+            individual,
+            trials,
+            default_fitness,
+            FitnessMode::CumulativeReward,
+        )
+    }
+
+    /// Evaluates `individual` on every trial, combining the scores according to `fitness_mode`.
+    /// Non-finite scores are replaced by `default_fitness` and count as failures.
+    ///
+    /// The individual's fitness metadata describes these trials.
+    ///
+    /// Trials (environments included) are built once per run and reset before every evaluation, never
+    /// cloned per individual, so no environment is created or torn down while evaluating. Parallel
+    /// evaluation leases its copies from a [`TrialPool`] kept for the whole run.
+    fn eval_individual_with(
+        individual: &mut Self::Individual,
+        trials: &mut [Self::State],
+        default_fitness: f64,
+        fitness_mode: FitnessMode,
+    ) {
+        Self::eval_individual_penalized(
+            individual,
+            trials,
+            default_fitness,
+            fitness_mode,
+            1.,
+            Objective::Maximize,
+            &[],
+        )
+    }
+
+    /// Same as [`Core::eval_individual_with`], taking every penalty incurred on a trial off the
+    /// individual's fitness (or adding it when minimizing). The mean weighted penalty is kept in the
+    /// fitness metadata and recorded for telemetry.
+    ///
+    /// `risk_aversion` weighs the standard deviation of [`FitnessMode::RiskAdjusted`].
+    fn eval_individual_penalized(
+        individual: &mut Self::Individual,
+        trials: &mut [Self::State],
+        default_fitness: f64,
+        fitness_mode: FitnessMode,
+        risk_aversion: f64,
+        objective: Objective,
+        penalties: &[Penalty<Self::Individual, Self::State>],
+    ) {
+        let start = environment_steps();
+        let scores = trials
+            .iter_mut()
+            .map(|trial| {
+                Self::Reset::reset(individual);
+                Self::Reset::reset(trial);
+
+                debug_assert_eq!(
+                    trial.per_evaluation(),
+                    trial.fresh(),
+                    "Resetting the trial did not restore the state of a fresh evaluation."
+                );
+                #[cfg(debug_assertions)]
+                let per_generation = trial.per_generation();
+
+                let executions = program_executions();
+                let score = Self::Fitness::eval_fitness(individual, trial);
+                record_trial_program_executions(program_executions().saturating_sub(executions));
+
+                #[cfg(debug_assertions)]
+                assert_eq!(
+                    trial.per_generation(),
+                    per_generation,
+                    "An evaluation changed state which should last the whole generation."
+                );
+
+                let score = match score.is_finite() {
+                    true => (score, trial.succeeded()),
+                    false => (default_fitness, false),
+                };
+
+                let incurred = penalties
+                    .iter()
+                    .map(|penalty| penalty.weight * (penalty.measure)(individual, trial))
+                    .collect_vec();
+                if !incurred.is_empty() {
+                    record_penalties(&incurred);
+                }
+
+                (
+                    score,
+                    trial.constraint_violation(),
+                    incurred.iter().sum::<f64>(),
+                )
+            })
+            .collect_vec();
+        // Without trials there is nothing to average, rather than a NaN violation and penalty.
+        let n_trials = scores.len().max(1) as f64;
+        let violation = scores
+            .iter()
+            .map(|(_, violation, _)| violation)
+            .sum::<f64>()
+            / n_trials;
+        let penalty = scores.iter().map(|(_, _, penalty)| penalty).sum::<f64>() / n_trials;
+        let scores = scores.into_iter().map(|(score, _, _)| score).collect_vec();
+
+        let mut metadata = FitnessMetadata::new(&scores, environment_steps() - start);
+        metadata.violation = violation;
+        metadata.penalty = penalty;
+
+        let fitness = match objective {
+            Objective::Maximize => fitness_mode.aggregate_with(&scores, risk_aversion) - penalty,
+            Objective::Minimize => fitness_mode.aggregate_with(&scores, -risk_aversion) + penalty,
+        };
+
+        Self::Status::set_fitness(individual, fitness);
+        Self::Status::set_metadata(individual, metadata);
+    }
+
     fn eval_fitness(
         population: &mut Vec<Self::Individual>,
         trials: &mut Vec<Self::State>,
         default_fitness: f64,
     ) {
         for individual in population.iter_mut() {
-            let mut scores = trials
-                .iter_mut()
-                .map(|trial| {
-                    Self::Reset::reset(individual);
-                    Self::Reset::reset(trial);
-                    Self::Fitness::eval_fitness(individual, trial)
-                })
-                .collect_vec();
+            Self::eval_individual(individual, trials, default_fitness);
+        }
+    }
+
+    /// Screens offspring (individuals without a fitness) on the first `surrogate_trials` trials and
+    /// discards the worst `surrogate_discard` fraction by assigning them a fitness of negative infinity,
+    /// guaranteeing they are dropped when surviving.
+    ///
+    /// Returns which individuals were discarded; the remaining offspring are left unevaluated.
+    fn screen_offspring(
+        population: &mut [Self::Individual],
+        trials: &mut [Self::State],
+        params: &HyperParameters<Self>,
+        penalties: &[Penalty<Self::Individual, Self::State>],
+    ) -> Vec<bool>
+    where
+        Self: Sized,
+    {
+        let mut discarded = vec![false; population.len()];
+        let n_proxy_trials = params.surrogate_trials.min(trials.len());
+
+        if n_proxy_trials == 0 {
+            return discarded;
+        }
+
+        let mut offspring = population
+            .iter_mut()
+            .enumerate()
+            .filter(|(_, individual)| !Self::Status::evaluated(individual))
+            .collect_vec();
+
+        for (_, individual) in offspring.iter_mut() {
+            Self::eval_individual_penalized(
+                individual,
+                &mut trials[..n_proxy_trials],
+                params.default_fitness,
+                params.fitness_mode,
+                params.risk_aversion,
+                params.objective,
+                penalties,
+            );
+        }
+
+        // Worst first.
+        offspring.sort_by(|(_, a), (_, b)| {
+            params
+                .objective
+                .compare(Self::Status::get_fitness(a), Self::Status::get_fitness(b))
+        });
+
+        let n_discarded = (offspring.len() as f64 * params.surrogate_discard).floor() as usize;
+
+        for (rank, (idx, individual)) in offspring.into_iter().enumerate() {
+            if rank < n_discarded {
+                discarded[idx] = true;
+                Self::Status::set_fitness(individual, f64::NEG_INFINITY);
+            } else {
+                Self::Status::set_fitness(individual, f64::NAN);
+            }
+        }
+
+        discarded
+    }
+
+    /// Memetic step: each offspring (individual without a fitness) tries `local_search_steps` mutations,
+    /// keeping a mutant only when it scores better on the first `local_search_trials` trials.
+    ///
+    /// Offspring are left unevaluated, so they are evaluated on every trial with the rest of the
+    /// population.
+    fn local_search(
+        population: &mut [Self::Individual],
+        trials: &mut [Self::State],
+        params: &HyperParameters<Self>,
+        penalties: &[Penalty<Self::Individual, Self::State>],
+    ) where
+        Self: Sized,
+    {
+        let n_proxy_trials = params.local_search_trials.clamp(1, trials.len().max(1));
+
+        if params.local_search_steps == 0 || trials.is_empty() {
+            return;
+        }
+
+        let proxy_trials = &mut trials[..n_proxy_trials];
+
+        for individual in population
+            .iter_mut()
+            .filter(|individual| !Self::Status::evaluated(individual))
+        {
+            Self::eval_individual_penalized(
+                individual,
+                proxy_trials,
+                params.default_fitness,
+                params.fitness_mode,
+                params.risk_aversion,
+                params.objective,
+                penalties,
+            );
+
+            for _ in 0..params.local_search_steps {
+                let mut candidate = individual.clone();
+                Self::Mutate::mutate(&mut candidate, params.program_parameters);
+                Self::eval_individual_penalized(
+                    &mut candidate,
+                    proxy_trials,
+                    params.default_fitness,
+                    params.fitness_mode,
+                    params.risk_aversion,
+                    params.objective,
+                    penalties,
+                );
 
-            let n_trials = scores.len();
-            scores = scores
-                .into_iter()
-                .map(|s| if !s.is_finite() { default_fitness } else { s })
-                .collect_vec();
-            let average = scores.into_iter().sum::<f64>() / n_trials as f64;
-            Self::Status::set_fitness(individual, average);
+                if params.objective.is_better(
+                    Self::Status::get_fitness(&candidate),
+                    Self::Status::get_fitness(individual),
+                ) {
+                    *individual = candidate;
+                }
+            }
+
+            Self::Reset::reset(individual);
         }
     }
 
+    /// Evaluates the individuals of `population` which need it (see [`EvaluationStrategy`]) with
+    /// [`SequentialEvaluation`]. Problems whose states can be sent to other threads override it with
+    /// [`evaluate_with`] to honour [`HyperParameters::evaluator`].
+    fn evaluate(
+        population: &mut Vec<Self::Individual>,
+        trials: &mut Vec<Self::State>,
+        params: &HyperParameters<Self>,
+        penalties: &[Penalty<Self::Individual, Self::State>],
+        pool: &mut TrialPool<Self::State>,
+    ) where
+        Self: Sized,
+    {
+        SequentialEvaluation.eval_population(population, trials, params, penalties, pool)
+    }
+
+    /// Evaluates the population within `step_budget` environment steps using successive halving:
+    /// candidates are first evaluated on a single trial, then every round the best half is re-evaluated
+    /// on twice as many trials, until all trials are used or the budget runs out. Individuals eliminated
+    /// early keep the estimate of their last round.
+    ///
+    /// The budget is checked before each evaluation, so a generation may overrun it by the steps of a
+    /// single evaluation. At least one individual is always evaluated. Individuals left without a
+    /// fitness are removed from the population and returned so they can be evaluated in a later
+    /// generation.
+    fn evaluate_with_budget(
+        population: &mut Vec<Self::Individual>,
+        trials: &mut Vec<Self::State>,
+        params: &HyperParameters<Self>,
+        step_budget: usize,
+        penalties: &[Penalty<Self::Individual, Self::State>],
+    ) -> Vec<Self::Individual>
+    where
+        Self: Sized,
+    {
+        let start = environment_steps();
+        let exhausted = || environment_steps() - start >= step_budget;
+
+        let mut candidates = population
+            .iter()
+            .enumerate()
+            .filter(|(_, individual)| match params.evaluation_strategy {
+                EvaluationStrategy::Sequential => true,
+                EvaluationStrategy::Cached | EvaluationStrategy::Keyed => {
+                    !Self::Status::evaluated(individual)
+                }
+            })
+            .map(|(idx, _)| idx)
+            .collect_vec();
+
+        let mut n_trials = trials.len().min(1);
+        let mut n_evaluations = 0;
+
+        while !candidates.is_empty() {
+            let mut evaluated = vec![];
+
+            for &idx in candidates.iter() {
+                if n_evaluations > 0 && exhausted() {
+                    break;
+                }
+
+                Self::eval_individual_penalized(
+                    &mut population[idx],
+                    &mut trials[..n_trials],
+                    params.default_fitness,
+                    params.fitness_mode,
+                    params.risk_aversion,
+                    params.objective,
+                    penalties,
+                );
+                evaluated.push(idx);
+                n_evaluations += 1;
+            }
+
+            if exhausted() || n_trials >= trials.len() {
+                break;
+            }
+
+            // Best first.
+            evaluated.sort_by(|a, b| {
+                params.objective.compare(
+                    Self::Status::get_fitness(&population[*b]),
+                    Self::Status::get_fitness(&population[*a]),
+                )
+            });
+            evaluated.truncate((evaluated.len() + 1) / 2);
+
+            candidates = evaluated;
+            n_trials = (n_trials * 2).min(trials.len());
+        }
+
+        let (evaluated, deferred): (Vec<_>, Vec<_>) = population
+            .drain(..)
+            .partition(|individual| Self::Status::evaluated(individual));
+        *population = evaluated;
+
+        deferred
+    }
+
     fn rank(population: &mut Vec<Self::Individual>) {
         population.sort_by(|a, b| b.cmp(a));
         debug_assert!(population.windows(2).all(|w| {
@@ -202,6 +1408,68 @@ pub trait Core {
         }));
     }
 
+    /// Ranks the population best first according to `objective`.
+    ///
+    /// When minimizing, invalid individuals (e.g. discarded by screening) are ranked last so `survive`
+    /// still drops them first. Ties are broken by the individuals' own order, as with `rank`.
+    fn rank_by(population: &mut Vec<Self::Individual>, objective: Objective) {
+        match objective {
+            Objective::Maximize => Self::rank(population),
+            Objective::Minimize => population.sort_by(|a, b| {
+                Self::Status::valid(b)
+                    .cmp(&Self::Status::valid(a))
+                    .then_with(|| {
+                        objective
+                            .compare(Self::Status::get_fitness(b), Self::Status::get_fitness(a))
+                    })
+                    .then_with(|| b.cmp(a))
+            }),
+        }
+    }
+
+    /// Stochastic ranking (Runarsson & Yao): bubble-sort sweeps over the population where neighbours
+    /// are compared by fitness when both are feasible, or with probability `probability`, and by
+    /// constraint violation (lower first) otherwise. Invalid individuals are moved behind valid ones.
+    fn rank_stochastic(
+        population: &mut Vec<Self::Individual>,
+        objective: Objective,
+        probability: f64,
+    ) {
+        let violation = |individual: &Self::Individual| {
+            Self::Status::get_metadata(individual).map_or(0., |metadata| metadata.violation)
+        };
+
+        for _ in 0..population.len() {
+            let mut swapped = false;
+
+            for idx in 0..population.len().saturating_sub(1) {
+                let (a, b) = (&population[idx], &population[idx + 1]);
+                let (violation_a, violation_b) = (violation(a), violation(b));
+
+                let by_fitness = (violation_a == 0. && violation_b == 0.)
+                    || generator().gen::<f64>() < probability;
+
+                let swap = match (Self::Status::valid(a), Self::Status::valid(b)) {
+                    (false, true) => true,
+                    (true, false) | (false, false) => false,
+                    (true, true) if by_fitness => objective
+                        .is_better(Self::Status::get_fitness(b), Self::Status::get_fitness(a)),
+                    (true, true) => violation_a > violation_b,
+                };
+
+                if swap {
+                    population.swap(idx, idx + 1);
+                    swapped = true;
+                }
+            }
+
+            if !swapped {
+                break;
+            }
+        }
+    }
+
+    /// Drops the worst `gap` of a population ranked with `rank` or `rank_by`.
     fn survive(population: &mut Vec<Self::Individual>, gap: f64) {
         let n_individuals = population.len();
 
@@ -218,6 +1486,27 @@ pub trait Core {
         }
     }
 
+    /// Ages the survivors of a generation by one, then retires those older than `max_age`. The best
+    /// survivor is kept if every survivor would be retired, so variation always has a parent.
+    fn age(population: &mut Vec<Self::Individual>, max_age: Option<usize>) {
+        for individual in population.iter_mut() {
+            let age = Self::Status::get_age(individual);
+            Self::Status::set_age(individual, age + 1);
+        }
+
+        let max_age = match max_age {
+            Some(max_age) => max_age,
+            None => return,
+        };
+
+        let best = population.first().cloned();
+        population.retain(|individual| Self::Status::get_age(individual) <= max_age);
+
+        if population.is_empty() {
+            population.extend(best);
+        }
+    }
+
     fn variation(
         population: &mut Vec<Self::Individual>,
         crossover_percent: f64,
@@ -235,8 +1524,11 @@ pub trait Core {
             return;
         }
 
-        let n_mutations = (remaining_pool_spots as f64 * mutation_percent).floor() as usize;
-        let n_crossovers = (remaining_pool_spots as f64 * crossover_percent).floor() as usize;
+        // Rates summing above 1 (e.g. set by hand) are honoured as far as there are spots left.
+        let n_mutations = ((remaining_pool_spots as f64 * mutation_percent).floor() as usize)
+            .min(remaining_pool_spots);
+        let n_crossovers = ((remaining_pool_spots as f64 * crossover_percent).floor() as usize)
+            .min(remaining_pool_spots - n_mutations);
         let n_clones = remaining_pool_spots - n_mutations - n_crossovers;
 
         let mut clone_offspring: Vec<Self::Individual> = Vec::with_capacity(n_clones);
@@ -246,55 +1538,64 @@ pub trait Core {
         debug_assert!(n_mutations + n_crossovers <= remaining_pool_spots);
 
         let rc_population = Arc::new(population.clone());
+        // Each task runs on a rayon thread, drawing from a generator seeded by this thread's.
+        let [crossover_seed, mutation_seed, clone_seed]: [u64; 3] = generator().gen();
 
         rayon::scope(|s| {
             s.spawn(|_| {
-                crossover_offspring.extend((0..n_crossovers).filter_map(|_| {
-                    let population_to_read = rc_population.clone();
-                    let parent_a = population_to_read.iter().choose(&mut generator());
-                    let parent_b = population_to_read.iter().choose(&mut generator());
-
-                    if let (Some(parent_a), Some(parent_b)) = (parent_a, parent_b) {
-                        let children = Self::Breed::two_point_crossover(&parent_a, &parent_b);
-                        match generator().gen_range(0..2) {
-                            0 => Some(children.0),
-                            1 => Some(children.1),
-                            _ => unreachable!(),
+                with_seed(crossover_seed, || {
+                    crossover_offspring.extend((0..n_crossovers).filter_map(|_| {
+                        let population_to_read = rc_population.clone();
+                        let parent_a = population_to_read.iter().choose(&mut generator());
+                        let parent_b = population_to_read.iter().choose(&mut generator());
+
+                        if let (Some(parent_a), Some(parent_b)) = (parent_a, parent_b) {
+                            let children = Self::Breed::two_point_crossover(&parent_a, &parent_b);
+                            match generator().gen_range(0..2) {
+                                0 => Some(children.0),
+                                1 => Some(children.1),
+                                _ => unreachable!(),
+                            }
+                        } else {
+                            None
                         }
-                    } else {
-                        None
-                    }
-                }));
+                    }));
+                })
             });
 
             s.spawn(|_| {
-                mutation_offspring.extend((0..n_mutations).filter_map(|_| {
-                    let population_to_read = rc_population.clone();
-                    let parent = population_to_read.iter().choose(&mut generator());
-
-                    if let Some(internal_parent) = parent {
-                        let mut clone = internal_parent.clone();
-                        Self::Mutate::mutate(&mut clone, program_parameters);
-                        Some(clone)
-                    } else {
-                        None
-                    }
-                }))
+                with_seed(mutation_seed, || {
+                    mutation_offspring.extend((0..n_mutations).filter_map(|_| {
+                        let population_to_read = rc_population.clone();
+                        let parent = population_to_read.iter().choose(&mut generator());
+
+                        if let Some(internal_parent) = parent {
+                            let mut clone = internal_parent.clone();
+                            Self::Mutate::mutate(&mut clone, program_parameters);
+                            Some(clone)
+                        } else {
+                            None
+                        }
+                    }))
+                })
             });
 
             s.spawn(|_| {
-                clone_offspring.extend((0..n_clones).filter_map(|_| {
-                    let population_to_read = rc_population.clone();
-                    let parent = population_to_read.iter().choose(&mut generator());
-
-                    if let Some(internal_parent) = parent {
-                        let mut clone = internal_parent.clone();
-                        Self::Reset::reset(&mut clone);
-                        Some(clone)
-                    } else {
-                        None
-                    }
-                }))
+                with_seed(clone_seed, || {
+                    clone_offspring.extend((0..n_clones).filter_map(|_| {
+                        let population_to_read = rc_population.clone();
+                        let parent = population_to_read.iter().choose(&mut generator());
+
+                        if let Some(internal_parent) = parent {
+                            let mut clone = internal_parent.clone();
+                            Self::Reset::reset(&mut clone);
+                            Self::Status::set_age(&mut clone, 0);
+                            Some(clone)
+                        } else {
+                            None
+                        }
+                    }))
+                })
             });
         });
 
@@ -304,3 +1605,286 @@ pub trait Core {
         population.append(&mut clone_offspring);
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::engines::{
+        breed_engine::BreedEngine, fitness_engine::FitnessEngine, freeze_engine::FreezeEngine,
+        generate_engine::GenerateEngine, mutate_engine::MutateEngine, reset_engine::ResetEngine,
+        status_engine::StatusEngine,
+    };
+    use crate::core::program::{Program, ProgramGeneratorParameters};
+    use crate::extensions::classification::{Dataset, DatasetEngine};
+    use crate::problems::problem::program_parameters;
+
+    #[test]
+    fn given_schedules_when_evaluated_then_values_follow_the_generation() {
+        let linear = Schedule::Linear {
+            start: 0.9,
+            end: 0.1,
+            n_generations: 8,
+        };
+        let exponential = Schedule::Exponential {
+            start: 1.,
+            rate: 0.5,
+        };
+        let step = Schedule::Step {
+            start: 1.,
+            factor: 0.1,
+            step_size: 3,
+        };
+
+        assert_eq!(linear.value(0), 0.9);
+        assert!((linear.value(4) - 0.5).abs() < 1e-12);
+        assert_eq!(linear.value(100), 0.1);
+        assert_eq!(exponential.value(3), 0.125);
+        assert_eq!(step.value(2), 1.);
+        assert!((step.value(3) - 0.1).abs() < 1e-12);
+    }
+
+    /// Counts the samples it classified as if that lasted the whole generation.
+    #[derive(Clone, Debug)]
+    struct Leaky {
+        dataset: Dataset,
+        n_classified: usize,
+    }
+
+    impl State for Leaky {
+        fn get_value(&self, at_idx: usize) -> f64 {
+            self.dataset.get_value(at_idx)
+        }
+
+        fn execute_action(&mut self, action: usize) -> f64 {
+            self.n_classified += 1;
+            self.dataset.execute_action(action)
+        }
+
+        fn get(&mut self) -> Option<&mut Self> {
+            self.dataset.get()?;
+
+            Some(self)
+        }
+    }
+
+    impl GenerationAware for Leaky {}
+
+    impl FreshState for Leaky {
+        type PerEvaluation = usize;
+        type PerGeneration = usize;
+        type PerRun = ();
+
+        fn per_evaluation(&self) -> usize {
+            self.dataset.per_evaluation()
+        }
+
+        fn fresh(&self) -> usize {
+            0
+        }
+
+        fn per_generation(&self) -> usize {
+            self.n_classified
+        }
+
+        fn per_run(&self) {}
+    }
+
+    impl Reset<Leaky> for ResetEngine {
+        fn reset(item: &mut Leaky) {
+            ResetEngine::reset(&mut item.dataset);
+        }
+    }
+
+    impl Generate<(), Leaky> for GenerateEngine {
+        fn generate(_using: ()) -> Leaky {
+            Leaky {
+                dataset: Dataset::new(vec![vec![1.]], vec![0]),
+                n_classified: 0,
+            }
+        }
+    }
+
+    struct LeakyEngine;
+
+    impl Core for LeakyEngine {
+        type Individual = Program;
+        type ProgramParameters = ProgramGeneratorParameters;
+        type State = Leaky;
+        type FitnessMarker = ();
+        type Generate = GenerateEngine;
+        type Fitness = FitnessEngine;
+        type Reset = ResetEngine;
+        type Breed = BreedEngine;
+        type Mutate = MutateEngine;
+        type Status = StatusEngine;
+        type Freeze = FreezeEngine;
+    }
+
+    #[test]
+    fn given_keyed_evaluations_when_trials_change_then_cached_fitness_is_dropped() {
+        let program_parameters = program_parameters(1, 2);
+        let params = HyperParametersBuilder::<DatasetEngine>::default()
+            .program_parameters(program_parameters)
+            .evaluation_strategy(EvaluationStrategy::Keyed)
+            .build()
+            .unwrap();
+
+        let dataset = Dataset::new(vec![vec![1.], vec![2.]], vec![0, 1]);
+        let mut engine = CoreIter::with_trials(params, vec![], vec![dataset.clone()]);
+
+        let elite = Program::parse("r0 = r0 + 1", program_parameters).unwrap();
+        let mut population = vec![elite.clone()];
+        engine.apply_evaluation_cache(&mut population);
+        DatasetEngine::evaluate(
+            &mut population,
+            &mut engine.trials,
+            &engine.params,
+            &[],
+            &mut TrialPool::default(),
+        );
+        engine.fill_evaluation_cache(&population);
+        assert_eq!(population[0].fitness, 0.5);
+
+        // A copy of the elite, a new genome and the elite reading none of its inputs, on the same
+        // trials.
+        let mut masked = elite.clone();
+        masked.input_mask = Some(vec![false]);
+        let mut next = vec![
+            elite.clone(),
+            Program::parse("r1 = r1 + 1", program_parameters).unwrap(),
+            masked,
+        ];
+        engine.apply_evaluation_cache(&mut next);
+        assert_eq!(next[0].fitness, 0.5);
+        assert_eq!(next[0].fitness_metadata.unwrap().n_trials, 1);
+        assert!(next[1].fitness.is_nan());
+        assert!(next[2].fitness.is_nan());
+
+        engine.trials_mut()[0] = Dataset::new(vec![vec![1.], vec![2.]], vec![0, 0]);
+        engine.apply_evaluation_cache(&mut next);
+        assert!(next.iter().all(|program| program.fitness.is_nan()));
+    }
+
+    #[test]
+    fn given_curriculum_when_iterated_then_trials_and_parameters_follow_the_tasks() {
+        let program_parameters = program_parameters(1, 2);
+        let params = HyperParametersBuilder::<DatasetEngine>::default()
+            .program_parameters(program_parameters)
+            .population_size(4)
+            .n_generations(3)
+            .build()
+            .unwrap();
+
+        let easy = Dataset::new(vec![vec![1.]], vec![0]);
+        let hard = Dataset::new(vec![vec![1.], vec![2.], vec![3.]], vec![0, 1, 0]);
+        let curriculum = Curriculum::new(
+            vec![
+                Task::new("easy", 1, vec![easy])
+                    .with_parameters(|params| params.default_fitness = 0.),
+                Task::new("hard", 2, vec![hard])
+                    .with_parameters(|params| params.default_fitness = -1.),
+            ],
+            true,
+        );
+        assert_eq!(
+            (0..5)
+                .map(|generation| curriculum.task_at(generation))
+                .collect_vec(),
+            vec![0, 1, 1, 0, 1]
+        );
+
+        let mut engine = CoreIter::with_trials(params, vec![], vec![]).with_curriculum(curriculum);
+
+        for expected in [(1, 0.), (3, -1.), (3, -1.), (1, 0.)] {
+            let population = engine.next().unwrap();
+
+            assert!(population.iter().all(StatusEngine::evaluated));
+            assert_eq!(
+                (engine.trials[0].labels.len(), engine.params.default_fitness),
+                expected
+            );
+        }
+
+        // The trials the run started with are kept aside, not lost.
+        let curriculum = engine.curriculum.unwrap();
+        assert!(curriculum.tasks[0].trials.is_empty());
+        assert_eq!(curriculum.tasks[1].trials[0].labels.len(), 3);
+    }
+
+    #[test]
+    fn given_max_age_when_survivors_age_then_old_individuals_are_retired() {
+        let program_parameters = program_parameters(1, 2);
+
+        let mut population = (0..3)
+            .map(|age| {
+                let mut program = Program::parse("r0 = r0 + 1", program_parameters).unwrap();
+                program.age = age;
+                program
+            })
+            .collect_vec();
+        let youngest = population[0].id;
+
+        LeakyEngine::age(&mut population, Some(1));
+        assert_eq!(population.len(), 1);
+        assert_eq!((population[0].id, population[0].age), (youngest, 1));
+
+        // Everyone is too old: the best survivor is kept regardless.
+        LeakyEngine::age(&mut population, Some(0));
+        assert_eq!(population.len(), 1);
+
+        LeakyEngine::age(&mut population, None);
+        assert_eq!(population[0].age, 3);
+    }
+
+    #[test]
+    fn given_constraint_violations_when_ranked_stochastically_then_probability_trades_fitness_for_feasibility(
+    ) {
+        let program_parameters = program_parameters(1, 2);
+
+        // `(fitness, violation)`, worst fitness first.
+        let population = [(1., 0.), (2., 1.), (3., 0.), (4., 2.)]
+            .into_iter()
+            .map(|(fitness, violation)| {
+                let mut program = Program::parse("r0 = r0 + 1", program_parameters).unwrap();
+                StatusEngine::set_fitness(&mut program, fitness);
+                StatusEngine::set_metadata(
+                    &mut program,
+                    FitnessMetadata {
+                        violation,
+                        ..FitnessMetadata::new(&[(fitness, false)], 0)
+                    },
+                );
+                program
+            })
+            .collect_vec();
+        let fitness = |population: &[Program]| {
+            population
+                .iter()
+                .map(StatusEngine::get_fitness)
+                .collect_vec()
+        };
+
+        // Infeasible neighbours are never compared by fitness: feasible programs come first.
+        let mut feasible_first = population.clone();
+        LeakyEngine::rank_stochastic(&mut feasible_first, Objective::Maximize, 0.);
+        assert_eq!(fitness(&feasible_first), vec![3., 1., 2., 4.]);
+
+        let mut fittest_first = population;
+        LeakyEngine::rank_stochastic(&mut fittest_first, Objective::Maximize, 1.);
+        assert_eq!(fitness(&fittest_first), vec![4., 3., 2., 1.]);
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(
+        expected = "An evaluation changed state which should last the whole generation."
+    )]
+    fn given_state_leaking_between_evaluations_when_evaluated_then_the_leak_is_caught() {
+        let program_parameters = program_parameters(1, 2);
+
+        let mut program = Program::parse("r0 = r0 + 1", program_parameters).unwrap();
+        let mut trials = [GenerateEngine::generate(())];
+
+        LeakyEngine::eval_individual(&mut program, &mut trials, 0.);
+    }
+}
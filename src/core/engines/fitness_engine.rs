@@ -1,4 +1,22 @@
-use super::reset_engine::{Reset, ResetEngine};
+use std::cmp::Ordering;
+
+use clap::ValueEnum;
+use itertools::Itertools;
+use rand::Rng;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::core::environment::{current_noise, with_noise};
+use crate::utils::{
+    random::{generator, with_seed},
+    telemetry::{add_thread_counters, take_thread_counters},
+};
+
+use super::{
+    core_engine::{Core, HyperParameters},
+    reset_engine::{Reset, ResetEngine},
+    status_engine::Status,
+};
 
 pub trait Fitness<I, S, P> {
     fn eval_fitness(program: &mut I, states: &mut S) -> f64;
@@ -11,3 +29,545 @@ impl Reset<f64> for ResetEngine {
 }
 
 pub struct FitnessEngine;
+
+/// Decides which individuals of a population are evaluated every generation.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ValueEnum)]
+pub enum EvaluationStrategy {
+    /// Every individual is evaluated on every trial.
+    #[default]
+    Sequential,
+    /// Individuals which already hold a fitness (e.g. survivors) are not evaluated again.
+    Cached,
+    /// Individuals whose genome was already evaluated on the same trials reuse that evaluation, trials
+    /// being the same while their per-generation and per-run state (see
+    /// [`FreshState`](crate::core::environment::FreshState)) are. Safe when the initial states change
+    /// between generations, unlike [`EvaluationStrategy::Cached`].
+    Keyed,
+}
+
+/// How the individuals a generation evaluates are spread over threads (see [`PopulationEvaluator`]).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ValueEnum)]
+pub enum Evaluator {
+    /// [`SequentialEvaluation`].
+    #[default]
+    Sequential,
+    /// [`ParallelEvaluation`], for the problems whose states can be sent to other threads (see
+    /// [`evaluate_with`]); the others evaluate sequentially.
+    Parallel,
+}
+
+/// Evaluates the individuals of a generation which need it according to the
+/// [`EvaluationStrategy`] of the run.
+pub trait PopulationEvaluator<C>
+where
+    C: Core,
+{
+    fn eval_population(
+        &self,
+        population: &mut Vec<C::Individual>,
+        trials: &mut Vec<C::State>,
+        params: &HyperParameters<C>,
+        penalties: &[Penalty<C::Individual, C::State>],
+        pool: &mut TrialPool<C::State>,
+    );
+}
+
+/// Evaluates one individual after the other on the calling thread.
+pub struct SequentialEvaluation;
+
+/// Evaluates chunks of the population on rayon threads, one chunk per thread, each on its own copy
+/// of the trials leased from the [`TrialPool`] of the run. Every chunk draws from a generator seeded by the caller's, so seeded runs stay
+/// reproducible, and what the chunks record is reported on the calling thread.
+pub struct ParallelEvaluation;
+
+/// Copies of the trials of a run, leased to the chunks of [`ParallelEvaluation`] (one per thread, so
+/// at most `rayon::current_num_threads()`) and given back once they are evaluated. Leases are kept
+/// from one generation to the next and refreshed with `clone_from`, which reuses what the previous
+/// generation allocated instead of building the trials again; they are reset before every
+/// evaluation like the trials themselves.
+#[derive(Debug, Clone)]
+pub struct TrialPool<S> {
+    leases: Vec<Vec<S>>,
+}
+
+impl<S> Default for TrialPool<S> {
+    fn default() -> Self {
+        TrialPool { leases: vec![] }
+    }
+}
+
+impl<S> TrialPool<S>
+where
+    S: Clone,
+{
+    /// Leases `n` copies of `trials`.
+    pub fn lease(&mut self, trials: &[S], n: usize) -> Vec<Vec<S>> {
+        let mut leases = std::mem::take(&mut self.leases);
+        leases.resize_with(n, Vec::new);
+
+        for lease in leases.iter_mut() {
+            if lease.len() == trials.len() {
+                lease.clone_from_slice(trials);
+            } else {
+                *lease = trials.to_vec();
+            }
+        }
+
+        leases
+    }
+
+    /// Gives back the leases of [`TrialPool::lease`], to be reused by the next one.
+    pub fn give_back(&mut self, leases: Vec<Vec<S>>) {
+        self.leases = leases;
+    }
+
+    /// Number of copies kept for the next lease.
+    pub fn len(&self) -> usize {
+        self.leases.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leases.is_empty()
+    }
+}
+
+/// Whether `strategy` evaluates `individual`, which screening may have `discarded`.
+fn needs_evaluation<C>(
+    individual: &C::Individual,
+    discarded: bool,
+    strategy: EvaluationStrategy,
+) -> bool
+where
+    C: Core,
+{
+    match strategy {
+        EvaluationStrategy::Sequential => !discarded,
+        EvaluationStrategy::Cached | EvaluationStrategy::Keyed => {
+            !discarded && !C::Status::evaluated(individual)
+        }
+    }
+}
+
+impl<C> PopulationEvaluator<C> for SequentialEvaluation
+where
+    C: Core,
+{
+    fn eval_population(
+        &self,
+        population: &mut Vec<C::Individual>,
+        trials: &mut Vec<C::State>,
+        params: &HyperParameters<C>,
+        penalties: &[Penalty<C::Individual, C::State>],
+        _pool: &mut TrialPool<C::State>,
+    ) {
+        let discarded = C::screen_offspring(population, trials, params, penalties);
+
+        for (individual, discarded) in population.iter_mut().zip(discarded) {
+            if needs_evaluation::<C>(individual, discarded, params.evaluation_strategy) {
+                C::eval_individual_penalized(
+                    individual,
+                    trials,
+                    params.default_fitness,
+                    params.fitness_mode,
+                    params.risk_aversion,
+                    params.objective,
+                    penalties,
+                );
+            }
+        }
+    }
+}
+
+impl<C> PopulationEvaluator<C> for ParallelEvaluation
+where
+    C: Core,
+    C::State: Clone + Send,
+    HyperParameters<C>: Sync,
+{
+    fn eval_population(
+        &self,
+        population: &mut Vec<C::Individual>,
+        trials: &mut Vec<C::State>,
+        params: &HyperParameters<C>,
+        penalties: &[Penalty<C::Individual, C::State>],
+        pool: &mut TrialPool<C::State>,
+    ) {
+        let discarded = C::screen_offspring(population, trials, params, penalties);
+
+        let chunk_size = population
+            .len()
+            .div_ceil(rayon::current_num_threads())
+            .max(1);
+        let n_chunks = population.len().div_ceil(chunk_size);
+        let seeds = (0..n_chunks)
+            .map(|_| generator().gen::<u64>())
+            .collect_vec();
+        let leases = pool.lease(trials, n_chunks);
+        let noise = current_noise();
+
+        let (leases, counters): (Vec<_>, Vec<_>) = population
+            .par_chunks_mut(chunk_size)
+            .zip(discarded.par_chunks(chunk_size))
+            .zip(leases.into_par_iter().zip(seeds))
+            .map(|((individuals, discarded), (mut trials, seed))| {
+                with_seed(seed, || {
+                    with_noise(noise, || {
+                        // Keeps what the thread recorded before, e.g. when it is the calling one.
+                        let previous = take_thread_counters();
+
+                        for (individual, discarded) in individuals.iter_mut().zip(discarded) {
+                            if needs_evaluation::<C>(
+                                individual,
+                                *discarded,
+                                params.evaluation_strategy,
+                            ) {
+                                C::eval_individual_penalized(
+                                    individual,
+                                    &mut trials,
+                                    params.default_fitness,
+                                    params.fitness_mode,
+                                    params.risk_aversion,
+                                    params.objective,
+                                    penalties,
+                                );
+                            }
+                        }
+
+                        let counters = take_thread_counters();
+                        add_thread_counters(&previous);
+                        (trials, counters)
+                    })
+                })
+            })
+            .unzip();
+
+        pool.give_back(leases);
+        for counters in &counters {
+            add_thread_counters(counters);
+        }
+    }
+}
+
+/// Evaluates `population` with the evaluator `params.evaluator` selects, for the
+/// [`Core::evaluate`] of problems whose states can be sent to other threads.
+pub fn evaluate_with<C>(
+    population: &mut Vec<C::Individual>,
+    trials: &mut Vec<C::State>,
+    params: &HyperParameters<C>,
+    penalties: &[Penalty<C::Individual, C::State>],
+    pool: &mut TrialPool<C::State>,
+) where
+    C: Core,
+    C::State: Clone + Send,
+    HyperParameters<C>: Sync,
+{
+    match params.evaluator {
+        Evaluator::Sequential => {
+            SequentialEvaluation.eval_population(population, trials, params, penalties, pool)
+        }
+        Evaluator::Parallel => {
+            ParallelEvaluation.eval_population(population, trials, params, penalties, pool)
+        }
+    }
+}
+
+/// How the scores of an individual's trials are combined into its fitness.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ValueEnum)]
+pub enum FitnessMode {
+    /// The mean score (e.g. cumulative reward) over all trials.
+    #[default]
+    CumulativeReward,
+    /// Ranks by the number of trials which met their success criterion, individuals solving as many
+    /// trials being ranked by mean score, squashed into `[0, 1)` so it never outweighs an additional
+    /// success. The fitness is `(n_successes + squashed score) / (n_trials + 1)`, within `[0, 1)`.
+    SuccessRate,
+    /// The mean score minus `risk_aversion` standard deviations of the scores (plus, when minimizing),
+    /// so consistent individuals are preferred over ones with a few lucky trials.
+    RiskAdjusted,
+}
+
+impl FitnessMode {
+    /// Combines `(score, succeeded)` pairs; scores must already be finite.
+    pub fn aggregate(self, trials: &[(f64, bool)]) -> f64 {
+        self.aggregate_with(trials, 1.)
+    }
+
+    /// Same as [`FitnessMode::aggregate`], weighing the standard deviation of
+    /// [`FitnessMode::RiskAdjusted`] by `risk_aversion`.
+    pub fn aggregate_with(self, trials: &[(f64, bool)], risk_aversion: f64) -> f64 {
+        let n_trials = trials.len() as f64;
+        let mean_score = trials.iter().map(|(score, _)| score).sum::<f64>() / n_trials;
+
+        match self {
+            FitnessMode::CumulativeReward => mean_score,
+            FitnessMode::SuccessRate => {
+                let n_successes = trials.iter().filter(|(_, succeeded)| *succeeded).count() as f64;
+                let tie_break =
+                    (0.5 + 0.5 * mean_score / (1. + mean_score.abs())).clamp(0., 1. - f64::EPSILON);
+
+                (n_successes + tie_break) / (n_trials + 1.)
+            }
+            FitnessMode::RiskAdjusted => {
+                mean_score - risk_aversion * FitnessMetadata::new(trials, 0).std()
+            }
+        }
+    }
+}
+
+/// A cost of how an individual behaved on a trial (e.g. how often it switched actions or how large its
+/// registers grew), scaled by `weight` and combined with the fitness it scored on the trial.
+pub struct Penalty<I, S> {
+    /// Key of the penalty in telemetry.
+    pub name: String,
+    pub weight: f64,
+    pub measure: Box<dyn Fn(&I, &S) -> f64 + Send + Sync>,
+}
+
+/// How a fitness was measured. Kept alongside the fitness without taking part in comparisons.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+pub struct FitnessMetadata {
+    pub n_trials: usize,
+    /// Variance of the trial scores, non-finite scores counting as `default_fitness`.
+    pub variance: f64,
+    /// Environment steps taken over every trial.
+    pub environment_steps: usize,
+    pub n_successes: usize,
+    /// Mean constraint violation over the trials, 0 when every trial was feasible.
+    #[serde(default)]
+    pub violation: f64,
+    /// Mean weighted penalty over the trials, already taken off the fitness.
+    #[serde(default)]
+    pub penalty: f64,
+}
+
+impl FitnessMetadata {
+    /// Summarizes `(score, succeeded)` pairs; scores must already be finite.
+    pub fn new(trials: &[(f64, bool)], environment_steps: usize) -> Self {
+        let n_trials = trials.len();
+        let mean = trials.iter().map(|(score, _)| score).sum::<f64>() / n_trials as f64;
+        let variance = trials
+            .iter()
+            .map(|(score, _)| (score - mean).powi(2))
+            .sum::<f64>()
+            / n_trials as f64;
+
+        FitnessMetadata {
+            n_trials,
+            variance,
+            environment_steps,
+            n_successes: trials.iter().filter(|(_, succeeded)| *succeeded).count(),
+            violation: 0.,
+            penalty: 0.,
+        }
+    }
+
+    pub fn std(&self) -> f64 {
+        self.variance.sqrt()
+    }
+}
+
+/// A fitness with a total order which is the same on every platform: `NaN`s, whatever their sign or
+/// payload, are equal to each other and below every number, and `-0` equals `0`.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct OrderedFitness(pub f64);
+
+impl PartialEq for OrderedFitness {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for OrderedFitness {}
+
+impl Ord for OrderedFitness {
+    fn cmp(&self, other: &Self) -> Ordering {
+        match (self.0.is_nan(), other.0.is_nan()) {
+            (true, true) => Ordering::Equal,
+            (true, false) => Ordering::Less,
+            (false, true) => Ordering::Greater,
+            (false, false) => self.0.partial_cmp(&other.0).unwrap(),
+        }
+    }
+}
+
+impl PartialOrd for OrderedFitness {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+/// How individuals are ordered before survival.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ValueEnum)]
+pub enum Ranking {
+    /// By fitness alone.
+    #[default]
+    Fitness,
+    /// Stochastic ranking (Runarsson & Yao): neighbours are compared by fitness when both are
+    /// feasible or with probability `fitness_comparison_probability`, by constraint violation
+    /// otherwise.
+    Stochastic,
+}
+
+/// Whether larger or smaller fitness values are better.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ValueEnum)]
+pub enum Objective {
+    #[default]
+    Maximize,
+    /// Fitness is a cost; `default_fitness` should then be a large value.
+    Minimize,
+}
+
+impl Objective {
+    /// Orders `a` relative to `b`, `Ordering::Greater` meaning `a` is the better fitness. `NaN` is
+    /// worse than any number under either objective.
+    pub fn compare(self, a: f64, b: f64) -> Ordering {
+        match (self, a.is_nan() || b.is_nan()) {
+            (Objective::Maximize, _) | (Objective::Minimize, true) => {
+                OrderedFitness(a).cmp(&OrderedFitness(b))
+            }
+            (Objective::Minimize, false) => OrderedFitness(b).cmp(&OrderedFitness(a)),
+        }
+    }
+
+    pub fn is_better(self, a: f64, b: f64) -> bool {
+        self.compare(a, b) == Ordering::Greater
+    }
+
+    /// How much better `a` is than `b` (negative when worse).
+    pub fn improvement(self, a: f64, b: f64) -> f64 {
+        match self {
+            Objective::Maximize => a - b,
+            Objective::Minimize => b - a,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::engines::{
+        generate_engine::{Generate, GenerateEngine},
+        status_engine::StatusEngine,
+    };
+    use crate::extensions::regression::{RegressionEngine, RegressionInput};
+    use crate::problems::{problem::Problem, symbolic::Koza1};
+    use crate::utils::{random::update_seed, telemetry::ThreadCounters};
+
+    type Regression = RegressionEngine<Koza1>;
+
+    #[test]
+    fn given_parallel_evaluator_when_population_is_evaluated_then_it_matches_sequential_evaluation()
+    {
+        let mut params = Regression::default_hyper_parameters();
+        Regression::build_fitness_parameters(&mut params);
+        params.population_size = 30;
+
+        update_seed(Some(5));
+        let population =
+            Regression::init_population(params.program_parameters, params.population_size);
+        let trials: Vec<RegressionInput<Koza1>> =
+            (0..2).map(|_| GenerateEngine::generate(())).collect_vec();
+
+        let mut pool = TrialPool::default();
+        let mut evaluate = |evaluator| -> (Vec<f64>, ThreadCounters) {
+            let mut population = population.clone();
+            let mut trials = trials.clone();
+
+            take_thread_counters();
+            Regression::evaluate(
+                &mut population,
+                &mut trials,
+                &HyperParameters {
+                    evaluator,
+                    ..params
+                },
+                &[],
+                &mut pool,
+            );
+            assert!(population.iter().all(StatusEngine::evaluated));
+
+            (
+                population.iter().map(StatusEngine::get_fitness).collect(),
+                take_thread_counters(),
+            )
+        };
+
+        let (sequential, sequential_counters) = evaluate(Evaluator::Sequential);
+        let (parallel, parallel_counters) = evaluate(Evaluator::Parallel);
+
+        assert_eq!(sequential, parallel);
+        assert_eq!(sequential_counters, parallel_counters);
+        assert!(parallel_counters.environment_steps > 0);
+
+        // The copies of the trials are kept for the next generation, one per thread at most.
+        assert!(!pool.is_empty() && pool.len() <= rayon::current_num_threads());
+    }
+
+    #[test]
+    fn given_objectives_when_fitnesses_are_compared_then_direction_is_honored() {
+        assert!(Objective::Maximize.is_better(2., 1.));
+        assert!(Objective::Minimize.is_better(1., 2.));
+        assert!(!Objective::Minimize.is_better(1., 1.));
+        assert_eq!(Objective::Minimize.improvement(1., 3.), 2.);
+    }
+
+    #[test]
+    fn given_special_values_when_ordered_then_order_is_total_and_sign_agnostic() {
+        let negative_nan = -f64::NAN;
+
+        assert_eq!(OrderedFitness(f64::NAN), OrderedFitness(negative_nan));
+        assert!(OrderedFitness(negative_nan) < OrderedFitness(f64::NEG_INFINITY));
+        assert_eq!(OrderedFitness(-0.), OrderedFitness(0.));
+        assert!(OrderedFitness(1.) < OrderedFitness(f64::INFINITY));
+
+        assert!(Objective::Minimize.is_better(f64::INFINITY, f64::NAN));
+        assert!(Objective::Minimize.is_better(f64::INFINITY, negative_nan));
+        assert!(Objective::Maximize.is_better(f64::NEG_INFINITY, negative_nan));
+    }
+
+    #[test]
+    fn given_trials_when_summarized_then_metadata_describes_them() {
+        let metadata = FitnessMetadata::new(&[(1., true), (3., false)], 7);
+
+        assert_eq!(metadata.n_trials, 2);
+        assert_eq!(metadata.variance, 1.);
+        assert_eq!(metadata.std(), 1.);
+        assert_eq!(metadata.environment_steps, 7);
+        assert_eq!(metadata.n_successes, 1);
+    }
+
+    #[test]
+    fn given_success_rate_mode_when_aggregated_then_successes_outweigh_rewards() {
+        let one_success = FitnessMode::SuccessRate.aggregate(&[(-1e6, true), (-1e6, false)]);
+        let no_success = FitnessMode::SuccessRate.aggregate(&[(1e6, false), (1e6, false)]);
+        let better_reward = FitnessMode::SuccessRate.aggregate(&[(0., true), (0., false)]);
+
+        let all_successes = FitnessMode::SuccessRate.aggregate(&[(1e6, true), (1e6, true)]);
+
+        assert!(one_success > no_success);
+        assert!(better_reward > one_success);
+        assert_eq!(better_reward, 0.5);
+        assert!(all_successes > better_reward && all_successes < 1.);
+        assert_eq!(
+            FitnessMode::CumulativeReward.aggregate(&[(1., true), (3., false)]),
+            2.
+        );
+    }
+
+    #[test]
+    fn given_risk_adjusted_mode_when_aggregated_then_consistent_scores_beat_lucky_ones() {
+        let consistent = FitnessMode::RiskAdjusted.aggregate(&[(2., false), (2., false)]);
+        let lucky = FitnessMode::RiskAdjusted.aggregate(&[(0., false), (5., false)]);
+
+        assert_eq!(consistent, 2.);
+        assert_eq!(lucky, 0.);
+        assert_eq!(
+            FitnessMode::RiskAdjusted.aggregate_with(&[(1., false), (3., false)], 0.5),
+            1.5
+        );
+        assert_eq!(
+            FitnessMode::RiskAdjusted.aggregate_with(&[(0., false), (5., false)], 0.),
+            FitnessMode::CumulativeReward.aggregate(&[(0., false), (5., false)])
+        );
+    }
+}
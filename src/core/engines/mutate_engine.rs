@@ -1,5 +1,73 @@
+use clap::Args;
+use derive_builder::Builder;
+use serde::{Deserialize, Serialize};
+
 pub struct MutateEngine;
 
 pub trait Mutate<F, I> {
     fn mutate(item: &mut I, using: F);
 }
+
+#[derive(Clone, Copy, Debug, Args, Serialize, Deserialize, PartialEq, Builder)]
+pub struct MutationParameters {
+    /// Probability of each instruction being mutated.
+    /// When no instruction is picked, a single random instruction is mutated instead.
+    #[arg(long, default_value = "0.")]
+    #[builder(default = "0.")]
+    pub instruction_mutation_rate: f64,
+    /// Probability of replacing the second operand (register or input) of a mutated instruction.
+    #[arg(long, default_value = "0.5")]
+    #[builder(default = "0.5")]
+    pub target_mutation_rate: f64,
+    /// Probability of replacing the destination and first source registers of a mutated instruction.
+    #[arg(long, default_value = "0.5")]
+    #[builder(default = "0.5")]
+    pub source_mutation_rate: f64,
+    /// Probability of replacing the operation of a mutated instruction.
+    #[arg(long, default_value = "0.5")]
+    #[builder(default = "0.5")]
+    pub op_mutation_rate: f64,
+    /// Probability of inserting a block of new instructions.
+    #[arg(long, default_value = "0.")]
+    #[builder(default = "0.")]
+    pub insertion_rate: f64,
+    /// Probability of deleting a block of instructions.
+    #[arg(long, default_value = "0.")]
+    #[builder(default = "0.")]
+    pub deletion_rate: f64,
+    /// Largest block inserted or deleted by a single macro-mutation.
+    #[arg(long, default_value = "1")]
+    #[builder(default = "1")]
+    pub max_block_size: usize,
+    /// Probability of flipping each gene of a program's input mask. Programs only carry (and evolve) an
+    /// input mask, disabling the inputs they should ignore, when this is positive.
+    #[arg(long, default_value = "0.")]
+    #[builder(default = "0.")]
+    #[serde(default)]
+    pub input_mask_rate: f64,
+    /// Standard deviation of the Gaussian nudge given to a mutated immediate operand.
+    #[arg(long, default_value = "1.")]
+    #[builder(default = "1.")]
+    #[serde(default = "default_immediate_step")]
+    pub immediate_step: f64,
+}
+
+fn default_immediate_step() -> f64 {
+    1.
+}
+
+impl Default for MutationParameters {
+    fn default() -> Self {
+        Self {
+            instruction_mutation_rate: 0.,
+            target_mutation_rate: 0.5,
+            source_mutation_rate: 0.5,
+            op_mutation_rate: 0.5,
+            insertion_rate: 0.,
+            deletion_rate: 0.,
+            max_block_size: 1,
+            input_mask_rate: 0.,
+            immediate_step: default_immediate_step(),
+        }
+    }
+}
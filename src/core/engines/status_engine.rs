@@ -1,3 +1,5 @@
+use super::fitness_engine::FitnessMetadata;
+
 pub struct StatusEngine;
 
 pub trait Status<T> {
@@ -5,4 +7,26 @@ pub trait Status<T> {
     fn evaluated(item: &T) -> bool;
     fn set_fitness(program: &mut T, fitness: f64);
     fn get_fitness(program: &T) -> f64;
+
+    /// Records how the current fitness of `item` was measured; individuals which do not keep metadata
+    /// ignore it.
+    fn set_metadata(_item: &mut T, _metadata: FitnessMetadata) {}
+
+    fn get_metadata(_item: &T) -> Option<FitnessMetadata> {
+        None
+    }
+
+    /// Number of generations `item` survived; individuals which do not keep an age are always new.
+    fn get_age(_item: &T) -> usize {
+        0
+    }
+
+    fn set_age(_item: &mut T, _age: usize) {}
+
+    /// Identifies individuals which always score the same on the same trials, for
+    /// [`EvaluationStrategy::Keyed`](super::fitness_engine::EvaluationStrategy::Keyed) and evaluation
+    /// ledgers; `None` for individuals which cannot be cached (e.g. learning while evaluated).
+    fn genome_hash(_item: &T) -> Option<u64> {
+        None
+    }
 }
@@ -1,3 +1,21 @@
+use std::{
+    cell::Cell,
+    collections::{hash_map::DefaultHasher, BTreeMap},
+    error::Error,
+    fmt::Debug,
+    hash::{Hash, Hasher},
+};
+
+use clap::{Args, ValueEnum};
+use derive_builder::Builder;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    core::instruction::InstructionGeneratorParameters,
+    utils::random::{generator, standard_normal},
+};
+
 /// Defines a single state which can use the current context to get the next data.
 pub trait State: Sized {
     fn get_value(&self, at_idx: usize) -> f64;
@@ -8,6 +26,67 @@ pub trait State: Sized {
 
     /// We take a mutable reference and return self.
     fn get(&mut self) -> Option<&mut Self>;
+
+    /// Whether the last evaluation met the success criterion of the task (e.g. an RL episode reached
+    /// the goal). Used by [`FitnessMode::SuccessRate`](crate::core::engines::fitness_engine::FitnessMode).
+    fn succeeded(&self) -> bool {
+        false
+    }
+
+    /// How much the current sample counts towards a classification fitness, e.g. to make up for
+    /// imbalanced classes.
+    fn sample_weight(&self) -> f64 {
+        1.
+    }
+
+    /// How far the last evaluation strayed outside the constraints of the task (e.g. a limit on
+    /// control effort), 0 when it stayed within them. Only used by stochastic ranking.
+    fn constraint_violation(&self) -> f64 {
+        0.
+    }
+}
+
+/// Where an episode stands after a step.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum EpisodeStatus {
+    #[default]
+    Running,
+    /// The environment reached a terminal state, nothing follows.
+    Terminated,
+    /// The episode was cut short (e.g. by a step limit), the state it ended in still has a future.
+    Truncated,
+}
+
+/// Additional feedback of an environment, passed through untouched (e.g. gym's `info` dict).
+pub type StepInfo = BTreeMap<String, serde_json::Value>;
+
+/// The outcome of acting on an [`RlState`].
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct StepResult {
+    pub reward: f64,
+    pub status: EpisodeStatus,
+    pub info: StepInfo,
+}
+
+impl StepResult {
+    pub fn is_done(&self) -> bool {
+        self.status != EpisodeStatus::Running
+    }
+
+    pub fn terminated(&self) -> bool {
+        self.status == EpisodeStatus::Terminated
+    }
+
+    pub fn truncated(&self) -> bool {
+        self.status == EpisodeStatus::Truncated
+    }
+
+    /// Folds a later step into this one: rewards add up, the status and info of `next` win.
+    pub fn accumulate(&mut self, next: StepResult) {
+        self.reward += next.reward;
+        self.status = next.status;
+        self.info.extend(next.info);
+    }
 }
 
 pub trait RlState: State {
@@ -16,4 +95,272 @@ pub trait RlState: State {
 
     // Returns the initial state.
     fn get_initial_state(&self) -> Vec<f64>;
+
+    /// Whether the episode ended because it was cut short rather than by reaching a terminal state.
+    fn is_truncated(&self) -> bool {
+        false
+    }
+
+    /// Feedback of the last step on top of its reward.
+    fn info(&self) -> StepInfo {
+        StepInfo::new()
+    }
+
+    /// Executes `action`, reporting its reward along with how the episode stands and any info.
+    fn step(&mut self, action: usize) -> StepResult {
+        let reward = self.execute_action(action);
+
+        let status = match (self.is_terminal(), self.is_truncated()) {
+            (false, _) => EpisodeStatus::Running,
+            (true, true) => EpisodeStatus::Truncated,
+            (true, false) => EpisodeStatus::Terminated,
+        };
+
+        StepResult {
+            reward,
+            status,
+            info: self.info(),
+        }
+    }
+}
+
+/// When [`NoiseParameters`] apply.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ValueEnum)]
+pub enum NoisePhase {
+    /// While evolving, i.e. to the trials the population is evaluated on.
+    Training,
+    /// While testing the evolved programs, e.g. in a robustness report.
+    Test,
+    #[default]
+    Both,
+}
+
+/// Perturbations of RL evaluations, to measure how robust a policy is to faulty sensors and
+/// actuators.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize, Args, Builder)]
+pub struct NoiseParameters {
+    /// Standard deviation of the gaussian noise added to every observation programs read.
+    #[builder(default = "0.")]
+    #[arg(long, default_value = "0")]
+    #[serde(default)]
+    pub observation_noise: f64,
+    /// Probability the action selected on each step is replaced by a uniformly random one.
+    #[builder(default = "0.")]
+    #[arg(long, default_value = "0")]
+    #[serde(default)]
+    pub action_noise: f64,
+    #[builder(default = "NoisePhase::Both")]
+    #[arg(long, value_enum, default_value_t = NoisePhase::Both)]
+    #[serde(default)]
+    pub noise_phase: NoisePhase,
+}
+
+impl NoiseParameters {
+    pub const CLEAN: NoiseParameters = NoiseParameters {
+        observation_noise: 0.,
+        action_noise: 0.,
+        noise_phase: NoisePhase::Both,
+    };
+
+    pub fn is_clean(&self) -> bool {
+        self.observation_noise == 0. && self.action_noise == 0.
+    }
+
+    /// The noise to evolve with.
+    pub fn training(&self) -> NoiseParameters {
+        match self.noise_phase {
+            NoisePhase::Training | NoisePhase::Both => *self,
+            NoisePhase::Test => Self::CLEAN,
+        }
+    }
+
+    /// The noise to test evolved programs with.
+    pub fn test(&self) -> NoiseParameters {
+        match self.noise_phase {
+            NoisePhase::Test | NoisePhase::Both => *self,
+            NoisePhase::Training => Self::CLEAN,
+        }
+    }
+}
+
+impl Default for NoiseParameters {
+    fn default() -> Self {
+        Self::CLEAN
+    }
+}
+
+thread_local! {
+    static NOISE: Cell<NoiseParameters> = Cell::new(NoiseParameters::CLEAN);
+}
+
+/// Applies `noise` to the RL evaluations `f` makes on this thread (see [`observe`] and
+/// [`perturb_action`]).
+pub fn with_noise<T>(noise: NoiseParameters, f: impl FnOnce() -> T) -> T {
+    let previous = NOISE.with(|current| current.replace(noise));
+    let result = f();
+    NOISE.with(|current| current.set(previous));
+
+    result
+}
+
+/// The noise in effect on this thread, e.g. to apply it to work handed to other threads.
+pub fn current_noise() -> NoiseParameters {
+    NOISE.with(|current| current.get())
+}
+
+/// What programs read of an [`RlState`]: the state itself, or a noisy copy of its observation.
+pub enum Observed<'a, T> {
+    Clean(&'a T),
+    Noisy(Vec<f64>),
+}
+
+impl<T> State for Observed<'_, T>
+where
+    T: State,
+{
+    fn get_value(&self, at_idx: usize) -> f64 {
+        match self {
+            Observed::Clean(state) => state.get_value(at_idx),
+            Observed::Noisy(observation) => observation[at_idx],
+        }
+    }
+
+    /// Observations are read only, actions are executed against the state they were taken from.
+    fn execute_action(&mut self, _action: usize) -> f64 {
+        0.
+    }
+
+    fn get(&mut self) -> Option<&mut Self> {
+        Some(self)
+    }
+}
+
+/// The observation of `state` through the observation noise in effect on this thread, drawn once so
+/// every read of a step sees the same values. Draws nothing without observation noise.
+pub fn observe<T>(state: &T) -> Observed<'_, T>
+where
+    T: RlState,
+{
+    let std = NOISE.with(|noise| noise.get().observation_noise);
+
+    if std == 0. {
+        return Observed::Clean(state);
+    }
+
+    Observed::Noisy(
+        (0..state.get_initial_state().len())
+            .map(|idx| state.get_value(idx) + std * standard_normal())
+            .collect(),
+    )
+}
+
+/// `action`, replaced by one of the `n_actions` actions drawn uniformly at random with the
+/// probability of the action noise in effect on this thread. Draws nothing without action noise.
+pub fn perturb_action(action: usize, n_actions: usize) -> usize {
+    let probability = NOISE.with(|noise| noise.get().action_noise);
+
+    if probability == 0. || n_actions == 0 || generator().gen::<f64>() >= probability {
+        return action;
+    }
+
+    generator().gen_range(0..n_actions)
+}
+
+/// What a state shows programs and what they can do about it, known at runtime so parameters built
+/// at runtime (e.g. from a loaded dataset) can be checked against it.
+pub trait SpaceInfo {
+    /// Number of values programs observe.
+    fn n_inputs(&self) -> usize;
+
+    /// Number of actions (or classes) programs choose from.
+    fn n_actions(&self) -> usize;
+
+    /// `(low, high)` for every input, infinite where the input is unbounded.
+    fn input_bounds(&self) -> Vec<(f64, f64)> {
+        vec![(f64::NEG_INFINITY, f64::INFINITY); self.n_inputs()]
+    }
+
+    /// Fails if programs generated with `parameters` would read or act outside of the space.
+    fn check(&self, parameters: &InstructionGeneratorParameters) -> Result<(), Box<dyn Error>> {
+        if parameters.n_inputs != self.n_inputs() {
+            return Err(format!(
+                "Programs read {} inputs but the environment has {}.",
+                parameters.n_inputs,
+                self.n_inputs()
+            )
+            .into());
+        }
+
+        if parameters.n_actions != self.n_actions() {
+            return Err(format!(
+                "Programs choose from {} actions but the environment has {}.",
+                parameters.n_actions,
+                self.n_actions()
+            )
+            .into());
+        }
+
+        Ok(())
+    }
+}
+
+/// The `(low, high)` range of every column of `rows`, all of the same length.
+pub fn column_bounds(rows: &[Vec<f64>]) -> Vec<(f64, f64)> {
+    let n_columns = rows.first().map_or(0, Vec::len);
+
+    (0..n_columns)
+        .map(|column| {
+            rows.iter()
+                .fold((f64::INFINITY, f64::NEG_INFINITY), |(low, high), row| {
+                    (low.min(row[column]), high.max(row[column]))
+                })
+        })
+        .collect()
+}
+
+/// Lets a state change between generations, e.g. to rotate through a list of initial states so programs
+/// are not evaluated on the same episodes for the whole run.
+///
+/// Called on every trial by [`CoreIter`](crate::core::engines::core_engine::CoreIter) before each
+/// generation is evaluated.
+pub trait GenerationAware {
+    fn on_generation(&mut self, _generation: usize) {}
+}
+
+/// Spells out how long each part of a trial lives, so state cannot leak from one individual to the
+/// next unnoticed:
+///
+/// - per evaluation: what an evaluation changes (e.g. the sample index, the episode being played),
+///   restored by [`Reset`](crate::core::engines::reset_engine::Reset) before every evaluation;
+/// - per generation: what only [`GenerationAware::on_generation`] changes (e.g. the initial state of
+///   the episodes, the samples of a stream);
+/// - per run: what never changes once the trial is built (e.g. the samples of a dataset).
+///
+/// Debug builds of [`Core`](crate::core::engines::core_engine::Core) check every evaluation starts
+/// from [`FreshState::fresh`] and leaves the other two parts untouched, and that generations leave the
+/// per-run part untouched.
+pub trait FreshState {
+    type PerEvaluation: PartialEq + Debug;
+    type PerGeneration: PartialEq + Debug;
+    type PerRun: PartialEq + Debug;
+
+    fn per_evaluation(&self) -> Self::PerEvaluation;
+
+    /// What [`FreshState::per_evaluation`] must be at the start of an evaluation, given the rest of
+    /// the state.
+    fn fresh(&self) -> Self::PerEvaluation;
+
+    fn per_generation(&self) -> Self::PerGeneration;
+
+    fn per_run(&self) -> Self::PerRun;
+
+    /// Hash of the per-generation state: as the per-run state never changes once the trial is built,
+    /// a trial hashing the same starts every evaluation from the same states. Trials replaced by
+    /// others (e.g. another dataset) are not told apart, their owner has to track that.
+    fn regime_hash(&self) -> u64 {
+        let mut hasher = DefaultHasher::new();
+        format!("{:?}", self.per_generation()).hash(&mut hasher);
+
+        hasher.finish()
+    }
 }
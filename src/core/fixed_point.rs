@@ -0,0 +1,501 @@
+//! Fixed-point execution of programs, for targets without a floating point unit such as
+//! microcontrollers.
+//!
+//! Every value is an `i32` scaled by `2^fractional_bits`: additions and subtractions act on the raw
+//! values, multiplications rescale their 64-bit product and halvings are arithmetic shifts. Compiled
+//! programs run here, to check they still behave like the floating point program they come from, and
+//! are exported as C source (see [`FixedPointProgram::to_c`]).
+use std::{error::Error, fmt::Write};
+
+use clap::{Args, ValueEnum};
+use derive_builder::Builder;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use super::{
+    batch::Row,
+    engines::reset_engine::{Reset, ResetEngine},
+    environment::State,
+    instruction::{Instruction, Op, Operand},
+    program::Program,
+    registers::{Readout, TieBreak},
+    simplify::live_instruction_indices,
+};
+
+/// What happens to a result which does not fit in an `i32`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ValueEnum)]
+pub enum OverflowPolicy {
+    /// Results become the closest representable value.
+    #[default]
+    Saturate,
+    /// Results wrap around, as plain integer arithmetic does on most targets.
+    Wrap,
+}
+
+fn default_fractional_bits() -> u32 {
+    16
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, Args, Builder)]
+pub struct FixedPointParameters {
+    /// Bits of the fractional part of every value, e.g. `16` for values in `[-32768, 32768)` with a
+    /// resolution of `2^-16`.
+    #[builder(default = "16")]
+    #[arg(long, default_value = "16")]
+    #[serde(default = "default_fractional_bits")]
+    pub fractional_bits: u32,
+    #[builder(default = "OverflowPolicy::Saturate")]
+    #[arg(long, value_enum, default_value_t = OverflowPolicy::Saturate)]
+    #[serde(default)]
+    pub overflow_policy: OverflowPolicy,
+    /// Rejects programs which multiply, input factors other than `1` included, for targets without a
+    /// hardware multiplier. Evolve such programs without `Mult` through the operator weights.
+    #[builder(default = "false")]
+    #[arg(long)]
+    #[serde(default)]
+    pub no_multiplication: bool,
+}
+
+impl Default for FixedPointParameters {
+    fn default() -> Self {
+        FixedPointParametersBuilder::default().build().unwrap()
+    }
+}
+
+impl FixedPointParameters {
+    /// The raw value of `1`.
+    pub fn one(&self) -> f64 {
+        (1u64 << self.fractional_bits) as f64
+    }
+
+    /// The closest raw value to `value`, `None` when it is out of range.
+    pub fn to_fixed(&self, value: f64) -> Option<i32> {
+        let raw = (value * self.one()).round();
+
+        Some(raw as i32).filter(|_| raw >= i32::MIN as f64 && raw <= i32::MAX as f64)
+    }
+
+    /// The closest raw value to `value`, saturating out of range values and reading `NaN` as `0`.
+    pub fn quantize(&self, value: f64) -> i32 {
+        (value * self.one()).round() as i32
+    }
+
+    pub fn to_float(&self, raw: i32) -> f64 {
+        raw as f64 / self.one()
+    }
+
+    fn narrow(&self, value: i64) -> i32 {
+        match self.overflow_policy {
+            OverflowPolicy::Saturate => value.clamp(i32::MIN as i64, i32::MAX as i64) as i32,
+            OverflowPolicy::Wrap => value as i32,
+        }
+    }
+
+    pub fn apply(&self, op: Op, a: i32, b: i32) -> i32 {
+        match op {
+            Op::Add => self.narrow(a as i64 + b as i64),
+            Op::Sub => self.narrow(a as i64 - b as i64),
+            Op::Mult => self.narrow((a as i64 * b as i64) >> self.fractional_bits),
+            Op::Divide => a >> 1,
+            Op::Load => b,
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub enum FixedOperand {
+    Register(usize),
+    /// The input, multiplied by `factor` unless it is `None` (a factor of `1`).
+    Input {
+        input: usize,
+        factor: Option<i32>,
+    },
+    Immediate(i32),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct FixedInstruction {
+    pub op: Op,
+    pub dest: usize,
+    pub src1: usize,
+    pub src2: FixedOperand,
+}
+
+fn compile_instruction(
+    instruction: &Instruction,
+    parameters: FixedPointParameters,
+) -> Result<FixedInstruction, String> {
+    let constant = |value: f64| {
+        parameters.to_fixed(value).ok_or_else(|| {
+            format!(
+                "`{}`: {} is out of the fixed-point range.",
+                instruction, value
+            )
+        })
+    };
+
+    let src2 = match instruction.src2() {
+        Operand::Register(register) => FixedOperand::Register(register),
+        Operand::Immediate(value) => FixedOperand::Immediate(constant(value)?),
+        // Masked inputs (see `Instruction::masked`) read as `0`.
+        Operand::Input(_) if instruction.external_factor() == 0. => FixedOperand::Immediate(0),
+        Operand::Input(input) => match instruction.external_factor() {
+            factor if factor == 1. => FixedOperand::Input {
+                input,
+                factor: None,
+            },
+            factor => FixedOperand::Input {
+                input,
+                factor: Some(constant(factor)?),
+            },
+        },
+    };
+
+    // Halvings never read their operand.
+    let multiplies = match (instruction.op(), src2) {
+        (Op::Mult, _) => true,
+        (Op::Divide, _) => false,
+        (_, operand) => matches!(
+            operand,
+            FixedOperand::Input {
+                factor: Some(_),
+                ..
+            }
+        ),
+    };
+    if parameters.no_multiplication && multiplies {
+        return Err(format!("`{}` multiplies.", instruction));
+    }
+
+    Ok(FixedInstruction {
+        op: instruction.op(),
+        dest: instruction.dest(),
+        src1: instruction.src1(),
+        src2,
+    })
+}
+
+/// A program compiled to fixed-point, along with the registers it carries over between executions.
+#[derive(Clone, Debug, PartialEq, Serialize, Deserialize)]
+pub struct FixedPointProgram {
+    pub parameters: FixedPointParameters,
+    instructions: Vec<FixedInstruction>,
+    registers: Vec<i32>,
+    n_inputs: usize,
+    n_actions: usize,
+}
+
+impl FixedPointProgram {
+    /// Compiles the instructions of `program` which can influence its actions (see
+    /// [`live_instruction_indices`]).
+    ///
+    /// Fails when the parameters leave no integer bits, when the program reads its actions through a
+    /// readout other than [`Readout::Direct`], when a constant is out of range, or when the program
+    /// multiplies despite [`FixedPointParameters::no_multiplication`], or when the program is longer
+    /// than its instruction budget. Inputs masked out by the program read as `0`. The numeric policy of
+    /// the program is replaced by the overflow policy.
+    pub fn compile(
+        program: &Program,
+        parameters: FixedPointParameters,
+    ) -> Result<FixedPointProgram, Box<dyn Error>> {
+        if parameters.fractional_bits > 30 {
+            return Err(format!(
+                "{} fractional bits leave no integer bits in an i32.",
+                parameters.fractional_bits
+            )
+            .into());
+        }
+
+        if program.registers.readout() != Readout::Direct {
+            return Err("Only programs reading their actions directly can be compiled.".into());
+        }
+
+        if let Some(budget) = program.instruction_budget {
+            if program.instructions.len() > budget {
+                return Err(format!(
+                    "The program runs {} instructions, over its budget of {}.",
+                    program.instructions.len(),
+                    budget
+                )
+                .into());
+            }
+        }
+
+        let input_mask = program.input_mask.as_deref().unwrap_or(&[]);
+        let instructions =
+            live_instruction_indices(&program.instructions, &program.output_registers())
+                .into_iter()
+                .map(|idx| match program.instructions[idx] {
+                    instruction if instruction.reads_masked_input(input_mask) => {
+                        compile_instruction(&instruction.masked(), parameters)
+                    }
+                    instruction => compile_instruction(&instruction, parameters),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(FixedPointProgram {
+            parameters,
+            instructions,
+            registers: vec![0; program.registers.len()],
+            n_inputs: program.n_inputs_read(),
+            n_actions: program.registers.n_actions(),
+        })
+    }
+
+    pub fn instructions(&self) -> &[FixedInstruction] {
+        &self.instructions
+    }
+
+    pub fn reset(&mut self) {
+        self.registers.fill(0);
+    }
+
+    /// Executes the instructions over raw inputs.
+    pub fn exec(&mut self, inputs: &[i32]) {
+        let parameters = self.parameters;
+
+        for instruction in &self.instructions {
+            let operand = match instruction.src2 {
+                FixedOperand::Register(register) => self.registers[register],
+                FixedOperand::Input { input, factor } => match factor {
+                    Some(factor) => parameters.apply(Op::Mult, factor, inputs[input]),
+                    None => inputs[input],
+                },
+                FixedOperand::Immediate(value) => value,
+            };
+
+            self.registers[instruction.dest] =
+                parameters.apply(instruction.op, self.registers[instruction.src1], operand);
+        }
+    }
+
+    /// Quantizes the inputs of `input` (see [`FixedPointParameters::quantize`]), then executes the
+    /// instructions over them.
+    pub fn run(&mut self, input: &impl State) {
+        let inputs = (0..self.n_inputs)
+            .map(|idx| self.parameters.quantize(input.get_value(idx)))
+            .collect_vec();
+
+        self.exec(&inputs);
+    }
+
+    /// The first of the largest action registers; unlike floating point registers, they cannot
+    /// overflow.
+    pub fn select_action(&self) -> usize {
+        let actions = &self.registers[..self.n_actions];
+        let max = actions.iter().copied().max().unwrap_or_default();
+
+        actions.iter().position(|&value| value == max).unwrap_or(0)
+    }
+
+    /// The value of every register.
+    pub fn registers(&self) -> Vec<f64> {
+        self.registers
+            .iter()
+            .map(|&raw| self.parameters.to_float(raw))
+            .collect()
+    }
+
+    /// The largest difference between the action registers of `program` and of the fixed-point program
+    /// when both run over `samples` in turn from reset registers, and the number of samples on which
+    /// they selected different actions.
+    pub fn deviation(&self, program: &Program, samples: &[Vec<f64>]) -> (f64, usize) {
+        let mut fixed = self.clone();
+        let mut program = program.clone();
+        fixed.reset();
+        ResetEngine::reset(&mut program.registers);
+
+        let mut max_error: f64 = 0.;
+        let mut n_disagreements = 0;
+
+        for sample in samples {
+            let input = Row(sample);
+            fixed.run(&input);
+            program.run(&input);
+
+            let registers = fixed.registers();
+            for action in 0..self.n_actions {
+                max_error =
+                    max_error.max((registers[action] - program.registers.get(action)).abs());
+            }
+
+            if program.select_action(TieBreak::First) != Some(fixed.select_action()) {
+                n_disagreements += 1;
+            }
+        }
+
+        (max_error, n_disagreements)
+    }
+
+    /// C source defining `void <name>(int32_t *r, const int32_t *in)`, which executes the program over
+    /// raw inputs, and `int <name>_action(const int32_t *r)`, which selects the action. `r` holds
+    /// `<NAME>_N_REGISTERS` registers, zeroed before the first execution and carried over between
+    /// executions, and `in` `<NAME>_N_INPUTS` inputs.
+    ///
+    /// Halvings shift negative values right, which compilers for the usual targets make arithmetic.
+    pub fn to_c(&self, name: &str) -> String {
+        let parameters = self.parameters;
+        let prefix = name.to_uppercase();
+        let mut source = String::new();
+
+        let narrow = match parameters.overflow_policy {
+            OverflowPolicy::Saturate => {
+                "return r > INT32_MAX ? INT32_MAX : r < INT32_MIN ? INT32_MIN : (int32_t)r;"
+            }
+            OverflowPolicy::Wrap => "return (int32_t)r;",
+        };
+
+        let _ = writeln!(
+            source,
+            "/* Fixed-point program, Q{}.{} with {} arithmetic. */",
+            31 - parameters.fractional_bits,
+            parameters.fractional_bits,
+            match parameters.overflow_policy {
+                OverflowPolicy::Saturate => "saturating",
+                OverflowPolicy::Wrap => "wrapping",
+            }
+        );
+        let _ = writeln!(source, "#include <stdint.h>\n");
+        let _ = writeln!(
+            source,
+            "#define {}_N_REGISTERS {}",
+            prefix,
+            self.registers.len()
+        );
+        let _ = writeln!(source, "#define {}_N_INPUTS {}", prefix, self.n_inputs);
+        let _ = writeln!(source, "#define {}_N_ACTIONS {}\n", prefix, self.n_actions);
+
+        let product = format!("((int64_t)a * b) >> {}", parameters.fractional_bits);
+        for (op, expression) in [
+            ("add", "(int64_t)a + b"),
+            ("sub", "(int64_t)a - b"),
+            ("mul", product.as_str()),
+        ] {
+            let _ = writeln!(
+                source,
+                "static inline int32_t {}_{}(int32_t a, int32_t b) {{ int64_t r = {}; {} }}",
+                name, op, expression, narrow
+            );
+        }
+
+        let _ = writeln!(source, "\nvoid {}(int32_t *r, const int32_t *in) {{", name);
+        for instruction in &self.instructions {
+            let operand = match instruction.src2 {
+                FixedOperand::Register(register) => format!("r[{}]", register),
+                FixedOperand::Input { input, factor } => match factor {
+                    Some(factor) => format!("{}_mul({}, in[{}])", name, factor, input),
+                    None => format!("in[{}]", input),
+                },
+                FixedOperand::Immediate(value) => value.to_string(),
+            };
+            let expression = match instruction.op {
+                Op::Add => format!("{}_add(r[{}], {})", name, instruction.src1, operand),
+                Op::Sub => format!("{}_sub(r[{}], {})", name, instruction.src1, operand),
+                Op::Mult => format!("{}_mul(r[{}], {})", name, instruction.src1, operand),
+                Op::Divide => format!("r[{}] >> 1", instruction.src1),
+                Op::Load => operand,
+            };
+
+            let _ = writeln!(source, "    r[{}] = {};", instruction.dest, expression);
+        }
+        let _ = writeln!(source, "}}\n");
+
+        let _ = writeln!(source, "int {}_action(const int32_t *r) {{", name);
+        let _ = writeln!(source, "    int action = 0;");
+        let _ = writeln!(
+            source,
+            "    for (int i = 1; i < {}_N_ACTIONS; i++) {{",
+            prefix
+        );
+        let _ = writeln!(source, "        if (r[i] > r[action]) action = i;");
+        let _ = writeln!(source, "    }}");
+        let _ = writeln!(source, "    return action;");
+        let _ = writeln!(source, "}}");
+
+        source
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::problems::problem::program_parameters;
+    use crate::utils::misc::VoidResultAnyError;
+
+    #[test]
+    fn given_program_when_compiled_to_fixed_point_then_it_behaves_like_the_original(
+    ) -> VoidResultAnyError {
+        let program_parameters = program_parameters(2, 2);
+
+        let program = Program::parse(
+            "r0 = r0 + 1 * in0; r1 = r1 - 0.25; r1 = r1 * 1 * in1; r2 = r2 + 1 * in1; r0 = r0 / r0",
+            program_parameters,
+        )?;
+        let samples = vec![vec![1., 2.], vec![-3., 0.5], vec![0.1, -7.]];
+
+        let mut fixed = FixedPointProgram::compile(&program, FixedPointParameters::default())?;
+
+        // Nothing reads r2.
+        assert_eq!(fixed.instructions().len(), 4);
+
+        let (max_error, n_disagreements) = fixed.deviation(&program, &samples);
+        assert!(max_error < 1e-3);
+        assert_eq!(n_disagreements, 0);
+
+        fixed.run(&Row(&[1., 2.]));
+        assert_eq!(fixed.registers()[..2], [0.5, -0.5]);
+        assert_eq!(fixed.select_action(), 0);
+
+        let source = fixed.to_c("controller");
+        assert!(source.contains("#define CONTROLLER_N_REGISTERS 3"));
+        assert!(source.contains("void controller(int32_t *r, const int32_t *in) {"));
+        assert!(source.contains("    r[1] = controller_sub(r[1], 16384);"));
+        assert!(source.contains("    r[1] = controller_mul(r[1], in[1]);"));
+        assert!(source.contains("    r[0] = r[0] >> 1;"));
+
+        let no_multiplication = FixedPointParametersBuilder::default()
+            .no_multiplication(true)
+            .build()?;
+        assert!(FixedPointProgram::compile(&program, no_multiplication).is_err());
+
+        let huge = Program::parse("r0 = r0 + 1e6", program_parameters)?;
+        assert!(FixedPointProgram::compile(&huge, FixedPointParameters::default()).is_err());
+
+        let mut budgeted = program.clone();
+        budgeted.instruction_budget = Some(4);
+        assert!(FixedPointProgram::compile(&budgeted, FixedPointParameters::default()).is_err());
+
+        // With `in1` masked out, `r1` ends at `0` and the controller still agrees with the program.
+        let mut masked = program.clone();
+        masked.input_mask = Some(vec![true, false]);
+        let mut masked_fixed =
+            FixedPointProgram::compile(&masked, FixedPointParameters::default())?;
+        let (max_error, n_disagreements) = masked_fixed.deviation(&masked, &samples);
+        assert!(max_error < 1e-3);
+        assert_eq!(n_disagreements, 0);
+
+        masked_fixed.run(&Row(&[1., 2.]));
+        assert_eq!(masked_fixed.registers()[1], 0.);
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_overflow_when_executed_then_overflow_policy_applies() -> VoidResultAnyError {
+        let saturate = FixedPointParameters::default();
+        let wrap = FixedPointParametersBuilder::default()
+            .overflow_policy(OverflowPolicy::Wrap)
+            .build()?;
+        let large = saturate.quantize(30000.);
+
+        assert_eq!(saturate.apply(Op::Add, large, large), i32::MAX);
+        assert!(wrap.apply(Op::Add, large, large) < 0);
+        assert_eq!(saturate.apply(Op::Mult, -large, large), i32::MIN);
+        assert_eq!(
+            saturate.apply(Op::Divide, saturate.quantize(-3.), 0),
+            saturate.quantize(-1.5)
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,705 @@
+//! Turning raw (text) columns into the numeric inputs programs read.
+//!
+//! Declare how every column is encoded with [`FeatureSpecs`], fit an [`Encoder`] on the training rows, and
+//! save it alongside the champion so inference encodes rows exactly as training did. Numeric inputs are
+//! then transformed by an [`InputPipeline`], built and fitted through an [`InputPipelineBuilder`].
+use std::{
+    collections::{BTreeMap, BTreeSet},
+    error::Error,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::utils::{
+    loader::{resolve_column, Column, Table},
+    random::standard_normal,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Encoding {
+    /// Parsed as a number.
+    Numeric,
+    /// One input per category seen during fitting, `1` for the row's category and `0` otherwise.
+    OneHot,
+    /// The index of the category, in sorted order; unseen categories are encoded as `-1`.
+    Ordinal,
+    /// The mean target of the category; unseen categories are encoded as the mean of all targets.
+    Target,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FeatureSpec {
+    pub column: Column,
+    pub encoding: Encoding,
+}
+
+/// The ordered list of columns used as features and how each is encoded.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct FeatureSpecs {
+    pub features: Vec<FeatureSpec>,
+}
+
+impl FeatureSpecs {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn feature(mut self, column: Column, encoding: Encoding) -> Self {
+        self.features.push(FeatureSpec { column, encoding });
+        self
+    }
+
+    pub fn numeric(self, name: &str) -> Self {
+        self.feature(Column::Name(name.to_owned()), Encoding::Numeric)
+    }
+
+    pub fn one_hot(self, name: &str) -> Self {
+        self.feature(Column::Name(name.to_owned()), Encoding::OneHot)
+    }
+
+    pub fn ordinal(self, name: &str) -> Self {
+        self.feature(Column::Name(name.to_owned()), Encoding::Ordinal)
+    }
+
+    pub fn target(self, name: &str) -> Self {
+        self.feature(Column::Name(name.to_owned()), Encoding::Target)
+    }
+
+    /// Learns the categories (and target means) of the categorical columns.
+    ///
+    /// `targets` holds one value per row and is only required by [`Encoding::Target`] columns.
+    pub fn fit(
+        &self,
+        headers: &Option<Vec<String>>,
+        rows: &[Vec<String>],
+        targets: Option<&[f64]>,
+    ) -> Result<Encoder, Box<dyn Error>> {
+        let mut features = vec![];
+
+        for spec in &self.features {
+            let column = resolve_column(&spec.column, headers)?;
+            let name = match headers {
+                Some(headers) => headers[column].clone(),
+                None => column.to_string(),
+            };
+
+            let values = rows
+                .iter()
+                .map(|row| {
+                    row.get(column)
+                        .map(|value| value.trim().to_owned())
+                        .ok_or_else(|| format!("Missing column `{}`.", name))
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+
+            let feature = match spec.encoding {
+                Encoding::Numeric => FittedFeature::Numeric { column },
+                Encoding::OneHot => FittedFeature::OneHot {
+                    column,
+                    categories: values
+                        .iter()
+                        .cloned()
+                        .collect::<BTreeSet<_>>()
+                        .into_iter()
+                        .collect(),
+                },
+                Encoding::Ordinal => FittedFeature::Ordinal {
+                    column,
+                    categories: values
+                        .iter()
+                        .cloned()
+                        .collect::<BTreeSet<_>>()
+                        .into_iter()
+                        .collect(),
+                },
+                Encoding::Target => {
+                    let targets = targets.ok_or_else(|| {
+                        format!("Target encoding of `{}` requires targets.", name)
+                    })?;
+
+                    if targets.len() != values.len() {
+                        return Err("Expected one target per row.".into());
+                    }
+
+                    let mut sums: BTreeMap<String, (f64, usize)> = BTreeMap::new();
+                    for (value, target) in values.iter().zip(targets) {
+                        let (sum, count) = sums.entry(value.clone()).or_default();
+                        *sum += target;
+                        *count += 1;
+                    }
+
+                    FittedFeature::Target {
+                        column,
+                        means: sums
+                            .into_iter()
+                            .map(|(category, (sum, count))| (category, sum / count as f64))
+                            .collect(),
+                        default: targets.iter().sum::<f64>() / targets.len() as f64,
+                    }
+                }
+            };
+
+            features.push((name, feature));
+        }
+
+        Ok(Encoder { features })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum FittedFeature {
+    Numeric {
+        column: usize,
+    },
+    OneHot {
+        column: usize,
+        categories: Vec<String>,
+    },
+    Ordinal {
+        column: usize,
+        categories: Vec<String>,
+    },
+    Target {
+        column: usize,
+        means: BTreeMap<String, f64>,
+        default: f64,
+    },
+}
+
+/// Encodes raw rows consistently between training and inference.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Encoder {
+    features: Vec<(String, FittedFeature)>,
+}
+
+impl Encoder {
+    /// Names of the encoded inputs, e.g. `color=red` for one-hot inputs.
+    pub fn feature_names(&self) -> Vec<String> {
+        self.features
+            .iter()
+            .flat_map(|(name, feature)| match feature {
+                FittedFeature::OneHot { categories, .. } => categories
+                    .iter()
+                    .map(|category| format!("{}={}", name, category))
+                    .collect(),
+                _ => vec![name.clone()],
+            })
+            .collect()
+    }
+
+    /// Number of inputs produced per row, i.e. the `n_inputs` of the programs.
+    pub fn n_inputs(&self) -> usize {
+        self.feature_names().len()
+    }
+
+    pub fn encode(&self, row: &[String]) -> Result<Vec<f64>, Box<dyn Error>> {
+        let mut encoded = Vec::with_capacity(self.features.len());
+
+        for (name, feature) in &self.features {
+            let column = match feature {
+                FittedFeature::Numeric { column }
+                | FittedFeature::OneHot { column, .. }
+                | FittedFeature::Ordinal { column, .. }
+                | FittedFeature::Target { column, .. } => *column,
+            };
+
+            let value = row
+                .get(column)
+                .map(|value| value.trim())
+                .ok_or_else(|| format!("Missing column `{}`.", name))?;
+
+            match feature {
+                FittedFeature::Numeric { .. } => encoded.push(
+                    value
+                        .parse::<f64>()
+                        .map_err(|error| format!("Column `{}`: {}", name, error))?,
+                ),
+                FittedFeature::OneHot { categories, .. } => encoded.extend(
+                    categories
+                        .iter()
+                        .map(|category| (category == value) as usize as f64),
+                ),
+                FittedFeature::Ordinal { categories, .. } => encoded.push(
+                    categories
+                        .iter()
+                        .position(|category| category == value)
+                        .map_or(-1., |idx| idx as f64),
+                ),
+                FittedFeature::Target { means, default, .. } => {
+                    encoded.push(means.get(value).copied().unwrap_or(*default))
+                }
+            }
+        }
+
+        Ok(encoded)
+    }
+
+    pub fn encode_all(&self, rows: &[Vec<String>]) -> Result<Vec<Vec<f64>>, Box<dyn Error>> {
+        rows.iter().map(|row| self.encode(row)).collect()
+    }
+}
+
+/// How missing (`NaN`) features are handled.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub enum MissingValuePolicy {
+    DropRow,
+    /// Replaced by the mean of the column's observed values.
+    Mean,
+    Constant(f64),
+    /// Mean imputation, plus one input per column with missing values flagging whether the value was
+    /// missing.
+    Indicator,
+}
+
+/// Column statistics gathered on the training rows, reused to fill rows at inference.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Imputer {
+    pub policy: MissingValuePolicy,
+    /// Means of the observed values, `0` for columns without any.
+    pub means: Vec<f64>,
+    /// Number of missing values per column during fitting.
+    pub n_missing: Vec<usize>,
+}
+
+impl Imputer {
+    pub fn fit(features: &[Vec<f64>], policy: MissingValuePolicy) -> Self {
+        let n_columns = features.iter().map(Vec::len).max().unwrap_or(0);
+        let mut sums = vec![0.; n_columns];
+        let mut n_observed = vec![0; n_columns];
+        let mut n_missing = vec![0; n_columns];
+
+        for row in features {
+            for (column, value) in row.iter().enumerate() {
+                if value.is_nan() {
+                    n_missing[column] += 1;
+                } else {
+                    sums[column] += value;
+                    n_observed[column] += 1;
+                }
+            }
+        }
+
+        let means = sums
+            .iter()
+            .zip(n_observed.iter())
+            .map(|(sum, count)| if *count > 0 { sum / *count as f64 } else { 0. })
+            .collect();
+
+        Imputer {
+            policy,
+            means,
+            n_missing,
+        }
+    }
+
+    /// Columns which receive an indicator input under [`MissingValuePolicy::Indicator`].
+    pub fn indicator_columns(&self) -> Vec<usize> {
+        match self.policy {
+            MissingValuePolicy::Indicator => (0..self.n_missing.len())
+                .filter(|column| self.n_missing[*column] > 0)
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    pub fn n_inputs(&self) -> usize {
+        self.means.len() + self.indicator_columns().len()
+    }
+
+    /// Fills the missing values of `row`, `None` when the row should be dropped.
+    pub fn transform(&self, row: &[f64]) -> Option<Vec<f64>> {
+        let has_missing = row.iter().any(|value| value.is_nan());
+
+        if has_missing && self.policy == MissingValuePolicy::DropRow {
+            return None;
+        }
+
+        let mut filled = row
+            .iter()
+            .enumerate()
+            .map(|(column, value)| match (value.is_nan(), self.policy) {
+                (false, _) => *value,
+                (true, MissingValuePolicy::Constant(constant)) => constant,
+                (true, _) => self.means.get(column).copied().unwrap_or(0.),
+            })
+            .collect::<Vec<_>>();
+
+        filled.extend(self.indicator_columns().into_iter().map(|column| {
+            row.get(column)
+                .map_or(1., |value| value.is_nan() as usize as f64)
+        }));
+
+        Some(filled)
+    }
+
+    /// Fills (or drops) the rows of `table`, keeping labels aligned with their rows.
+    pub fn transform_table(&self, table: &mut Table) {
+        let has_labels = !table.labels.is_empty();
+        let mut features = vec![];
+        let mut labels = vec![];
+
+        for (idx, row) in table.features.iter().enumerate() {
+            if let Some(row) = self.transform(row) {
+                features.push(row);
+
+                if has_labels {
+                    labels.push(table.labels[idx].clone());
+                }
+            }
+        }
+
+        for column in self.indicator_columns() {
+            let name = table
+                .feature_names
+                .get(column)
+                .cloned()
+                .unwrap_or_else(|| column.to_string());
+            table.feature_names.push(format!("{}_missing", name));
+        }
+
+        table.features = features;
+        table.labels = labels;
+    }
+}
+
+/// A transform of the numeric inputs, as declared before fitting.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum Transform {
+    Impute(MissingValuePolicy),
+    /// Centers every input on `0` with a unit standard deviation.
+    Standardize,
+    /// Rescales every input into `[0, 1]`.
+    MinMax,
+    /// Keeps the inputs at these indices, in this order.
+    Select(Vec<usize>),
+    /// Adds gaussian noise with standard deviation `std` to every input, during training only, so
+    /// programs cannot rely on exact input values.
+    Noise {
+        std: f64,
+    },
+}
+
+/// Chains transforms, applied in the order they were added.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct InputPipelineBuilder {
+    pub transforms: Vec<Transform>,
+}
+
+impl InputPipelineBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn transform(mut self, transform: Transform) -> Self {
+        self.transforms.push(transform);
+        self
+    }
+
+    pub fn impute(self, policy: MissingValuePolicy) -> Self {
+        self.transform(Transform::Impute(policy))
+    }
+
+    pub fn standardize(self) -> Self {
+        self.transform(Transform::Standardize)
+    }
+
+    pub fn min_max(self) -> Self {
+        self.transform(Transform::MinMax)
+    }
+
+    pub fn select(self, features: Vec<usize>) -> Self {
+        self.transform(Transform::Select(features))
+    }
+
+    pub fn noise(self, std: f64) -> Self {
+        self.transform(Transform::Noise { std })
+    }
+
+    /// Learns the statistics of every transform on the training rows, each transform being fitted on
+    /// the output of the previous ones.
+    pub fn fit(&self, features: &[Vec<f64>]) -> Result<InputPipeline, Box<dyn Error>> {
+        let mut rows = features.to_vec();
+        let mut steps = vec![];
+
+        for transform in &self.transforms {
+            let n_columns = rows.iter().map(Vec::len).max().unwrap_or(0);
+            let column = |idx: usize| {
+                rows.iter()
+                    .filter_map(move |row| row.get(idx).copied())
+                    .filter(|value| !value.is_nan())
+            };
+
+            let step = match transform {
+                Transform::Impute(policy) => FittedTransform::Impute(Imputer::fit(&rows, *policy)),
+                Transform::Standardize => {
+                    let (means, stds) = (0..n_columns)
+                        .map(|idx| {
+                            let values = column(idx).collect::<Vec<_>>();
+                            let n = values.len().max(1) as f64;
+                            let mean = values.iter().sum::<f64>() / n;
+                            let variance =
+                                values.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / n;
+
+                            (mean, variance.sqrt())
+                        })
+                        .unzip();
+
+                    FittedTransform::Standardize { means, stds }
+                }
+                Transform::MinMax => {
+                    let (mins, maxs) = (0..n_columns)
+                        .map(|idx| {
+                            column(idx).fold((f64::INFINITY, f64::NEG_INFINITY), |(min, max), v| {
+                                (min.min(v), max.max(v))
+                            })
+                        })
+                        .unzip();
+
+                    FittedTransform::MinMax { mins, maxs }
+                }
+                Transform::Select(features) => {
+                    if let Some(feature) = features.iter().find(|feature| **feature >= n_columns) {
+                        return Err(format!(
+                            "Cannot select input {} out of {}.",
+                            feature, n_columns
+                        )
+                        .into());
+                    }
+
+                    FittedTransform::Select(features.clone())
+                }
+                Transform::Noise { std } => FittedTransform::Noise { std: *std },
+            };
+
+            rows = rows
+                .iter()
+                .filter_map(|row| step.apply(row, false))
+                .collect();
+            steps.push(step);
+        }
+
+        Ok(InputPipeline { steps })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub enum FittedTransform {
+    Impute(Imputer),
+    Standardize { means: Vec<f64>, stds: Vec<f64> },
+    MinMax { mins: Vec<f64>, maxs: Vec<f64> },
+    Select(Vec<usize>),
+    Noise { std: f64 },
+}
+
+impl FittedTransform {
+    /// `None` when the row is dropped (e.g. by [`MissingValuePolicy::DropRow`]).
+    fn apply(&self, row: &[f64], training: bool) -> Option<Vec<f64>> {
+        // Constant inputs are only centered (or shifted to `0`).
+        let scale = |spread: f64| if spread > 0. { spread } else { 1. };
+
+        let transformed = match self {
+            FittedTransform::Impute(imputer) => return imputer.transform(row),
+            FittedTransform::Standardize { means, stds } => row
+                .iter()
+                .enumerate()
+                .map(|(idx, value)| match (means.get(idx), stds.get(idx)) {
+                    (Some(mean), Some(std)) => (value - mean) / scale(*std),
+                    _ => *value,
+                })
+                .collect(),
+            FittedTransform::MinMax { mins, maxs } => row
+                .iter()
+                .enumerate()
+                .map(|(idx, value)| match (mins.get(idx), maxs.get(idx)) {
+                    (Some(min), Some(max)) if min.is_finite() => (value - min) / scale(max - min),
+                    _ => *value,
+                })
+                .collect(),
+            FittedTransform::Select(features) => features
+                .iter()
+                .map(|feature| row.get(*feature).copied().unwrap_or(f64::NAN))
+                .collect(),
+            FittedTransform::Noise { std } if training => row
+                .iter()
+                .map(|value| value + std * standard_normal())
+                .collect(),
+            FittedTransform::Noise { .. } => row.to_vec(),
+        };
+
+        Some(transformed)
+    }
+}
+
+/// Fitted transforms, applied identically at training and inference time (apart from
+/// [`Transform::Noise`], which only perturbs training rows). Save it alongside the programs it was
+/// trained with.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct InputPipeline {
+    pub steps: Vec<FittedTransform>,
+}
+
+impl InputPipeline {
+    /// Transforms a row at inference time, `None` when the row is dropped.
+    pub fn transform(&self, row: &[f64]) -> Option<Vec<f64>> {
+        self.steps
+            .iter()
+            .try_fold(row.to_vec(), |row, step| step.apply(&row, false))
+    }
+
+    /// Transforms a row to train on, injecting noise where requested.
+    pub fn transform_training(&self, row: &[f64]) -> Option<Vec<f64>> {
+        self.steps
+            .iter()
+            .try_fold(row.to_vec(), |row, step| step.apply(&row, true))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::{
+        loader::{load_records, load_table, CsvLoadOptions},
+        misc::VoidResultAnyError,
+    };
+
+    const CONTENT: &str = "size,color,city,label\n1.5,red,a,1\n2.5,blue,b,0\n3.5,red,a,0\n";
+
+    #[test]
+    fn given_feature_specs_when_fitted_then_rows_are_encoded_consistently() -> VoidResultAnyError {
+        let (headers, rows) = load_records(
+            CONTENT,
+            &CsvLoadOptions {
+                has_headers: true,
+                ..Default::default()
+            },
+        )?;
+        let targets = [1., 0., 0.];
+
+        let encoder = FeatureSpecs::new()
+            .numeric("size")
+            .one_hot("color")
+            .ordinal("color")
+            .target("city")
+            .fit(&headers, &rows, Some(&targets))?;
+
+        assert_eq!(
+            encoder.feature_names(),
+            vec!["size", "color=blue", "color=red", "color", "city"]
+        );
+        assert_eq!(encoder.encode(&rows[0])?, vec![1.5, 0., 1., 1., 0.5]);
+        assert_eq!(encoder.encode(&rows[1])?, vec![2.5, 1., 0., 0., 0.]);
+
+        // Unseen categories.
+        let unseen = ["0", "green", "c", "1"].map(str::to_owned);
+        assert_eq!(encoder.encode(&unseen)?, vec![0., 0., 0., -1., 1. / 3.]);
+
+        let restored: Encoder = serde_json::from_str(&serde_json::to_string(&encoder)?)?;
+        assert_eq!(restored.encode_all(&rows)?, encoder.encode_all(&rows)?);
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_target_encoding_without_targets_when_fitted_then_an_error_is_returned(
+    ) -> VoidResultAnyError {
+        let (headers, rows) = load_records(
+            CONTENT,
+            &CsvLoadOptions {
+                has_headers: true,
+                ..Default::default()
+            },
+        )?;
+
+        assert!(FeatureSpecs::new()
+            .target("city")
+            .fit(&headers, &rows, None)
+            .is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_missing_values_when_imputed_then_policy_is_applied() -> VoidResultAnyError {
+        let options = CsvLoadOptions {
+            label_column: Some(Column::Index(2)),
+            missing_values: vec!["".to_owned(), "?".to_owned()],
+            ..Default::default()
+        };
+        let content = "1.0,,a\n3.0,4.0,b\n?,6.0,c\n";
+
+        let (table, _) = load_table(content, 0, &options)?;
+
+        let mut dropped = table.clone();
+        Imputer::fit(&table.features, MissingValuePolicy::DropRow).transform_table(&mut dropped);
+        assert_eq!(dropped.features, vec![vec![3., 4.]]);
+        assert_eq!(dropped.labels, vec!["b"]);
+
+        let mut indicated = table.clone();
+        let imputer = Imputer::fit(&table.features, MissingValuePolicy::Indicator);
+        imputer.transform_table(&mut indicated);
+        assert_eq!(imputer.n_inputs(), 4);
+        assert_eq!(
+            indicated.feature_names,
+            vec!["0", "1", "0_missing", "1_missing"]
+        );
+        assert_eq!(
+            indicated.features,
+            vec![
+                vec![1., 5., 0., 1.],
+                vec![3., 4., 0., 0.],
+                vec![2., 6., 1., 0.]
+            ]
+        );
+
+        let constant = Imputer::fit(&table.features, MissingValuePolicy::Constant(-1.));
+        assert_eq!(constant.transform(&[f64::NAN, 2.]), Some(vec![-1., 2.]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_pipeline_when_fitted_then_transforms_are_chained_and_noise_is_training_only(
+    ) -> VoidResultAnyError {
+        let features = vec![
+            vec![1., 10., f64::NAN],
+            vec![3., 30., 5.],
+            vec![5., 20., 7.],
+        ];
+
+        let pipeline = InputPipelineBuilder::new()
+            .impute(MissingValuePolicy::Mean)
+            .select(vec![2, 0])
+            .min_max()
+            .noise(0.1)
+            .fit(&features)?;
+
+        // The missing value is imputed with the mean (6) before selection and rescaling.
+        assert_eq!(pipeline.transform(&features[0]), Some(vec![0.5, 0.]));
+        assert_eq!(pipeline.transform(&features[2]), Some(vec![1., 1.]));
+        assert_ne!(
+            pipeline.transform_training(&features[2]),
+            pipeline.transform(&features[2])
+        );
+
+        let restored: InputPipeline = serde_json::from_str(&serde_json::to_string(&pipeline)?)?;
+        assert_eq!(
+            restored.transform(&features[1]),
+            pipeline.transform(&features[1])
+        );
+
+        let standardized = InputPipelineBuilder::new()
+            .standardize()
+            .fit(&features[1..])?;
+        assert_eq!(
+            standardized.transform(&[4., 25., 6.]),
+            Some(vec![0., 0., 0.])
+        );
+
+        assert!(InputPipelineBuilder::new()
+            .select(vec![3])
+            .fit(&features)
+            .is_err());
+
+        Ok(())
+    }
+}
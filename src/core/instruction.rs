@@ -1,20 +1,23 @@
-use clap::Args;
+use clap::{Args, ValueEnum};
 use derivative::Derivative;
 use derive_builder::Builder;
 use rand::distributions::Standard;
 use rand::prelude::Distribution;
 use rand::Rng;
-use serde::{Deserialize, Serialize};
-use std::fmt::Debug;
+use serde::{Deserialize, Deserializer, Serialize};
+use std::error::Error;
+use std::fmt::{self, Debug};
+use std::str::FromStr;
 
-use crate::utils::random::generator;
+use crate::utils::random::{generator, standard_normal};
 
 use super::engines::generate_engine::{Generate, GenerateEngine};
-use super::engines::mutate_engine::{Mutate, MutateEngine};
-use super::environment::State;
-use super::registers::Registers;
+use super::engines::mutate_engine::{Mutate, MutateEngine, MutationParameters};
+use super::environment::{SpaceInfo, State};
+use super::registers::{narrow, Readout, RegisterValue, Registers};
 use derive_more::Display;
 
+/// Whether the operand of a legacy (two-address) instruction was an input or a register.
 #[derive(Clone, Debug, PartialEq, Eq, Serialize, Copy, Deserialize)]
 pub enum Mode {
     External,
@@ -31,15 +34,37 @@ pub enum Op {
     Divide,
     #[display(fmt = "-")]
     Sub,
+    /// Overwrites the register with the operand, whatever the register held (even `inf` or `NaN`).
+    /// Never generated, only written by hand, e.g. `r0 = 1 * in1` (see [`Instruction::parse`]).
+    #[display(fmt = "load")]
+    Load,
 }
 
 impl Op {
-    pub fn apply(&self, a: f64, b: f64) -> f64 {
+    /// Combines two scalar operands, `a` being the value of the register written to and `b` the
+    /// operand. Operations never read or write more than one register, so there is nothing to broadcast;
+    /// `Divide` is the protected halving of `a` and ignores `b`, `Load` ignores `a`.
+    pub fn apply(&self, a: RegisterValue, b: RegisterValue) -> RegisterValue {
         match *self {
             Op::Add => a + b,
             Op::Mult => a * b,
             Op::Divide => a / 2.,
             Op::Sub => a - b,
+            Op::Load => b,
+        }
+    }
+}
+
+impl FromStr for Op {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "+" => Ok(Op::Add),
+            "*" => Ok(Op::Mult),
+            "/" => Ok(Op::Divide),
+            "-" => Ok(Op::Sub),
+            _ => Err(format!("Unknown operation `{}`.", s)),
         }
     }
 }
@@ -55,15 +80,6 @@ impl Distribution<Op> for Standard {
     }
 }
 
-impl Distribution<Mode> for Standard {
-    fn sample<R: Rng + ?Sized>(&self, rng: &mut R) -> Mode {
-        match rng.gen_bool(0.5) {
-            false => Mode::External,
-            true => Mode::Internal,
-        }
-    }
-}
-
 #[derive(Clone, Derivative, Debug, Serialize, Args, PartialEq, Deserialize, Builder)]
 #[derivative(Copy)]
 pub struct InstructionGeneratorParameters {
@@ -77,18 +93,196 @@ pub struct InstructionGeneratorParameters {
     pub n_actions: usize,
     #[arg(skip)]
     pub n_inputs: usize,
+    /// Operand kinds generated instructions may use.
+    #[arg(long, value_enum, default_value_t = Modes::Standard)]
+    #[builder(default = "Modes::Standard")]
+    #[serde(default)]
+    pub modes: Modes,
+    /// Number of registers the readout maps to actions, one per action when unset (or lower).
+    #[arg(long)]
+    #[builder(default)]
+    #[serde(default)]
+    pub n_outputs: Option<usize>,
+    #[arg(long, value_enum, default_value_t = Readout::Direct)]
+    #[builder(default = "Readout::Direct")]
+    #[serde(default)]
+    pub readout: Readout,
+    #[command(flatten)]
+    #[builder(default)]
+    #[serde(default)]
+    pub op_weights: OpWeights,
+}
+
+/// Relative probabilities of generating each operation, e.g. to keep programs away from divisions
+/// early on. Weights can be annealed like any other hyperparameter with
+/// [`CoreIter::with_schedule`](crate::core::engines::core_engine::CoreIter::with_schedule).
+#[derive(Clone, Copy, Debug, Args, Serialize, Deserialize, PartialEq, Builder)]
+pub struct OpWeights {
+    #[arg(long, default_value = "1.")]
+    #[builder(default = "1.")]
+    pub add_weight: f64,
+    #[arg(long, default_value = "1.")]
+    #[builder(default = "1.")]
+    pub mult_weight: f64,
+    #[arg(long, default_value = "1.")]
+    #[builder(default = "1.")]
+    pub divide_weight: f64,
+    #[arg(long, default_value = "1.")]
+    #[builder(default = "1.")]
+    pub sub_weight: f64,
+}
+
+impl Default for OpWeights {
+    fn default() -> Self {
+        Self {
+            add_weight: 1.,
+            mult_weight: 1.,
+            divide_weight: 1.,
+            sub_weight: 1.,
+        }
+    }
+}
+
+impl OpWeights {
+    fn weights(&self) -> [(Op, f64); 4] {
+        [
+            (Op::Add, self.add_weight),
+            (Op::Mult, self.mult_weight),
+            (Op::Divide, self.divide_weight),
+            (Op::Sub, self.sub_weight),
+        ]
+    }
+
+    /// Draws an operation with probability proportional to its (non-negative) weight, uniformly when
+    /// every weight is the same.
+    pub fn sample(&self) -> Op {
+        let weights = self.weights();
+
+        if weights.iter().all(|(_, weight)| *weight == weights[0].1) {
+            return generator().gen();
+        }
+
+        let total = weights
+            .iter()
+            .map(|(_, weight)| weight.max(0.))
+            .sum::<f64>();
+        assert!(
+            total > 0.,
+            "At least one operation must have a positive weight."
+        );
+
+        let mut threshold = generator().gen_range(0.0..total);
+        for (op, weight) in weights {
+            let weight = weight.max(0.);
+
+            if threshold < weight {
+                return op;
+            }
+            threshold -= weight;
+        }
+
+        // Rounding may leave the threshold past the last weight.
+        weights
+            .iter()
+            .rev()
+            .find(|(_, weight)| *weight > 0.)
+            .unwrap()
+            .0
+    }
+}
+
+/// The kinds of second operand instructions are generated with.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+pub enum Modes {
+    /// Registers and inputs.
+    #[default]
+    Standard,
+    /// Registers, inputs and constants embedded in the instruction, drawn from
+    /// `[-external_factor, external_factor]`.
+    Immediate,
 }
 
 impl InstructionGeneratorParameters {
+    /// Parameters for inputs and actions only known at runtime (e.g. read from a dataset), with the
+    /// standard modes and a direct readout.
+    pub fn new(n_inputs: usize, n_actions: usize, n_extras: usize, external_factor: f64) -> Self {
+        InstructionGeneratorParameters {
+            n_extras,
+            external_factor,
+            n_actions,
+            n_inputs,
+            modes: Modes::Standard,
+            n_outputs: None,
+            readout: Readout::Direct,
+            op_weights: OpWeights::default(),
+        }
+    }
+
+    /// Parameters reading every input of `space` and choosing from its actions.
+    pub fn for_space(space: &impl SpaceInfo, n_extras: usize, external_factor: f64) -> Self {
+        Self::new(
+            space.n_inputs(),
+            space.n_actions(),
+            n_extras,
+            external_factor,
+        )
+    }
+
+    pub fn n_outputs(&self) -> usize {
+        self.n_outputs.unwrap_or(self.n_actions).max(self.n_actions)
+    }
+
     pub fn n_registers(&self) -> usize {
         // Mountain Car Example: | -1 | 0 | 1 | Extra |
-        self.n_actions + self.n_extras
+        self.n_outputs() + self.n_extras
+    }
+
+    /// Empty registers laid out as `| outputs | extras |`.
+    pub fn registers(&self) -> Registers {
+        Registers::with_readout(
+            self.n_actions,
+            self.n_outputs(),
+            self.n_extras,
+            self.readout,
+        )
     }
 }
 
-#[derive(Serialize, PartialEq, Debug, Deserialize, Derivative)]
+/// What an instruction combines its first source register with.
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum Operand {
+    Register(usize),
+    /// An input, scaled by the instruction's external factor.
+    Input(usize),
+    /// A constant, tuned by micro-mutations.
+    Immediate(f64),
+}
+
+/// A three-address instruction, `r[dest] = r[src1] <op> src2`.
+#[derive(Serialize, PartialEq, Debug, Derivative)]
 #[derivative(Copy, Clone)]
 pub struct Instruction {
+    dest: usize,
+    src1: usize,
+    src2: Operand,
+    op: Op,
+    external_factor: f64,
+}
+
+/// The serialized layout of an [`Instruction`].
+#[derive(Deserialize)]
+struct ThreeAddressInstruction {
+    dest: usize,
+    src1: usize,
+    src2: Operand,
+    op: Op,
+    external_factor: f64,
+}
+
+/// The two-address layout programs used to be saved in: `src_idx` is both read and written, and
+/// `tgt_idx` is a register or an input depending on `mode`.
+#[derive(Deserialize)]
+struct LegacyInstruction {
     src_idx: usize,
     tgt_idx: usize,
     mode: Mode,
@@ -96,26 +290,84 @@ pub struct Instruction {
     external_factor: f64,
 }
 
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum SerializedInstruction {
+    ThreeAddress(ThreeAddressInstruction),
+    Legacy(LegacyInstruction),
+}
+
+impl From<ThreeAddressInstruction> for Instruction {
+    fn from(instruction: ThreeAddressInstruction) -> Self {
+        Instruction {
+            dest: instruction.dest,
+            src1: instruction.src1,
+            src2: instruction.src2,
+            op: instruction.op,
+            external_factor: instruction.external_factor,
+        }
+    }
+}
+
+impl From<LegacyInstruction> for Instruction {
+    fn from(instruction: LegacyInstruction) -> Self {
+        let src2 = match instruction.mode {
+            Mode::External => Operand::Input(instruction.tgt_idx),
+            Mode::Internal => Operand::Register(instruction.tgt_idx),
+        };
+
+        Instruction {
+            dest: instruction.src_idx,
+            src1: instruction.src_idx,
+            src2,
+            op: instruction.op,
+            external_factor: instruction.external_factor,
+        }
+    }
+}
+
+impl<'de> Deserialize<'de> for Instruction {
+    fn deserialize<D>(deserializer: D) -> Result<Self, D::Error>
+    where
+        D: Deserializer<'de>,
+    {
+        // Only self-describing formats can be probed for the legacy layout. Binary populations holding
+        // it have format version 1, which is rejected before decoding.
+        if !deserializer.is_human_readable() {
+            return ThreeAddressInstruction::deserialize(deserializer).map(Instruction::from);
+        }
+
+        Ok(match SerializedInstruction::deserialize(deserializer)? {
+            SerializedInstruction::ThreeAddress(instruction) => instruction.into(),
+            SerializedInstruction::Legacy(instruction) => instruction.into(),
+        })
+    }
+}
+
 impl Generate<InstructionGeneratorParameters, Instruction> for GenerateEngine {
     fn generate(using: InstructionGeneratorParameters) -> Instruction {
-        let src_idx = generator().gen_range(0..using.n_registers());
+        let dest = generator().gen_range(0..using.n_registers());
+        let src1 = generator().gen_range(0..using.n_registers());
 
-        let mode = generator().gen();
-
-        let upper_bound_target_index = if mode == Mode::External {
-            using.n_inputs
-        } else {
-            using.n_registers()
+        let n_modes = match using.modes {
+            Modes::Standard => 2,
+            Modes::Immediate => 3,
         };
 
-        let target_index = generator().gen_range(0..upper_bound_target_index);
+        let src2 = match generator().gen_range(0..n_modes) {
+            0 => Operand::Register(generator().gen_range(0..using.n_registers())),
+            1 => Operand::Input(generator().gen_range(0..using.n_inputs)),
+            _ => Operand::Immediate(
+                generator().gen_range(-using.external_factor..=using.external_factor),
+            ),
+        };
 
-        let executable = generator().gen();
+        let executable = using.op_weights.sample();
 
         Instruction {
-            src_idx,
-            tgt_idx: target_index,
-            mode,
+            dest,
+            src1,
+            src2,
             op: executable,
             external_factor: using.external_factor,
         }
@@ -124,21 +376,37 @@ impl Generate<InstructionGeneratorParameters, Instruction> for GenerateEngine {
 
 impl Mutate<InstructionGeneratorParameters, Instruction> for MutateEngine {
     fn mutate(instruction: &mut Instruction, using: InstructionGeneratorParameters) {
-        let mutated = GenerateEngine::generate(using);
+        MutateEngine::mutate(instruction, (using, MutationParameters::default()))
+    }
+}
+
+impl Mutate<(InstructionGeneratorParameters, MutationParameters), Instruction> for MutateEngine {
+    fn mutate(
+        instruction: &mut Instruction,
+        using: (InstructionGeneratorParameters, MutationParameters),
+    ) {
+        let (generator_parameters, mutation_parameters) = using;
+        let mutated = GenerateEngine::generate(generator_parameters);
 
-        let swap_target = generator().gen();
-        let swap_source = generator().gen();
-        let swap_exec = generator().gen();
+        let swap_target = generator().gen::<f64>() < mutation_parameters.target_mutation_rate;
+        let swap_source = generator().gen::<f64>() < mutation_parameters.source_mutation_rate;
+        let swap_exec = generator().gen::<f64>() < mutation_parameters.op_mutation_rate;
 
-        // Flip a Coin: Target
+        // Flip a Coin: Second Operand
+        // Constants are as likely to be nudged as to be replaced, so they can be tuned.
         if swap_target {
-            instruction.mode = mutated.mode;
-            instruction.tgt_idx = mutated.tgt_idx;
+            instruction.src2 = match instruction.src2 {
+                Operand::Immediate(value) if generator().gen_bool(0.5) => Operand::Immediate(
+                    value + mutation_parameters.immediate_step * standard_normal(),
+                ),
+                _ => mutated.src2,
+            };
         }
 
-        // Flip a Coin: Source
+        // Flip a Coin: Registers
         if swap_source {
-            instruction.src_idx = mutated.src_idx;
+            instruction.dest = mutated.dest;
+            instruction.src1 = mutated.src1;
         }
 
         // Flip a Coin: Executable
@@ -149,15 +417,473 @@ impl Mutate<InstructionGeneratorParameters, Instruction> for MutateEngine {
 }
 
 impl Instruction {
+    /// The register written by this instruction.
+    pub fn dest(&self) -> usize {
+        self.dest
+    }
+
+    /// The register combined with the second operand.
+    pub fn src1(&self) -> usize {
+        self.src1
+    }
+
+    pub fn src2(&self) -> Operand {
+        self.src2
+    }
+
+    /// The input read by this instruction, if any.
+    pub fn input(&self) -> Option<usize> {
+        match self.src2 {
+            Operand::Input(input) => Some(input),
+            Operand::Register(_) | Operand::Immediate(_) => None,
+        }
+    }
+
+    /// The registers read by this instruction.
+    pub fn read_registers(&self) -> impl Iterator<Item = usize> {
+        let src1 = Some(self.src1).filter(|_| self.op != Op::Load);
+        let src2 = match self.src2 {
+            Operand::Register(register) => Some(register),
+            Operand::Input(_) | Operand::Immediate(_) => None,
+        };
+
+        src1.into_iter().chain(src2)
+    }
+
+    pub fn op(&self) -> Op {
+        self.op
+    }
+
+    pub fn external_factor(&self) -> f64 {
+        self.external_factor
+    }
+
+    /// Whether the instruction reads an input disabled by `input_mask` (`false` for disabled inputs).
+    pub fn reads_masked_input(&self, input_mask: &[bool]) -> bool {
+        self.input().map_or(false, |input| {
+            !input_mask.get(input).copied().unwrap_or(true)
+        })
+    }
+
+    /// The instruction reading its input as `0`, i.e. with a factor of `0`.
+    pub fn masked(&self) -> Instruction {
+        Instruction {
+            external_factor: 0.,
+            ..*self
+        }
+    }
+
+    /// Whether the instruction leaves every register as is: adding or subtracting `0`, or multiplying
+    /// by `1`, in place.
+    pub fn is_identity(&self) -> bool {
+        self.dest == self.src1
+            && match (self.op, self.src2) {
+                (Op::Add | Op::Sub, Operand::Immediate(value)) => value == 0.,
+                (Op::Mult, Operand::Immediate(value)) => value == 1.,
+                (Op::Load, Operand::Register(register)) => register == self.dest,
+                _ => false,
+            }
+    }
+
+    /// A single instruction equivalent (up to rounding) to running this instruction then `next`, when
+    /// both update the same register in place with constants: additions and subtractions add up,
+    /// multiplications and halvings multiply out.
+    pub fn merge(&self, next: &Instruction) -> Option<Instruction> {
+        if self.dest != self.src1 || next.dest != next.src1 || self.dest != next.dest {
+            return None;
+        }
+
+        let offset = |instruction: &Instruction| match (instruction.op, instruction.src2) {
+            (Op::Add, Operand::Immediate(value)) => Some(value),
+            (Op::Sub, Operand::Immediate(value)) => Some(-value),
+            _ => None,
+        };
+        let scale = |instruction: &Instruction| match (instruction.op, instruction.src2) {
+            (Op::Mult, Operand::Immediate(value)) => Some(value),
+            (Op::Divide, _) => Some(0.5),
+            _ => None,
+        };
+
+        let (op, value) = match (offset(self), offset(next), scale(self), scale(next)) {
+            (Some(first), Some(second), ..) => (Op::Add, first + second),
+            (_, _, Some(first), Some(second)) => (Op::Mult, first * second),
+            _ => return None,
+        };
+
+        Some(Instruction {
+            op,
+            src2: Operand::Immediate(value),
+            ..*self
+        })
+    }
+
     pub fn apply<'b>(&self, registers: &'b mut Registers, input: &impl State) {
-        let target_value = match self.mode {
-            Mode::External => self.external_factor * input.get_value(self.tgt_idx),
-            _ => *registers.get(self.tgt_idx),
+        let operand_value = match self.src2 {
+            Operand::Input(idx) => narrow(self.external_factor * input.get_value(idx)),
+            Operand::Register(idx) => registers[idx],
+            Operand::Immediate(value) => narrow(value),
         };
 
-        let source_value = *registers.get(self.src_idx);
-        let new_source_value = self.op.apply(source_value, target_value);
+        let source_value = registers[self.src1];
+        let new_value = self.op.apply(source_value, operand_value);
+
+        registers.update(self.dest, new_value);
+    }
+}
 
-        registers.update(self.src_idx, new_source_value);
+fn parse_index(token: &str, prefix: &str, upper_bound: usize) -> Result<usize, Box<dyn Error>> {
+    let index: usize = token
+        .strip_prefix(prefix)
+        .ok_or_else(|| format!("Expected `{}<index>`, found `{}`.", prefix, token))?
+        .parse()?;
+
+    if index >= upper_bound {
+        return Err(format!("Index in `{}` must be less than {}.", token, upper_bound).into());
+    }
+
+    Ok(index)
+}
+
+/// Prints the instruction in the text format accepted by [`Instruction::parse`],
+/// e.g. `r0 = r2 * 10 * in1`, `r1 = r1 + r0`, `r2 = r2 * 0.5` or, for loads, `r0 = 1 * in1`.
+impl fmt::Display for Instruction {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.op, self.src2) {
+            (Op::Load, Operand::Input(input)) => {
+                write!(f, "r{} = {} * in{}", self.dest, self.external_factor, input)
+            }
+            (Op::Load, Operand::Register(register)) => write!(f, "r{} = r{}", self.dest, register),
+            (Op::Load, Operand::Immediate(value)) => write!(f, "r{} = {}", self.dest, value),
+            _ => self.fmt_combination(f),
+        }
+    }
+}
+
+impl Instruction {
+    fn fmt_combination(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.src2 {
+            Operand::Input(input) => write!(
+                f,
+                "r{} = r{} {} {} * in{}",
+                self.dest, self.src1, self.op, self.external_factor, input
+            ),
+            Operand::Register(register) => write!(
+                f,
+                "r{} = r{} {} r{}",
+                self.dest, self.src1, self.op, register
+            ),
+            Operand::Immediate(value) => {
+                write!(f, "r{} = r{} {} {}", self.dest, self.src1, self.op, value)
+            }
+        }
+    }
+}
+
+impl Instruction {
+    /// Parses a single instruction of the form `r0 = r1 * in1` (input operand), `r0 = r0 + r1`
+    /// (register operand) or `r0 = r0 * 0.5` (immediate operand). Input operands may carry an explicit
+    /// factor, `r0 = r0 * 2.5 * in1`, otherwise the factor of the parameters is used. Without a source
+    /// register, e.g. `r0 = 2.5 * in1`, `r0 = r1` or `r0 = 0.5`, the instruction loads its operand
+    /// ([`Op::Load`]).
+    ///
+    /// Tokens must be separated by whitespace.
+    pub fn parse(
+        text: &str,
+        parameters: InstructionGeneratorParameters,
+    ) -> Result<Instruction, Box<dyn Error>> {
+        let tokens: Vec<&str> = text.split_whitespace().collect();
+
+        let (destination, source, op, factor, operand) = match tokens.as_slice() {
+            [destination, "=", operand] => (*destination, *destination, None, None, *operand),
+            [destination, "=", factor, "*", operand] if factor.parse::<f64>().is_ok() => {
+                (*destination, *destination, None, Some(*factor), *operand)
+            }
+            [destination, "=", source, op, operand] => {
+                (*destination, *source, Some(*op), None, *operand)
+            }
+            [destination, "=", source, op, factor, "*", operand] => {
+                (*destination, *source, Some(*op), Some(*factor), *operand)
+            }
+            _ => {
+                return Err(format!("Expected `rX = rY <op> <operand>`, found `{}`.", text).into())
+            }
+        };
+
+        let dest = parse_index(destination, "r", parameters.n_registers())?;
+        let src1 = parse_index(source, "r", parameters.n_registers())?;
+
+        let op = match op {
+            Some(op) => op.parse()?,
+            None => Op::Load,
+        };
+
+        let external_factor = match factor {
+            Some(factor) if operand.starts_with("in") => factor.parse()?,
+            Some(_) => {
+                return Err(format!("Only inputs may be scaled, found `{}`.", text).into());
+            }
+            None => parameters.external_factor,
+        };
+
+        let src2 = if operand.starts_with("in") {
+            Operand::Input(parse_index(operand, "in", parameters.n_inputs)?)
+        } else if operand.starts_with('r') {
+            Operand::Register(parse_index(operand, "r", parameters.n_registers())?)
+        } else {
+            Operand::Immediate(
+                operand
+                    .parse()
+                    .map_err(|_| format!("Unknown operand `{}` in `{}`.", operand, text))?,
+            )
+        };
+
+        Ok(Instruction {
+            dest,
+            src1,
+            src2,
+            op,
+            external_factor,
+        })
+    }
+
+    /// Parses a statement of a human-written policy: an instruction (see [`Instruction::parse`]), or
+    /// an assignment whose first operand is an input, `r0 = in1 * 2.0` or `r0 = in1`. The latter loads
+    /// the input as is (whatever the factor of the parameters) then applies the operation in place,
+    /// e.g. `r0 = 1 * in1; r0 = r0 * 2.0`.
+    pub fn parse_statement(
+        text: &str,
+        parameters: InstructionGeneratorParameters,
+    ) -> Result<Vec<Instruction>, Box<dyn Error>> {
+        let tokens: Vec<&str> = text.split_whitespace().collect();
+
+        let (destination, input, operation) = match tokens.as_slice() {
+            [destination, "=", input, operation @ ..] if input.starts_with("in") => {
+                (*destination, *input, operation)
+            }
+            _ => return Ok(vec![Instruction::parse(text, parameters)?]),
+        };
+
+        if operation.last() == Some(&destination) {
+            return Err(format!(
+                "`{}` reads the register it overwrites after its input, found `{}`.",
+                destination, text
+            )
+            .into());
+        }
+
+        let mut statements = vec![format!("{} = 1 * {}", destination, input)];
+        if !operation.is_empty() {
+            statements.push(format!("{0} = {0} {1}", destination, operation.join(" ")));
+        }
+
+        statements
+            .iter()
+            .map(|statement| Instruction::parse(statement, parameters))
+            .collect::<Result<_, _>>()
+            .map_err(|error| format!("{} (in `{}`)", error, text).into())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extensions::classification::Dataset;
+
+    fn parameters() -> InstructionGeneratorParameters {
+        InstructionGeneratorParameters {
+            n_extras: 1,
+            external_factor: 10.,
+            n_actions: 2,
+            n_inputs: 4,
+            modes: Modes::Immediate,
+            n_outputs: None,
+            readout: Readout::Direct,
+            op_weights: OpWeights::default(),
+        }
+    }
+
+    #[test]
+    fn given_runtime_dimensions_when_parameters_are_built_then_they_match_the_builder() {
+        let built = InstructionGeneratorParametersBuilder::default()
+            .n_inputs(3)
+            .n_actions(2)
+            .build()
+            .unwrap();
+
+        assert_eq!(InstructionGeneratorParameters::new(3, 2, 1, 10.), built);
+
+        let dataset = Dataset::new(vec![vec![0., 1., 2.]], vec![1]);
+        let parameters = InstructionGeneratorParameters::for_space(&dataset, 1, 10.);
+
+        assert_eq!(parameters, built);
+        assert!(dataset.check(&parameters).is_ok());
+    }
+
+    #[test]
+    fn given_op_weights_when_instructions_are_generated_then_ops_follow_the_weights() {
+        let mut parameters = parameters();
+        parameters.op_weights.divide_weight = 0.;
+
+        let ops = (0..200)
+            .map(|_| GenerateEngine::generate(parameters).op)
+            .collect::<Vec<Op>>();
+        assert!(!ops.contains(&Op::Divide));
+        assert!(ops.contains(&Op::Add) && ops.contains(&Op::Mult) && ops.contains(&Op::Sub));
+
+        parameters.op_weights = OpWeightsBuilder::default()
+            .mult_weight(0.)
+            .sub_weight(0.)
+            .build()
+            .unwrap();
+        parameters.op_weights.divide_weight = 0.;
+        assert!((0..50).all(|_| GenerateEngine::generate(parameters).op == Op::Add));
+    }
+
+    #[test]
+    fn given_input_first_operand_when_statement_is_parsed_then_register_is_loaded_then_updated() {
+        // Default parameters, whose factor (10) scales the inputs of generated instructions.
+        let parameters = InstructionGeneratorParametersBuilder::default()
+            .n_inputs(4)
+            .n_actions(2)
+            .build()
+            .unwrap();
+        let statement = Instruction::parse_statement("r0 = in1 * 2.0", parameters).unwrap();
+
+        assert_eq!(
+            statement,
+            vec![
+                Instruction::parse("r0 = 1 * in1", parameters).unwrap(),
+                Instruction::parse("r0 = r0 * 2", parameters).unwrap(),
+            ]
+        );
+
+        // Registers persist between states, so the register may hold anything beforehand.
+        let input = crate::core::batch::Row(&[0., 3., 0., 0.]);
+        for previous in [0., 5., RegisterValue::INFINITY, RegisterValue::NAN] {
+            let mut registers = Registers::from_values(vec![previous, 0., 0.], 2);
+            for instruction in &statement {
+                instruction.apply(&mut registers, &input);
+            }
+
+            assert_eq!(registers.get(0), 6.);
+        }
+
+        assert_eq!(
+            Instruction::parse_statement("r1 = in0", parameters()).unwrap(),
+            vec![Instruction {
+                dest: 1,
+                src1: 1,
+                src2: Operand::Input(0),
+                op: Op::Load,
+                external_factor: 1.,
+            }]
+        );
+        assert_eq!(
+            Instruction::parse_statement("r1 = r0 + in0", parameters()).unwrap(),
+            vec![Instruction::parse("r1 = r0 + in0", parameters()).unwrap()]
+        );
+        assert!(Instruction::parse_statement("r0 = in1 - r0", parameters()).is_err());
+        assert!(Instruction::parse_statement("r0 = in9 * 2", parameters()).is_err());
+    }
+
+    #[test]
+    fn given_valid_text_when_instruction_is_parsed_then_fields_are_set() {
+        let external = Instruction::parse("r0 = r0 * in3", parameters()).unwrap();
+        let internal = Instruction::parse("r2 = r2 - r1", parameters()).unwrap();
+
+        assert_eq!(
+            external,
+            Instruction {
+                dest: 0,
+                src1: 0,
+                src2: Operand::Input(3),
+                op: Op::Mult,
+                external_factor: 10.,
+            }
+        );
+        assert_eq!(
+            internal,
+            Instruction {
+                dest: 2,
+                src1: 2,
+                src2: Operand::Register(1),
+                op: Op::Sub,
+                external_factor: 10.,
+            }
+        );
+    }
+
+    #[test]
+    fn given_invalid_text_when_instruction_is_parsed_then_error_is_returned() {
+        assert!(Instruction::parse("r3 = r3 * in0", parameters()).is_err());
+        assert!(Instruction::parse("r0 = r0 * in4", parameters()).is_err());
+        assert!(Instruction::parse("r0 = r0 % r1", parameters()).is_err());
+        assert!(Instruction::parse("r0 = r0", parameters()).is_err());
+        assert!(Instruction::parse("r0 = r0 * 2 * r1", parameters()).is_err());
+    }
+
+    #[test]
+    fn given_distinct_source_when_instruction_is_parsed_then_three_addresses_are_kept() {
+        let instruction = Instruction::parse("r0 = r1 * in0", parameters()).unwrap();
+
+        assert_eq!(
+            (instruction.dest(), instruction.src1(), instruction.src2()),
+            (0, 1, Operand::Input(0))
+        );
+        assert_eq!(instruction.to_string(), "r0 = r1 * 10 * in0");
+    }
+
+    #[test]
+    fn given_legacy_layout_when_instruction_is_deserialized_then_destination_is_also_read() {
+        let legacy =
+            r#"{"src_idx":1,"tgt_idx":2,"mode":"Internal","op":"Add","external_factor":10.0}"#;
+        let instruction: Instruction = serde_json::from_str(legacy).unwrap();
+
+        assert_eq!(
+            instruction,
+            Instruction::parse("r1 = r1 + r2", parameters()).unwrap()
+        );
+
+        let serialized = serde_json::to_string(&instruction).unwrap();
+        let deserialized: Instruction = serde_json::from_str(&serialized).unwrap();
+
+        assert_eq!(deserialized, instruction);
+    }
+
+    #[test]
+    fn given_immediate_operand_when_instruction_is_applied_then_constant_is_used() {
+        let instruction = Instruction::parse("r2 = r2 * 0.5", parameters()).unwrap();
+
+        assert_eq!(instruction.src2(), Operand::Immediate(0.5));
+        assert_eq!(instruction.read_registers().collect::<Vec<_>>(), vec![2]);
+
+        let mut registers = Registers::from_values(vec![0., 0., 3.], 2);
+        instruction.apply(&mut registers, &crate::core::batch::Row(&[]));
+
+        assert_eq!(registers.get(2), 1.5);
+    }
+
+    #[test]
+    fn given_explicit_factor_when_instruction_is_parsed_then_factor_is_used() {
+        let instruction = Instruction::parse("r1 = r1 + 2.5 * in0", parameters()).unwrap();
+
+        assert_eq!(instruction.external_factor, 2.5);
+    }
+
+    #[test]
+    fn given_generated_instructions_when_printed_and_parsed_then_round_trip_holds() {
+        for _ in 0..100 {
+            let instruction: Instruction = GenerateEngine::generate(parameters());
+            let parsed = Instruction::parse(&instruction.to_string(), parameters()).unwrap();
+
+            assert_eq!(parsed, instruction);
+        }
+
+        for text in ["r0 = 2.5 * in1", "r1 = r2", "r2 = -0.5"] {
+            let instruction = Instruction::parse(text, parameters()).unwrap();
+
+            assert_eq!(instruction.op(), Op::Load);
+            assert_eq!(instruction.to_string(), text);
+        }
     }
 }
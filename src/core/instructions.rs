@@ -1,6 +1,8 @@
+use std::collections::BTreeSet;
+
 use crate::utils::random::generator;
 use itertools::Itertools;
-use rand::Rng;
+use rand::{seq::SliceRandom, Rng};
 
 use super::{
     engines::breed_engine::{Breed, BreedEngine},
@@ -74,9 +76,84 @@ impl Breed<Instructions> for BreedEngine {
 
 pub type Instructions = Vec<Instruction>;
 
+/// The registers written to by `instructions`.
+pub fn destination_registers(instructions: &[Instruction]) -> BTreeSet<usize> {
+    instructions
+        .iter()
+        .map(|instruction| instruction.dest())
+        .collect()
+}
+
+/// Jaccard similarity of the destination registers of two segments.
+fn alignment(segment_a: &BTreeSet<usize>, segment_b: &BTreeSet<usize>) -> f64 {
+    let union = segment_a.union(segment_b).count();
+
+    if union == 0 {
+        return 1.;
+    }
+
+    segment_a.intersection(segment_b).count() as f64 / union as f64
+}
+
+/// Crossover which exchanges segments writing to the same registers.
+///
+/// A random segment of `mate_1` is swapped with the equally long segment of `mate_2` whose destination
+/// registers overlap the most with its own (ties are broken randomly), so spliced code keeps updating
+/// the registers its new neighbours (and, for Q-programs, the Q-table rows) expect. Both children keep
+/// the length of their parent.
+pub fn aligned_crossover(
+    mate_1: &Instructions,
+    mate_2: &Instructions,
+) -> (Instructions, Instructions) {
+    let mut instructions_a = mate_1.clone();
+    let mut instructions_b = mate_2.clone();
+
+    debug_assert!(instructions_a.len() > 0);
+    debug_assert!(instructions_b.len() > 0);
+
+    let length = generator().gen_range(1..=instructions_a.len().min(instructions_b.len()));
+    let a_start = generator().gen_range(0..=(instructions_a.len() - length));
+    let a_targets = destination_registers(&instructions_a[a_start..(a_start + length)]);
+
+    let scores = (0..=(instructions_b.len() - length))
+        .map(|b_start| {
+            let b_targets = destination_registers(&instructions_b[b_start..(b_start + length)]);
+            (b_start, alignment(&a_targets, &b_targets))
+        })
+        .collect_vec();
+    let best_score = scores
+        .iter()
+        .map(|(_, score)| *score)
+        .fold(f64::NEG_INFINITY, f64::max);
+    let b_start = scores
+        .iter()
+        .filter(|(_, score)| *score == best_score)
+        .map(|(b_start, _)| *b_start)
+        .collect_vec()
+        .choose(&mut generator())
+        .copied()
+        .expect("At least one segment to be aligned.");
+
+    let a_chunk = instructions_a[a_start..(a_start + length)].to_vec();
+    let b_chunk = instructions_b[b_start..(b_start + length)].to_vec();
+
+    instructions_a.splice(a_start..(a_start + length), b_chunk);
+    instructions_b.splice(b_start..(b_start + length), a_chunk);
+
+    (instructions_a, instructions_b)
+}
+
 #[cfg(test)]
 mod tests {
 
+    use std::collections::BTreeSet;
+
+    use itertools::Itertools;
+
+    use super::{aligned_crossover, destination_registers, Instructions};
+    use crate::core::engines::mutate_engine::MutationParameters;
+    use crate::core::instruction::{Instruction, Modes, OpWeights};
+    use crate::core::registers::{NumericParameters, Readout};
     use crate::core::{
         engines::{
             breed_engine::{Breed, BreedEngine},
@@ -86,6 +163,48 @@ mod tests {
         program::ProgramGeneratorParameters,
     };
 
+    #[test]
+    fn given_mates_when_aligned_crossover_then_segments_writing_the_same_registers_are_swapped() {
+        let parameters = InstructionGeneratorParameters {
+            n_extras: 1,
+            external_factor: 10.,
+            n_inputs: 2,
+            n_actions: 2,
+            modes: Modes::Standard,
+            n_outputs: None,
+            readout: Readout::Direct,
+            op_weights: OpWeights::default(),
+        };
+        let parse = |lines: &[&str]| -> Instructions {
+            lines
+                .iter()
+                .map(|line| Instruction::parse(line, parameters).unwrap())
+                .collect()
+        };
+
+        let mate_1 = parse(&["r0 = r0 + in0", "r0 = r0 * in1", "r0 = r0 - r1"]);
+        let mate_2 = parse(&[
+            "r1 = r1 + in0",
+            "r1 = r1 + in1",
+            "r1 = r1 + r2",
+            "r0 = r0 + in1",
+            "r0 = r0 / in0",
+            "r0 = r0 + r2",
+        ]);
+
+        for _ in 0..100 {
+            let (child_1, child_2) = aligned_crossover(&mate_1, &mate_2);
+
+            assert_eq!(child_1.len(), mate_1.len());
+            assert_eq!(child_2.len(), mate_2.len());
+            assert_eq!(destination_registers(&child_1), BTreeSet::from([0]));
+            assert_eq!(
+                child_2.iter().map(|i| i.dest()).collect_vec(),
+                vec![1, 1, 1, 0, 0, 0]
+            );
+        }
+    }
+
     #[test]
     fn given_two_programs_when_two_point_crossover_multiple_times_then_instruction_set_never_grows()
     {
@@ -97,7 +216,16 @@ mod tests {
                 external_factor: 10.,
                 n_inputs: 4,
                 n_actions: 2,
+                modes: Modes::Standard,
+                n_outputs: None,
+                readout: Readout::Direct,
+                op_weights: OpWeights::default(),
             },
+            mutation_parameters: MutationParameters::default(),
+            numeric_parameters: NumericParameters::default(),
+            tie_break: None,
+            frame_skip: 1,
+            instruction_budget: None,
         };
 
         let mut program_a = GenerateEngine::generate(parameters);
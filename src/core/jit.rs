@@ -0,0 +1,265 @@
+//! Native compilation of champion programs through cranelift (`jit` feature).
+//!
+//! Only the effective instructions (see [`Program::effective_instructions`]) are compiled: after a single
+//! execution the action registers always match the interpreter, while the values left in the extra
+//! registers may differ. Reset the registers between executions to keep both paths in agreement.
+//! Inputs masked out by the program are read as `0`. Values are always propagated as is, so programs
+//! with another [`NumericPolicy`], or longer than their instruction budget, are not compiled.
+#[cfg(feature = "f32-registers")]
+compile_error!("The `jit` feature compiles `f64` registers only, disable `f32-registers`.");
+
+use std::{error::Error, mem};
+
+use cranelift_codegen::{
+    ir::{types, AbiParam, InstBuilder, MemFlags, Value},
+    settings::{self, Configurable},
+};
+use cranelift_frontend::{FunctionBuilder, FunctionBuilderContext};
+use cranelift_jit::{JITBuilder, JITModule};
+use cranelift_module::{default_libcall_names, Linkage, Module};
+
+use super::{
+    environment::State,
+    instruction::{Op, Operand},
+    program::Program,
+    registers::{NumericPolicy, Registers},
+};
+
+type CompiledFn = extern "C" fn(*mut f64, *const f64);
+
+const VALUE_SIZE: usize = mem::size_of::<f64>();
+
+pub struct JitProgram {
+    module: Option<JITModule>,
+    function: CompiledFn,
+    n_registers: usize,
+    n_inputs: usize,
+}
+
+impl JitProgram {
+    pub fn compile(program: &Program) -> Result<JitProgram, Box<dyn Error>> {
+        let numeric_policy = program.numeric_parameters.numeric_policy;
+        if numeric_policy != NumericPolicy::Propagate {
+            return Err(format!(
+                "Only programs propagating values can be compiled, not {:?}.",
+                numeric_policy
+            )
+            .into());
+        }
+
+        if let Some(budget) = program.instruction_budget {
+            if program.instructions.len() > budget {
+                return Err(format!(
+                    "The program runs {} instructions, over its budget of {}.",
+                    program.instructions.len(),
+                    budget
+                )
+                .into());
+            }
+        }
+
+        let input_mask = program.input_mask.as_deref().unwrap_or(&[]);
+        let instructions = program
+            .effective_instructions()
+            .into_iter()
+            .map(
+                |instruction| match instruction.reads_masked_input(input_mask) {
+                    true => instruction.masked(),
+                    false => instruction,
+                },
+            )
+            .collect::<Vec<_>>();
+        let n_registers = program.registers.len();
+        let n_inputs = instructions
+            .iter()
+            .filter_map(|instruction| instruction.input())
+            .map(|input| input + 1)
+            .max()
+            .unwrap_or(0);
+
+        let mut flag_builder = settings::builder();
+        flag_builder.set("use_colocated_libcalls", "false")?;
+        flag_builder.set("is_pic", "false")?;
+        let isa = cranelift_native::builder()?.finish(settings::Flags::new(flag_builder))?;
+
+        let mut module = JITModule::new(JITBuilder::with_isa(isa, default_libcall_names()));
+        let pointer_type = module.target_config().pointer_type();
+
+        let mut context = module.make_context();
+        context
+            .func
+            .signature
+            .params
+            .extend([AbiParam::new(pointer_type), AbiParam::new(pointer_type)]);
+
+        let mut builder_context = FunctionBuilderContext::new();
+        let mut builder = FunctionBuilder::new(&mut context.func, &mut builder_context);
+
+        let block = builder.create_block();
+        builder.append_block_params_for_function_params(block);
+        builder.switch_to_block(block);
+        builder.seal_block(block);
+
+        let registers_ptr = builder.block_params(block)[0];
+        let inputs_ptr = builder.block_params(block)[1];
+        let flags = MemFlags::trusted();
+
+        let mut registers: Vec<Value> = (0..n_registers)
+            .map(|idx| {
+                builder
+                    .ins()
+                    .load(types::F64, flags, registers_ptr, (idx * VALUE_SIZE) as i32)
+            })
+            .collect();
+
+        for instruction in instructions.iter() {
+            let source = registers[instruction.src1()];
+
+            let result = match instruction.op() {
+                Op::Divide => {
+                    let two = builder.ins().f64const(2.);
+                    builder.ins().fdiv(source, two)
+                }
+                op => {
+                    let operand = match instruction.src2() {
+                        Operand::Input(input) => {
+                            let input = builder.ins().load(
+                                types::F64,
+                                flags,
+                                inputs_ptr,
+                                (input * VALUE_SIZE) as i32,
+                            );
+                            let factor = builder.ins().f64const(instruction.external_factor());
+                            builder.ins().fmul(factor, input)
+                        }
+                        Operand::Register(register) => registers[register],
+                        Operand::Immediate(value) => builder.ins().f64const(value),
+                    };
+
+                    match op {
+                        Op::Add => builder.ins().fadd(source, operand),
+                        Op::Sub => builder.ins().fsub(source, operand),
+                        Op::Load => operand,
+                        _ => builder.ins().fmul(source, operand),
+                    }
+                }
+            };
+
+            registers[instruction.dest()] = result;
+        }
+
+        for (idx, value) in registers.into_iter().enumerate() {
+            builder
+                .ins()
+                .store(flags, value, registers_ptr, (idx * VALUE_SIZE) as i32);
+        }
+
+        builder.ins().return_(&[]);
+        builder.finalize();
+
+        let id = module.declare_function("program", Linkage::Export, &context.func.signature)?;
+        module.define_function(id, &mut context)?;
+        module.clear_context(&mut context);
+        module.finalize_definitions()?;
+
+        let code = module.get_finalized_function(id);
+        // SAFETY: the function was declared with two pointer parameters and no return value.
+        let function = unsafe { mem::transmute::<*const u8, CompiledFn>(code) };
+
+        Ok(JitProgram {
+            module: Some(module),
+            function,
+            n_registers,
+            n_inputs,
+        })
+    }
+
+    /// Executes the compiled program, the native counterpart of [`Program::run`].
+    pub fn exec(&self, registers: &mut Registers, input: &impl State) {
+        assert_eq!(registers.len(), self.n_registers);
+
+        let inputs: Vec<f64> = (0..self.n_inputs).map(|idx| input.get_value(idx)).collect();
+
+        (self.function)(registers.as_mut_slice().as_mut_ptr(), inputs.as_ptr());
+    }
+}
+
+impl Drop for JitProgram {
+    fn drop(&mut self) {
+        if let Some(module) = self.module.take() {
+            // SAFETY: `function` is never called once the program is dropped.
+            unsafe { module.free_memory() };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+
+    use super::*;
+    use crate::core::{
+        batch::Row,
+        engines::{
+            generate_engine::{Generate, GenerateEngine},
+            reset_engine::{Reset, ResetEngine},
+        },
+    };
+    use crate::problems::problem::program_parameters;
+    use crate::utils::misc::VoidResultAnyError;
+    use crate::utils::test::program_parameters_of_length;
+
+    #[test]
+    fn given_random_programs_when_jit_compiled_then_action_registers_match_interpreter(
+    ) -> VoidResultAnyError {
+        let program_parameters = program_parameters_of_length(4, 3, 50);
+
+        let rows = (0..5)
+            .map(|row| (0..4).map(|col| (row * 4 + col) as f64 / 3.).collect_vec())
+            .collect_vec();
+
+        for idx in 0..20 {
+            let mut program: Program = GenerateEngine::generate(program_parameters);
+            if idx % 2 == 0 {
+                program.input_mask = Some(vec![true, false, true, false]);
+            }
+            let compiled = JitProgram::compile(&program)?;
+            let n_actions = program.registers.n_actions();
+
+            for row in rows.iter() {
+                ResetEngine::reset(&mut program.registers);
+                let mut registers = program.registers.clone();
+
+                program.interpret(&Row(row));
+                compiled.exec(&mut registers, &Row(row));
+
+                assert!(program.registers[0..n_actions]
+                    .iter()
+                    .zip(registers[0..n_actions].iter())
+                    .all(|(a, b)| a == b || (a.is_nan() && b.is_nan())));
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_budget_or_numeric_policy_the_jit_cannot_honour_when_compiled_then_it_fails(
+    ) -> VoidResultAnyError {
+        let program_parameters = program_parameters(1, 1);
+        let program = Program::parse("r0 = r0 + 1 * in0; r0 = r0 * r0", program_parameters)?;
+
+        let mut budgeted = program.clone();
+        budgeted.instruction_budget = Some(1);
+        assert!(JitProgram::compile(&budgeted).is_err());
+
+        let mut clamped = program.clone();
+        clamped.numeric_parameters.numeric_policy = NumericPolicy::Clamp;
+        assert!(JitProgram::compile(&clamped).is_err());
+
+        budgeted.instruction_budget = Some(2);
+        assert!(JitProgram::compile(&budgeted).is_ok());
+
+        Ok(())
+    }
+}
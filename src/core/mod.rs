@@ -1,9 +1,18 @@
+pub mod batch;
+pub mod bytecode;
 pub mod characteristics;
 pub mod config;
+pub mod cost;
 pub mod environment;
+pub mod fixed_point;
+pub mod inputs;
 pub mod instruction;
 pub mod instructions;
+#[cfg(feature = "jit")]
+pub mod jit;
+pub mod population;
 pub mod program;
 pub mod registers;
+pub mod simplify;
 
 pub mod engines;
@@ -0,0 +1,187 @@
+//! Whole populations on disk, for external analysis tools and for moving runs between machines.
+use std::{
+    error::Error,
+    fs::File,
+    io::{BufReader, BufWriter, Read, Write},
+    path::Path,
+};
+
+use clap::ValueEnum;
+use flate2::{read::GzDecoder, write::GzEncoder, Compression};
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+use crate::utils::benchmark_tools::create_path;
+
+use super::characteristics::{from_versioned_str, Versioned, FORMAT_VERSION};
+
+/// Prefix of binary population files, followed by the format version.
+const BINCODE_MAGIC: &[u8; 4] = b"LGPP";
+const GZIP_MAGIC: [u8; 2] = [0x1f, 0x8b];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+pub enum PopulationFormat {
+    /// Versioned JSON, readable by [`Load`](super::characteristics::Load) and the Python scripts.
+    Json,
+    /// Compact binary encoding, only readable by this crate.
+    Bincode,
+    /// Gzipped [`PopulationFormat::Json`].
+    CompressedJson,
+    /// Gzipped [`PopulationFormat::Bincode`].
+    CompressedBincode,
+}
+
+impl PopulationFormat {
+    fn compressed(self) -> bool {
+        matches!(
+            self,
+            PopulationFormat::CompressedJson | PopulationFormat::CompressedBincode
+        )
+    }
+}
+
+/// The individuals of a generation, saved as is: ids, fitness and every other field of the individuals
+/// survive a round trip.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct Population<T> {
+    pub generation: usize,
+    pub individuals: Vec<T>,
+}
+
+impl<T> Population<T>
+where
+    T: Serialize + DeserializeOwned,
+{
+    pub fn new(generation: usize, individuals: Vec<T>) -> Self {
+        Population {
+            generation,
+            individuals,
+        }
+    }
+
+    pub fn save(&self, path: &str, format: PopulationFormat) -> Result<(), Box<dyn Error>> {
+        create_path(path, true)?;
+
+        let mut encoded = vec![];
+
+        match format {
+            PopulationFormat::Json | PopulationFormat::CompressedJson => {
+                serde_json::to_writer(
+                    &mut encoded,
+                    &Versioned {
+                        format_version: FORMAT_VERSION,
+                        data: self,
+                    },
+                )?;
+            }
+            PopulationFormat::Bincode | PopulationFormat::CompressedBincode => {
+                encoded.extend_from_slice(BINCODE_MAGIC);
+                encoded.extend_from_slice(&FORMAT_VERSION.to_le_bytes());
+                bincode::serialize_into(&mut encoded, self)?;
+            }
+        }
+
+        let mut file = BufWriter::new(File::create(Path::new(path))?);
+
+        if format.compressed() {
+            let mut encoder = GzEncoder::new(file, Compression::default());
+            encoder.write_all(&encoded)?;
+            encoder.finish()?.flush()?;
+        } else {
+            file.write_all(&encoded)?;
+            file.flush()?;
+        }
+
+        Ok(())
+    }
+
+    /// Loads a population saved in any [`PopulationFormat`], which is detected from the contents.
+    pub fn load(path: &str) -> Result<Self, Box<dyn Error>> {
+        let mut contents = vec![];
+        BufReader::new(File::open(Path::new(path))?).read_to_end(&mut contents)?;
+
+        if contents.starts_with(&GZIP_MAGIC) {
+            let mut decompressed = vec![];
+            GzDecoder::new(contents.as_slice()).read_to_end(&mut decompressed)?;
+            contents = decompressed;
+        }
+
+        Self::decode(&contents).map_err(|error| format!("{}: {}", path, error).into())
+    }
+
+    fn decode(contents: &[u8]) -> Result<Self, Box<dyn Error>> {
+        match contents.strip_prefix(BINCODE_MAGIC) {
+            Some(rest) => {
+                if rest.len() < 8 {
+                    return Err("binary population has no format version".into());
+                }
+
+                let (version, data) = rest.split_at(8);
+                let version = u64::from_le_bytes(version.try_into()?);
+
+                // Binary files cannot be migrated field by field, only the current layout is read.
+                if version != FORMAT_VERSION {
+                    return Err(format!(
+                        "binary population has format version {}, but this build reads version {}",
+                        version, FORMAT_VERSION
+                    )
+                    .into());
+                }
+
+                Ok(bincode::deserialize(data)?)
+            }
+            None => from_versioned_str(std::str::from_utf8(contents)?),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+
+    use super::*;
+    use crate::core::engines::generate_engine::{Generate, GenerateEngine};
+    use crate::core::engines::status_engine::{Status, StatusEngine};
+    use crate::core::program::Program;
+    use crate::utils::misc::VoidResultAnyError;
+    use crate::utils::test::{program_parameters_of_length, temp_dir};
+
+    #[test]
+    fn given_population_when_saved_in_every_format_then_it_loads_unchanged() -> VoidResultAnyError {
+        let program_parameters = program_parameters_of_length(4, 2, 20);
+
+        let individuals = (0..5)
+            .map(|idx| {
+                let mut program: Program = GenerateEngine::generate(program_parameters);
+                StatusEngine::set_fitness(&mut program, idx as f64);
+                program
+            })
+            .collect_vec();
+        let population = Population::new(3, individuals);
+
+        let directory = temp_dir("population-formats");
+
+        for format in PopulationFormat::value_variants() {
+            let path = directory.join(format!("{:?}.population", format));
+            let path = path.to_str().unwrap();
+
+            population.save(path, *format)?;
+            let loaded = Population::<Program>::load(path)?;
+
+            assert_eq!(loaded.generation, population.generation);
+            for (original, loaded) in population.individuals.iter().zip(&loaded.individuals) {
+                assert_eq!(loaded.id, original.id);
+                assert_eq!(loaded.fitness, original.fitness);
+                assert_eq!(loaded.instructions, original.instructions);
+            }
+        }
+
+        // Binary populations of version 1 hold legacy instructions.
+        let mut legacy = BINCODE_MAGIC.to_vec();
+        legacy.extend_from_slice(&1u64.to_le_bytes());
+        legacy.extend_from_slice(&[0; 16]);
+        let error = Population::<Program>::decode(&legacy).unwrap_err();
+        assert!(error.to_string().contains("format version 1"));
+
+        Ok(())
+    }
+}
@@ -1,27 +1,43 @@
-use std::iter::repeat_with;
+use std::{
+    collections::{BTreeSet, HashMap, HashSet},
+    error::Error,
+    fmt::{self, Display},
+    hash::Hasher,
+    iter::repeat_with,
+    sync::OnceLock,
+};
 
-use crate::utils::random::generator;
+use crate::utils::{
+    misc::StableHasher,
+    random::{generator, random_id},
+    telemetry::record_program_execution,
+};
 use clap::Args;
 use derivative::Derivative;
 use derive_builder::Builder;
 use rand::{seq::IteratorRandom, Rng};
 
-use serde::{Deserialize, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 use uuid::Uuid;
 
 use super::{
+    bytecode::{Bytecode, ExecOutcome},
     engines::{
         breed_engine::{Breed, BreedEngine},
+        fitness_engine::{FitnessMetadata, OrderedFitness},
         freeze_engine::{Freeze, FreezeEngine},
         generate_engine::{Generate, GenerateEngine},
-        mutate_engine::{Mutate, MutateEngine},
+        mutate_engine::{Mutate, MutateEngine, MutationParameters},
         reset_engine::{Reset, ResetEngine},
         status_engine::{Status, StatusEngine},
     },
     environment::State,
-    instruction::InstructionGeneratorParameters,
-    instructions::Instructions,
-    registers::Registers,
+    instruction::{Instruction, InstructionGeneratorParameters, Op, Operand},
+    instructions::{aligned_crossover, Instructions},
+    registers::{
+        widen, ActionRegister, ArgmaxInput, NumericParameters, NumericPolicy, RegisterValue,
+        Registers, TieBreak,
+    },
 };
 
 #[derive(Clone, Debug, Args, Deserialize, Serialize, Derivative, Builder)]
@@ -32,12 +48,43 @@ pub struct ProgramGeneratorParameters {
     pub max_instructions: usize,
     #[command(flatten)]
     pub instruction_generator_parameters: InstructionGeneratorParameters,
+    #[command(flatten)]
+    #[builder(default)]
+    #[serde(default)]
+    pub mutation_parameters: MutationParameters,
+    #[command(flatten)]
+    #[builder(default)]
+    #[serde(default)]
+    pub numeric_parameters: NumericParameters,
+    /// How ties between registers are broken; each problem falls back to its own default when unset.
+    #[arg(long, value_enum)]
+    #[builder(default)]
+    #[serde(default)]
+    pub tie_break: Option<TieBreak>,
+    /// Number of environment steps each action selected by the program is repeated for.
+    #[arg(long, default_value = "1")]
+    #[builder(default = "1")]
+    #[serde(default = "default_frame_skip")]
+    pub frame_skip: usize,
+    /// Most instructions a single execution may run, executions past it ending
+    /// [`ExecOutcome::OutOfBounds`]; unbounded when unset.
+    #[arg(long)]
+    #[builder(default)]
+    #[serde(default)]
+    pub instruction_budget: Option<usize>,
+}
+
+fn default_frame_skip() -> usize {
+    1
 }
 
 impl Reset<Program> for ResetEngine {
     fn reset(item: &mut Program) {
         ResetEngine::reset(&mut item.registers);
         ResetEngine::reset(&mut item.fitness);
+        item.fitness_metadata = None;
+        item.compiled = None;
+        item.structural_hash = OnceLock::new();
     }
 }
 
@@ -46,12 +93,33 @@ impl Freeze<Program> for FreezeEngine {}
 impl Status<Program> for StatusEngine {
     fn set_fitness(program: &mut Program, fitness: f64) {
         program.fitness = fitness;
+        program.fitness_metadata = None;
     }
 
     fn get_fitness(program: &Program) -> f64 {
         program.fitness
     }
 
+    fn set_metadata(program: &mut Program, metadata: FitnessMetadata) {
+        program.fitness_metadata = Some(metadata);
+    }
+
+    fn get_metadata(program: &Program) -> Option<FitnessMetadata> {
+        program.fitness_metadata
+    }
+
+    fn get_age(program: &Program) -> usize {
+        program.age
+    }
+
+    fn genome_hash(program: &Program) -> Option<u64> {
+        Some(program.genome_hash())
+    }
+
+    fn set_age(program: &mut Program, age: usize) {
+        program.age = age;
+    }
+
     fn valid(item: &Program) -> bool {
         item.fitness.is_finite()
     }
@@ -61,12 +129,93 @@ impl Status<Program> for StatusEngine {
     }
 }
 
+/// JSON writes `NaN` (an unevaluated program, e.g. offspring in a checkpoint) and infinities as
+/// `null`; binary formats keep the value as is.
+fn serialize_fitness<S>(fitness: &f64, serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    match serializer.is_human_readable() && !fitness.is_finite() {
+        true => None::<f64>.serialize(serializer),
+        false => fitness.serialize(serializer),
+    }
+}
+
+/// Reads `null` back as `NaN`, so the program is evaluated again.
+fn deserialize_fitness<'de, D>(deserializer: D) -> Result<f64, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    match deserializer.is_human_readable() {
+        true => Ok(Option::<f64>::deserialize(deserializer)?.unwrap_or(f64::NAN)),
+        false => f64::deserialize(deserializer),
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize, Derivative, Builder)]
 pub struct Program {
     pub id: Uuid,
+    /// Reset the program after editing the instructions directly, so its compiled code and
+    /// [`Program::structural_hash`] are rebuilt from the new ones.
     pub instructions: Instructions,
     pub registers: Registers,
+    #[serde(
+        serialize_with = "serialize_fitness",
+        deserialize_with = "deserialize_fitness"
+    )]
     pub fitness: f64,
+    /// How `fitness` was measured, unset until the program is evaluated.
+    #[serde(default)]
+    #[builder(default)]
+    pub fitness_metadata: Option<FitnessMetadata>,
+    #[serde(default)]
+    #[builder(default)]
+    pub numeric_parameters: NumericParameters,
+    #[serde(default)]
+    #[builder(default)]
+    pub tie_break: Option<TieBreak>,
+    #[serde(default = "default_frame_skip")]
+    #[builder(default = "1")]
+    pub frame_skip: usize,
+    /// One gene per input, inputs whose gene is `false` being read as `0`. Only evolved when
+    /// [`MutationParameters::input_mask_rate`] is positive.
+    #[serde(default)]
+    #[builder(default)]
+    pub input_mask: Option<Vec<bool>>,
+    #[serde(default)]
+    #[builder(default)]
+    pub instruction_budget: Option<usize>,
+    /// Compiled form of `instructions`, built on first run and dropped whenever the program is reset.
+    #[serde(skip)]
+    #[builder(setter(skip))]
+    compiled: Option<Bytecode>,
+    /// Number of generations the program survived.
+    #[serde(default)]
+    #[builder(default)]
+    pub age: usize,
+    #[serde(default)]
+    #[builder(default)]
+    pub origin: Origin,
+    /// Cache of [`Program::structural_hash`], dropped whenever mutation or crossover changes
+    /// `instructions` and when the program is reset.
+    #[serde(skip)]
+    #[builder(setter(skip))]
+    structural_hash: OnceLock<u64>,
+}
+
+/// How a program came to be, linking it to the programs it was bred from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum Origin {
+    /// Generated from scratch, or saved before origins were kept.
+    #[default]
+    Generated,
+    Mutation {
+        parent: Uuid,
+    },
+    /// The first parent is the mate whose genes outside of the exchanged segment were kept.
+    Crossover {
+        parents: (Uuid, Uuid),
+    },
 }
 
 impl PartialEq for Program {
@@ -75,9 +224,14 @@ impl PartialEq for Program {
     }
 }
 
+/// Programs are ordered by fitness, ties going to the shorter program and then to the smaller id, so
+/// sorting a population gives the same order whatever its initial order.
 impl Ord for Program {
     fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        f64::total_cmp(&self.fitness, &other.fitness)
+        OrderedFitness(self.fitness)
+            .cmp(&OrderedFitness(other.fitness))
+            .then_with(|| other.instructions.len().cmp(&self.instructions.len()))
+            .then_with(|| other.id.cmp(&self.id))
     }
 }
 
@@ -89,11 +243,424 @@ impl PartialOrd for Program {
     }
 }
 
-impl Program {
-    pub fn run(&mut self, input: &impl State) {
+/// Individuals built around a [`Program`], giving analyses access to their code.
+pub trait AsProgram {
+    fn as_program(&self) -> &Program;
+
+    /// The registers read to choose an action or class.
+    fn output_registers(&self) -> Vec<usize> {
+        self.as_program().output_registers()
+    }
+
+    fn length(&self) -> usize {
+        self.as_program().instructions.len()
+    }
+
+    /// Number of instructions which can influence the output registers.
+    fn effective_length(&self) -> usize {
+        self.as_program()
+            .effective_instruction_indices(&self.output_registers())
+            .len()
+    }
+}
+
+impl AsProgram for Program {
+    fn as_program(&self) -> &Program {
+        self
+    }
+}
+
+/// Appends the `candidates` structurally different from every individual of `population` and from
+/// each other, returning how many were dropped as duplicates.
+pub fn extend_distinct<I>(population: &mut Vec<I>, candidates: impl IntoIterator<Item = I>) -> usize
+where
+    I: AsProgram,
+{
+    let mut seen = population
+        .iter()
+        .map(|individual| individual.as_program().structural_hash())
+        .collect::<HashSet<_>>();
+    let mut n_duplicates = 0;
+
+    for candidate in candidates {
+        match seen.insert(candidate.as_program().structural_hash()) {
+            true => population.push(candidate),
+            false => n_duplicates += 1,
+        }
+    }
+
+    n_duplicates
+}
+
+/// Prints one instruction per line; the output can be loaded back with [`Program::parse`].
+impl Display for Program {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         for instruction in &self.instructions {
-            instruction.apply(&mut self.registers, input)
+            writeln!(f, "{}", instruction)?;
+        }
+
+        Ok(())
+    }
+}
+
+fn register_node(
+    definitions: &mut HashMap<usize, String>,
+    lines: &mut Vec<String>,
+    register: usize,
+) -> String {
+    definitions
+        .entry(register)
+        .or_insert_with(|| {
+            lines.push(format!(
+                "    r{0} [label=\"r{0}\", shape=plaintext];",
+                register
+            ));
+            format!("r{}", register)
+        })
+        .clone()
+}
+
+impl Program {
+    /// The registers read when an action or class is chosen.
+    pub fn output_registers(&self) -> Vec<usize> {
+        self.registers.output_registers().collect()
+    }
+
+    /// Returns the (ordered) indices of the instructions which can influence the `outputs` registers.
+    ///
+    /// Every operation is treated as reading both of its operands, so the result may contain
+    /// instructions which are not strictly effective, but never omits one which is.
+    pub fn effective_instruction_indices(&self, outputs: &[usize]) -> Vec<usize> {
+        let mut effective_registers: HashSet<usize> = outputs.iter().copied().collect();
+        let mut indices = vec![];
+
+        for (idx, instruction) in self.instructions.iter().enumerate().rev() {
+            // Overwriting the destination hides earlier writes to it, unless it is read again.
+            if effective_registers.remove(&instruction.dest()) {
+                indices.push(idx);
+                effective_registers.extend(instruction.read_registers());
+            }
+        }
+
+        indices.reverse();
+        indices
+    }
+
+    /// Instructions which influence the output registers, see [`Program::effective_instruction_indices`].
+    pub fn effective_instructions(&self) -> Instructions {
+        self.effective_instruction_indices(&self.output_registers())
+            .into_iter()
+            .map(|idx| self.instructions[idx])
+            .collect()
+    }
+
+    /// The program running `instructions` instead, otherwise left as is (id, fitness, parameters).
+    pub(crate) fn with_instructions(&self, instructions: Instructions) -> Program {
+        Program {
+            instructions,
+            compiled: None,
+            structural_hash: OnceLock::new(),
+            ..self.clone()
+        }
+    }
+
+    /// Renders the dataflow of the effective instructions as a Graphviz (DOT) graph.
+    ///
+    /// Operations are nodes named after their instruction index, fed by the latest definition of their
+    /// first source register and by their second operand (a register or a scaled input).
+    pub fn to_dot(&self) -> String {
+        let mut lines = vec!["digraph program {".to_owned(), "    rankdir=LR;".to_owned()];
+        let mut definitions = HashMap::new();
+        let mut inputs = HashSet::new();
+
+        let outputs = self.output_registers();
+
+        for idx in self.effective_instruction_indices(&outputs) {
+            let instruction = &self.instructions[idx];
+            let node = format!("i{}", idx);
+
+            // Loads overwrite their register without reading it.
+            let source = (instruction.op() != Op::Load)
+                .then(|| register_node(&mut definitions, &mut lines, instruction.src1()));
+            lines.push(format!(
+                "    {} [label=\"{}\", shape=circle];",
+                node,
+                instruction.op()
+            ));
+            if let Some(source) = source {
+                lines.push(format!("    {} -> {};", source, node));
+            }
+
+            match instruction.src2() {
+                Operand::Input(input) => {
+                    if inputs.insert(input) {
+                        lines.push(format!("    in{0} [label=\"in{0}\", shape=box];", input));
+                    }
+                    lines.push(format!(
+                        "    in{} -> {} [label=\"x{}\"];",
+                        input,
+                        node,
+                        instruction.external_factor()
+                    ));
+                }
+                Operand::Register(register) => {
+                    let operand = register_node(&mut definitions, &mut lines, register);
+                    lines.push(format!("    {} -> {};", operand, node));
+                }
+                Operand::Immediate(value) => {
+                    lines.push(format!(
+                        "    c{} [label=\"{}\", shape=plaintext];",
+                        idx, value
+                    ));
+                    lines.push(format!("    c{} -> {};", idx, node));
+                }
+            }
+
+            definitions.insert(instruction.dest(), node);
+        }
+
+        for register in outputs {
+            let definition = register_node(&mut definitions, &mut lines, register);
+            lines.push(format!(
+                "    out{0} [label=\"r{0}\", shape=doublecircle];",
+                register
+            ));
+            lines.push(format!("    {} -> out{};", definition, register));
+        }
+
+        lines.push("}".to_owned());
+        lines.join("\n")
+    }
+
+    /// Parses a human-written policy, one statement per line or separated by `;`,
+    /// e.g. `r0 = in1 * 2.0; r1 = r0 + in0`. See [`Instruction::parse_statement`].
+    pub fn parse(text: &str, using: ProgramGeneratorParameters) -> Result<Program, Box<dyn Error>> {
+        let instruction_generator_parameters = using.instruction_generator_parameters;
+
+        let instructions = text
+            .split(|c: char| c == ';' || c == '\n')
+            .map(str::trim)
+            .filter(|line| !line.is_empty())
+            .map(|line| Instruction::parse_statement(line, instruction_generator_parameters))
+            .collect::<Result<Vec<Instructions>, _>>()?
+            .concat();
+
+        if instructions.is_empty() {
+            return Err("A program requires at least one instruction.".into());
         }
+
+        let registers = instruction_generator_parameters.registers();
+
+        Ok(Program {
+            id: random_id(),
+            instructions,
+            registers,
+            fitness: f64::NAN,
+            fitness_metadata: None,
+            numeric_parameters: using.numeric_parameters,
+            tie_break: using.tie_break,
+            frame_skip: using.frame_skip,
+            input_mask: None,
+            instruction_budget: using.instruction_budget,
+            compiled: None,
+            structural_hash: OnceLock::new(),
+            age: 0,
+            origin: Origin::Generated,
+        })
+    }
+
+    /// The inputs the program reads through its effective instructions and has not masked out, in
+    /// increasing order.
+    pub fn selected_features(&self) -> Vec<usize> {
+        let mask = self.input_mask.as_deref().unwrap_or(&[]);
+
+        self.effective_instruction_indices(&self.output_registers())
+            .into_iter()
+            .map(|idx| &self.instructions[idx])
+            .filter(|instruction| !instruction.reads_masked_input(mask))
+            .filter_map(|instruction| instruction.input())
+            .collect::<BTreeSet<_>>()
+            .into_iter()
+            .collect()
+    }
+
+    /// Executes the program over `input`. Instructions are compiled to [`Bytecode`] on the first run;
+    /// reset the program after editing `instructions` directly so the cached code is rebuilt.
+    pub fn run(&mut self, input: &impl State) -> ExecOutcome {
+        record_program_execution();
+
+        let instructions = &self.instructions;
+        let input_mask = self.input_mask.as_deref();
+        let bytecode = self
+            .compiled
+            .get_or_insert_with(|| Bytecode::compile_masked(instructions, input_mask));
+
+        bytecode.exec(
+            &mut self.registers,
+            input,
+            self.numeric_parameters,
+            self.instruction_budget,
+        )
+    }
+
+    /// Hash of the operations and operands of the instructions, so structurally identical programs
+    /// share it whatever their id. Computed with a [`StableHasher`], as ledgers keep it on disk. Cached
+    /// on first use until the program is mutated, bred or reset.
+    pub fn structural_hash(&self) -> u64 {
+        *self.structural_hash.get_or_init(|| {
+            let mut hasher = StableHasher::default();
+
+            for instruction in &self.instructions {
+                hasher.write_u64(instruction.dest() as u64);
+                hasher.write_u64(instruction.src1() as u64);
+                let (kind, operand) = match instruction.src2() {
+                    Operand::Register(register) => (0, register as u64),
+                    Operand::Input(input) => (1, input as u64),
+                    Operand::Immediate(value) => (2, value.to_bits()),
+                };
+                hasher.write_u8(kind);
+                hasher.write_u64(operand);
+                hasher.write(instruction.op().to_string().as_bytes());
+                hasher.write_u8(0xff);
+                hasher.write_u64(instruction.external_factor().to_bits());
+            }
+
+            hasher.finish()
+        })
+    }
+
+    /// Hash of everything deciding how the program behaves: its instructions (see
+    /// [`Program::structural_hash`]), input mask, frame skip, tie break, instruction budget, numeric
+    /// parameters and register layout. Programs sharing it score the same on the same trials, which is
+    /// what [`EvaluationStrategy::Keyed`](super::engines::fitness_engine::EvaluationStrategy::Keyed) and
+    /// evaluation ledgers key on.
+    pub fn genome_hash(&self) -> u64 {
+        let mut hasher = StableHasher::default();
+        hasher.write_u64(self.structural_hash());
+
+        match &self.input_mask {
+            Some(input_mask) => {
+                hasher.write_u8(1);
+                hasher.write_u64(input_mask.len() as u64);
+                input_mask
+                    .iter()
+                    .for_each(|gene| hasher.write_u8(*gene as u8));
+            }
+            None => hasher.write_u8(0),
+        }
+
+        hasher.write_u64(self.frame_skip as u64);
+        hasher.write(format!("{:?}", self.tie_break).as_bytes());
+        hasher.write_u8(0xff);
+        hasher.write(format!("{:?}", self.instruction_budget).as_bytes());
+        hasher.write_u8(0xff);
+        hasher.write(format!("{:?}", self.numeric_parameters.numeric_policy).as_bytes());
+        hasher.write_u8(0xff);
+        hasher.write_u64(self.numeric_parameters.register_bound.to_bits());
+        hasher.write_u64(self.registers.len() as u64);
+        hasher.write_u64(self.registers.n_actions() as u64);
+
+        hasher.finish()
+    }
+
+    /// The action (or class) selected by the action registers, `None` when they overflow.
+    pub fn select_action(&self, default_tie_break: TieBreak) -> Option<usize> {
+        match self.registers.argmax(ArgmaxInput::ActionRegisters).resolve(
+            self.tie_break.unwrap_or(default_tie_break),
+            self.registers.n_actions(),
+        ) {
+            ActionRegister::Value(action) => Some(action),
+            ActionRegister::Overflow => None,
+        }
+    }
+
+    /// Executes the instructions one by one, without compiling them.
+    pub fn interpret(&mut self, input: &impl State) -> ExecOutcome {
+        self.interpret_with(input, |_, _| {})
+    }
+
+    /// Interprets the program over `input`, returning every executed instruction along with the
+    /// registers right after it. The trace stops early when an overflow (or the instruction budget) ends
+    /// the execution.
+    pub fn exec_traced(&mut self, input: &impl State) -> Vec<TraceStep> {
+        let mut trace = vec![];
+
+        self.interpret_with(input, |instruction, registers| {
+            trace.push(TraceStep {
+                instruction: *instruction,
+                registers: registers.iter().copied().map(widen).collect(),
+            })
+        });
+
+        trace
+    }
+
+    /// Number of inputs the instructions read, i.e. the shortest input they can run over.
+    pub fn n_inputs_read(&self) -> usize {
+        self.instructions
+            .iter()
+            .filter_map(Instruction::input)
+            .max()
+            .map_or(0, |input| input + 1)
+    }
+
+    fn interpret_with(
+        &mut self,
+        input: &impl State,
+        mut on_step: impl FnMut(&Instruction, &Registers),
+    ) -> ExecOutcome {
+        record_program_execution();
+
+        let settle = self.numeric_parameters.numeric_policy != NumericPolicy::Propagate;
+        let instruction_budget = self.instruction_budget.unwrap_or(usize::MAX);
+
+        for (n_executed, instruction) in self.instructions.iter().enumerate() {
+            if n_executed >= instruction_budget {
+                self.registers.as_mut_slice().fill(RegisterValue::NAN);
+                return ExecOutcome::OutOfBounds;
+            }
+
+            match self.input_mask.as_deref() {
+                Some(mask) if instruction.reads_masked_input(mask) => {
+                    instruction.masked().apply(&mut self.registers, input)
+                }
+                _ => instruction.apply(&mut self.registers, input),
+            }
+
+            let settled = !settle
+                || self
+                    .numeric_parameters
+                    .settle(self.registers.as_mut_slice(), instruction.dest());
+
+            on_step(instruction, &self.registers);
+
+            if !settled {
+                return ExecOutcome::Invalidated;
+            }
+        }
+
+        ExecOutcome::Completed
+    }
+}
+
+/// An instruction executed by [`Program::exec_traced`] and the registers it left behind.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TraceStep {
+    pub instruction: Instruction,
+    pub registers: Vec<f64>,
+}
+
+impl Display for TraceStep {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let registers = self
+            .registers
+            .iter()
+            .enumerate()
+            .map(|(idx, value)| format!("r{}: {}", idx, value))
+            .collect::<Vec<_>>()
+            .join(", ");
+
+        write!(f, "{:<24} [{}]", self.instruction.to_string(), registers)
     }
 }
 
@@ -105,10 +672,7 @@ impl Generate<ProgramGeneratorParameters, Program> for GenerateEngine {
             ..
         } = using;
 
-        let registers = Registers::new(
-            instruction_generator_parameters.n_actions,
-            instruction_generator_parameters.n_extras,
-        );
+        let registers = instruction_generator_parameters.registers();
         let n_instructions = generator().gen_range(1..=max_instructions);
         let instructions =
             repeat_with(|| GenerateEngine::generate(instruction_generator_parameters))
@@ -116,40 +680,135 @@ impl Generate<ProgramGeneratorParameters, Program> for GenerateEngine {
                 .collect();
 
         Program {
-            id: Uuid::new_v4(),
+            id: random_id(),
             instructions,
             registers,
             fitness: f64::NAN,
+            fitness_metadata: None,
+            numeric_parameters: using.numeric_parameters,
+            tie_break: using.tie_break,
+            frame_skip: using.frame_skip,
+            // Every input starts enabled, masks only drop inputs through mutation.
+            input_mask: (using.mutation_parameters.input_mask_rate > 0.)
+                .then(|| vec![true; instruction_generator_parameters.n_inputs]),
+            instruction_budget: using.instruction_budget,
+            compiled: None,
+            structural_hash: OnceLock::new(),
+            age: 0,
+            origin: Origin::Generated,
         }
     }
 }
 
 impl Mutate<ProgramGeneratorParameters, Program> for MutateEngine {
     fn mutate(item: &mut Program, using: ProgramGeneratorParameters) {
-        // Pick instruction to mutate.
-        let instruction = item
-            .instructions
-            .iter_mut()
-            .choose(&mut generator())
-            .unwrap();
+        let ProgramGeneratorParameters {
+            max_instructions,
+            instruction_generator_parameters,
+            mutation_parameters,
+            ..
+        } = using;
+
+        // Micro-mutations: each instruction is mutated independently.
+        let mut n_mutated = 0;
+        for instruction in item.instructions.iter_mut() {
+            if generator().gen::<f64>() < mutation_parameters.instruction_mutation_rate {
+                MutateEngine::mutate(
+                    instruction,
+                    (instruction_generator_parameters, mutation_parameters),
+                );
+                n_mutated += 1;
+            }
+        }
+
+        // Ensure children always differ from their parent by at least one instruction.
+        if n_mutated == 0 {
+            let instruction = item
+                .instructions
+                .iter_mut()
+                .choose(&mut generator())
+                .unwrap();
+
+            MutateEngine::mutate(
+                instruction,
+                (instruction_generator_parameters, mutation_parameters),
+            );
+        }
+
+        if let Some(mask) = item.input_mask.as_mut() {
+            for gene in mask.iter_mut() {
+                if generator().gen::<f64>() < mutation_parameters.input_mask_rate {
+                    *gene = !*gene;
+                }
+            }
+        }
+
+        // Macro-mutations: insert or delete whole blocks.
+        let max_block_size = mutation_parameters.max_block_size.max(1);
+
+        if generator().gen::<f64>() < mutation_parameters.insertion_rate
+            && item.instructions.len() < max_instructions
+        {
+            let block_size = generator()
+                .gen_range(1..=max_block_size)
+                .min(max_instructions - item.instructions.len());
+            let position = generator().gen_range(0..=item.instructions.len());
+
+            for _ in 0..block_size {
+                item.instructions.insert(
+                    position,
+                    GenerateEngine::generate(instruction_generator_parameters),
+                );
+            }
+        }
+
+        if generator().gen::<f64>() < mutation_parameters.deletion_rate
+            && item.instructions.len() > 1
+        {
+            let block_size = generator()
+                .gen_range(1..=max_block_size)
+                .min(item.instructions.len() - 1);
+            let start = generator().gen_range(0..=(item.instructions.len() - block_size));
 
-        MutateEngine::mutate(instruction, using.instruction_generator_parameters);
+            item.instructions.drain(start..(start + block_size));
+        }
 
+        item.structural_hash = OnceLock::new();
+        item.age = 0;
+        item.origin = Origin::Mutation { parent: item.id };
         ResetEngine::reset(&mut item.id);
         ResetEngine::reset(item);
     }
 }
 
-impl Breed<Program> for BreedEngine {
-    fn two_point_crossover(mate_1: &Program, mate_2: &Program) -> (Program, Program) {
-        let (child_1_instructions, child_2_instructions) =
-            BreedEngine::two_point_crossover(&mate_1.instructions, &mate_2.instructions);
+impl Program {
+    /// Crossover exchanging segments which write to the same registers, see [`aligned_crossover`].
+    pub fn aligned_crossover(mate_1: &Program, mate_2: &Program) -> (Program, Program) {
+        let children_instructions = aligned_crossover(&mate_1.instructions, &mate_2.instructions);
+
+        Program::offspring(mate_1, mate_2, children_instructions)
+    }
 
+    fn offspring(
+        mate_1: &Program,
+        mate_2: &Program,
+        (child_1_instructions, child_2_instructions): (Instructions, Instructions),
+    ) -> (Program, Program) {
         let mut child_1 = mate_1.clone();
         let mut child_2 = mate_2.clone();
 
         child_1.instructions = child_1_instructions;
         child_2.instructions = child_2_instructions;
+        child_1.structural_hash = OnceLock::new();
+        child_2.structural_hash = OnceLock::new();
+        child_1.age = 0;
+        child_2.age = 0;
+        child_1.origin = Origin::Crossover {
+            parents: (mate_1.id, mate_2.id),
+        };
+        child_2.origin = Origin::Crossover {
+            parents: (mate_2.id, mate_1.id),
+        };
 
         ResetEngine::reset(&mut child_1.id);
         ResetEngine::reset(&mut child_2.id);
@@ -161,21 +820,53 @@ impl Breed<Program> for BreedEngine {
     }
 }
 
+impl Breed<Program> for BreedEngine {
+    fn two_point_crossover(mate_1: &Program, mate_2: &Program) -> (Program, Program) {
+        let children_instructions =
+            BreedEngine::two_point_crossover(&mate_1.instructions, &mate_2.instructions);
+
+        Program::offspring(mate_1, mate_2, children_instructions)
+    }
+}
+
 #[cfg(test)]
 mod tests {
 
-    use crate::core::instruction::InstructionGeneratorParameters;
+    use crate::core::instruction::InstructionGeneratorParametersBuilder;
+    use crate::core::registers::Readout;
+    use crate::extensions::coevolution::Observation;
 
     use super::*;
 
+    /// Parameters of programs of up to 10 instructions choosing between 2 actions of `n_inputs` inputs,
+    /// read with the default factor (10), once `configure` set the fields the test exercises.
+    fn program_params(
+        n_inputs: usize,
+        configure: impl FnOnce(
+            &mut InstructionGeneratorParametersBuilder,
+            &mut ProgramGeneratorParametersBuilder,
+        ),
+    ) -> ProgramGeneratorParameters {
+        let mut instruction_parameters = InstructionGeneratorParametersBuilder::default();
+        instruction_parameters.n_inputs(n_inputs).n_actions(2);
+        let mut program_parameters = ProgramGeneratorParametersBuilder::default();
+        program_parameters.max_instructions(10);
+
+        configure(&mut instruction_parameters, &mut program_parameters);
+
+        program_parameters
+            .instruction_generator_parameters(instruction_parameters.build().unwrap())
+            .build()
+            .unwrap()
+    }
+
     #[test]
     fn given_instructions_when_breed_then_two_children_are_produced_using_genes_of_parents() {
-        let params = InstructionGeneratorParameters {
-            n_extras: 1,
-            external_factor: 10.,
-            n_actions: 4,
-            n_inputs: 2,
-        };
+        let params = InstructionGeneratorParametersBuilder::default()
+            .n_actions(4)
+            .n_inputs(2)
+            .build()
+            .unwrap();
         let instructions_a: Instructions =
             (0..10).map(|_| GenerateEngine::generate(params)).collect();
         let instructions_b: Instructions =
@@ -194,16 +885,9 @@ mod tests {
 
     #[test]
     fn given_programs_when_two_point_crossover_then_two_children_are_produced() {
-        let instruction_generator_parameters = InstructionGeneratorParameters {
-            n_extras: 1,
-            external_factor: 10.,
-            n_actions: 2,
-            n_inputs: 4,
-        };
-        let program_params = ProgramGeneratorParameters {
-            max_instructions: 100,
-            instruction_generator_parameters,
-        };
+        let program_params = program_params(4, |_, program| {
+            program.max_instructions(100);
+        });
 
         let program_a = GenerateEngine::generate(program_params);
         let program_b = GenerateEngine::generate(program_params);
@@ -218,4 +902,266 @@ mod tests {
         assert_ne!(program_b, child_a);
         assert_ne!(program_b, child_b);
     }
+
+    #[test]
+    fn given_policy_text_when_parsed_then_program_contains_each_instruction() {
+        let program_params = program_params(4, |_, _| {});
+
+        let program = Program::parse(
+            "r0 = r0 * in1; r1 = r1 + r0\nr2 = r2 - in3;",
+            program_params,
+        )
+        .unwrap();
+
+        assert_eq!(program.instructions.len(), 3);
+        assert!(program.fitness.is_nan());
+        assert!(Program::parse(" ; ", program_params).is_err());
+    }
+
+    #[test]
+    fn given_policy_reading_inputs_first_when_printed_and_parsed_then_it_round_trips() {
+        let program_params = program_params(2, |instruction, _| {
+            instruction.external_factor(1.);
+        });
+
+        let mut program = Program::parse("r0 = in1 * 2.0; r1 = r0 + in0", program_params).unwrap();
+        let parsed = Program::parse(&program.to_string(), program_params).unwrap();
+
+        assert_eq!(parsed.instructions, program.instructions);
+
+        program.run(&Observation::new(vec![1., 3.]));
+
+        assert_eq!(program.registers.get(0), 6.);
+        assert_eq!(program.registers.get(1), 7.);
+    }
+
+    #[test]
+    fn given_generated_program_when_printed_and_parsed_then_instructions_are_identical() {
+        let program_params = program_params(4, |instruction, program| {
+            instruction.n_extras(2).external_factor(0.1).n_actions(3);
+            program.max_instructions(50);
+        });
+
+        let program = GenerateEngine::generate(program_params);
+        let parsed = Program::parse(&program.to_string(), program_params).unwrap();
+
+        assert_eq!(parsed.instructions, program.instructions);
+    }
+
+    #[test]
+    fn given_program_with_introns_when_rendered_then_only_effective_instructions_are_drawn() {
+        let program_params = program_params(4, |_, _| {});
+
+        let program =
+            Program::parse("r2 = r2 + in0; r0 = r0 * in1; r1 = r1 + r0", program_params).unwrap();
+
+        assert_eq!(program.effective_instruction_indices(&[0, 1]), vec![1, 2]);
+        assert_eq!(program.effective_instruction_indices(&[0]), vec![1]);
+
+        let dot = program.to_dot();
+
+        assert!(dot.starts_with("digraph program {"));
+        assert!(dot.contains("i1 -> i2;"));
+        assert!(dot.contains("in1 -> i1"));
+        assert!(!dot.contains("i0 ["));
+    }
+
+    #[test]
+    fn given_macro_mutations_when_program_is_mutated_then_length_stays_within_bounds() {
+        let max_instructions = 20;
+        let program_params = program_params(4, |_, program| {
+            program
+                .max_instructions(max_instructions)
+                .mutation_parameters(MutationParameters {
+                    instruction_mutation_rate: 0.1,
+                    insertion_rate: 0.5,
+                    deletion_rate: 0.5,
+                    max_block_size: 5,
+                    ..Default::default()
+                });
+        });
+
+        let mut program = GenerateEngine::generate(program_params);
+
+        for _ in 0..1000 {
+            MutateEngine::mutate(&mut program, program_params);
+
+            assert!(program.instructions.len() >= 1);
+            assert!(program.instructions.len() <= max_instructions);
+        }
+    }
+
+    #[test]
+    fn given_input_mask_when_program_is_ran_then_masked_inputs_are_read_as_zero() {
+        let program_params = program_params(4, |_, program| {
+            program.mutation_parameters(MutationParameters {
+                input_mask_rate: 0.5,
+                ..Default::default()
+            });
+        });
+
+        let generated = GenerateEngine::generate(program_params);
+        assert_eq!(generated.input_mask, Some(vec![true; 4]));
+
+        let mut program = Program::parse("r0 = r0 + in1; r1 = r1 + in2", program_params).unwrap();
+        program.input_mask = Some(vec![true, false, true, true]);
+
+        let input = Observation::new(vec![1., 2., 3., 4.]);
+        program.run(&input);
+        let compiled = program.registers.clone();
+
+        ResetEngine::reset(&mut program);
+        program.interpret(&input);
+
+        for registers in [compiled, program.registers.clone()] {
+            assert_eq!(registers.get(0), 0.);
+            assert_ne!(registers.get(1), 0.);
+        }
+        assert_eq!(program.selected_features(), vec![2]);
+    }
+
+    #[test]
+    fn given_program_when_traced_then_registers_are_recorded_after_each_instruction() {
+        let program_params = program_params(1, |_, _| {});
+
+        let mut program = Program::parse("r0 = r0 + in0; r2 = r0 * 0.5", program_params).unwrap();
+        let trace = program.exec_traced(&Observation::new(vec![1.]));
+
+        assert_eq!(program.n_inputs_read(), 1);
+        assert_eq!(trace.len(), 2);
+        assert_eq!(trace[0].instruction, program.instructions[0]);
+        assert_eq!(trace[0].registers, vec![10., 0., 0.]);
+        assert_eq!(trace[1].registers, vec![10., 0., 5.]);
+        assert_eq!(
+            trace[1].registers,
+            program
+                .registers
+                .iter()
+                .copied()
+                .map(widen)
+                .collect::<Vec<_>>()
+        );
+        assert!(trace[1].to_string().ends_with("[r0: 10, r1: 0, r2: 5]"));
+    }
+
+    #[test]
+    fn given_programs_when_hashed_then_only_structure_matters() {
+        let program_params = program_params(1, |_, _| {});
+
+        let program = Program::parse("r0 = r0 + in0; r1 = r1 - in0", program_params).unwrap();
+        let twin = Program::parse("r0 = r0 + in0; r1 = r1 - in0", program_params).unwrap();
+        assert_ne!(program.id, twin.id);
+        assert_eq!(program.structural_hash(), twin.structural_hash());
+
+        let mut mutated = program.clone();
+        MutateEngine::mutate(&mut mutated, program_params);
+        assert_eq!(mutated.structural_hash(), {
+            let mut fresh = mutated.clone();
+            fresh.structural_hash = OnceLock::new();
+            fresh.structural_hash()
+        });
+
+        let other = Program::parse("r1 = r1 - in0", program_params).unwrap();
+        let mut population = vec![program];
+        let n_duplicates = extend_distinct(&mut population, [twin, other.clone(), other]);
+
+        assert_eq!(n_duplicates, 2);
+        assert_eq!(population.len(), 2);
+    }
+
+    #[test]
+    fn given_tied_fitness_when_ranked_then_order_does_not_depend_on_input_order() {
+        let program_params = program_params(1, |_, _| {});
+
+        // `-0` and `0` tie, as do positive and negative `NaN`s.
+        let population = [
+            ("r0 = r0 + in0; r1 = r1 - in0", 1.),
+            ("r0 = r0 + in0; r1 = r1 - in0", -0.),
+            ("r1 = r1 - in0", 0.),
+            ("r0 = r0 + in0", f64::NAN),
+            ("r0 = r0 + in0", -f64::NAN),
+        ]
+        .into_iter()
+        .map(|(text, fitness)| {
+            let mut program = Program::parse(text, program_params).unwrap();
+            StatusEngine::set_fitness(&mut program, fitness);
+            program
+        })
+        .collect::<Vec<_>>();
+
+        let rank = |mut population: Vec<Program>| {
+            population.sort_by(|a, b| b.cmp(a));
+            population
+                .into_iter()
+                .map(|program| program.id)
+                .collect::<Vec<_>>()
+        };
+
+        let ranked = rank(population.clone());
+        assert_eq!(rank(population.iter().rev().cloned().collect()), ranked);
+
+        // The shorter of the programs tied at zero goes first, `NaN`s last.
+        assert_eq!(
+            ranked[..3],
+            [population[0].id, population[2].id, population[1].id]
+        );
+        assert!(ranked[3..].contains(&population[3].id));
+        assert!(ranked[3..].contains(&population[4].id));
+    }
+
+    #[test]
+    fn given_instruction_budget_when_exceeded_then_execution_is_out_of_bounds() {
+        let program_params = |budget| {
+            program_params(1, |_, program| {
+                program.instruction_budget(budget);
+            })
+        };
+
+        let text = "r0 = r0 + in0; r1 = r1 - in0";
+        let input = Observation::new(vec![1.]);
+
+        let mut bounded = Program::parse(text, program_params(Some(1))).unwrap();
+        assert_eq!(bounded.run(&input), ExecOutcome::OutOfBounds);
+        assert!(bounded.registers.iter().all(|value| value.is_nan()));
+        assert_eq!(bounded.select_action(TieBreak::First), None);
+
+        ResetEngine::reset(&mut bounded);
+        assert_eq!(bounded.interpret(&input), ExecOutcome::OutOfBounds);
+        assert!(bounded.registers.iter().all(|value| value.is_nan()));
+
+        let mut program = Program::parse(text, program_params(Some(2))).unwrap();
+        assert_eq!(program.run(&input), ExecOutcome::Completed);
+        assert_eq!(program.select_action(TieBreak::First), Some(0));
+    }
+
+    #[test]
+    fn given_extra_output_registers_when_read_out_then_actions_sum_their_outputs() {
+        let program_params = |readout| {
+            program_params(1, |instruction, _| {
+                instruction
+                    .external_factor(1.)
+                    .n_outputs(Some(4))
+                    .readout(readout);
+            })
+        };
+
+        // Action 0 reads registers 0 and 2, action 1 registers 1 and 3; register 4 is a working register.
+        let text = "r1 = r1 + in0; r2 = r2 + in0; r2 = r2 + in0; r4 = r4 + in0";
+        let input = Observation::new(vec![1.]);
+
+        let mut summed = Program::parse(text, program_params(Readout::Sum)).unwrap();
+        summed.run(&input);
+
+        assert_eq!(summed.registers.len(), 5);
+        assert_eq!(summed.select_action(TieBreak::Fail), Some(0));
+        assert_eq!(summed.output_registers(), vec![0, 1, 2, 3]);
+        assert_eq!(summed.effective_instructions().len(), 3);
+
+        // Without a readout the outputs past the actions are working registers.
+        let mut direct = Program::parse(text, program_params(Readout::Direct)).unwrap();
+        direct.run(&input);
+
+        assert_eq!(direct.select_action(TieBreak::Fail), Some(1));
+        assert_eq!(direct.output_registers(), vec![0, 1]);
+    }
 }
@@ -1,15 +1,69 @@
 use core::slice::Iter;
-use std::{ops::Index, slice::SliceIndex};
+use std::{borrow::Cow, ops::Index, ops::Range, slice::SliceIndex};
 
+use clap::{Args, ValueEnum};
+use derive_builder::Builder;
 use itertools::Itertools;
 use rand::seq::SliceRandom;
-use serde::{Deserialize, Deserializer, Serialize};
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
 
 use crate::utils::random::generator;
 
 use super::engines::reset_engine::{Reset, ResetEngine};
 
-fn deserialize_vec_with_null<'de, D>(deserializer: D) -> Result<Vec<f64>, D::Error>
+/// The floating point type registers are computed in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub enum Precision {
+    /// Registers saved before the precision was recorded were all `f64`.
+    #[default]
+    F64,
+    /// Halves the memory registers take and doubles the lanes of the batch interpreter, at the cost of
+    /// precision and range.
+    F32,
+}
+
+/// The value of a register: `f64`, or `f32` when built with the `f32-registers` feature. Inputs,
+/// constants and everything computed from the registers stay `f64`.
+#[cfg(not(feature = "f32-registers"))]
+pub type RegisterValue = f64;
+#[cfg(feature = "f32-registers")]
+pub type RegisterValue = f32;
+
+/// The precision of [`RegisterValue`].
+#[cfg(not(feature = "f32-registers"))]
+pub const PRECISION: Precision = Precision::F64;
+#[cfg(feature = "f32-registers")]
+pub const PRECISION: Precision = Precision::F32;
+
+/// Rounds `value` to the precision of the registers.
+#[allow(clippy::unnecessary_cast)]
+pub fn narrow(value: f64) -> RegisterValue {
+    value as RegisterValue
+}
+
+/// The exact `f64` value of a register.
+#[allow(clippy::unnecessary_cast)]
+pub fn widen(value: RegisterValue) -> f64 {
+    value as f64
+}
+
+/// Mirrors [`deserialize_vec_with_null`] so binary formats round-trip; JSON output is unchanged as
+/// `NaN` is written as `null` either way.
+fn serialize_vec_with_null<S>(data: &[RegisterValue], serializer: S) -> Result<S::Ok, S::Error>
+where
+    S: Serializer,
+{
+    let vec_opt: Option<Vec<Option<f64>>> = Some(
+        data.iter()
+            .map(|x| if x.is_nan() { None } else { Some(widen(*x)) })
+            .collect(),
+    );
+
+    vec_opt.serialize(serializer)
+}
+
+/// Values saved in another precision are rounded to the precision of the registers.
+fn deserialize_vec_with_null<'de, D>(deserializer: D) -> Result<Vec<RegisterValue>, D::Error>
 where
     D: Deserializer<'de>,
 {
@@ -17,15 +71,122 @@ where
     Ok(vec_opt
         .unwrap_or_default()
         .into_iter()
-        .map(|x| x.unwrap_or(f64::NAN))
+        .map(|x| narrow(x.unwrap_or(f64::NAN)))
         .collect())
 }
 
+/// What happens to a register once an instruction writes a value out of range into it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ValueEnum)]
+pub enum NumericPolicy {
+    /// Values are kept as is; a non-finite action register makes the program invalid once it acts.
+    #[default]
+    Propagate,
+    /// Values are clamped to `[-register_bound, register_bound]`, `NaN` becoming `0`.
+    Clamp,
+    /// Infinities become the largest finite values, `NaN` becoming `0`.
+    Saturate,
+    /// Execution stops at the first non-finite value and every register is set to `NaN`,
+    /// so the program is marked invalid.
+    Invalidate,
+}
+
+impl NumericPolicy {
+    /// Returns the value to store, or `None` when the program must be invalidated.
+    pub fn apply(&self, value: f64, register_bound: f64) -> Option<f64> {
+        match self {
+            NumericPolicy::Propagate => Some(value),
+            _ if value.is_nan() && *self != NumericPolicy::Invalidate => Some(0.),
+            NumericPolicy::Clamp => Some(value.clamp(-register_bound, register_bound)),
+            NumericPolicy::Saturate if value == f64::INFINITY => Some(widen(RegisterValue::MAX)),
+            NumericPolicy::Saturate if value == f64::NEG_INFINITY => {
+                Some(widen(RegisterValue::MIN))
+            }
+            NumericPolicy::Saturate => Some(value),
+            NumericPolicy::Invalidate if value.is_finite() => Some(value),
+            NumericPolicy::Invalidate => None,
+        }
+    }
+}
+
+fn default_register_bound() -> f64 {
+    1e12
+}
+
+#[derive(Clone, Copy, Debug, Args, Serialize, Deserialize, PartialEq, Builder)]
+pub struct NumericParameters {
+    #[arg(long, value_enum, default_value_t = NumericPolicy::Propagate)]
+    #[builder(default)]
+    #[serde(default)]
+    pub numeric_policy: NumericPolicy,
+    /// Largest absolute register value under [`NumericPolicy::Clamp`].
+    #[arg(long, default_value = "1e12")]
+    #[builder(default = "1e12")]
+    #[serde(default = "default_register_bound")]
+    pub register_bound: f64,
+}
+
+impl Default for NumericParameters {
+    fn default() -> Self {
+        Self {
+            numeric_policy: NumericPolicy::Propagate,
+            register_bound: default_register_bound(),
+        }
+    }
+}
+
+impl NumericParameters {
+    /// Applies the policy to the register at `idx`, returning `false` once the registers were invalidated.
+    pub fn settle(&self, registers: &mut [RegisterValue], idx: usize) -> bool {
+        match self
+            .numeric_policy
+            .apply(widen(registers[idx]), self.register_bound)
+        {
+            Some(value) => {
+                registers[idx] = narrow(value);
+                true
+            }
+            None => {
+                registers.fill(RegisterValue::NAN);
+                false
+            }
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Registers {
-    #[serde(deserialize_with = "deserialize_vec_with_null")]
-    data: Vec<f64>,
+    #[serde(
+        serialize_with = "serialize_vec_with_null",
+        deserialize_with = "deserialize_vec_with_null"
+    )]
+    data: Vec<RegisterValue>,
     n_actions: usize,
+    /// Registers read by the readout when above `n_actions` (`0` for programs saved before readouts).
+    #[serde(default)]
+    n_outputs: usize,
+    #[serde(default)]
+    readout: Readout,
+    /// The precision `data` was computed in, which may differ from [`PRECISION`] until the registers
+    /// are reset when they were loaded from another build.
+    #[serde(default)]
+    precision: Precision,
+}
+
+/// How the output registers of a program are mapped to actions (or classes).
+///
+/// With `n_outputs` output registers and `n_actions` actions, action `a` reads the outputs
+/// `a, a + n_actions, a + 2 * n_actions, ...` below `n_outputs`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+pub enum Readout {
+    /// Output `a` scores action `a`; the outputs past the actions are left to the program as working
+    /// registers.
+    #[default]
+    Direct,
+    /// Actions are scored by the sum of their outputs, a linear readout with unit weights.
+    Sum,
+    /// Actions are scored by the largest of their outputs, each output standing for one (sub)strategy
+    /// of its action.
+    Max,
 }
 
 pub enum ArgmaxResult {
@@ -38,6 +199,20 @@ pub enum ActionRegister {
     Overflow,
 }
 
+/// Which register wins when several share the maximum value.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+pub enum TieBreak {
+    /// Ties are treated as an overflow.
+    Fail,
+    Random,
+    /// The tied register with the lowest index.
+    First,
+    /// The lowest-indexed tied working register (index `>= n_actions`), falling back to [`TieBreak::First`]
+    /// when only action registers tie. Meant for Q-learning, where every register maps to a row of the
+    /// Q-table.
+    LowestIndexNonOutput,
+}
+
 impl ArgmaxResult {
     pub fn one(&self) -> ActionRegister {
         match self {
@@ -56,6 +231,28 @@ impl ArgmaxResult {
             _ => ActionRegister::Overflow,
         }
     }
+
+    pub fn resolve(&self, tie_break: TieBreak, n_actions: usize) -> ActionRegister {
+        match (self, tie_break) {
+            (_, TieBreak::Fail) => self.one(),
+            (_, TieBreak::Random) => self.any(),
+            (ArgmaxResult::MaxValues(indices), TieBreak::First) if !indices.is_empty() => {
+                ActionRegister::Value(indices[0])
+            }
+            (ArgmaxResult::MaxValues(indices), TieBreak::LowestIndexNonOutput)
+                if !indices.is_empty() =>
+            {
+                let register = indices
+                    .iter()
+                    .copied()
+                    .find(|idx| *idx >= n_actions)
+                    .unwrap_or(indices[0]);
+
+                ActionRegister::Value(register)
+            }
+            _ => ActionRegister::Overflow,
+        }
+    }
 }
 
 pub enum ArgmaxInput {
@@ -68,27 +265,105 @@ impl Reset<Registers> for ResetEngine {
         for value in item.data.as_mut_slice() {
             *value = 0.
         }
+        item.precision = PRECISION;
     }
 }
 
 impl Registers {
     pub fn new(n_actions: usize, n_working_registers: usize) -> Self {
-        let data = vec![0.; n_actions + n_working_registers];
+        Self::with_readout(n_actions, n_actions, n_working_registers, Readout::Direct)
+    }
+
+    /// Registers whose first `n_outputs` (at least `n_actions`) registers are mapped to the actions by
+    /// `readout`.
+    pub fn with_readout(
+        n_actions: usize,
+        n_outputs: usize,
+        n_working_registers: usize,
+        readout: Readout,
+    ) -> Self {
+        let n_outputs = n_outputs.max(n_actions);
+        let data = vec![0.; n_outputs + n_working_registers];
+
+        Registers {
+            data,
+            n_actions,
+            n_outputs,
+            readout,
+            precision: PRECISION,
+        }
+    }
+
+    /// Wraps existing register values, rounded to the precision of the registers, the first
+    /// `n_actions` being the action registers.
+    pub fn from_values(data: Vec<f64>, n_actions: usize) -> Self {
+        Registers {
+            data: data.into_iter().map(narrow).collect(),
+            n_actions,
+            n_outputs: n_actions,
+            readout: Readout::Direct,
+            precision: PRECISION,
+        }
+    }
+
+    pub fn readout(&self) -> Readout {
+        self.readout
+    }
+
+    pub fn precision(&self) -> Precision {
+        self.precision
+    }
+
+    /// The registers the readout scores actions from.
+    pub fn output_registers(&self) -> Range<usize> {
+        match self.readout {
+            Readout::Direct => 0..self.n_actions,
+            Readout::Sum | Readout::Max => 0..self.n_outputs.max(self.n_actions),
+        }
+    }
+
+    /// The score of every action, as read from the output registers.
+    pub fn action_scores(&self) -> Cow<[RegisterValue]> {
+        let outputs = &self.data[self.output_registers()];
+
+        let combine: fn(RegisterValue, RegisterValue) -> RegisterValue = match self.readout {
+            Readout::Direct => return Cow::Borrowed(outputs),
+            Readout::Sum => |a, b| a + b,
+            // Unlike `f64::max`, keeps an overflowing output from going unnoticed.
+            Readout::Max => |a, b| {
+                if a.is_nan() || b.is_nan() {
+                    RegisterValue::NAN
+                } else {
+                    a.max(b)
+                }
+            },
+        };
 
-        Registers { data, n_actions }
+        let scores = (0..self.n_actions)
+            .map(|action| {
+                outputs
+                    .iter()
+                    .skip(action)
+                    .step_by(self.n_actions)
+                    .copied()
+                    .reduce(combine)
+                    .unwrap()
+            })
+            .collect();
+
+        Cow::Owned(scores)
     }
 
     pub fn argmax(&self, range: ArgmaxInput) -> ArgmaxResult {
-        let range_to_use = match range {
-            ArgmaxInput::All => 0..(self.data.len()),
-            ArgmaxInput::ActionRegisters => 0..(self.n_actions),
+        let sliced_data = match range {
+            ArgmaxInput::All => Cow::Borrowed(&self.data[..]),
+            ArgmaxInput::ActionRegisters => self.action_scores(),
         };
 
-        let sliced_data = &self.data[range_to_use];
         let max_value = sliced_data
             .iter()
             .copied()
-            .reduce(f64::max)
+            .reduce(RegisterValue::max)
             .expect("Sliced values to not be of cardinality 0.");
 
         if max_value.is_infinite() || max_value.is_nan() {
@@ -106,29 +381,75 @@ impl Registers {
         ArgmaxResult::MaxValues(max_indices)
     }
 
+    pub fn n_actions(&self) -> usize {
+        self.n_actions
+    }
+
+    /// Softmax of the action scores, `None` when one of them is not finite.
+    pub fn softmax(&self) -> Option<Vec<f64>> {
+        let actions = self.action_scores();
+
+        if actions.iter().any(|value| !value.is_finite()) {
+            return None;
+        }
+
+        let max_value = actions
+            .iter()
+            .copied()
+            .map(widen)
+            .fold(f64::NEG_INFINITY, f64::max);
+        let exponentials = actions
+            .iter()
+            .map(|value| (widen(*value) - max_value).exp())
+            .collect_vec();
+        let total = exponentials.iter().sum::<f64>();
+
+        Some(
+            exponentials
+                .into_iter()
+                .map(|value| value / total)
+                .collect(),
+        )
+    }
+
     pub fn len(&self) -> usize {
         let Registers { data, .. } = self;
         data.len()
     }
 
-    pub fn update(&mut self, index: usize, value: f64) {
+    pub fn update(&mut self, index: usize, value: RegisterValue) {
         let Registers { data, .. } = self;
         data[index] = value;
     }
 
-    pub fn get(&self, index: usize) -> &f64 {
+    /// The value of the register at `index`, as an `f64` whatever the precision of the registers.
+    pub fn get(&self, index: usize) -> f64 {
         let Registers { data, .. } = self;
-        data.get(index).unwrap()
+        widen(data[index])
+    }
+
+    pub fn as_mut_slice(&mut self) -> &mut [RegisterValue] {
+        self.data.as_mut_slice()
     }
 
-    pub fn iter(&self) -> Iter<f64> {
+    pub fn iter(&self) -> Iter<RegisterValue> {
         self.data.iter()
     }
+
+    /// Mean absolute value of the registers, e.g. to penalize programs relying on huge intermediate
+    /// values.
+    pub fn magnitude(&self) -> f64 {
+        self.data
+            .iter()
+            .map(|value| widen(value.abs()))
+            .sum::<f64>()
+            / self.data.len().max(1) as f64
+    }
 }
 
 impl<Idx> Index<Idx> for Registers
 where
-    Idx: SliceIndex<[f64]>,
+    Idx: SliceIndex<[RegisterValue]>,
 {
     type Output = Idx::Output;
 
@@ -139,7 +460,11 @@ where
 
 #[cfg(test)]
 mod tests {
-    use crate::core::registers::Registers;
+    use crate::core::registers::{
+        widen, ActionRegister, ArgmaxInput, NumericParameters, NumericPolicy, Precision, Readout,
+        RegisterValue, Registers, TieBreak, PRECISION,
+    };
+    use crate::utils::misc::VoidResultAnyError;
 
     #[test]
     fn given_registers_when_indexed_with_range_then_slice_is_returned() {
@@ -150,4 +475,140 @@ mod tests {
 
         assert_eq!(slice, &[1., 0.]);
     }
+
+    #[test]
+    fn given_extra_outputs_when_read_out_then_actions_combine_their_outputs() {
+        let scores = |readout: Readout, values: [RegisterValue; 5]| {
+            let mut registers = Registers::with_readout(2, 4, 1, readout);
+            registers.as_mut_slice().copy_from_slice(&values);
+            registers.action_scores().into_owned()
+        };
+
+        // Action 0 reads outputs 0 and 2, action 1 outputs 1 and 3; register 4 is a working register.
+        let values = [1., 0., 2., 5., 9.];
+
+        assert_eq!(scores(Readout::Direct, values), vec![1., 0.]);
+        assert_eq!(scores(Readout::Sum, values), vec![3., 5.]);
+        assert_eq!(scores(Readout::Max, values), vec![2., 5.]);
+        assert!(scores(Readout::Max, [1., 0., RegisterValue::NAN, 5., 9.])[0].is_nan());
+
+        let registers = Registers::with_readout(2, 4, 1, Readout::Sum);
+        assert_eq!(registers.len(), 5);
+        assert_eq!(registers.output_registers(), 0..4);
+        assert_eq!(Registers::new(2, 1).output_registers(), 0..2);
+    }
+
+    #[test]
+    fn given_action_registers_when_softmaxed_then_scores_sum_to_one() {
+        // `f32` registers round `1e3 + ln 2` to a few decimals.
+        let tolerance = match PRECISION {
+            Precision::F64 => 1e-12,
+            Precision::F32 => 1e-3,
+        };
+        let registers = Registers::from_values(vec![1e3, 1e3 + 2f64.ln(), -5.], 2);
+        let scores = registers.softmax().unwrap();
+
+        assert_eq!(scores.len(), 2);
+        assert!((scores[0] - 1. / 3.).abs() < tolerance);
+        assert!((scores[1] - 2. / 3.).abs() < tolerance);
+        assert!(Registers::from_values(vec![f64::NAN, 0.], 2)
+            .softmax()
+            .is_none());
+    }
+
+    #[test]
+    fn given_overflowing_values_when_policy_is_applied_then_value_is_settled() {
+        let bound = 10.;
+
+        assert_eq!(NumericPolicy::Clamp.apply(f64::INFINITY, bound), Some(10.));
+        assert_eq!(NumericPolicy::Clamp.apply(-20., bound), Some(-10.));
+        assert_eq!(NumericPolicy::Clamp.apply(f64::NAN, bound), Some(0.));
+        assert_eq!(
+            NumericPolicy::Saturate.apply(f64::NEG_INFINITY, bound),
+            Some(widen(RegisterValue::MIN))
+        );
+        assert_eq!(NumericPolicy::Saturate.apply(20., bound), Some(20.));
+        assert_eq!(NumericPolicy::Invalidate.apply(f64::NAN, bound), None);
+        assert!(NumericPolicy::Propagate
+            .apply(f64::NAN, bound)
+            .unwrap()
+            .is_nan());
+    }
+
+    #[test]
+    fn given_invalidate_policy_when_register_overflows_then_every_register_is_nan() {
+        let parameters = NumericParameters {
+            numeric_policy: NumericPolicy::Invalidate,
+            ..Default::default()
+        };
+        let mut registers = [1., RegisterValue::INFINITY, 2.];
+
+        assert!(!parameters.settle(&mut registers, 1));
+        assert!(registers.iter().all(|value| value.is_nan()));
+    }
+
+    #[test]
+    fn given_tied_registers_when_resolved_then_tie_break_picks_register() {
+        let registers = Registers::from_values(vec![1., 0., 1., 1.], 2);
+        let winner = |tie_break, input| match registers
+            .argmax(input)
+            .resolve(tie_break, registers.n_actions())
+        {
+            ActionRegister::Value(register) => Some(register),
+            ActionRegister::Overflow => None,
+        };
+
+        assert_eq!(winner(TieBreak::Fail, ArgmaxInput::All), None);
+        assert_eq!(winner(TieBreak::First, ArgmaxInput::All), Some(0));
+        assert_eq!(
+            winner(TieBreak::LowestIndexNonOutput, ArgmaxInput::All),
+            Some(2)
+        );
+        assert_eq!(
+            winner(TieBreak::LowestIndexNonOutput, ArgmaxInput::ActionRegisters),
+            Some(0)
+        );
+        assert!(matches!(
+            winner(TieBreak::Random, ArgmaxInput::All),
+            Some(0 | 2 | 3)
+        ));
+    }
+
+    #[test]
+    fn given_precision_when_values_are_stored_then_they_are_rounded_to_it() {
+        let registers = Registers::from_values(vec![1. + 1e-10, 1e39, 0.1], 1);
+        assert_eq!(registers.precision(), PRECISION);
+
+        match PRECISION {
+            Precision::F64 => {
+                assert_eq!(registers.get(0), 1. + 1e-10);
+                assert_eq!(registers.get(1), 1e39);
+                assert_eq!(registers.get(2), 0.1);
+            }
+            Precision::F32 => {
+                // Below the resolution of `f32`, beyond its range, and rounded to its nearest value.
+                assert_eq!(registers.get(0), 1.);
+                assert_eq!(registers.get(1), f64::INFINITY);
+                assert_eq!(registers.get(2), 0.1f32 as f64);
+            }
+        }
+    }
+
+    #[test]
+    fn given_precision_when_saved_then_precision_is_recorded() -> VoidResultAnyError {
+        let registers = Registers::from_values(vec![0.5, 1. / 3.], 1);
+
+        let saved = serde_json::to_string(&registers)?;
+        let loaded: Registers = serde_json::from_str(&saved)?;
+        assert_eq!(loaded.precision(), PRECISION);
+        assert_eq!(loaded.get(1), registers.get(1));
+
+        // Registers saved before the precision was recorded were computed in `f64`.
+        let legacy: Registers = serde_json::from_str(r#"{"data":[0.5,null],"n_actions":1}"#)?;
+        assert_eq!(legacy.precision(), Precision::F64);
+        assert_eq!(legacy.get(0), 0.5);
+        assert!(legacy.get(1).is_nan());
+
+        Ok(())
+    }
 }
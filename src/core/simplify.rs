@@ -0,0 +1,210 @@
+//! Post-training simplification of programs: instructions which cannot influence the output registers
+//! are removed, and runs of constant updates to a register are merged into one, the result being
+//! checked against the original program on sampled inputs.
+use std::{collections::HashSet, error::Error};
+
+use itertools::Itertools;
+
+use super::{
+    batch::Row,
+    engines::reset_engine::{Reset, ResetEngine},
+    instruction::Instruction,
+    instructions::Instructions,
+    program::Program,
+    registers::{NumericPolicy, TieBreak},
+};
+
+/// Relative difference below which two register values are considered the same, leaving room for the
+/// rounding merged constants introduce.
+const TOLERANCE: f64 = 1e-9;
+
+/// Returns the (ordered) indices of the instructions which can influence the `outputs` registers, on
+/// the same execution or on a later one through the registers carried over between executions.
+///
+/// Unlike [`Program::effective_instruction_indices`], registers read before being written are kept
+/// alive until the end of the program, since they hold what the previous execution left behind.
+pub fn live_instruction_indices(instructions: &[Instruction], outputs: &[usize]) -> Vec<usize> {
+    let mut live_out: HashSet<usize> = outputs.iter().copied().collect();
+
+    loop {
+        let mut live = live_out.clone();
+        let mut indices = vec![];
+
+        for (idx, instruction) in instructions.iter().enumerate().rev() {
+            if live.remove(&instruction.dest()) {
+                indices.push(idx);
+                live.extend(instruction.read_registers());
+            }
+        }
+
+        if live.is_subset(&live_out) {
+            indices.reverse();
+            return indices;
+        }
+
+        live_out.extend(live);
+    }
+}
+
+/// Merges consecutive constant updates of the same register (see [`Instruction::merge`]) and drops
+/// the updates which leave registers as is, keeping at least one instruction.
+pub fn merge_constants(instructions: &[Instruction]) -> Instructions {
+    let mut merged: Instructions = vec![];
+    let mut last_identity = None;
+
+    for instruction in instructions {
+        let instruction = match merged.last().and_then(|last| last.merge(instruction)) {
+            Some(combined) => {
+                merged.pop();
+                combined
+            }
+            None => *instruction,
+        };
+
+        match instruction.is_identity() {
+            true => last_identity = Some(instruction),
+            false => merged.push(instruction),
+        }
+    }
+
+    if merged.is_empty() {
+        merged.extend(last_identity);
+    }
+
+    merged
+}
+
+/// A simplified program, along with what was taken out of it.
+#[derive(Debug, Clone)]
+pub struct Simplification {
+    pub program: Program,
+    /// Instructions removed for not influencing the output registers.
+    pub n_introns: usize,
+    /// Instructions saved by merging constant updates and dropping identities.
+    pub n_merged: usize,
+}
+
+impl Program {
+    /// Removes the instructions which cannot influence the output registers (see
+    /// [`live_instruction_indices`]) and, when values propagate as is, merges runs of constant updates
+    /// (see [`merge_constants`]). The result must behave like the program on `samples` (see
+    /// [`Program::behaves_like`]); merges are given up when they do not.
+    ///
+    /// Fails if a sample is shorter than the inputs the program reads, or if even the program without
+    /// its introns behaves differently, e.g. because a removed instruction tripped the numeric policy.
+    pub fn simplify(&self, samples: &[Vec<f64>]) -> Result<Simplification, Box<dyn Error>> {
+        if let Some(sample) = samples
+            .iter()
+            .find(|sample| sample.len() < self.n_inputs_read())
+        {
+            return Err(format!(
+                "The program reads {} inputs, a sample has {}.",
+                self.n_inputs_read(),
+                sample.len()
+            )
+            .into());
+        }
+
+        let mut live = live_instruction_indices(&self.instructions, &self.output_registers())
+            .into_iter()
+            .map(|idx| self.instructions[idx])
+            .collect_vec();
+        let n_introns = self.instructions.len() - live.len();
+
+        // Every instruction left writes a register nothing reads, any one of them is harmless.
+        if live.is_empty() {
+            live.extend(self.instructions.last().copied());
+        }
+
+        let mut candidates = vec![];
+        if self.numeric_parameters.numeric_policy == NumericPolicy::Propagate {
+            candidates.push(merge_constants(&live));
+        }
+        candidates.push(live.clone());
+
+        for instructions in candidates {
+            let n_merged = live.len() - instructions.len();
+            let program = self.with_instructions(instructions);
+
+            if program.behaves_like(self, samples) {
+                return Ok(Simplification {
+                    program,
+                    n_introns,
+                    n_merged,
+                });
+            }
+        }
+
+        Err("The simplified program does not behave like the original on the samples.".into())
+    }
+
+    /// Whether the program ends every execution like `other` (same outcome, same action and, up to
+    /// rounding, same output registers) when both run over `samples` in turn from reset registers.
+    pub fn behaves_like(&self, other: &Program, samples: &[Vec<f64>]) -> bool {
+        let (mut program, mut other) = (self.clone(), other.clone());
+        ResetEngine::reset(&mut program.registers);
+        ResetEngine::reset(&mut other.registers);
+
+        let outputs = self.output_registers();
+        let same = |a: f64, b: f64| {
+            a == b
+                || (a.is_nan() && b.is_nan())
+                || (a - b).abs() <= TOLERANCE * a.abs().max(b.abs()).max(1.)
+        };
+
+        samples.iter().all(|sample| {
+            let input = Row(sample);
+
+            program.run(&input) == other.run(&input)
+                && outputs.iter().all(|&register| {
+                    same(
+                        program.registers.get(register),
+                        other.registers.get(register),
+                    )
+                })
+                && program.select_action(TieBreak::First) == other.select_action(TieBreak::First)
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::problems::problem::program_parameters;
+    use crate::utils::misc::VoidResultAnyError;
+
+    #[test]
+    fn given_program_with_introns_and_constant_runs_when_simplified_then_it_behaves_the_same(
+    ) -> VoidResultAnyError {
+        let mut program_parameters = program_parameters(2, 2);
+        program_parameters.instruction_generator_parameters.n_extras = 2;
+
+        // r2 is read before being written, so its write carries over to the next execution and is not
+        // an intron, whereas nothing ever reads r3.
+        let program = Program::parse(
+            "r0 = r0 + r2; r2 = r2 + 1 * in0; r3 = r3 + 1 * in1; r1 = r1 + 1; r1 = r1 - 0.25; \
+             r1 = r1 * 1; r0 = r0 / r0; r0 = r0 / r0",
+            program_parameters,
+        )?;
+        let samples = vec![vec![1., 2.], vec![-3., 0.5], vec![0.1, -7.]];
+
+        let simplification = program.simplify(&samples)?;
+
+        assert_eq!(simplification.n_introns, 1);
+        assert_eq!(simplification.n_merged, 3);
+        assert_eq!(
+            simplification.program.to_string(),
+            "r0 = r0 + r2\nr2 = r2 + 1 * in0\nr1 = r1 + 0.75\nr0 = r0 * 0.25\n"
+        );
+        assert!(simplification.program.behaves_like(&program, &samples));
+
+        // A program made of introns only keeps one of them.
+        let intron = Program::parse("r3 = r3 + 1 * in1", program_parameters)?;
+        let simplification = intron.simplify(&samples)?;
+        assert_eq!(simplification.program.instructions.len(), 1);
+
+        assert!(program.simplify(&[vec![1.]]).is_err());
+
+        Ok(())
+    }
+}
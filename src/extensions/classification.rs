@@ -1,8 +1,30 @@
-use crate::core::{
-    engines::fitness_engine::{Fitness, FitnessEngine},
-    environment::State,
-    program::Program,
-    registers::{ActionRegister, ArgmaxInput},
+use std::{cell::RefCell, error::Error, path::Path};
+
+use clap::ValueEnum;
+use csv::Writer;
+use itertools::Itertools;
+use rand::seq::SliceRandom;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    core::{
+        characteristics::Save,
+        engines::{
+            breed_engine::BreedEngine,
+            core_engine::{Core, HyperParameters},
+            fitness_engine::{evaluate_with, Fitness, FitnessEngine, Penalty, TrialPool},
+            freeze_engine::FreezeEngine,
+            generate_engine::{Generate, GenerateEngine},
+            mutate_engine::MutateEngine,
+            reset_engine::{Reset, ResetEngine},
+            status_engine::StatusEngine,
+        },
+        environment::{column_bounds, FreshState, GenerationAware, SpaceInfo, State},
+        inputs::InputPipeline,
+        program::{Program, ProgramGeneratorParameters},
+        registers::{widen, TieBreak},
+    },
+    utils::{benchmark_tools::create_path, random::generator, telemetry::record_environment_step},
 };
 
 impl<T> Fitness<Program, T, ()> for FitnessEngine
@@ -16,18 +38,1095 @@ where
         while let Some(state) = states.get() {
             program.run(state);
 
-            match program.registers.argmax(ArgmaxInput::ActionRegisters).one() {
-                ActionRegister::Overflow => {
+            match program.select_action(TieBreak::Fail) {
+                None => {
                     return f64::NEG_INFINITY;
                 }
-                ActionRegister::Value(predicted_class) => {
-                    n_correct += state.execute_action(predicted_class);
+                Some(predicted_class) => {
+                    let weight = state.sample_weight();
+                    n_correct += weight * state.execute_action(predicted_class);
+                    n_total += weight;
+                    record_environment_step();
                 }
             };
-
-            n_total += 1.;
         }
 
         n_correct / n_total
     }
 }
+
+/// How the samples of a [`Dataset`] are weighted in its classification fitness.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ValueEnum)]
+pub enum SampleWeighting {
+    /// Every sample counts as much.
+    #[default]
+    Uniform,
+    /// Samples are weighted by the inverse frequency of their class, so every class weighs as much
+    /// in total (balanced accuracy).
+    InverseClassFrequency,
+    /// Weights given sample by sample, see [`Dataset::with_weights`].
+    Custom,
+}
+
+/// Labelled feature vectors, classified one after the other.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Dataset {
+    pub features: Vec<Vec<f64>>,
+    pub labels: Vec<usize>,
+    #[serde(default)]
+    pub weighting: SampleWeighting,
+    /// One weight per sample, empty for [`SampleWeighting::Uniform`].
+    #[serde(default)]
+    pub weights: Vec<f64>,
+    /// Transforms the samples go through before programs read them, see [`Dataset::with_pipeline`].
+    #[serde(default)]
+    pub pipeline: InputPipeline,
+    #[serde(skip)]
+    idx: usize,
+    /// The current sample passed through `pipeline`, along with its index.
+    #[serde(skip)]
+    row: Option<(usize, Vec<f64>)>,
+}
+
+impl Dataset {
+    pub fn new(features: Vec<Vec<f64>>, labels: Vec<usize>) -> Self {
+        assert_eq!(features.len(), labels.len());
+
+        Dataset {
+            features,
+            labels,
+            weighting: SampleWeighting::Uniform,
+            weights: vec![],
+            pipeline: InputPipeline::default(),
+            idx: 0,
+            row: None,
+        }
+    }
+
+    /// Passes the samples through `pipeline` (with its training-only transforms) as programs read
+    /// them, so noise is drawn anew on every evaluation. The samples the pipeline drops are removed,
+    /// along with their label and weight.
+    pub fn with_pipeline(mut self, pipeline: InputPipeline) -> Self {
+        let kept = (0..self.labels.len())
+            .filter(|idx| pipeline.transform(&self.features[*idx]).is_some())
+            .collect_vec();
+
+        self.features = kept.iter().map(|idx| self.features[*idx].clone()).collect();
+        self.labels = kept.iter().map(|idx| self.labels[*idx]).collect();
+        if !self.weights.is_empty() {
+            self.weights = kept.iter().map(|idx| self.weights[*idx]).collect();
+        }
+        self.pipeline = pipeline;
+        self.row = None;
+
+        self
+    }
+
+    /// The samples as programs read them, passed through the pipeline without its training-only
+    /// transforms.
+    fn inputs(&self) -> Vec<Vec<f64>> {
+        self.features
+            .iter()
+            .filter_map(|row| self.pipeline.transform(row))
+            .collect()
+    }
+
+    /// Weights the samples according to `weighting`; use [`Dataset::with_weights`] for
+    /// [`SampleWeighting::Custom`] weights.
+    pub fn with_weighting(mut self, weighting: SampleWeighting) -> Self {
+        self.weights = match weighting {
+            SampleWeighting::Uniform => vec![],
+            SampleWeighting::InverseClassFrequency => {
+                let counts = self.labels.iter().counts();
+                // Normalized so the weights average to one, keeping fitness within `[0, 1]`.
+                let n_samples = self.labels.len() as f64;
+                let n_classes = counts.len() as f64;
+
+                self.labels
+                    .iter()
+                    .map(|label| n_samples / (n_classes * counts[label] as f64))
+                    .collect()
+            }
+            SampleWeighting::Custom => {
+                assert_eq!(
+                    self.weights.len(),
+                    self.labels.len(),
+                    "custom weighting requires one weight per sample"
+                );
+                self.weights
+            }
+        };
+        self.weighting = weighting;
+
+        self
+    }
+
+    /// Weights every sample by the corresponding (non-negative) entry of `weights`.
+    pub fn with_weights(mut self, weights: Vec<f64>) -> Self {
+        assert_eq!(weights.len(), self.labels.len());
+        assert!(weights.iter().all(|weight| *weight >= 0.));
+
+        self.weights = weights;
+        self.weighting = SampleWeighting::Custom;
+
+        self
+    }
+
+    /// Number of inputs programs read, i.e. after the pipeline.
+    pub fn n_features(&self) -> usize {
+        self.features
+            .first()
+            .and_then(|row| self.pipeline.transform(row))
+            .map_or(0, |row| row.len())
+    }
+
+    /// The samples passed through `pipeline` (with training-only transforms when `training`); dropped
+    /// rows take their label and weight with them.
+    pub fn transformed(&self, pipeline: &InputPipeline, training: bool) -> Self {
+        let mut dataset = Dataset::new(vec![], vec![]);
+        dataset.weighting = self.weighting;
+
+        for (idx, row) in self.features.iter().enumerate() {
+            let row = match training {
+                true => pipeline.transform_training(row),
+                false => pipeline.transform(row),
+            };
+
+            if let Some(row) = row {
+                dataset.features.push(row);
+                dataset.labels.push(self.labels[idx]);
+
+                if let Some(weight) = self.weights.get(idx) {
+                    dataset.weights.push(*weight);
+                }
+            }
+        }
+
+        dataset
+    }
+
+    /// Moves the last `validation_fraction` of the samples to a held-out dataset, returning the
+    /// (training, validation) pair. Shuffle the samples first if they are ordered, e.g. by class.
+    pub fn split(&self, validation_fraction: f64) -> (Self, Self) {
+        let n_validation =
+            ((self.labels.len() as f64) * validation_fraction.clamp(0., 1.)).round() as usize;
+        let n_training = self.labels.len() - n_validation;
+
+        let part = |range: std::ops::Range<usize>| {
+            let mut dataset = Dataset::new(
+                self.features[range.clone()].to_vec(),
+                self.labels[range.clone()].to_vec(),
+            );
+            dataset.weighting = self.weighting;
+            dataset.weights = self.weights.get(range).map_or(vec![], <[f64]>::to_vec);
+            dataset.pipeline = self.pipeline.clone();
+            dataset
+        };
+
+        (part(0..n_training), part(n_training..self.labels.len()))
+    }
+
+    /// A copy of the dataset with its samples (and their weights) in random order.
+    pub fn shuffled(&self) -> Self {
+        let mut order = (0..self.labels.len()).collect_vec();
+        order.shuffle(&mut generator());
+
+        let mut dataset = Dataset::new(
+            order
+                .iter()
+                .map(|idx| self.features[*idx].clone())
+                .collect(),
+            order.iter().map(|idx| self.labels[*idx]).collect(),
+        );
+        dataset.weighting = self.weighting;
+        dataset.weights = match self.weights.is_empty() {
+            true => vec![],
+            false => order.iter().map(|idx| self.weights[*idx]).collect(),
+        };
+        dataset.pipeline = self.pipeline.clone();
+
+        dataset
+    }
+
+    /// A copy of the dataset with the column of `feature` shuffled across samples.
+    pub fn permuted(&self, feature: usize) -> Self {
+        let mut column = self.features.iter().map(|row| row[feature]).collect_vec();
+        column.shuffle(&mut generator());
+
+        let mut dataset = self.clone();
+        for (row, value) in dataset.features.iter_mut().zip(column) {
+            row[feature] = value;
+        }
+
+        dataset
+    }
+}
+
+impl State for Dataset {
+    fn get_value(&self, at_idx: usize) -> f64 {
+        match &self.row {
+            Some((idx, row)) if *idx == self.idx => row[at_idx],
+            _ => self.features[self.idx][at_idx],
+        }
+    }
+
+    fn execute_action(&mut self, action: usize) -> f64 {
+        let is_correct = self.labels[self.idx] == action;
+        self.idx += 1;
+
+        is_correct as usize as f64
+    }
+
+    fn get(&mut self) -> Option<&mut Self> {
+        if self.idx >= self.labels.len() {
+            return None;
+        }
+
+        let current = self.row.as_ref().map(|(idx, _)| *idx);
+        if !self.pipeline.steps.is_empty() && current != Some(self.idx) {
+            let row = self
+                .pipeline
+                .transform_training(&self.features[self.idx])
+                .unwrap_or_default();
+            self.row = Some((self.idx, row));
+        }
+
+        Some(self)
+    }
+
+    fn sample_weight(&self) -> f64 {
+        self.weights.get(self.idx).copied().unwrap_or(1.)
+    }
+}
+
+impl Reset<Dataset> for ResetEngine {
+    fn reset(item: &mut Dataset) {
+        item.idx = 0;
+        item.row = None;
+    }
+}
+
+impl GenerationAware for Dataset {}
+
+impl FreshState for Dataset {
+    type PerEvaluation = usize;
+    type PerGeneration = ();
+    type PerRun = Vec<usize>;
+
+    fn per_evaluation(&self) -> usize {
+        self.idx
+    }
+
+    fn fresh(&self) -> usize {
+        0
+    }
+
+    fn per_generation(&self) {}
+
+    fn per_run(&self) -> Vec<usize> {
+        self.labels.clone()
+    }
+}
+
+/// Bounded by the samples themselves; classes are numbered up to the largest label.
+impl SpaceInfo for Dataset {
+    fn n_inputs(&self) -> usize {
+        self.n_features()
+    }
+
+    fn n_actions(&self) -> usize {
+        self.labels.iter().max().map_or(0, |label| label + 1)
+    }
+
+    fn input_bounds(&self) -> Vec<(f64, f64)> {
+        column_bounds(&self.inputs())
+    }
+}
+
+thread_local! {
+    static DATASET: RefCell<Option<Dataset>> = RefCell::new(None);
+}
+
+/// Generates `dataset` as the trials (and validation trials) of the [`DatasetEngine`] runs `f` starts
+/// on this thread, e.g. through [`CoreIter::new`](crate::core::engines::core_engine::CoreIter::new).
+pub fn with_dataset<T>(dataset: Dataset, f: impl FnOnce() -> T) -> T {
+    let previous = DATASET.with(|current| current.replace(Some(dataset)));
+    let result = f();
+    DATASET.with(|current| current.replace(previous));
+
+    result
+}
+
+/// Datasets cannot be generated from nothing: this is the dataset given to [`with_dataset`], the
+/// samples to evolve on may also be passed to
+/// [`CoreIter::with_trials`](crate::core::engines::core_engine::CoreIter::with_trials).
+///
+/// # Panics
+///
+/// Outside of [`with_dataset`], rather than evolving on an empty dataset.
+impl Generate<(), Dataset> for GenerateEngine {
+    fn generate(_using: ()) -> Dataset {
+        DATASET
+            .with(|current| current.borrow().clone())
+            .expect("No dataset to generate: run within `with_dataset` or pass the trials to `CoreIter::with_trials`.")
+    }
+}
+
+/// Classifies the samples of a [`Dataset`] loaded at runtime, e.g. fetched from OpenML (see the
+/// `openml` feature).
+#[derive(Clone)]
+pub struct DatasetEngine;
+
+impl Core for DatasetEngine {
+    type Individual = Program;
+    type ProgramParameters = ProgramGeneratorParameters;
+    type State = Dataset;
+    type FitnessMarker = ();
+    type Generate = GenerateEngine;
+    type Fitness = FitnessEngine;
+    type Reset = ResetEngine;
+    type Breed = BreedEngine;
+    type Mutate = MutateEngine;
+    type Status = StatusEngine;
+    type Freeze = FreezeEngine;
+
+    fn evaluate(
+        population: &mut Vec<Self::Individual>,
+        trials: &mut Vec<Self::State>,
+        params: &HyperParameters<Self>,
+        penalties: &[Penalty<Self::Individual, Self::State>],
+        pool: &mut TrialPool<Self::State>,
+    ) {
+        evaluate_with(population, trials, params, penalties, pool)
+    }
+}
+
+/// A class along with the confidence of the classifier.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Prediction {
+    /// The class with the highest score, the lowest of tied classes.
+    pub class: usize,
+    /// Softmax of the action registers, one score per class.
+    pub scores: Vec<f64>,
+    /// Difference between the two highest scores (the highest score with a single class); small
+    /// margins mark predictions worth rejecting.
+    pub margin: f64,
+}
+
+/// What a binary decision threshold is calibrated to maximize.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ValueEnum)]
+pub enum CalibrationMetric {
+    /// Harmonic mean of the precision and recall of the positive class (class 1).
+    #[default]
+    F1,
+    /// Youden's J statistic: sensitivity plus specificity minus one.
+    YoudensJ,
+}
+
+impl CalibrationMetric {
+    fn score(
+        self,
+        true_positives: f64,
+        false_positives: f64,
+        true_negatives: f64,
+        false_negatives: f64,
+    ) -> f64 {
+        let ratio = |numerator: f64, denominator: f64| match denominator == 0. {
+            true => 0.,
+            false => numerator / denominator,
+        };
+
+        match self {
+            CalibrationMetric::F1 => ratio(
+                2. * true_positives,
+                2. * true_positives + false_positives + false_negatives,
+            ),
+            CalibrationMetric::YoudensJ => {
+                ratio(true_positives, true_positives + false_negatives)
+                    + ratio(true_negatives, true_negatives + false_positives)
+                    - 1.
+            }
+        }
+    }
+}
+
+/// The threshold over binary margins (see [`Program::binary_margin`]) above which samples are
+/// predicted positive which maximizes `metric` over `(margin, label)` pairs. Candidates lie between
+/// consecutive margins; ties go to the threshold closest to `0`, the uncalibrated decision.
+pub fn calibrate_threshold(samples: &[(f64, usize)], metric: CalibrationMetric) -> f64 {
+    let margins = samples
+        .iter()
+        .map(|(margin, _)| *margin)
+        .sorted_by(f64::total_cmp)
+        .dedup()
+        .collect_vec();
+
+    let candidates = margins
+        .first()
+        .map(|lowest| lowest - 1.)
+        .into_iter()
+        .chain(margins.windows(2).map(|pair| (pair[0] + pair[1]) / 2.))
+        .chain(margins.last().copied());
+
+    let score = |threshold: f64| {
+        let (mut true_positives, mut false_positives) = (0., 0.);
+        let (mut true_negatives, mut false_negatives) = (0., 0.);
+
+        for (margin, label) in samples {
+            match (*margin > threshold, *label == 1) {
+                (true, true) => true_positives += 1.,
+                (true, false) => false_positives += 1.,
+                (false, false) => true_negatives += 1.,
+                (false, true) => false_negatives += 1.,
+            }
+        }
+
+        metric.score(
+            true_positives,
+            false_positives,
+            true_negatives,
+            false_negatives,
+        )
+    };
+
+    candidates
+        .map(|threshold| (threshold, score(threshold)))
+        .fold(
+            None,
+            |best: Option<(f64, f64)>, (threshold, score)| match best {
+                Some((best_threshold, best_score))
+                    if best_score > score
+                        || (best_score == score && best_threshold.abs() <= threshold.abs()) =>
+                {
+                    best
+                }
+                _ => Some((threshold, score)),
+            },
+        )
+        .map_or(0., |(threshold, _)| threshold)
+}
+
+/// A program along with the pipeline its inputs went through during training, saved and loaded as one
+/// so inference transforms inputs exactly as training did.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Classifier {
+    pub pipeline: InputPipeline,
+    pub program: Program,
+    /// Decision threshold of a binary classifier over its margin, set by [`Classifier::calibrate`];
+    /// unset picks the class with the highest score.
+    #[serde(default)]
+    pub threshold: Option<f64>,
+}
+
+impl Classifier {
+    /// Classifies a raw row, `None` when the pipeline drops it or the program overflows.
+    pub fn predict(&mut self, row: &[f64]) -> Option<usize> {
+        let row = self.pipeline.transform(row)?;
+        let dataset = Dataset::new(vec![row], vec![0]);
+
+        self.program.run(&dataset);
+
+        match self.threshold {
+            Some(threshold) => self
+                .program
+                .binary_margin()
+                .map(|margin| (margin > threshold) as usize),
+            None => self.program.select_action(TieBreak::Fail),
+        }
+    }
+
+    /// Sets the decision threshold maximizing `metric` over the raw samples of `validation`, returning
+    /// it. Samples the pipeline drops or the program overflows on are left out.
+    pub fn calibrate(
+        &mut self,
+        validation: &Dataset,
+        metric: CalibrationMetric,
+    ) -> Result<f64, Box<dyn Error>> {
+        if self.program.registers.n_actions() != 2 {
+            return Err(format!(
+                "Thresholds are calibrated for binary classifiers, this one has {} classes.",
+                self.program.registers.n_actions()
+            )
+            .into());
+        }
+
+        let validation = validation.transformed(&self.pipeline, false);
+        let samples = self
+            .program
+            .binary_margins(&validation)
+            .into_iter()
+            .zip(validation.labels)
+            .filter_map(|(margin, label)| margin.map(|margin| (margin, label)))
+            .collect_vec();
+
+        if samples.is_empty() {
+            return Err("No validation sample could be classified.".into());
+        }
+
+        let threshold = calibrate_threshold(&samples, metric);
+        self.threshold = Some(threshold);
+
+        Ok(threshold)
+    }
+}
+
+/// How much a classifier relies on one of its inputs.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct FeatureImportance {
+    pub feature: usize,
+    /// Effective instructions reading the feature.
+    pub references: usize,
+    /// Mean loss of accuracy when the feature is shuffled across samples.
+    pub accuracy_drop: f64,
+}
+
+/// Precision and recall of a single class.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct ClassMetrics {
+    pub class: usize,
+    /// Fraction of the predictions of the class which were correct, `0` when it was never predicted.
+    pub precision: f64,
+    /// Fraction of the samples of the class which were predicted as such, `0` without samples.
+    pub recall: f64,
+    /// Number of samples of the class.
+    pub support: usize,
+}
+
+/// How a classifier fares on every class of a dataset: its confusion matrix along with the precision
+/// and recall of each class.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ClassificationReport {
+    /// `confusion[actual][predicted]` counts the samples of class `actual` predicted as `predicted`.
+    pub confusion: Vec<Vec<usize>>,
+    /// Samples of each class the program could not classify, its action registers overflowing.
+    pub unclassified: Vec<usize>,
+    pub classes: Vec<ClassMetrics>,
+    /// Fraction of the samples classified correctly, every sample counting as much.
+    pub accuracy: f64,
+}
+
+impl ClassificationReport {
+    /// Tallies `(actual, predicted)` pairs over `n_classes` classes, `None` marking samples which
+    /// could not be classified.
+    pub fn new(n_classes: usize, predictions: &[(usize, Option<usize>)]) -> Self {
+        let mut confusion = vec![vec![0; n_classes]; n_classes];
+        let mut unclassified = vec![0; n_classes];
+
+        for (actual, predicted) in predictions {
+            match predicted {
+                Some(predicted) => confusion[*actual][*predicted] += 1,
+                None => unclassified[*actual] += 1,
+            }
+        }
+
+        let ratio = |numerator: usize, denominator: usize| match denominator {
+            0 => 0.,
+            _ => numerator as f64 / denominator as f64,
+        };
+
+        let classes = (0..n_classes)
+            .map(|class| {
+                let n_predicted = confusion.iter().map(|row| row[class]).sum();
+                let support = confusion[class].iter().sum::<usize>() + unclassified[class];
+
+                ClassMetrics {
+                    class,
+                    precision: ratio(confusion[class][class], n_predicted),
+                    recall: ratio(confusion[class][class], support),
+                    support,
+                }
+            })
+            .collect();
+        let n_correct = (0..n_classes).map(|class| confusion[class][class]).sum();
+
+        ClassificationReport {
+            confusion,
+            unclassified,
+            classes,
+            accuracy: ratio(n_correct, predictions.len()),
+        }
+    }
+
+    /// Writes the confusion matrix, one row per actual class and one column per predicted class
+    /// followed by the unclassified samples.
+    pub fn save_confusion_csv(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        create_path(path, true)?;
+
+        let mut writer = Writer::from_path(path)?;
+        writer.write_record(
+            ["actual".to_owned()]
+                .into_iter()
+                .chain((0..self.confusion.len()).map(|class| class.to_string()))
+                .chain(["unclassified".to_owned()]),
+        )?;
+
+        for (actual, row) in self.confusion.iter().enumerate() {
+            writer.write_record(
+                [actual.to_string()]
+                    .into_iter()
+                    .chain(row.iter().map(usize::to_string))
+                    .chain([self.unclassified[actual].to_string()]),
+            )?;
+        }
+
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Writes the precision, recall and support of every class, one row per class.
+    pub fn save_classes_csv(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        create_path(path, true)?;
+
+        let mut writer = Writer::from_path(path)?;
+
+        for class in &self.classes {
+            writer.serialize(class)?;
+        }
+
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Writes `classification.json`, `confusion.csv` and `classes.csv` to `dir`, where
+    /// [`RunReport::load`](crate::utils::report::RunReport::load) picks the report up.
+    pub fn export(&self, dir: &Path) -> Result<(), Box<dyn Error>> {
+        let path = |file: &str| dir.join(file).to_string_lossy().into_owned();
+
+        self.save(&path("classification.json"))?;
+        self.save_confusion_csv(&path("confusion.csv"))?;
+        self.save_classes_csv(&path("classes.csv"))?;
+
+        Ok(())
+    }
+}
+
+impl Program {
+    /// Fraction of `dataset` classified correctly, as evaluated during evolution (`0` on overflow).
+    pub fn accuracy(&self, dataset: &Dataset) -> f64 {
+        let mut program = self.clone();
+        let mut dataset = dataset.clone();
+
+        ResetEngine::reset(&mut program);
+        ResetEngine::reset(&mut dataset);
+
+        <FitnessEngine as Fitness<Program, Dataset, ()>>::eval_fitness(&mut program, &mut dataset)
+            .max(0.)
+    }
+
+    /// Classifies every sample of `dataset` as evaluated during evolution, breaking the results down
+    /// by class.
+    pub fn classification_report(&self, dataset: &Dataset) -> ClassificationReport {
+        let mut program = self.clone();
+        let mut dataset = dataset.clone();
+
+        ResetEngine::reset(&mut program);
+        ResetEngine::reset(&mut dataset);
+
+        let mut predictions = vec![];
+        while let Some(state) = dataset.get() {
+            program.run(state);
+            predictions.push((
+                state.labels[state.idx],
+                program.select_action(TieBreak::Fail),
+            ));
+            state.idx += 1;
+        }
+
+        let n_classes = dataset.n_actions().max(program.registers.n_actions());
+        ClassificationReport::new(n_classes, &predictions)
+    }
+
+    /// Score of class 1 minus the score of class 0 after a run, `None` when either overflowed.
+    /// Only meaningful for binary classifiers.
+    pub fn binary_margin(&self) -> Option<f64> {
+        let scores = self.registers.action_scores();
+        let margin = widen(scores[1]) - widen(scores[0]);
+
+        Some(margin).filter(|margin| margin.is_finite())
+    }
+
+    /// The binary margin of every sample of `dataset`, as evaluated during evolution.
+    pub fn binary_margins(&self, dataset: &Dataset) -> Vec<Option<f64>> {
+        let mut program = self.clone();
+        let mut dataset = dataset.clone();
+
+        ResetEngine::reset(&mut program);
+        ResetEngine::reset(&mut dataset);
+
+        let mut margins = vec![];
+        while let Some(state) = dataset.get() {
+            program.run(state);
+            margins.push(program.binary_margin());
+            state.idx += 1;
+        }
+
+        margins
+    }
+
+    /// Runs the program over `input`, as during evaluation, and scores every class.
+    /// Returns `None` when the action registers overflow.
+    pub fn predict_with_scores(&mut self, input: &impl State) -> Option<Prediction> {
+        self.run(input);
+
+        let scores = self.registers.softmax()?;
+        let ranked = scores
+            .iter()
+            .copied()
+            .enumerate()
+            .sorted_by(|(a_idx, a), (b_idx, b)| b.total_cmp(a).then(a_idx.cmp(b_idx)))
+            .collect_vec();
+
+        let (class, best) = ranked[0];
+        let margin = best - ranked.get(1).map_or(0., |(_, score)| *score);
+
+        Some(Prediction {
+            class,
+            scores,
+            margin,
+        })
+    }
+
+    /// Number of effective instructions reading each of the first `n_inputs` inputs.
+    pub fn feature_references(&self, n_inputs: usize) -> Vec<usize> {
+        let mut references = vec![0; n_inputs];
+
+        for idx in self.effective_instruction_indices(&self.output_registers()) {
+            let instruction = &self.instructions[idx];
+
+            if let Some(input) = instruction.input().filter(|input| *input < n_inputs) {
+                references[input] += 1;
+            }
+        }
+
+        references
+    }
+
+    /// Permutation importance of every feature of `inputs`: the accuracy lost when the feature is
+    /// shuffled, averaged over `n_permutations` shuffles, alongside its effective references.
+    pub fn feature_importance(
+        &self,
+        inputs: &Dataset,
+        n_permutations: usize,
+    ) -> Vec<FeatureImportance> {
+        let baseline = self.accuracy(inputs);
+        let references = self.feature_references(inputs.n_features());
+
+        references
+            .into_iter()
+            .enumerate()
+            .map(|(feature, references)| {
+                let permuted_accuracy = (0..n_permutations)
+                    .map(|_| self.accuracy(&inputs.permuted(feature)))
+                    .sum::<f64>()
+                    / n_permutations.max(1) as f64;
+
+                FeatureImportance {
+                    feature,
+                    references,
+                    accuracy_drop: baseline - permuted_accuracy,
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::characteristics::Load;
+    use crate::core::inputs::{InputPipelineBuilder, MissingValuePolicy};
+    use crate::core::instruction::InstructionGeneratorParametersBuilder;
+    use crate::problems::problem::program_parameters;
+    use crate::utils::misc::VoidResultAnyError;
+    use crate::utils::random::update_seed;
+    use crate::utils::test::temp_dir;
+
+    #[test]
+    fn given_dataset_when_generated_within_with_dataset_then_trials_are_its_samples() {
+        let dataset = Dataset::new(vec![vec![0.], vec![1.]], vec![0, 1]);
+
+        let generated: Dataset = with_dataset(dataset.clone(), || GenerateEngine::generate(()));
+
+        assert_eq!(generated, dataset);
+    }
+
+    #[test]
+    #[should_panic(expected = "No dataset to generate")]
+    fn given_no_dataset_when_generated_then_it_panics() {
+        let _: Dataset = GenerateEngine::generate(());
+    }
+
+    #[test]
+    fn given_classifier_when_predicting_then_scores_and_margin_are_returned() -> VoidResultAnyError
+    {
+        let program_parameters = program_parameters(2, 2);
+        let mut program =
+            Program::parse("r0 = r0 + 1 * in0; r1 = r1 + 1 * in1", program_parameters)?;
+
+        let dataset = Dataset::new(vec![vec![0., 2f64.ln()]], vec![1]);
+        let prediction = program.predict_with_scores(&dataset).unwrap();
+
+        assert_eq!(prediction.class, 1);
+        assert!((prediction.scores[1] - 2. / 3.).abs() < 1e-12);
+        assert!((prediction.margin - 1. / 3.).abs() < 1e-12);
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_dataset_when_inspected_then_space_matches_the_samples() -> VoidResultAnyError {
+        let dataset = Dataset::new(
+            vec![vec![0., 5.], vec![-1., 2.], vec![3., 4.]],
+            vec![0, 2, 1],
+        );
+
+        assert_eq!(dataset.n_inputs(), 2);
+        assert_eq!(dataset.n_actions(), 3);
+        assert_eq!(dataset.input_bounds(), vec![(-1., 3.), (2., 5.)]);
+
+        let mut parameters = InstructionGeneratorParametersBuilder::default()
+            .n_actions(3)
+            .n_inputs(2)
+            .build()?;
+        assert!(dataset.check(&parameters).is_ok());
+
+        parameters.n_inputs = 4;
+        assert!(dataset.check(&parameters).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_imbalanced_dataset_when_weighted_by_inverse_class_frequency_then_classes_weigh_the_same(
+    ) -> VoidResultAnyError {
+        let program_parameters = program_parameters(1, 2);
+        // Always predicts the majority class.
+        let program = Program::parse("r0 = r0 + 1 * in0", program_parameters)?;
+
+        let dataset = Dataset::new(vec![vec![1.]; 4], vec![0, 0, 0, 1]);
+        assert_eq!(program.accuracy(&dataset), 0.75);
+
+        let balanced = dataset
+            .clone()
+            .with_weighting(SampleWeighting::InverseClassFrequency);
+        assert_eq!(balanced.weights, vec![2. / 3., 2. / 3., 2. / 3., 2.]);
+        assert!((program.accuracy(&balanced) - 0.5).abs() < 1e-12);
+
+        let reloaded: Dataset = serde_json::from_str(&serde_json::to_string(&balanced)?)?;
+        assert_eq!(reloaded.weighting, SampleWeighting::InverseClassFrequency);
+        assert_eq!(reloaded.weights, balanced.weights);
+
+        let custom = dataset.with_weights(vec![0., 0., 0., 1.]);
+        assert_eq!(program.accuracy(&custom), 0.);
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_majority_classifier_when_reported_then_classes_are_broken_down() -> VoidResultAnyError
+    {
+        let program_parameters = program_parameters(1, 2);
+        // Always predicts the majority class.
+        let program = Program::parse("r0 = r0 + 1 * in0", program_parameters)?;
+
+        let dataset = Dataset::new(vec![vec![1.]; 4], vec![0, 0, 0, 1]);
+        let report = program.classification_report(&dataset);
+
+        assert_eq!(report.confusion, vec![vec![3, 0], vec![1, 0]]);
+        assert_eq!(report.unclassified, vec![0, 0]);
+        assert_eq!(report.accuracy, program.accuracy(&dataset));
+        assert_eq!(
+            report.classes,
+            vec![
+                ClassMetrics {
+                    class: 0,
+                    precision: 0.75,
+                    recall: 1.,
+                    support: 3
+                },
+                ClassMetrics {
+                    class: 1,
+                    precision: 0.,
+                    recall: 0.,
+                    support: 1
+                },
+            ]
+        );
+
+        let overflowed = ClassificationReport::new(2, &[(0, None), (1, Some(1))]);
+        assert_eq!(overflowed.classes[0].recall, 0.);
+        assert_eq!(overflowed.classes[1].precision, 1.);
+        assert_eq!(overflowed.accuracy, 0.5);
+
+        let dir = temp_dir("classification-report");
+        report.export(&dir)?;
+
+        assert_eq!(
+            ClassificationReport::try_load(dir.join("classification.json"))?,
+            report
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.join("confusion.csv"))?,
+            "actual,0,1,unclassified\n0,3,0,0\n1,1,0,0\n"
+        );
+        assert_eq!(
+            std::fs::read_to_string(dir.join("classes.csv"))?,
+            "class,precision,recall,support\n0,0.75,1.0,3\n1,0.0,0.0,1\n"
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_weighted_dataset_when_split_then_samples_keep_their_weights() {
+        let dataset = Dataset::new(
+            (0..10).map(|idx| vec![idx as f64]).collect(),
+            (0..10).map(|idx| idx % 2).collect(),
+        )
+        .with_weights((0..10).map(|idx| idx as f64).collect());
+
+        let (training, validation) = dataset.split(0.3);
+
+        assert_eq!(training.labels.len(), 7);
+        assert_eq!(validation.features, vec![vec![7.], vec![8.], vec![9.]]);
+        assert_eq!(validation.weights, vec![7., 8., 9.]);
+        assert_eq!(validation.weighting, SampleWeighting::Custom);
+    }
+
+    #[test]
+    fn given_classifier_with_pipeline_when_predicting_then_raw_rows_are_transformed(
+    ) -> VoidResultAnyError {
+        let program_parameters = program_parameters(1, 2);
+        // Class 0 for positive (standardized) inputs.
+        let program = Program::parse("r0 = r0 * 0 * in0; r0 = r0 + 1 * in0", program_parameters)?;
+
+        let dataset = Dataset::new(vec![vec![10.], vec![30.]], vec![1, 0]);
+        let pipeline = InputPipelineBuilder::new()
+            .standardize()
+            .fit(&dataset.features)?;
+
+        let transformed = dataset.transformed(&pipeline, false);
+        assert_eq!(transformed.features, vec![vec![-1.], vec![1.]]);
+
+        let mut classifier = Classifier {
+            pipeline,
+            program,
+            threshold: None,
+        };
+        assert_eq!(classifier.predict(&[25.]), Some(0));
+
+        let restored: Classifier = serde_json::from_str(&serde_json::to_string(&classifier)?)?;
+        assert_eq!(restored.pipeline, classifier.pipeline);
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_dataset_with_pipeline_when_evaluated_twice_then_noise_is_drawn_per_evaluation(
+    ) -> VoidResultAnyError {
+        let dataset = Dataset::new(vec![vec![10.], vec![f64::NAN], vec![30.]], vec![1, 0, 0]);
+        let pipeline = InputPipelineBuilder::new()
+            .impute(MissingValuePolicy::DropRow)
+            .standardize()
+            .noise(0.1)
+            .fit(&dataset.features)?;
+        let mut dataset = dataset.with_pipeline(pipeline);
+        update_seed(Some(1));
+
+        assert_eq!(dataset.labels, vec![1, 0]);
+        assert_eq!(dataset.n_features(), 1);
+        assert_eq!(dataset.input_bounds(), vec![(-1., 1.)]);
+
+        let mut read = || {
+            ResetEngine::reset(&mut dataset);
+            let mut inputs = vec![];
+            while let Some(sample) = dataset.get() {
+                inputs.push(sample.get_value(0));
+                sample.execute_action(0);
+            }
+            inputs
+        };
+
+        let (first, second) = (read(), read());
+        assert_eq!(first.len(), 2);
+        assert_ne!(first, second);
+        assert!(first
+            .iter()
+            .zip([-1., 1.])
+            .all(|(input, clean)| (input - clean).abs() < 1.));
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_binary_classifier_when_calibrated_then_threshold_separates_the_classes(
+    ) -> VoidResultAnyError {
+        let program_parameters = program_parameters(1, 2);
+        // The margin is the input itself.
+        let program = Program::parse("r1 = r1 * 0 * in0; r1 = r1 + 1 * in0", program_parameters)?;
+
+        let validation = Dataset::new(
+            vec![vec![-2.], vec![-1.], vec![0.5], vec![1.], vec![2.]],
+            vec![0, 0, 0, 1, 1],
+        );
+        let pipeline = InputPipelineBuilder::new().fit(&validation.features)?;
+
+        let mut classifier = Classifier {
+            pipeline,
+            program,
+            threshold: None,
+        };
+        assert_eq!(classifier.predict(&[0.5]), Some(1));
+
+        assert_eq!(
+            classifier.calibrate(&validation, CalibrationMetric::F1)?,
+            0.75
+        );
+        assert_eq!(classifier.predict(&[0.5]), Some(0));
+        assert_eq!(classifier.predict(&[1.]), Some(1));
+
+        let restored: Classifier = serde_json::from_str(&serde_json::to_string(&classifier)?)?;
+        assert_eq!(restored.threshold, Some(0.75));
+
+        // The negative sample with the highest margin cannot be separated, both metrics give it up.
+        let samples = [(-1., 0), (0., 0), (1., 1), (3., 1), (4., 0)];
+        assert_eq!(
+            calibrate_threshold(&samples, CalibrationMetric::YoudensJ),
+            0.5
+        );
+        assert_eq!(calibrate_threshold(&samples, CalibrationMetric::F1), 0.5);
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_classifier_reading_one_feature_when_permuted_then_only_that_feature_matters(
+    ) -> VoidResultAnyError {
+        update_seed(Some(5));
+
+        let program_parameters = program_parameters(2, 2);
+        // Class 0 when in0 exceeds in1; the last instruction is not effective.
+        let program = Program::parse(
+            "r0 = r0 * 0 * in0; r0 = r0 + 1 * in0; r1 = r1 * 0 * in1; r1 = r1 + 1 * in1; \
+             r2 = r2 + 1 * in1",
+            program_parameters,
+        )?;
+
+        let features = (0..20).map(|idx| vec![(idx % 2) as f64, 0.5]).collect_vec();
+        let labels = (0..20).map(|idx| 1 - idx % 2).collect_vec();
+        let dataset = Dataset::new(features, labels);
+
+        assert_eq!(program.accuracy(&dataset), 1.);
+
+        let importance = program.feature_importance(&dataset, 5);
+
+        assert_eq!(importance[0].references, 2);
+        assert_eq!(importance[1].references, 2);
+        assert!(importance[0].accuracy_drop > 0.);
+        assert_eq!(importance[1].accuracy_drop, 0.);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,347 @@
+//! Competitive co-evolution: two populations of programs evolve against each other, the fitness of an
+//! individual being its average score against opponents sampled from the other population.
+use std::marker::PhantomData;
+
+use itertools::Itertools;
+use rand::seq::IteratorRandom;
+use tracing::info;
+
+use crate::{
+    core::{
+        engines::{
+            breed_engine::BreedEngine,
+            core_engine::{Core, HyperParameters},
+            fitness_engine::FitnessEngine,
+            freeze_engine::FreezeEngine,
+            generate_engine::{Generate, GenerateEngine},
+            mutate_engine::MutateEngine,
+            reset_engine::{Reset, ResetEngine},
+            status_engine::{Status, StatusEngine},
+        },
+        environment::{FreshState, GenerationAware, State},
+        program::{Program, ProgramGeneratorParameters},
+        registers::TieBreak,
+    },
+    utils::{
+        random::{generator, update_seed},
+        telemetry::record_environment_step,
+    },
+};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Role {
+    First,
+    Second,
+}
+
+/// A two-player environment where both players act simultaneously.
+pub trait Arena: Clone {
+    const N_INPUTS: usize;
+    const N_ACTIONS: usize;
+    const EPISODE_LENGTH: usize;
+
+    /// Samples a random initial state.
+    fn sample() -> Self;
+
+    /// What `role` observes of the arena; must contain `N_INPUTS` values.
+    fn observation(&self, role: Role) -> Vec<f64>;
+
+    /// Advances the arena, returning the rewards of both players and whether the game ended.
+    fn step(&mut self, first_action: usize, second_action: usize) -> (f64, f64, bool);
+}
+
+/// A snapshot of what a single player sees.
+///
+/// Actions are read back from the program's registers, executing one only marks the snapshot as consumed.
+#[derive(Clone, Debug, Default)]
+pub struct Observation {
+    values: Vec<f64>,
+    consumed: bool,
+}
+
+impl Observation {
+    pub fn new(values: Vec<f64>) -> Self {
+        Observation {
+            values,
+            consumed: false,
+        }
+    }
+}
+
+impl State for Observation {
+    fn get_value(&self, at_idx: usize) -> f64 {
+        self.values[at_idx]
+    }
+
+    fn execute_action(&mut self, _action: usize) -> f64 {
+        self.consumed = true;
+        0.
+    }
+
+    fn get(&mut self) -> Option<&mut Self> {
+        if self.consumed {
+            return None;
+        }
+
+        Some(self)
+    }
+}
+
+impl GenerationAware for Observation {}
+
+impl FreshState for Observation {
+    type PerEvaluation = bool;
+    type PerGeneration = Vec<f64>;
+    type PerRun = ();
+
+    fn per_evaluation(&self) -> bool {
+        self.consumed
+    }
+
+    fn fresh(&self) -> bool {
+        false
+    }
+
+    fn per_generation(&self) -> Vec<f64> {
+        self.values.clone()
+    }
+
+    fn per_run(&self) {}
+}
+
+impl Reset<Observation> for ResetEngine {
+    fn reset(item: &mut Observation) {
+        item.consumed = false;
+    }
+}
+
+impl Generate<(), Observation> for GenerateEngine {
+    fn generate(_using: ()) -> Observation {
+        Observation::default()
+    }
+}
+
+/// Supplies the generation, ranking and variation operators of a co-evolving population.
+#[derive(Clone)]
+pub struct ArenaEngine<A>(PhantomData<A>);
+
+impl<A> Core for ArenaEngine<A>
+where
+    A: Arena,
+{
+    type Individual = Program;
+    type ProgramParameters = ProgramGeneratorParameters;
+    type State = Observation;
+    type FitnessMarker = ();
+    type Generate = GenerateEngine;
+    type Fitness = FitnessEngine;
+    type Reset = ResetEngine;
+    type Breed = BreedEngine;
+    type Mutate = MutateEngine;
+    type Status = StatusEngine;
+    type Freeze = FreezeEngine;
+}
+
+fn act<A>(program: &mut Program, arena: &A, role: Role) -> Option<usize>
+where
+    A: Arena,
+{
+    let observation = Observation::new(arena.observation(role));
+    program.run(&observation);
+
+    program.select_action(TieBreak::Random)
+}
+
+/// Plays a single game, returning the scores of `first` and `second`.
+/// A player whose registers overflow forfeits with a score of negative infinity.
+pub fn play<A>(first: &mut Program, second: &mut Program, arena: &mut A) -> (f64, f64)
+where
+    A: Arena,
+{
+    ResetEngine::reset(&mut first.registers);
+    ResetEngine::reset(&mut second.registers);
+
+    let mut first_score = 0.;
+    let mut second_score = 0.;
+
+    for _ in 0..A::EPISODE_LENGTH {
+        let first_action = act(first, arena, Role::First);
+        let second_action = act(second, arena, Role::Second);
+
+        let (first_action, second_action) = match (first_action, second_action) {
+            (Some(first_action), Some(second_action)) => (first_action, second_action),
+            (None, _) => return (f64::NEG_INFINITY, second_score),
+            (_, None) => return (first_score, f64::NEG_INFINITY),
+        };
+
+        let (first_reward, second_reward, done) = arena.step(first_action, second_action);
+        record_environment_step();
+
+        first_score += first_reward;
+        second_score += second_reward;
+
+        if done {
+            break;
+        }
+    }
+
+    (first_score, second_score)
+}
+
+fn eval_against<A>(
+    population: &mut [Program],
+    opponents: &[Program],
+    role: Role,
+    trials: &[A],
+    n_opponents: usize,
+    default_fitness: f64,
+) where
+    A: Arena,
+{
+    for individual in population.iter_mut() {
+        let sampled = opponents
+            .iter()
+            .choose_multiple(&mut generator(), n_opponents);
+
+        let mut scores = vec![];
+
+        for opponent in sampled {
+            let mut opponent = opponent.clone();
+
+            for trial in trials {
+                let mut arena = trial.clone();
+
+                let score = match role {
+                    Role::First => play(individual, &mut opponent, &mut arena).0,
+                    Role::Second => play(&mut opponent, individual, &mut arena).1,
+                };
+
+                scores.push(if score.is_finite() {
+                    score
+                } else {
+                    default_fitness
+                });
+            }
+        }
+
+        let average = scores.iter().sum::<f64>() / scores.len() as f64;
+        StatusEngine::set_fitness(individual, average);
+    }
+}
+
+/// Evolves two populations against each other.
+///
+/// Each generation alternates two phases: the first population is evaluated against opponents sampled
+/// from the second, then the second population against opponents sampled from the (re-evaluated) first.
+pub struct CoevolutionIter<A>
+where
+    A: Arena,
+{
+    generation: usize,
+    first: Vec<Program>,
+    second: Vec<Program>,
+    first_params: HyperParameters<ArenaEngine<A>>,
+    second_params: HyperParameters<ArenaEngine<A>>,
+    n_opponents: usize,
+    trials: Vec<A>,
+}
+
+impl<A> CoevolutionIter<A>
+where
+    A: Arena,
+{
+    /// Uses the seed, number of generations and trials of `first_params`.
+    pub fn new(
+        first_params: HyperParameters<ArenaEngine<A>>,
+        second_params: HyperParameters<ArenaEngine<A>>,
+        n_opponents: usize,
+    ) -> Self {
+        update_seed(first_params.seed);
+
+        let first = ArenaEngine::<A>::init_population(
+            first_params.program_parameters,
+            first_params.population_size,
+        );
+        let second = ArenaEngine::<A>::init_population(
+            second_params.program_parameters,
+            second_params.population_size,
+        );
+        let trials = (0..first_params.n_trials)
+            .map(|_| A::sample())
+            .collect_vec();
+
+        Self {
+            generation: 0,
+            first,
+            second,
+            first_params,
+            second_params,
+            n_opponents: n_opponents.max(1),
+            trials,
+        }
+    }
+}
+
+impl<A> Iterator for CoevolutionIter<A>
+where
+    A: Arena,
+{
+    type Item = (Vec<Program>, Vec<Program>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.generation > self.first_params.n_generations {
+            return None;
+        }
+
+        let mut first = self.first.clone();
+        eval_against(
+            &mut first,
+            &self.second,
+            Role::First,
+            &self.trials,
+            self.n_opponents,
+            self.first_params.default_fitness,
+        );
+        ArenaEngine::<A>::rank_by(&mut first, self.first_params.objective);
+
+        let mut second = self.second.clone();
+        eval_against(
+            &mut second,
+            &first,
+            Role::Second,
+            &self.trials,
+            self.n_opponents,
+            self.second_params.default_fitness,
+        );
+        ArenaEngine::<A>::rank_by(&mut second, self.second_params.objective);
+
+        info!(
+            generation = serde_json::to_string(&self.generation).unwrap(),
+            first_best =
+                serde_json::to_string(&first.first().map(StatusEngine::get_fitness)).unwrap(),
+            second_best =
+                serde_json::to_string(&second.first().map(StatusEngine::get_fitness)).unwrap()
+        );
+
+        for (population, params, next) in [
+            (&first, &self.first_params, &mut self.first),
+            (&second, &self.second_params, &mut self.second),
+        ] {
+            let mut new_population = population.clone();
+
+            ArenaEngine::<A>::survive(&mut new_population, params.gap);
+            ArenaEngine::<A>::variation(
+                &mut new_population,
+                params.crossover_percent,
+                params.mutation_percent,
+                params.program_parameters,
+            );
+
+            *next = new_population;
+        }
+
+        self.generation += 1;
+
+        Some((first, second))
+    }
+}
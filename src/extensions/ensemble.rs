@@ -0,0 +1,166 @@
+//! Ensembles of the best programs of a population, acting by majority vote.
+use std::collections::BTreeMap;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    core::{
+        engines::{
+            fitness_engine::{Fitness, FitnessEngine},
+            reset_engine::{Reset, ResetEngine},
+            status_engine::{Status, StatusEngine},
+        },
+        environment::{observe, perturb_action, RlState, State},
+        program::Program,
+        registers::TieBreak,
+    },
+    extensions::interactive::{repeat_action, UseRlFitness},
+    utils::telemetry::{record_environment_step, record_episode},
+};
+
+/// Programs voting on every class or action.
+///
+/// Each member runs over the same state and votes for the action it selects; the most voted action
+/// wins, the lowest of tied actions. Members whose registers overflow abstain.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Ensemble {
+    pub members: Vec<Program>,
+}
+
+impl Ensemble {
+    /// Takes the `k` first (best) programs of a ranked population.
+    pub fn from_population(population: &[Program], k: usize) -> Self {
+        assert!(k > 0, "An ensemble requires at least one member.");
+
+        Ensemble {
+            members: population.iter().take(k).cloned().collect(),
+        }
+    }
+
+    /// Runs every member over `state` and returns the winning action, `None` when every member abstains.
+    pub fn vote(&mut self, state: &impl State, tie_break: TieBreak) -> Option<usize> {
+        let mut votes = BTreeMap::new();
+
+        for member in self.members.iter_mut() {
+            member.run(state);
+
+            if let Some(action) = member.select_action(tie_break) {
+                *votes.entry(action).or_insert(0) += 1;
+            }
+        }
+
+        votes
+            .into_iter()
+            .rev()
+            .max_by_key(|(_, n_votes)| *n_votes)
+            .map(|(action, _)| action)
+    }
+
+    /// Fitness of the best member, as recorded when it was evaluated.
+    pub fn best_fitness(&self) -> f64 {
+        StatusEngine::get_fitness(&self.members[0])
+    }
+}
+
+impl Reset<Ensemble> for ResetEngine {
+    fn reset(item: &mut Ensemble) {
+        for member in item.members.iter_mut() {
+            ResetEngine::reset(member);
+        }
+    }
+}
+
+/// Accuracy of the majority vote, as for single classifiers.
+impl<T> Fitness<Ensemble, T, ()> for FitnessEngine
+where
+    T: State,
+{
+    fn eval_fitness(ensemble: &mut Ensemble, states: &mut T) -> f64 {
+        let mut n_correct = 0.;
+        let mut n_total = 0.;
+
+        while let Some(state) = states.get() {
+            match ensemble.vote(state, TieBreak::Fail) {
+                None => return f64::NEG_INFINITY,
+                Some(predicted_class) => {
+                    let weight = state.sample_weight();
+                    n_correct += weight * state.execute_action(predicted_class);
+                    n_total += weight;
+                    record_environment_step();
+                }
+            }
+        }
+
+        n_correct / n_total
+    }
+}
+
+/// Cumulative reward of the voted policy, repeating actions according to the best member's frame skip.
+impl<T> Fitness<Ensemble, T, UseRlFitness> for FitnessEngine
+where
+    T: RlState,
+{
+    fn eval_fitness(ensemble: &mut Ensemble, states: &mut T) -> f64 {
+        let frame_skip = ensemble.members[0].frame_skip;
+        let n_actions = ensemble.members[0].registers.n_actions();
+        let mut score = 0.;
+
+        while let Some(state) = states.get() {
+            score += match ensemble.vote(&observe(state), TieBreak::Random) {
+                Some(action) => {
+                    repeat_action(state, perturb_action(action, n_actions), frame_skip).reward
+                }
+                None => {
+                    record_episode(false);
+                    return f64::NEG_INFINITY;
+                }
+            };
+        }
+
+        record_episode(states.succeeded());
+
+        score
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::extensions::classification::Dataset;
+    use crate::problems::problem::program_parameters;
+    use crate::utils::misc::VoidResultAnyError;
+
+    #[test]
+    fn given_disagreeing_members_when_voting_then_majority_wins() -> VoidResultAnyError {
+        let program_parameters = program_parameters(2, 2);
+
+        // The first two members predict class 0, the last one class 1.
+        let members = [
+            "r0 = r0 + 1 * in0",
+            "r0 = r0 + 1 * in1",
+            "r1 = r1 + 1 * in0",
+        ]
+        .into_iter()
+        .map(|text| Program::parse(text, program_parameters))
+        .collect::<Result<Vec<_>, _>>()?;
+        let mut ensemble = Ensemble::from_population(&members, 3);
+
+        let mut dataset = Dataset::new(vec![vec![1., 1.]], vec![0]);
+        ResetEngine::reset(&mut ensemble);
+
+        assert_eq!(
+            <FitnessEngine as Fitness<Ensemble, Dataset, ()>>::eval_fitness(
+                &mut ensemble,
+                &mut dataset
+            ),
+            1.
+        );
+
+        let serialized = serde_json::to_string(&ensemble)?;
+        let deserialized: Ensemble = serde_json::from_str(&serialized)?;
+        assert_eq!(deserialized.members.len(), 3);
+        assert_eq!(Ensemble::from_population(&members, 1).members.len(), 1);
+
+        Ok(())
+    }
+}
@@ -5,10 +5,10 @@ use serde::Serialize;
 use crate::core::engines::fitness_engine::Fitness;
 use crate::core::engines::fitness_engine::FitnessEngine;
 
-use crate::core::environment::RlState;
+use crate::core::environment::{observe, perturb_action, RlState, StepResult};
 use crate::core::program::Program;
-use crate::core::registers::ActionRegister;
-use crate::core::registers::ArgmaxInput;
+use crate::core::registers::TieBreak;
+use crate::utils::telemetry::{record_environment_step, record_episode};
 
 #[derive(Debug, Serialize, Clone, Copy)]
 pub enum Reward {
@@ -34,6 +34,26 @@ impl Reward {
 
 pub struct UseRlFitness;
 
+/// Executes `action` for `frame_skip` environment steps (at least one), stopping early once the episode
+/// ends, and returns the accumulated step.
+pub fn repeat_action<T>(state: &mut T, action: usize, frame_skip: usize) -> StepResult
+where
+    T: RlState,
+{
+    let mut result = StepResult::default();
+
+    for _ in 0..frame_skip.max(1) {
+        result.accumulate(state.step(action));
+        record_environment_step();
+
+        if result.is_done() {
+            break;
+        }
+    }
+
+    result
+}
+
 impl<T> Fitness<Program, T, UseRlFitness> for FitnessEngine
 where
     T: RlState,
@@ -43,12 +63,16 @@ where
 
         while let Some(state) = states.get() {
             // Run program.
-            program.run(state);
+            program.run(&observe(state));
 
             // Eval
-            let reward = match program.registers.argmax(ArgmaxInput::ActionRegisters).any() {
-                ActionRegister::Value(action) => state.execute_action(action),
-                ActionRegister::Overflow => {
+            let reward = match program.select_action(TieBreak::Random) {
+                Some(action) => {
+                    let action = perturb_action(action, program.registers.n_actions());
+                    repeat_action(state, action, program.frame_skip).reward
+                }
+                None => {
+                    record_episode(false);
                     return f64::NEG_INFINITY;
                 }
             };
@@ -56,6 +80,8 @@ where
             score += reward;
         }
 
+        record_episode(states.succeeded());
+
         score
     }
 }
@@ -1,3 +1,9 @@
 pub mod classification;
+pub mod coevolution;
+pub mod ensemble;
 pub mod interactive;
+pub mod optimizers;
+pub mod organism;
 pub mod q_learning;
+pub mod regression;
+pub mod streaming;
@@ -0,0 +1,229 @@
+//! Single-solution baselines over the same representation and fitness functions as the genetic algorithm.
+//!
+//! [`OptimizerIter`] yields one ranked "population" per iteration, holding the best program found so far
+//! along with the candidates evaluated during the iteration, so results go through the same metrics and
+//! plots as [`CoreIter`](crate::core::engines::core_engine::CoreIter) at an equal evaluation budget.
+use std::iter::repeat_with;
+
+use clap::{Args, ValueEnum};
+use derive_builder::Builder;
+use itertools::Itertools;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    core::{
+        engines::{
+            core_engine::{Core, HyperParameters},
+            generate_engine::Generate,
+            mutate_engine::Mutate,
+            status_engine::Status,
+        },
+        environment::GenerationAware,
+    },
+    utils::random::{generator, update_seed},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ValueEnum)]
+pub enum Optimizer {
+    /// Every candidate is generated from scratch.
+    RandomSearch,
+    /// Candidates are mutants of the best program, which is replaced by the best improving mutant.
+    HillClimbing,
+    /// Candidates are mutants of the current program, which moves to worse mutants with a probability
+    /// decreasing with the temperature.
+    SimulatedAnnealing,
+}
+
+#[derive(Debug, Clone, Copy, Args, Serialize, Deserialize, Builder)]
+pub struct OptimizerParameters {
+    #[arg(long, value_enum, default_value_t = Optimizer::HillClimbing)]
+    #[builder(default = "Optimizer::HillClimbing")]
+    pub optimizer: Optimizer,
+    #[arg(long, default_value = "1.")]
+    #[builder(default = "1.")]
+    pub initial_temperature: f64,
+    /// Factor applied to the temperature after every iteration.
+    #[arg(long, default_value = "0.95")]
+    #[builder(default = "0.95")]
+    pub cooling_rate: f64,
+}
+
+/// Runs for `n_generations` iterations of `population_size - 1` evaluations each.
+pub struct OptimizerIter<C>
+where
+    C: Core,
+{
+    iteration: usize,
+    params: HyperParameters<C>,
+    optimizer: OptimizerParameters,
+    trials: Vec<C::State>,
+    current: C::Individual,
+    best: C::Individual,
+    temperature: f64,
+}
+
+impl<C> OptimizerIter<C>
+where
+    C: Core,
+{
+    pub fn new(params: HyperParameters<C>, optimizer: OptimizerParameters) -> Self {
+        update_seed(params.seed);
+
+        let mut trials: Vec<C::State> = repeat_with(|| C::Generate::generate(()))
+            .take(params.n_trials)
+            .collect_vec();
+
+        let mut current = C::Generate::generate(params.program_parameters);
+        C::eval_individual_penalized(
+            &mut current,
+            &mut trials,
+            params.default_fitness,
+            params.fitness_mode,
+            params.risk_aversion,
+            params.objective,
+            &[],
+        );
+
+        Self {
+            iteration: 0,
+            best: current.clone(),
+            current,
+            params,
+            optimizer,
+            trials,
+            temperature: optimizer.initial_temperature,
+        }
+    }
+
+    fn fitness(individual: &C::Individual) -> f64 {
+        C::Status::get_fitness(individual)
+    }
+
+    fn candidate(&self) -> C::Individual {
+        match self.optimizer.optimizer {
+            Optimizer::RandomSearch => C::Generate::generate(self.params.program_parameters),
+            Optimizer::HillClimbing | Optimizer::SimulatedAnnealing => {
+                let mut candidate = self.current.clone();
+                C::Mutate::mutate(&mut candidate, self.params.program_parameters);
+                candidate
+            }
+        }
+    }
+}
+
+impl<C> Iterator for OptimizerIter<C>
+where
+    C: Core,
+{
+    type Item = Vec<C::Individual>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.iteration > self.params.n_generations {
+            return None;
+        }
+
+        for trial in self.trials.iter_mut() {
+            trial.on_generation(self.iteration);
+        }
+
+        let n_candidates = self.params.population_size.saturating_sub(1).max(1);
+        let mut candidates = Vec::with_capacity(n_candidates + 1);
+
+        for _ in 0..n_candidates {
+            let mut candidate = self.candidate();
+            C::eval_individual_penalized(
+                &mut candidate,
+                &mut self.trials,
+                self.params.default_fitness,
+                self.params.fitness_mode,
+                self.params.risk_aversion,
+                self.params.objective,
+                &[],
+            );
+
+            let objective = self.params.objective;
+            let delta =
+                objective.improvement(Self::fitness(&candidate), Self::fitness(&self.current));
+
+            let accepted = match self.optimizer.optimizer {
+                Optimizer::RandomSearch => delta > 0.,
+                // Candidates of an iteration are all mutants of the same program.
+                Optimizer::HillClimbing => false,
+                Optimizer::SimulatedAnnealing => {
+                    delta > 0.
+                        || (self.temperature > 0.
+                            && generator().gen::<f64>() < (delta / self.temperature).exp())
+                }
+            };
+
+            if accepted {
+                self.current = candidate.clone();
+            }
+
+            if objective.is_better(Self::fitness(&candidate), Self::fitness(&self.best)) {
+                self.best = candidate.clone();
+            }
+
+            candidates.push(candidate);
+        }
+
+        if self.optimizer.optimizer == Optimizer::HillClimbing
+            && self
+                .params
+                .objective
+                .is_better(Self::fitness(&self.best), Self::fitness(&self.current))
+        {
+            self.current = self.best.clone();
+        }
+
+        candidates.push(self.best.clone());
+        C::rank_by(&mut candidates, self.params.objective);
+
+        self.temperature *= self.optimizer.cooling_rate;
+        self.iteration += 1;
+
+        Some(candidates)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::engines::status_engine::StatusEngine;
+    use crate::utils::misc::VoidResultAnyError;
+    use crate::utils::test::navigation_parameters;
+
+    #[test]
+    fn given_baseline_optimizers_when_run_then_best_fitness_never_decreases() -> VoidResultAnyError
+    {
+        let parameters = navigation_parameters(20)
+            .population_size(5)
+            .n_generations(5)
+            .n_trials(2)
+            .seed(Some(1))
+            .build()?;
+
+        for optimizer in [
+            Optimizer::RandomSearch,
+            Optimizer::HillClimbing,
+            Optimizer::SimulatedAnnealing,
+        ] {
+            let optimizer_parameters = OptimizerParametersBuilder::default()
+                .optimizer(optimizer)
+                .build()?;
+
+            let best = OptimizerIter::new(parameters.clone(), optimizer_parameters)
+                .take(parameters.n_generations)
+                .map(|population| {
+                    assert_eq!(population.len(), parameters.population_size);
+                    StatusEngine::get_fitness(population.first().unwrap())
+                })
+                .collect_vec();
+
+            assert!(best.windows(2).all(|w| w[0] <= w[1]));
+        }
+
+        Ok(())
+    }
+}
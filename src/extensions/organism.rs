@@ -0,0 +1,230 @@
+//! Heterogeneous populations: plain LGP programs and Q-learning programs competing on the same task, so
+//! evolution decides whether the Q-table pays off.
+use clap::Args;
+use derive_builder::Builder;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    core::{
+        engines::{
+            breed_engine::{Breed, BreedEngine},
+            fitness_engine::{Fitness, FitnessEngine, FitnessMetadata},
+            freeze_engine::{Freeze, FreezeEngine},
+            generate_engine::{Generate, GenerateEngine},
+            mutate_engine::{Mutate, MutateEngine},
+            reset_engine::{Reset, ResetEngine},
+            status_engine::{Status, StatusEngine},
+        },
+        environment::RlState,
+        program::{AsProgram, Program},
+    },
+    extensions::{
+        interactive::UseRlFitness,
+        q_learning::{QProgram, QProgramGeneratorParameters},
+    },
+    utils::random::generator,
+};
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum Organism {
+    Lgp(Program),
+    Q(QProgram),
+}
+
+impl Organism {
+    pub fn is_q(&self) -> bool {
+        matches!(self, Organism::Q(_))
+    }
+
+    /// How the organism's fitness was measured, unset until it is evaluated.
+    pub fn fitness_metadata(&self) -> Option<FitnessMetadata> {
+        self.as_program().fitness_metadata
+    }
+}
+
+impl AsProgram for Organism {
+    fn as_program(&self) -> &Program {
+        match self {
+            Organism::Lgp(program) => program,
+            Organism::Q(q_program) => &q_program.program,
+        }
+    }
+
+    fn output_registers(&self) -> Vec<usize> {
+        match self {
+            Organism::Lgp(program) => program.output_registers(),
+            Organism::Q(q_program) => q_program.output_registers(),
+        }
+    }
+}
+
+impl PartialEq for Organism {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_program() == other.as_program()
+    }
+}
+
+impl Eq for Organism {}
+
+impl Ord for Organism {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.as_program().cmp(other.as_program())
+    }
+}
+
+impl PartialOrd for Organism {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[derive(Debug, Clone, Copy, Args, Serialize, Deserialize, Builder)]
+pub struct OrganismGeneratorParameters {
+    #[command(flatten)]
+    pub q_program_parameters: QProgramGeneratorParameters,
+    /// Fraction of generated organisms which learn a Q-table.
+    #[arg(long, default_value = "0.5")]
+    #[builder(default = "0.5")]
+    pub q_fraction: f64,
+}
+
+impl Generate<OrganismGeneratorParameters, Organism> for GenerateEngine {
+    fn generate(using: OrganismGeneratorParameters) -> Organism {
+        if generator().gen::<f64>() < using.q_fraction {
+            Organism::Q(GenerateEngine::generate(using.q_program_parameters))
+        } else {
+            Organism::Lgp(GenerateEngine::generate(
+                using.q_program_parameters.program_parameters,
+            ))
+        }
+    }
+}
+
+impl Mutate<OrganismGeneratorParameters, Organism> for MutateEngine {
+    fn mutate(item: &mut Organism, using: OrganismGeneratorParameters) {
+        match item {
+            Organism::Lgp(program) => {
+                MutateEngine::mutate(program, using.q_program_parameters.program_parameters)
+            }
+            Organism::Q(q_program) => MutateEngine::mutate(q_program, using.q_program_parameters),
+        }
+    }
+}
+
+/// Children keep the kind of the parent they inherit from; mates of different kinds exchange code only.
+impl Breed<Organism> for BreedEngine {
+    fn two_point_crossover(mate_1: &Organism, mate_2: &Organism) -> (Organism, Organism) {
+        match (mate_1, mate_2) {
+            (Organism::Lgp(mate_1), Organism::Lgp(mate_2)) => {
+                let (child_1, child_2) = BreedEngine::two_point_crossover(mate_1, mate_2);
+                (Organism::Lgp(child_1), Organism::Lgp(child_2))
+            }
+            (Organism::Q(mate_1), Organism::Q(mate_2)) => {
+                let (child_1, child_2) = BreedEngine::two_point_crossover(mate_1, mate_2);
+                (Organism::Q(child_1), Organism::Q(child_2))
+            }
+            _ => {
+                let (program_1, program_2) =
+                    BreedEngine::two_point_crossover(mate_1.as_program(), mate_2.as_program());
+
+                let inherit = |mate: &Organism, program: Program| match mate {
+                    Organism::Lgp(_) => Organism::Lgp(program),
+                    Organism::Q(q_program) => {
+                        let mut child = q_program.clone();
+                        child.program = program;
+                        ResetEngine::reset(&mut child.q_table);
+                        Organism::Q(child)
+                    }
+                };
+
+                (inherit(mate_1, program_1), inherit(mate_2, program_2))
+            }
+        }
+    }
+}
+
+impl Reset<Organism> for ResetEngine {
+    fn reset(item: &mut Organism) {
+        match item {
+            Organism::Lgp(program) => ResetEngine::reset(program),
+            Organism::Q(q_program) => ResetEngine::reset(q_program),
+        }
+    }
+}
+
+impl Freeze<Organism> for FreezeEngine {
+    fn freeze(item: &mut Organism) {
+        match item {
+            Organism::Lgp(program) => FreezeEngine::freeze(program),
+            Organism::Q(q_program) => FreezeEngine::freeze(q_program),
+        }
+    }
+}
+
+impl Status<Organism> for StatusEngine {
+    fn valid(item: &Organism) -> bool {
+        StatusEngine::valid(item.as_program())
+    }
+
+    fn evaluated(item: &Organism) -> bool {
+        StatusEngine::evaluated(item.as_program())
+    }
+
+    fn set_fitness(item: &mut Organism, fitness: f64) {
+        match item {
+            Organism::Lgp(program) => StatusEngine::set_fitness(program, fitness),
+            Organism::Q(q_program) => StatusEngine::set_fitness(q_program, fitness),
+        }
+    }
+
+    fn get_fitness(item: &Organism) -> f64 {
+        StatusEngine::get_fitness(item.as_program())
+    }
+
+    fn set_metadata(item: &mut Organism, metadata: FitnessMetadata) {
+        match item {
+            Organism::Lgp(program) => StatusEngine::set_metadata(program, metadata),
+            Organism::Q(q_program) => StatusEngine::set_metadata(q_program, metadata),
+        }
+    }
+
+    fn get_metadata(item: &Organism) -> Option<FitnessMetadata> {
+        StatusEngine::get_metadata(item.as_program())
+    }
+
+    /// Q-learning organisms learn while evaluated, so only LGP organisms are cached.
+    fn genome_hash(item: &Organism) -> Option<u64> {
+        match item {
+            Organism::Lgp(program) => StatusEngine::genome_hash(program),
+            Organism::Q(_) => None,
+        }
+    }
+
+    fn get_age(item: &Organism) -> usize {
+        StatusEngine::get_age(item.as_program())
+    }
+
+    fn set_age(item: &mut Organism, age: usize) {
+        match item {
+            Organism::Lgp(program) => StatusEngine::set_age(program, age),
+            Organism::Q(q_program) => StatusEngine::set_age(q_program, age),
+        }
+    }
+}
+
+impl<T> Fitness<Organism, T, ()> for FitnessEngine
+where
+    T: RlState,
+{
+    fn eval_fitness(item: &mut Organism, states: &mut T) -> f64 {
+        match item {
+            Organism::Lgp(program) => {
+                <FitnessEngine as Fitness<Program, T, UseRlFitness>>::eval_fitness(program, states)
+            }
+            Organism::Q(q_program) => {
+                <FitnessEngine as Fitness<QProgram, T, ()>>::eval_fitness(q_program, states)
+            }
+        }
+    }
+}
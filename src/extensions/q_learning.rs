@@ -1,34 +1,49 @@
-use std::fmt::{self, Debug};
+use std::{
+    error::Error,
+    fmt::{self, Debug},
+    path::Path,
+};
 
-use clap::Args;
+use clap::{Args, ValueEnum};
 use derivative::Derivative;
 use derive_builder::Builder;
+use itertools::Itertools;
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 use tracing::info;
 
 use crate::{
     core::{
+        characteristics::Save,
         engines::{
             breed_engine::{Breed, BreedEngine},
-            fitness_engine::{Fitness, FitnessEngine},
+            fitness_engine::{Fitness, FitnessEngine, FitnessMetadata},
             freeze_engine::{Freeze, FreezeEngine},
             generate_engine::{Generate, GenerateEngine},
             mutate_engine::{Mutate, MutateEngine},
             reset_engine::{Reset, ResetEngine},
             status_engine::{Status, StatusEngine},
         },
-        environment::{RlState, State},
+        environment::{observe, perturb_action, RlState},
         instruction::InstructionGeneratorParameters,
-        program::{Program, ProgramGeneratorParameters},
-        registers::{ActionRegister, ArgmaxInput, Registers},
+        program::{AsProgram, Program, ProgramGeneratorParameters},
+        registers::{ActionRegister, ArgmaxInput, Registers, TieBreak},
+    },
+    extensions::interactive::repeat_action,
+    utils::{
+        benchmark_tools::benchmark_prefix,
+        float_ops,
+        random::{generator, in_stream},
+        telemetry::record_episode,
     },
-    utils::{float_ops, random::generator},
 };
 
 #[derive(Clone, Serialize, Deserialize)]
 pub struct QTable {
     table: Vec<Vec<f64>>,
+    /// How often each action was selected for each register, indexed like `table`.
+    #[serde(default)]
+    visits: Vec<Vec<usize>>,
     q_consts: QConsts,
     freeze: bool,
 }
@@ -43,6 +58,7 @@ impl Generate<(InstructionGeneratorParameters, QConsts), QTable> for GenerateEng
     fn generate(using: (InstructionGeneratorParameters, QConsts)) -> QTable {
         let mut table = QTable {
             table: vec![vec![0.; using.0.n_actions]; using.0.n_registers()],
+            visits: vec![],
             q_consts: using.1,
             freeze: false,
         };
@@ -67,10 +83,25 @@ pub struct ActionRegisterPair {
 impl Reset<QTable> for ResetEngine {
     fn reset(item: &mut QTable) {
         ResetEngine::reset(&mut item.q_consts);
+        item.visits = item
+            .table
+            .iter()
+            .map(|actions| vec![0; actions.len()])
+            .collect();
     }
 }
 
 impl QTable {
+    /// Q-values indexed by register, then action.
+    pub fn values(&self) -> &[Vec<f64>] {
+        &self.table
+    }
+
+    /// Selection counts indexed by register, then action.
+    pub fn visits(&self) -> &[Vec<usize>] {
+        &self.visits
+    }
+
     pub fn action_random(&self) -> usize {
         let n_actions = self.table[0].len();
         generator().gen_range(0..n_actions)
@@ -88,21 +119,103 @@ impl QTable {
         max.expect("Available action to yield an index.")
     }
 
-    pub fn get_action_register(&self, registers: &Registers) -> Option<ActionRegisterPair> {
-        let winning_register = match registers.argmax(ArgmaxInput::All).any() {
+    /// Samples an action with probability proportional to `exp(q / temperature)` (Boltzmann exploration),
+    /// falling back to the greedy action once the temperature has annealed to zero.
+    pub fn action_softmax(&self, register_number: usize, temperature: f64) -> usize {
+        let available_actions = self
+            .table
+            .get(register_number)
+            .expect("Register number to be less than length of QTable.");
+
+        let max = available_actions
+            .iter()
+            .copied()
+            .fold(f64::NEG_INFINITY, f64::max);
+
+        // Shifted by the maximum so the exponentials cannot overflow.
+        let weights = available_actions
+            .iter()
+            .map(|value| ((value - max) / temperature).exp())
+            .collect_vec();
+        let total: f64 = weights.iter().sum();
+
+        if !(temperature > 0.) || !total.is_finite() {
+            return self.action_argmax(register_number);
+        }
+
+        let mut threshold = generator().gen_range((0.)..total);
+
+        for (action, weight) in weights.iter().enumerate() {
+            if threshold < *weight {
+                return action;
+            }
+            threshold -= weight;
+        }
+
+        weights.len() - 1
+    }
+
+    /// Picks the action maximizing `q + c * sqrt(ln(n) / n_action)` (UCB1), where `n` is the number of
+    /// times the register won and `n_action` the number of times the action was selected for it.
+    /// Actions which were never selected are tried first.
+    pub fn action_ucb(&self, register_number: usize, exploration_constant: f64) -> usize {
+        let available_actions = self
+            .table
+            .get(register_number)
+            .expect("Register number to be less than length of QTable.");
+        let visits = match self.visits.get(register_number) {
+            Some(visits) => visits,
+            None => return self.action_argmax(register_number),
+        };
+
+        if let Some(unvisited) = visits.iter().position(|n| *n == 0) {
+            return unvisited;
+        }
+
+        let total_visits = visits.iter().sum::<usize>() as f64;
+        let bounds = available_actions.iter().zip(visits).map(|(value, n)| {
+            value + exploration_constant * (total_visits.ln() / *n as f64).sqrt()
+        });
+
+        float_ops::argmax(bounds).expect("Available action to yield an index.")
+    }
+
+    pub fn get_action_register(
+        &mut self,
+        registers: &Registers,
+        tie_break: TieBreak,
+    ) -> Option<ActionRegisterPair> {
+        let winning_register = match registers
+            .argmax(ArgmaxInput::All)
+            .resolve(tie_break, registers.n_actions())
+        {
             ActionRegister::Value(register) => register,
             _ => {
                 return None;
             }
         };
 
-        let prob = generator().gen_range((0.)..(1.));
+        let winning_action = in_stream("exploration", || match self.q_consts.exploration {
+            ExplorationPolicy::EpsilonGreedy => {
+                let prob = generator().gen_range((0.)..(1.));
 
-        let winning_action = if prob <= self.q_consts.epsilon_active {
-            self.action_random()
-        } else {
-            self.action_argmax(winning_register)
-        };
+                if prob <= self.q_consts.epsilon_active {
+                    self.action_random()
+                } else {
+                    self.action_argmax(winning_register)
+                }
+            }
+            ExplorationPolicy::Softmax => {
+                self.action_softmax(winning_register, self.q_consts.temperature_active)
+            }
+            ExplorationPolicy::Ucb => self.action_ucb(winning_register, self.q_consts.ucb_c),
+        });
+
+        if !self.freeze {
+            if let Some(visits) = self.visits.get_mut(winning_register) {
+                visits[winning_action] += 1;
+            }
+        }
 
         Some(ActionRegisterPair {
             action: winning_action,
@@ -116,12 +229,30 @@ impl QTable {
         current_reward: f64,
         next_action_state: ActionRegisterPair,
     ) {
+        let next_register = next_action_state.register;
+        let next_q_value = self.table[next_register][self.action_argmax(next_register)];
+
+        self.learn(
+            current_action_state,
+            current_reward + (self.q_consts.gamma * next_q_value),
+        );
+    }
+
+    /// Updates towards `current_reward` alone, the transition having led to a terminal state whose
+    /// value is zero.
+    pub fn update_terminal(
+        &mut self,
+        current_action_state: ActionRegisterPair,
+        current_reward: f64,
+    ) {
+        self.learn(current_action_state, current_reward);
+    }
+
+    fn learn(&mut self, current_action_state: ActionRegisterPair, target: f64) {
         let current_q_value =
             self.table[current_action_state.register][current_action_state.action];
-        let next_q_value = self.action_argmax(next_action_state.register) as f64;
 
-        let new_q_value = self.q_consts.alpha_active
-            * (current_reward + (self.q_consts.gamma * next_q_value) - current_q_value);
+        let new_q_value = self.q_consts.alpha_active * (target - current_q_value);
 
         self.table[current_action_state.register][current_action_state.action] += new_q_value;
 
@@ -139,6 +270,17 @@ pub struct QProgram {
     pub program: Program,
 }
 
+impl AsProgram for QProgram {
+    fn as_program(&self) -> &Program {
+        &self.program
+    }
+
+    /// Actions are chosen through the Q-table, which reads every register.
+    fn output_registers(&self) -> Vec<usize> {
+        (0..self.program.registers.len()).collect()
+    }
+}
+
 impl Freeze<QProgram> for FreezeEngine {
     fn freeze(item: &mut QProgram) {
         FreezeEngine::freeze(&mut item.q_table);
@@ -153,15 +295,16 @@ impl Reset<QProgram> for ResetEngine {
 
 fn get_action_state<T>(environment: &mut T, q_program: &mut QProgram) -> Option<ActionRegisterPair>
 where
-    T: State,
+    T: RlState,
 {
-    // Run the program on the current state.
-    q_program.program.run(environment);
+    // Run the program on what it observes of the current state.
+    q_program.program.run(&observe(environment));
 
     // Get the winning action-register pair.
-    let action_state = q_program
-        .q_table
-        .get_action_register(&q_program.program.registers);
+    let action_state = q_program.q_table.get_action_register(
+        &q_program.program.registers,
+        q_program.program.tie_break.unwrap_or(TieBreak::Random),
+    );
 
     action_state
 }
@@ -174,23 +317,44 @@ impl<T: RlState> Fitness<QProgram, T, ()> for FitnessEngine {
         let mut current_action_state = match get_action_state(states, program) {
             Some(action_state) => action_state,
             None => {
+                record_episode(false);
                 return f64::NEG_INFINITY;
             }
         };
 
         // We execute the selected action and continue to repeat the cycle until termination.
         while let Some(state) = states.get() {
-            // Act.
-            let reward = state.execute_action(current_action_state.action);
+            // Act, the action possibly being overridden by action noise.
+            let action = perturb_action(
+                current_action_state.action,
+                program.program.registers.n_actions(),
+            );
+            let step = repeat_action(state, action, program.program.frame_skip);
+            let reward = step.reward;
+            // The Q-table learns from the action which ran.
+            let current_action_state = ActionRegisterPair {
+                action,
+                register: current_action_state.register,
+            };
             score += reward;
 
-            if state.is_terminal() {
+            // Only a real termination zeroes the future, a truncated episode bootstraps from the
+            // state it was cut short in (unless configured otherwise).
+            let terminal = step.terminated()
+                || (step.truncated()
+                    && program.q_table.q_consts.truncation == TruncationHandling::Terminal);
+
+            if terminal {
+                program
+                    .q_table
+                    .update_terminal(current_action_state, reward);
                 break;
             }
 
             let next_action_state = match get_action_state(state, program) {
                 Some(action_state) => action_state,
                 None => {
+                    record_episode(false);
                     return f64::NEG_INFINITY;
                 }
             };
@@ -206,6 +370,8 @@ impl<T: RlState> Fitness<QProgram, T, ()> for FitnessEngine {
             current_action_state = next_action_state;
         }
 
+        record_episode(states.succeeded());
+
         info!(
             id = serde_json::to_string(&program.program.id.to_string()).unwrap(),
             q_table = serde_json::to_string(&program.q_table).unwrap(),
@@ -219,28 +385,65 @@ impl<T: RlState> Fitness<QProgram, T, ()> for FitnessEngine {
 
 impl Breed<QProgram> for BreedEngine {
     fn two_point_crossover(mate_1: &QProgram, mate_2: &QProgram) -> (QProgram, QProgram) {
-        let (_child_1_program, _child_2_program) =
-            BreedEngine::two_point_crossover(&mate_1.program, &mate_2.program);
-
-        let mut child_1 = mate_1.clone();
-        let mut child_2 = mate_2.clone();
+        let (child_1_program, child_2_program) = match mate_1.q_table.q_consts.crossover {
+            CrossoverVariant::TwoPoint => {
+                BreedEngine::two_point_crossover(&mate_1.program, &mate_2.program)
+            }
+            CrossoverVariant::Aligned => {
+                Program::aligned_crossover(&mate_1.program, &mate_2.program)
+            }
+        };
 
-        child_1.program = child_1.program;
-        child_2.program = child_2.program;
+        let child_1 = QProgram::offspring(child_1_program, mate_1, mate_2);
+        let child_2 = QProgram::offspring(child_2_program, mate_2, mate_1);
 
-        ResetEngine::reset(&mut child_1.program.id);
-        ResetEngine::reset(&mut child_2.program.id);
+        (child_1, child_2)
+    }
+}
 
-        ResetEngine::reset(&mut child_1.program);
-        ResetEngine::reset(&mut child_2.program);
+impl QProgram {
+    /// Pairs `program` with the Q-table of the mate it inherits most of its instructions from,
+    /// `mate` on a tie. The Q-values are kept, see [`Reset<QTable>`].
+    fn offspring(program: Program, mate: &QProgram, other_mate: &QProgram) -> QProgram {
+        let q_table = if n_inherited(&program, other_mate) > n_inherited(&program, mate) {
+            &other_mate.q_table
+        } else {
+            &mate.q_table
+        };
 
-        ResetEngine::reset(&mut child_1.q_table);
-        ResetEngine::reset(&mut child_2.q_table);
+        let mut child = QProgram {
+            q_table: q_table.clone(),
+            program,
+        };
+        ResetEngine::reset(&mut child.q_table);
 
-        (child_1, child_2)
+        child
     }
 }
 
+/// How many instructions of `program` also appear in `parent`, each instruction of the parent
+/// matching at most once.
+fn n_inherited(program: &Program, parent: &QProgram) -> usize {
+    let mut remaining = parent.program.instructions.clone();
+
+    program
+        .instructions
+        .iter()
+        .filter(|instruction| {
+            match remaining
+                .iter()
+                .position(|candidate| candidate == *instruction)
+            {
+                Some(index) => {
+                    remaining.swap_remove(index);
+                    true
+                }
+                None => false,
+            }
+        })
+        .count()
+}
+
 impl Status<QProgram> for StatusEngine {
     fn valid(item: &QProgram) -> bool {
         StatusEngine::valid(&item.program)
@@ -254,6 +457,22 @@ impl Status<QProgram> for StatusEngine {
         program.program.fitness
     }
 
+    fn set_metadata(program: &mut QProgram, metadata: FitnessMetadata) {
+        StatusEngine::set_metadata(&mut program.program, metadata)
+    }
+
+    fn get_metadata(program: &QProgram) -> Option<FitnessMetadata> {
+        StatusEngine::get_metadata(&program.program)
+    }
+
+    fn get_age(program: &QProgram) -> usize {
+        StatusEngine::get_age(&program.program)
+    }
+
+    fn set_age(program: &mut QProgram, age: usize) {
+        StatusEngine::set_age(&mut program.program, age)
+    }
+
     fn evaluated(item: &QProgram) -> bool {
         StatusEngine::evaluated(&item.program)
     }
@@ -289,6 +508,47 @@ pub struct QProgramGeneratorParameters {
     pub consts: QConsts,
 }
 
+/// How the Q-table picks an action for the winning register.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ValueEnum)]
+pub enum ExplorationPolicy {
+    /// A uniformly random action with probability `epsilon`, the greedy action otherwise.
+    #[default]
+    EpsilonGreedy,
+    /// Actions sampled proportionally to `exp(q / temperature)`, favoring those with higher Q-values.
+    Softmax,
+    /// The action with the highest upper confidence bound (UCB1), trying rarely selected actions more.
+    Ucb,
+}
+
+/// How the programs of two Q-programs are recombined.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ValueEnum)]
+pub enum CrossoverVariant {
+    /// Segments are exchanged at random positions.
+    #[default]
+    TwoPoint,
+    /// Segments are exchanged with the segment of the other mate writing to the most similar
+    /// registers, keeping the register roles the Q-table was learned on.
+    Aligned,
+}
+
+/// How Q-updates treat episodes cut short by a step limit.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ValueEnum)]
+pub enum TruncationHandling {
+    /// Bootstrap from the value of the state the episode was cut short in.
+    #[default]
+    Bootstrap,
+    /// Treat truncation like termination, ignoring the value of the final state.
+    Terminal,
+}
+
+fn default_temperature() -> f64 {
+    1.
+}
+
+fn default_ucb_c() -> f64 {
+    std::f64::consts::SQRT_2
+}
+
 #[derive(Debug, Clone, Copy, Args, Serialize, Deserialize, Builder)]
 pub struct QConsts {
     /// Learning Factor
@@ -311,6 +571,36 @@ pub struct QConsts {
     #[arg(long, default_value = "0.001")]
     #[builder(default = "0.001")]
     epsilon_decay: f64,
+    /// Action Selection Policy
+    #[arg(long, value_enum, default_value_t = ExplorationPolicy::EpsilonGreedy)]
+    #[builder(default = "ExplorationPolicy::EpsilonGreedy")]
+    #[serde(default)]
+    exploration: ExplorationPolicy,
+    /// Softmax Temperature
+    #[arg(long, default_value = "1")]
+    #[builder(default = "1.")]
+    #[serde(default = "default_temperature")]
+    temperature: f64,
+    /// Temperature Decay
+    #[arg(long, default_value = "0")]
+    #[builder(default = "0.")]
+    #[serde(default)]
+    temperature_decay: f64,
+    /// UCB Exploration Constant
+    #[arg(long, default_value = "1.4142135623730951")]
+    #[builder(default = "std::f64::consts::SQRT_2")]
+    #[serde(default = "default_ucb_c")]
+    ucb_c: f64,
+    /// Crossover Variant
+    #[arg(long, value_enum, default_value_t = CrossoverVariant::TwoPoint)]
+    #[builder(default = "CrossoverVariant::TwoPoint")]
+    #[serde(default)]
+    crossover: CrossoverVariant,
+    /// Truncation Handling
+    #[arg(long, value_enum, default_value_t = TruncationHandling::Bootstrap)]
+    #[builder(default = "TruncationHandling::Bootstrap")]
+    #[serde(default)]
+    truncation: TruncationHandling,
 
     /// To allow new programs to start from the new state, we have active
     /// properties to mutuate.
@@ -323,12 +613,18 @@ pub struct QConsts {
     #[arg(skip)]
     #[builder(setter(skip), default)]
     epsilon_active: f64,
+
+    #[serde(skip)]
+    #[arg(skip)]
+    #[builder(setter(skip), default)]
+    temperature_active: f64,
 }
 
 impl Reset<QConsts> for ResetEngine {
     fn reset(item: &mut QConsts) {
         item.alpha_active = item.alpha;
         item.epsilon_active = item.epsilon;
+        item.temperature_active = item.temperature;
     }
 }
 
@@ -342,12 +638,47 @@ impl QConsts {
             epsilon,
             alpha_decay,
             epsilon_decay,
+            exploration: ExplorationPolicy::EpsilonGreedy,
+            temperature: default_temperature(),
+            temperature_decay: 0.,
+            ucb_c: default_ucb_c(),
+            crossover: CrossoverVariant::TwoPoint,
+            truncation: TruncationHandling::Bootstrap,
+            temperature_active: default_temperature(),
         }
     }
 
+    /// Switches to softmax action selection, starting at `temperature` and annealed by
+    /// `temperature_decay` on every update.
+    pub fn with_softmax(mut self, temperature: f64, temperature_decay: f64) -> Self {
+        self.exploration = ExplorationPolicy::Softmax;
+        self.temperature = temperature;
+        self.temperature_decay = temperature_decay;
+        self.temperature_active = temperature;
+        self
+    }
+
+    /// Switches to UCB1 action selection with the exploration constant `ucb_c`.
+    pub fn with_ucb(mut self, ucb_c: f64) -> Self {
+        self.exploration = ExplorationPolicy::Ucb;
+        self.ucb_c = ucb_c;
+        self
+    }
+
+    pub fn with_crossover(mut self, crossover: CrossoverVariant) -> Self {
+        self.crossover = crossover;
+        self
+    }
+
+    pub fn with_truncation(mut self, truncation: TruncationHandling) -> Self {
+        self.truncation = truncation;
+        self
+    }
+
     pub fn decay(&mut self) {
         self.alpha_active *= 1. - self.alpha_decay;
-        self.epsilon_active *= 1. - self.epsilon_decay
+        self.epsilon_active *= 1. - self.epsilon_decay;
+        self.temperature_active *= 1. - self.temperature_decay;
     }
 }
 
@@ -364,8 +695,264 @@ impl Default for QConsts {
             epsilon,
             alpha_decay,
             epsilon_decay,
+            exploration: ExplorationPolicy::EpsilonGreedy,
+            temperature: default_temperature(),
+            temperature_decay: 0.,
+            ucb_c: default_ucb_c(),
+            crossover: CrossoverVariant::TwoPoint,
+            truncation: TruncationHandling::Bootstrap,
             alpha_active: alpha,
             epsilon_active: epsilon_decay,
+            temperature_active: default_temperature(),
         }
     }
 }
+
+/// The Q-table of the best individual of a generation, with summaries showing whether action
+/// preferences are being learned or the table stays near its initial (zero) values.
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq)]
+pub struct QTableSnapshot {
+    pub generation: usize,
+    pub fitness: f64,
+    pub table: Vec<Vec<f64>>,
+    pub mean_abs_value: f64,
+    pub max_abs_value: f64,
+    /// Number of registers with a single best action.
+    pub n_decided_registers: usize,
+}
+
+impl QTableSnapshot {
+    pub fn new(generation: usize, individual: &QProgram) -> Self {
+        let table = individual.q_table.values().to_vec();
+        let values = table
+            .iter()
+            .flatten()
+            .map(|value| value.abs())
+            .collect_vec();
+
+        let n_decided_registers = table
+            .iter()
+            .filter(|actions| {
+                let max = actions.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+                actions.iter().filter(|value| **value == max).count() == 1
+            })
+            .count();
+
+        QTableSnapshot {
+            generation,
+            fitness: StatusEngine::get_fitness(individual),
+            mean_abs_value: values.iter().sum::<f64>() / values.len().max(1) as f64,
+            max_abs_value: values.iter().copied().fold(0., f64::max),
+            n_decided_registers,
+            table,
+        }
+    }
+}
+
+/// Snapshots the Q-table of the best individual of every (ranked) population.
+pub fn q_table_snapshots(populations: &[Vec<QProgram>]) -> Vec<QTableSnapshot> {
+    populations
+        .iter()
+        .enumerate()
+        .filter_map(|(generation, population)| {
+            population
+                .first()
+                .map(|best| QTableSnapshot::new(generation, best))
+        })
+        .collect()
+}
+
+/// Writes the snapshots of `populations` to `q_tables.json`, next to the other artifacts of the
+/// experiment.
+pub fn save_q_tables(populations: &[Vec<QProgram>], test_name: &str) -> Result<(), Box<dyn Error>> {
+    q_table_snapshots(populations).save(
+        Path::new(&benchmark_prefix())
+            .join(test_name)
+            .join("q_tables.json")
+            .to_str()
+            .unwrap(),
+    )?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::instruction::InstructionGeneratorParametersBuilder;
+    use crate::utils::misc::VoidResultAnyError;
+    use crate::utils::test::program_parameters_of_length;
+
+    #[test]
+    fn given_q_program_when_snapshotted_then_table_is_summarized() -> VoidResultAnyError {
+        let program_parameters = program_parameters_of_length(2, 2, 10);
+        let parameters = QProgramGeneratorParametersBuilder::default()
+            .program_parameters(program_parameters)
+            .build()?;
+
+        let mut q_program: QProgram = GenerateEngine::generate(parameters);
+        q_program.q_table.table[0] = vec![1., -3.];
+        StatusEngine::set_fitness(&mut q_program, 2.);
+
+        let snapshots = q_table_snapshots(&[vec![q_program.clone()]]);
+        let snapshot = snapshots.first().unwrap();
+
+        assert_eq!(snapshot.generation, 0);
+        assert_eq!(snapshot.fitness, 2.);
+        assert_eq!(snapshot.max_abs_value, 3.);
+        // Every other register still has all-zero (undecided) actions.
+        assert_eq!(snapshot.n_decided_registers, 1);
+        assert_eq!(snapshot.table, q_program.q_table.values());
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_softmax_exploration_when_actions_are_sampled_then_higher_q_values_are_preferred(
+    ) -> VoidResultAnyError {
+        let instruction_parameters = InstructionGeneratorParametersBuilder::default()
+            .n_actions(3)
+            .n_inputs(2)
+            .build()?;
+        let consts = QConsts::new(0.1, 0.9, 0.05, 0.01, 0.001).with_softmax(0.5, 0.);
+        let mut q_table: QTable = GenerateEngine::generate((instruction_parameters, consts));
+        q_table.table[0] = vec![0., 2., 0.];
+
+        let mut counts = [0; 3];
+        for _ in 0..1000 {
+            counts[q_table.action_softmax(0, q_table.q_consts.temperature_active)] += 1;
+        }
+
+        assert!(counts[1] > counts[0] + counts[2]);
+        assert!(counts[0] > 0 && counts[2] > 0);
+        // An annealed temperature is greedy.
+        assert_eq!(q_table.action_softmax(0, 0.), 1);
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_ucb_exploration_when_actions_are_selected_then_visits_are_counted_and_reset(
+    ) -> VoidResultAnyError {
+        let instruction_parameters = InstructionGeneratorParametersBuilder::default()
+            .n_actions(2)
+            .n_inputs(1)
+            .build()?;
+        let consts = QConsts::new(0.1, 0.9, 0.05, 0.01, 0.001).with_ucb(1.);
+        let mut q_table: QTable = GenerateEngine::generate((instruction_parameters, consts));
+        q_table.table[0] = vec![1., 0.];
+
+        let mut values = vec![0.; instruction_parameters.n_registers()];
+        values[0] = 1.;
+        let registers = Registers::from_values(values, instruction_parameters.n_actions);
+
+        // Both actions are tried once before the bonus is weighed against the Q-values.
+        let first = q_table
+            .get_action_register(&registers, TieBreak::First)
+            .unwrap();
+        let second = q_table
+            .get_action_register(&registers, TieBreak::First)
+            .unwrap();
+        assert_eq!((first.action, second.action), (0, 1));
+        assert_eq!(q_table.visits()[0], vec![1, 1]);
+
+        let third = q_table
+            .get_action_register(&registers, TieBreak::First)
+            .unwrap();
+        assert_eq!(third.action, 0);
+
+        let serialized = serde_json::to_string(&q_table)?;
+        let deserialized: QTable = serde_json::from_str(&serialized)?;
+        assert_eq!(deserialized.visits()[0], vec![2, 1]);
+
+        ResetEngine::reset(&mut q_table);
+        assert!(q_table.visits().iter().flatten().all(|n| *n == 0));
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_terminal_transition_when_updated_then_future_value_is_ignored() -> VoidResultAnyError {
+        let instruction_parameters = InstructionGeneratorParametersBuilder::default()
+            .n_actions(2)
+            .n_inputs(1)
+            .build()?;
+        let consts = QConsts::new(0.5, 0.9, 0., 0., 0.);
+        let mut q_table: QTable = GenerateEngine::generate((instruction_parameters, consts));
+        q_table.table[1] = vec![0., 4.];
+
+        let current = ActionRegisterPair {
+            action: 0,
+            register: 0,
+        };
+        let next = ActionRegisterPair {
+            action: 1,
+            register: 1,
+        };
+
+        q_table.update_terminal(current, 1.);
+        assert_eq!(q_table.values()[0][0], 0.5);
+
+        q_table.table[0][0] = 0.;
+        q_table.update(current, 1., next);
+        // Bootstraps from the best value of the next register: 0.5 * (1 + 0.9 * 4).
+        assert!((q_table.values()[0][0] - 2.3).abs() < 1e-12);
+
+        assert_eq!(consts.truncation, TruncationHandling::Bootstrap);
+        assert_eq!(
+            consts
+                .with_truncation(TruncationHandling::Terminal)
+                .truncation,
+            TruncationHandling::Terminal
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_disjoint_mates_when_bred_then_children_keep_the_table_of_their_main_parent(
+    ) -> VoidResultAnyError {
+        let program_parameters = program_parameters_of_length(2, 2, 10);
+
+        for crossover in [CrossoverVariant::TwoPoint, CrossoverVariant::Aligned] {
+            let parameters = QProgramGeneratorParametersBuilder::default()
+                .program_parameters(program_parameters)
+                .consts(QConsts::default().with_crossover(crossover))
+                .build()?;
+
+            let mut mate_1: QProgram = GenerateEngine::generate(parameters);
+            let mut mate_2: QProgram = GenerateEngine::generate(parameters);
+            mate_1.program = Program::parse(&["r0 = r0 + 1"; 6].join("; "), program_parameters)?;
+            mate_2.program = Program::parse(&["r1 = r1 - 1"; 6].join("; "), program_parameters)?;
+            let n_registers = mate_1.q_table.table.len();
+            mate_1.q_table.table = vec![vec![1., 1.]; n_registers];
+            mate_2.q_table.table = vec![vec![2., 2.]; n_registers];
+
+            let mate_1_instruction = mate_1.program.instructions[0];
+
+            for _ in 0..100 {
+                let (child_1, child_2) = BreedEngine::two_point_crossover(&mate_1, &mate_2);
+
+                for (child, mate) in [(&child_1, &mate_1), (&child_2, &mate_2)] {
+                    let from_mate_1 = child
+                        .program
+                        .instructions
+                        .iter()
+                        .filter(|instruction| **instruction == mate_1_instruction)
+                        .count();
+                    let from_mate_2 = child.program.instructions.len() - from_mate_1;
+
+                    let expected = match from_mate_1.cmp(&from_mate_2) {
+                        std::cmp::Ordering::Greater => &mate_1,
+                        std::cmp::Ordering::Less => &mate_2,
+                        std::cmp::Ordering::Equal => mate,
+                    };
+
+                    assert_eq!(child.q_table.values(), expected.q_table.values());
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,308 @@
+//! Symbolic regression: programs approximate a function of their inputs, the prediction being the
+//! value of the first register once the program ran over a sample.
+use std::marker::PhantomData;
+
+use crate::{
+    core::{
+        batch::{run_batch, RowRegisters},
+        engines::{
+            breed_engine::BreedEngine,
+            core_engine::{Core, HyperParameters},
+            fitness_engine::{evaluate_with, Fitness, FitnessEngine, Penalty, TrialPool},
+            freeze_engine::FreezeEngine,
+            generate_engine::{Generate, GenerateEngine},
+            mutate_engine::MutateEngine,
+            reset_engine::{Reset, ResetEngine},
+            status_engine::StatusEngine,
+        },
+        environment::{column_bounds, FreshState, GenerationAware, SpaceInfo, State},
+        program::{Program, ProgramGeneratorParameters},
+    },
+    utils::telemetry::record_environment_step,
+};
+
+pub struct UseRegressionFitness;
+
+/// A function to approximate, along with the distribution its samples are drawn from.
+pub trait RegressionTask: Clone + Send {
+    /// Name of the problem on the command line and in benchmark directories.
+    const NAME: &'static str;
+    const N_INPUTS: usize;
+    /// Number of samples fitness is measured on.
+    const N_SAMPLES: usize = 20;
+    /// Fitness of a program whose output overflows, below the fitness of any sensible approximation.
+    /// Fitness is floored at it, so however large its error, a program never ranks below one which
+    /// overflows.
+    const DEFAULT_FITNESS: f64 = -10.;
+
+    /// Samples the inputs of a single point.
+    fn sample_input() -> Vec<f64>;
+
+    fn target(input: &[f64]) -> f64;
+}
+
+/// Points of `T` along with their targets, visited one after the other.
+#[derive(Clone, Debug)]
+pub struct RegressionInput<T> {
+    pub inputs: Vec<Vec<f64>>,
+    pub targets: Vec<f64>,
+    idx: usize,
+    task: PhantomData<T>,
+}
+
+impl<T> RegressionInput<T>
+where
+    T: RegressionTask,
+{
+    /// Evaluates the target of `T` on every point of `inputs`.
+    pub fn new(inputs: Vec<Vec<f64>>) -> Self {
+        let targets = inputs.iter().map(|input| T::target(input)).collect();
+
+        RegressionInput {
+            inputs,
+            targets,
+            idx: 0,
+            task: PhantomData,
+        }
+    }
+
+    /// The target of the current point.
+    pub fn target(&self) -> f64 {
+        self.targets[self.idx]
+    }
+
+    /// Mean squared error of the best constant prediction (the mean of the targets).
+    pub fn variance(&self) -> f64 {
+        let n_samples = self.targets.len() as f64;
+        let mean = self.targets.iter().sum::<f64>() / n_samples;
+
+        self.targets
+            .iter()
+            .map(|target| (target - mean).powi(2))
+            .sum::<f64>()
+            / n_samples
+    }
+}
+
+impl<T> State for RegressionInput<T> {
+    fn get_value(&self, at_idx: usize) -> f64 {
+        self.inputs[self.idx][at_idx]
+    }
+
+    fn execute_action(&mut self, _action: usize) -> f64 {
+        self.idx += 1;
+        0.
+    }
+
+    fn get(&mut self) -> Option<&mut Self> {
+        if self.idx >= self.targets.len() {
+            return None;
+        }
+
+        Some(self)
+    }
+}
+
+impl<T> GenerationAware for RegressionInput<T> {}
+
+impl<T> FreshState for RegressionInput<T> {
+    type PerEvaluation = usize;
+    type PerGeneration = ();
+    type PerRun = Vec<f64>;
+
+    fn per_evaluation(&self) -> usize {
+        self.idx
+    }
+
+    fn fresh(&self) -> usize {
+        0
+    }
+
+    fn per_generation(&self) {}
+
+    fn per_run(&self) -> Vec<f64> {
+        self.targets.clone()
+    }
+}
+
+/// A single output register, read as the prediction.
+impl<T> SpaceInfo for RegressionInput<T>
+where
+    T: RegressionTask,
+{
+    fn n_inputs(&self) -> usize {
+        T::N_INPUTS
+    }
+
+    fn n_actions(&self) -> usize {
+        1
+    }
+
+    fn input_bounds(&self) -> Vec<(f64, f64)> {
+        column_bounds(&self.inputs)
+    }
+}
+
+impl<T> Reset<RegressionInput<T>> for ResetEngine {
+    fn reset(item: &mut RegressionInput<T>) {
+        item.idx = 0;
+    }
+}
+
+impl<T> Generate<(), RegressionInput<T>> for GenerateEngine
+where
+    T: RegressionTask,
+{
+    fn generate(_using: ()) -> RegressionInput<T> {
+        RegressionInput::new((0..T::N_SAMPLES).map(|_| T::sample_input()).collect())
+    }
+}
+
+/// Mean squared error of the first register over the remaining points of `states`, the registers
+/// being reset before each point (not finite on overflow).
+fn run_mean_squared_error<T>(program: &mut Program, states: &mut RegressionInput<T>) -> f64
+where
+    T: RegressionTask,
+{
+    let mut squared_error = 0.;
+    let mut n_samples = 0.;
+
+    while let Some(state) = states.get() {
+        ResetEngine::reset(&mut program.registers);
+        program.run(state);
+
+        squared_error += (program.registers.get(0) - state.target()).powi(2);
+        n_samples += 1.;
+
+        state.execute_action(0);
+        record_environment_step();
+    }
+
+    squared_error / n_samples
+}
+
+/// The negative mean squared error of the first register over every point, floored at
+/// [`RegressionTask::DEFAULT_FITNESS`].
+impl<T> Fitness<Program, RegressionInput<T>, UseRegressionFitness> for FitnessEngine
+where
+    T: RegressionTask,
+{
+    fn eval_fitness(program: &mut Program, states: &mut RegressionInput<T>) -> f64 {
+        let mean_squared_error = run_mean_squared_error(program, states);
+
+        match mean_squared_error.is_finite() {
+            true => (-mean_squared_error).max(T::DEFAULT_FITNESS),
+            false => f64::NEG_INFINITY,
+        }
+    }
+}
+
+impl Program {
+    /// Mean squared error of the program on `inputs`, as evaluated during evolution (infinite on
+    /// overflow).
+    pub fn mean_squared_error<T>(&self, inputs: &RegressionInput<T>) -> f64
+    where
+        T: RegressionTask,
+    {
+        // Every point starts from zeroed registers, so they run through the batch interpreter.
+        let squared_errors = run_batch(self, &inputs.inputs, RowRegisters::Zeroed)
+            .iter()
+            .zip(inputs.targets.iter())
+            .map(|(registers, target)| (registers.get(0) - target).powi(2))
+            .collect::<Vec<_>>();
+        let mean_squared_error = squared_errors.iter().sum::<f64>() / squared_errors.len() as f64;
+
+        match mean_squared_error.is_finite() {
+            true => mean_squared_error,
+            false => f64::INFINITY,
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RegressionEngine<T>(PhantomData<T>);
+
+impl<T> Core for RegressionEngine<T>
+where
+    T: RegressionTask,
+{
+    type Individual = Program;
+    type ProgramParameters = ProgramGeneratorParameters;
+    type State = RegressionInput<T>;
+    type FitnessMarker = UseRegressionFitness;
+    type Generate = GenerateEngine;
+    type Fitness = FitnessEngine;
+    type Reset = ResetEngine;
+    type Breed = BreedEngine;
+    type Mutate = MutateEngine;
+    type Status = StatusEngine;
+    type Freeze = FreezeEngine;
+
+    fn evaluate(
+        population: &mut Vec<Self::Individual>,
+        trials: &mut Vec<Self::State>,
+        params: &HyperParameters<Self>,
+        penalties: &[Penalty<Self::Individual, Self::State>],
+        pool: &mut TrialPool<Self::State>,
+    ) {
+        evaluate_with(population, trials, params, penalties, pool)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::engines::status_engine::Status;
+    use crate::problems::problem::program_parameters;
+    use crate::utils::misc::VoidResultAnyError;
+
+    #[derive(Clone, Debug)]
+    struct Double;
+
+    impl RegressionTask for Double {
+        const NAME: &'static str = "double";
+        const N_INPUTS: usize = 1;
+
+        fn sample_input() -> Vec<f64> {
+            vec![0.]
+        }
+
+        fn target(input: &[f64]) -> f64 {
+            2. * input[0]
+        }
+    }
+
+    #[test]
+    fn given_program_when_evaluated_then_fitness_is_negative_mean_squared_error(
+    ) -> VoidResultAnyError {
+        let program_parameters = program_parameters(Double::N_INPUTS, 1);
+
+        let inputs = RegressionInput::<Double>::new(vec![vec![1.], vec![2.]]);
+        assert_eq!(inputs.targets, vec![2., 4.]);
+        assert_eq!(inputs.variance(), 1.);
+
+        // Predicts `x + 1`: exact on the first point, off by one on the second.
+        let program = Program::parse("r0 = r0 + 1 * in0; r0 = r0 + 1", program_parameters)?;
+        assert_eq!(program.mean_squared_error(&inputs), 0.5);
+
+        let exact = Program::parse("r0 = r0 + 1 * in0; r0 = r0 + 1 * in0", program_parameters)?;
+        assert_eq!(exact.mean_squared_error(&inputs), 0.);
+
+        // Far off the targets, fitness is floored at the fitness of an overflow.
+        let far = RegressionInput::<Double>::new(vec![vec![100.]]);
+        let mut constant = Program::parse("r0 = r0 + 1", program_parameters)?;
+        assert_eq!(constant.mean_squared_error(&far), 199f64.powi(2));
+
+        RegressionEngine::<Double>::eval_individual(
+            &mut constant,
+            &mut [far],
+            Double::DEFAULT_FITNESS,
+        );
+        assert_eq!(
+            StatusEngine::get_fitness(&constant),
+            Double::DEFAULT_FITNESS
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,324 @@
+//! Streaming classification: samples arrive between generations and programs are scored on a sliding
+//! window of the most recent ones, e.g. to simulate concept drift.
+use std::marker::PhantomData;
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    core::{
+        engines::{
+            breed_engine::BreedEngine,
+            core_engine::Core,
+            fitness_engine::FitnessEngine,
+            freeze_engine::FreezeEngine,
+            generate_engine::{Generate, GenerateEngine},
+            mutate_engine::MutateEngine,
+            reset_engine::{Reset, ResetEngine},
+            status_engine::StatusEngine,
+        },
+        environment::{FreshState, GenerationAware, SpaceInfo, State},
+        program::{Program, ProgramGeneratorParameters},
+    },
+    extensions::classification::Dataset,
+};
+
+/// A source of labelled samples arriving over time.
+pub trait DataStream: Clone {
+    const N_INPUTS: usize;
+    const N_CLASSES: usize;
+    /// Number of most recent samples the fitness is computed over.
+    const WINDOW: usize;
+
+    /// Samples arriving right before `generation` is evaluated. Every trial pulls its own arrivals,
+    /// so streams which should be shared by all trials must only depend on `generation`.
+    fn arrivals(generation: usize) -> Vec<(Vec<f64>, usize)>;
+}
+
+/// The last `capacity` samples of a stream, classified one after the other like a [`Dataset`].
+#[derive(Debug, Clone)]
+pub struct StreamingDataset<S> {
+    window: Dataset,
+    capacity: usize,
+    n_arrived: usize,
+    stream: PhantomData<S>,
+}
+
+impl<S> StreamingDataset<S> {
+    pub fn with_capacity(capacity: usize) -> Self {
+        StreamingDataset {
+            window: Dataset::new(vec![], vec![]),
+            capacity,
+            n_arrived: 0,
+            stream: PhantomData,
+        }
+    }
+
+    /// Appends a sample, dropping the oldest one once the window is full.
+    pub fn push(&mut self, features: Vec<f64>, label: usize) {
+        self.window.features.push(features);
+        self.window.labels.push(label);
+        self.n_arrived += 1;
+
+        let excess = self.window.labels.len().saturating_sub(self.capacity);
+        self.window.features.drain(..excess);
+        self.window.labels.drain(..excess);
+    }
+
+    /// The samples fitness is currently computed over, oldest first.
+    pub fn window(&self) -> &Dataset {
+        &self.window
+    }
+
+    /// Number of samples pushed since the stream started, including those which left the window.
+    pub fn n_arrived(&self) -> usize {
+        self.n_arrived
+    }
+}
+
+impl<S> State for StreamingDataset<S> {
+    fn get_value(&self, at_idx: usize) -> f64 {
+        self.window.get_value(at_idx)
+    }
+
+    fn execute_action(&mut self, action: usize) -> f64 {
+        self.window.execute_action(action)
+    }
+
+    fn get(&mut self) -> Option<&mut Self> {
+        self.window.get()?;
+
+        Some(self)
+    }
+
+    fn sample_weight(&self) -> f64 {
+        self.window.sample_weight()
+    }
+}
+
+impl<S> GenerationAware for StreamingDataset<S>
+where
+    S: DataStream,
+{
+    fn on_generation(&mut self, generation: usize) {
+        for (features, label) in S::arrivals(generation) {
+            self.push(features, label);
+        }
+    }
+}
+
+/// The window slides between generations, only its capacity lasts the whole run.
+impl<S> FreshState for StreamingDataset<S> {
+    type PerEvaluation = usize;
+    type PerGeneration = (usize, Vec<usize>);
+    type PerRun = usize;
+
+    fn per_evaluation(&self) -> usize {
+        self.window.per_evaluation()
+    }
+
+    fn fresh(&self) -> usize {
+        self.window.fresh()
+    }
+
+    fn per_generation(&self) -> (usize, Vec<usize>) {
+        (self.n_arrived, self.window.labels.clone())
+    }
+
+    fn per_run(&self) -> usize {
+        self.capacity
+    }
+}
+
+/// Samples yet to arrive may fall anywhere, so inputs are unbounded.
+impl<S> SpaceInfo for StreamingDataset<S>
+where
+    S: DataStream,
+{
+    fn n_inputs(&self) -> usize {
+        S::N_INPUTS
+    }
+
+    fn n_actions(&self) -> usize {
+        S::N_CLASSES
+    }
+}
+
+impl<S> Reset<StreamingDataset<S>> for ResetEngine {
+    fn reset(item: &mut StreamingDataset<S>) {
+        ResetEngine::reset(&mut item.window);
+    }
+}
+
+impl<S> Generate<(), StreamingDataset<S>> for GenerateEngine
+where
+    S: DataStream,
+{
+    fn generate(_using: ()) -> StreamingDataset<S> {
+        StreamingDataset::with_capacity(S::WINDOW)
+    }
+}
+
+#[derive(Clone)]
+pub struct StreamEngine<S>(PhantomData<S>);
+
+impl<S> Core for StreamEngine<S>
+where
+    S: DataStream,
+{
+    type Individual = Program;
+    type ProgramParameters = ProgramGeneratorParameters;
+    type State = StreamingDataset<S>;
+    type FitnessMarker = ();
+    type Generate = GenerateEngine;
+    type Fitness = FitnessEngine;
+    type Reset = ResetEngine;
+    type Breed = BreedEngine;
+    type Mutate = MutateEngine;
+    type Status = StatusEngine;
+    type Freeze = FreezeEngine;
+}
+
+/// How quickly a run recovered from a drift of its stream.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Adaptation {
+    pub drift_generation: usize,
+    /// Best fitness of the generation before the drift.
+    pub before: f64,
+    /// Lowest best fitness from the drift until the recovery (or the next drift).
+    pub lowest: f64,
+    /// Generations after the drift until the best fitness was back within `tolerance` of `before`,
+    /// `None` when it did not recover before the next drift or the end of the run.
+    pub recovery_generations: Option<usize>,
+}
+
+/// Measures the adaptation to every drift from the best fitness of each generation.
+///
+/// Drifts at generation `0`, or past the end of the run, are ignored.
+pub fn adaptation(best: &[f64], drift_generations: &[usize], tolerance: f64) -> Vec<Adaptation> {
+    drift_generations
+        .iter()
+        .enumerate()
+        .filter(|(_, drift)| **drift > 0 && **drift < best.len())
+        .map(|(idx, drift)| {
+            let end = drift_generations
+                .get(idx + 1)
+                .copied()
+                .unwrap_or(best.len())
+                .clamp(*drift, best.len());
+            let before = best[drift - 1];
+
+            let recovery = best[*drift..end]
+                .iter()
+                .position(|fitness| *fitness >= before - tolerance);
+            let lowest = best[*drift..recovery.map_or(end, |offset| drift + offset + 1)]
+                .iter()
+                .copied()
+                .fold(f64::INFINITY, f64::min);
+
+            Adaptation {
+                drift_generation: *drift,
+                before,
+                lowest,
+                recovery_generations: recovery,
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+
+    use super::*;
+    use crate::core::engines::core_engine::HyperParametersBuilder;
+    use crate::core::engines::status_engine::Status;
+    use crate::utils::misc::VoidResultAnyError;
+    use crate::utils::test::program_parameters_of_length;
+
+    const DRIFT_GENERATION: usize = 10;
+
+    /// Class `1` above `0.5`, flipped once the concept drifts.
+    #[derive(Clone, Debug)]
+    struct DriftingThreshold;
+
+    impl DataStream for DriftingThreshold {
+        const N_INPUTS: usize = 1;
+        const N_CLASSES: usize = 2;
+        const WINDOW: usize = 20;
+
+        fn arrivals(generation: usize) -> Vec<(Vec<f64>, usize)> {
+            let drifted = generation >= DRIFT_GENERATION;
+
+            (0..10)
+                .map(|idx| {
+                    let x = idx as f64 / 10.;
+                    (vec![x], ((x >= 0.5) != drifted) as usize)
+                })
+                .collect()
+        }
+    }
+
+    #[test]
+    fn given_full_window_when_samples_arrive_then_oldest_samples_are_dropped() {
+        let mut dataset = StreamingDataset::<DriftingThreshold>::with_capacity(3);
+
+        for idx in 0..5 {
+            dataset.push(vec![idx as f64], idx % 2);
+        }
+
+        assert_eq!(dataset.n_arrived(), 5);
+        assert_eq!(
+            dataset.window().features,
+            vec![vec![2.], vec![3.], vec![4.]]
+        );
+        assert_eq!(dataset.window().labels, vec![0, 1, 0]);
+    }
+
+    #[test]
+    fn given_best_fitness_when_drift_occurs_then_recovery_is_measured() {
+        let best = [0.5, 0.9, 0.9, 0.4, 0.6, 0.85, 0.9, 0.2];
+
+        let adaptations = adaptation(&best, &[3, 7], 0.1);
+
+        assert_eq!(
+            adaptations[0],
+            Adaptation {
+                drift_generation: 3,
+                before: 0.9,
+                lowest: 0.4,
+                recovery_generations: Some(2),
+            }
+        );
+        assert_eq!(adaptations[1].recovery_generations, None);
+        assert_eq!(adaptations[1].lowest, 0.2);
+    }
+
+    #[test]
+    fn given_drifting_stream_when_evolved_then_every_generation_is_scored_on_the_window(
+    ) -> VoidResultAnyError {
+        let program_parameters = program_parameters_of_length(
+            DriftingThreshold::N_INPUTS,
+            DriftingThreshold::N_CLASSES,
+            10,
+        );
+        let parameters = HyperParametersBuilder::<StreamEngine<DriftingThreshold>>::default()
+            .program_parameters(program_parameters)
+            .population_size(20)
+            .n_generations(2 * DRIFT_GENERATION)
+            .n_trials(1)
+            .seed(Some(3))
+            .build()?;
+
+        let best = parameters
+            .build_engine()
+            .take(parameters.n_generations)
+            .map(|population| StatusEngine::get_fitness(population.first().unwrap()))
+            .collect_vec();
+
+        assert_eq!(best.len(), parameters.n_generations);
+        assert!(best.iter().all(|fitness| (0. ..=1.).contains(fitness)));
+        assert_eq!(adaptation(&best, &[DRIFT_GENERATION], 0.05).len(), 1);
+
+        Ok(())
+    }
+}
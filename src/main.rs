@@ -1,7 +1,11 @@
 use clap::Parser;
-use lgp::core::config::Actuator;
+use lgp::core::config::Cli;
 
 fn main() {
-    let mut cli = Actuator::parse();
-    cli.run();
+    let mut cli = Cli::parse();
+
+    if let Err(error) = cli.run() {
+        eprintln!("error: {}", error);
+        std::process::exit(1);
+    }
 }
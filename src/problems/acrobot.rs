@@ -0,0 +1,312 @@
+//! The acrobot swing-up task, with a shaped variant rewarding the height of the tip.
+use std::f64::consts::PI;
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    core::engines::core_engine::HyperParameters,
+    problems::{
+        custom::{simulation_fitness_parameters, CustomEngine, RewardShaper, Shaped, Simulation},
+        problem::{hyper_parameters, program_parameters, Problem},
+    },
+    utils::random::generator,
+};
+
+/// Two links hanging from a pivot, actuated at the joint between them; the tip (end of the second link)
+/// must be swung above the height of the first link's length.
+///
+/// Observations: `[cos θ1, sin θ1, cos θ2, sin θ2, θ1', θ2']`. Actions: `0` applies a torque of `-1`,
+/// `1` no torque, `2` a torque of `+1`. Each step costs `-1` until the goal is reached.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Acrobot {
+    pub theta: [f64; 2],
+    pub velocity: [f64; 2],
+}
+
+impl Acrobot {
+    pub const DT: f64 = 0.2;
+    pub const LINK_LENGTH: f64 = 1.;
+    pub const LINK_MASS: f64 = 1.;
+    pub const LINK_CENTER_OF_MASS: f64 = 0.5;
+    pub const LINK_MOMENT_OF_INERTIA: f64 = 1.;
+    pub const GRAVITY: f64 = 9.8;
+    pub const MAX_VELOCITY: [f64; 2] = [4. * PI, 9. * PI];
+
+    /// Height of the tip above the pivot, in `[-2, 2]`.
+    pub fn tip_height(&self) -> f64 {
+        -self.theta[0].cos() - (self.theta[0] + self.theta[1]).cos()
+    }
+
+    fn derivatives(state: [f64; 4], torque: f64) -> [f64; 4] {
+        let [theta1, theta2, dtheta1, dtheta2] = state;
+        let (m, l, lc, i, g) = (
+            Self::LINK_MASS,
+            Self::LINK_LENGTH,
+            Self::LINK_CENTER_OF_MASS,
+            Self::LINK_MOMENT_OF_INERTIA,
+            Self::GRAVITY,
+        );
+
+        let d1 =
+            m * lc.powi(2) + m * (l.powi(2) + lc.powi(2) + 2. * l * lc * theta2.cos()) + 2. * i;
+        let d2 = m * (lc.powi(2) + l * lc * theta2.cos()) + i;
+        let phi2 = m * lc * g * (theta1 + theta2 - PI / 2.).cos();
+        let phi1 = -m * l * lc * dtheta2.powi(2) * theta2.sin()
+            - 2. * m * l * lc * dtheta2 * dtheta1 * theta2.sin()
+            + (m * lc + m * l) * g * (theta1 - PI / 2.).cos()
+            + phi2;
+
+        let ddtheta2 =
+            (torque + d2 / d1 * phi1 - m * l * lc * dtheta1.powi(2) * theta2.sin() - phi2)
+                / (m * lc.powi(2) + i - d2.powi(2) / d1);
+        let ddtheta1 = -(d2 * ddtheta2 + phi1) / d1;
+
+        [dtheta1, dtheta2, ddtheta1, ddtheta2]
+    }
+
+    /// Integrates the dynamics over one time step with the fourth-order Runge-Kutta method.
+    fn integrate(state: [f64; 4], torque: f64) -> [f64; 4] {
+        let offset = |state: [f64; 4], derivative: [f64; 4], scale: f64| {
+            let mut next = state;
+            for (value, derivative) in next.iter_mut().zip(derivative) {
+                *value += scale * derivative;
+            }
+            next
+        };
+
+        let k1 = Self::derivatives(state, torque);
+        let k2 = Self::derivatives(offset(state, k1, Self::DT / 2.), torque);
+        let k3 = Self::derivatives(offset(state, k2, Self::DT / 2.), torque);
+        let k4 = Self::derivatives(offset(state, k3, Self::DT), torque);
+
+        let mut next = state;
+        for idx in 0..4 {
+            next[idx] += Self::DT / 6. * (k1[idx] + 2. * k2[idx] + 2. * k3[idx] + k4[idx]);
+        }
+
+        next
+    }
+}
+
+/// Wraps `angle` into `[-π, π)`.
+fn wrap(angle: f64) -> f64 {
+    (angle + PI).rem_euclid(2. * PI) - PI
+}
+
+impl Simulation for Acrobot {
+    const N_INPUTS: usize = 6;
+    const N_ACTIONS: usize = 3;
+    const EPISODE_LENGTH: usize = 500;
+
+    fn sample() -> Self {
+        let mut sample = || generator().gen_range(-0.1..=0.1);
+
+        Acrobot {
+            theta: [sample(), sample()],
+            velocity: [sample(), sample()],
+        }
+    }
+
+    fn observe(&self, idx: usize) -> f64 {
+        match idx {
+            0 => self.theta[0].cos(),
+            1 => self.theta[0].sin(),
+            2 => self.theta[1].cos(),
+            3 => self.theta[1].sin(),
+            4 => self.velocity[0],
+            5 => self.velocity[1],
+            _ => unreachable!(),
+        }
+    }
+
+    fn step(&mut self, action: usize) -> (f64, bool) {
+        let torque = action as f64 - 1.;
+        let [theta1, theta2, dtheta1, dtheta2] = Self::integrate(
+            [
+                self.theta[0],
+                self.theta[1],
+                self.velocity[0],
+                self.velocity[1],
+            ],
+            torque,
+        );
+
+        self.theta = [wrap(theta1), wrap(theta2)];
+        self.velocity = [
+            dtheta1.clamp(-Self::MAX_VELOCITY[0], Self::MAX_VELOCITY[0]),
+            dtheta2.clamp(-Self::MAX_VELOCITY[1], Self::MAX_VELOCITY[1]),
+        ];
+
+        let done = self.succeeded();
+
+        (if done { 0. } else { -1. }, done)
+    }
+
+    fn succeeded(&self) -> bool {
+        self.tip_height() > Self::LINK_LENGTH
+    }
+}
+
+/// Adds a bonus proportional to the height of the tip after every step, so progress is rewarded before
+/// the goal is first reached.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct TipHeight;
+
+impl TipHeight {
+    pub const COEFFICIENT: f64 = 0.25;
+    /// Return of an episode spent with the tip at its lowest, where every step costs
+    /// `1 + 2 * COEFFICIENT`.
+    pub const WORST_RETURN: f64 = -(1. + 2. * Self::COEFFICIENT) * Acrobot::EPISODE_LENGTH as f64;
+}
+
+impl RewardShaper<Acrobot> for TipHeight {
+    fn shape(_before: &Acrobot, _action: usize, reward: f64, after: &Acrobot, _done: bool) -> f64 {
+        reward + Self::COEFFICIENT * after.tip_height()
+    }
+}
+
+pub type ShapedAcrobot = Shaped<Acrobot, TipHeight>;
+
+impl Problem for CustomEngine<Acrobot> {
+    const NAME: &'static str = "acrobot-lgp";
+
+    fn default_hyper_parameters() -> HyperParameters<Self> {
+        hyper_parameters(program_parameters(Acrobot::N_INPUTS, Acrobot::N_ACTIONS))
+    }
+
+    fn build_fitness_parameters(params: &mut HyperParameters<Self>) {
+        simulation_fitness_parameters(params, -(Acrobot::EPISODE_LENGTH as f64))
+    }
+
+    fn plot_range() -> (f64, f64) {
+        (-(Acrobot::EPISODE_LENGTH as f64), 0.)
+    }
+}
+
+impl Problem for CustomEngine<ShapedAcrobot> {
+    const NAME: &'static str = "acrobot-shaped-lgp";
+
+    fn default_hyper_parameters() -> HyperParameters<Self> {
+        hyper_parameters(program_parameters(
+            ShapedAcrobot::N_INPUTS,
+            ShapedAcrobot::N_ACTIONS,
+        ))
+    }
+
+    fn build_fitness_parameters(params: &mut HyperParameters<Self>) {
+        // Failing programs must not outrank those which merely keep the tip low.
+        simulation_fitness_parameters(params, TipHeight::WORST_RETURN)
+    }
+
+    fn plot_range() -> (f64, f64) {
+        (TipHeight::WORST_RETURN, 0.)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+
+    use super::*;
+    use crate::core::engines::core_engine::{Core, HyperParametersBuilder};
+    use crate::core::engines::generate_engine::{Generate, GenerateEngine};
+    use crate::core::engines::status_engine::{Status, StatusEngine};
+    use crate::core::program::Program;
+    use crate::problems::custom::SimulationInput;
+    use crate::utils::misc::VoidResultAnyError;
+    use crate::utils::test::program_parameters_of_length;
+
+    #[test]
+    fn given_hanging_acrobot_when_shaped_then_tip_height_is_added_to_reward() {
+        let acrobot = Acrobot {
+            theta: [0., 0.],
+            velocity: [0., 0.],
+        };
+        let mut shaped = ShapedAcrobot::new(acrobot);
+
+        let (reward, done) = shaped.step(1);
+
+        assert!(!done);
+        assert_eq!(
+            reward,
+            -1. + TipHeight::COEFFICIENT * shaped.simulation.tip_height()
+        );
+        // Hanging straight down, the tip stays close to its lowest point.
+        assert!(shaped.simulation.tip_height() < -1.99);
+    }
+
+    #[test]
+    fn given_raised_tip_when_stepped_then_episode_terminates() {
+        let mut acrobot = Acrobot {
+            theta: [PI, 0.],
+            velocity: [0., 0.],
+        };
+
+        assert_eq!(acrobot.step(1), (0., true));
+    }
+
+    #[test]
+    fn given_shaped_and_unshaped_acrobot_when_evolved_then_learning_curves_are_comparable(
+    ) -> VoidResultAnyError {
+        let program_parameters =
+            program_parameters_of_length(Acrobot::N_INPUTS, Acrobot::N_ACTIONS, 20);
+
+        let unshaped = HyperParametersBuilder::<CustomEngine<Acrobot>>::default()
+            .program_parameters(program_parameters)
+            .default_fitness(-(Acrobot::EPISODE_LENGTH as f64))
+            .population_size(10)
+            .n_generations(5)
+            .n_trials(2)
+            .seed(Some(11))
+            .build()?;
+        let mut shaped = HyperParametersBuilder::<CustomEngine<ShapedAcrobot>>::default()
+            .program_parameters(program_parameters)
+            .population_size(10)
+            .n_generations(5)
+            .n_trials(2)
+            .seed(Some(11))
+            .build()?;
+        CustomEngine::<ShapedAcrobot>::build_fitness_parameters(&mut shaped);
+        assert_eq!(shaped.default_fitness, TipHeight::WORST_RETURN);
+
+        // Champions of both runs are scored on the same, unshaped, task.
+        let mut trials = (0..5)
+            .map(|_| GenerateEngine::generate(()))
+            .collect::<Vec<SimulationInput<Acrobot>>>();
+        let mut score = |program: &Program| {
+            let mut program = program.clone();
+            CustomEngine::<Acrobot>::eval_individual(
+                &mut program,
+                &mut trials,
+                unshaped.default_fitness,
+            );
+            StatusEngine::get_fitness(&program)
+        };
+
+        let unshaped_curve = unshaped
+            .build_engine()
+            .take(unshaped.n_generations)
+            .map(|population| score(population.first().unwrap()))
+            .collect_vec();
+        let shaped_curve = shaped
+            .build_engine()
+            .take(shaped.n_generations)
+            .map(|population| {
+                // No program does worse than failing, however low it keeps the tip.
+                assert!(population
+                    .iter()
+                    .all(|program| StatusEngine::get_fitness(program) >= shaped.default_fitness));
+                score(population.first().unwrap())
+            })
+            .collect_vec();
+
+        assert_eq!(unshaped_curve.len(), shaped_curve.len());
+
+        for score in unshaped_curve.iter().chain(&shaped_curve) {
+            assert!((-(Acrobot::EPISODE_LENGTH as f64)..=0.).contains(score));
+        }
+
+        Ok(())
+    }
+}
@@ -0,0 +1,1472 @@
+//! Native environments which do not depend on gym_rs.
+//!
+//! Implement [`Simulation`] for a type holding the physical state of your task, and it can be evolved
+//! through [`CustomEngine`] (LGP) or [`CustomQEngine`] (LGP + Q-Learning).
+use std::{collections::VecDeque, iter::repeat, marker::PhantomData};
+
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    core::{
+        engines::{
+            breed_engine::BreedEngine,
+            core_engine::{Core, HyperParameters},
+            fitness_engine::{evaluate_with, FitnessEngine, Penalty, TrialPool},
+            freeze_engine::FreezeEngine,
+            generate_engine::{Generate, GenerateEngine},
+            mutate_engine::MutateEngine,
+            reset_engine::{Reset, ResetEngine},
+            status_engine::StatusEngine,
+        },
+        environment::{FreshState, GenerationAware, RlState, SpaceInfo, State, StepInfo},
+        program::{Program, ProgramGeneratorParameters},
+    },
+    extensions::{
+        interactive::UseRlFitness,
+        organism::{Organism, OrganismGeneratorParameters},
+        q_learning::{QProgram, QProgramGeneratorParameters},
+    },
+    problems::problem::{
+        hyper_parameters, program_parameters, q_program_parameters, set_dimensions, Problem,
+    },
+    utils::random::generator,
+};
+
+/// A small, self-contained environment.
+pub trait Simulation: Clone + Send {
+    const N_INPUTS: usize;
+    const N_ACTIONS: usize;
+    const EPISODE_LENGTH: usize;
+
+    /// Samples a random initial state.
+    fn sample() -> Self;
+
+    /// Returns the observation at `idx`, where `idx < N_INPUTS`.
+    fn observe(&self, idx: usize) -> f64;
+
+    /// Reward added to the step on which the episode first meets [`Simulation::succeeded`].
+    const SUCCESS_BONUS: f64 = 0.;
+
+    /// Advances the simulation, returning the reward and whether a terminal state was reached.
+    fn step(&mut self, action: usize) -> (f64, bool);
+
+    /// Success criterion of the task (e.g. reaching the goal); episodes end as soon as it is met.
+    fn succeeded(&self) -> bool {
+        false
+    }
+
+    /// Control effort an episode may spend; what it spends beyond is its constraint violation under
+    /// stochastic ranking.
+    const CONTROL_EFFORT_LIMIT: f64 = f64::INFINITY;
+
+    /// Control effort of taking `action` from the current state, e.g. the magnitude of a force.
+    fn control_effort(&self, _action: usize) -> f64 {
+        0.
+    }
+
+    fn observation(&self) -> Vec<f64> {
+        (0..Self::N_INPUTS).map(|idx| self.observe(idx)).collect()
+    }
+}
+
+/// An environment whose observation is the index of its current state, e.g. a grid world.
+///
+/// Programs read real-valued inputs, so discrete simulations are evolved through an encoding:
+/// [`OneHot`] (one input per state) or [`Embedded`] (`EMBEDDING_DIM` inputs per state).
+pub trait DiscreteSimulation: Clone + Send {
+    const N_STATES: usize;
+    const N_ACTIONS: usize;
+    const EPISODE_LENGTH: usize;
+    /// Defaults to the number of bits needed to write every state index.
+    const EMBEDDING_DIM: usize = (usize::BITS - (Self::N_STATES - 1).leading_zeros()) as usize;
+
+    fn sample() -> Self;
+
+    fn state(&self) -> usize;
+
+    fn step(&mut self, action: usize) -> (f64, bool);
+
+    fn succeeded(&self) -> bool {
+        false
+    }
+
+    /// Returns component `idx < EMBEDDING_DIM` of the embedding of `state`; defaults to its binary
+    /// representation.
+    fn embed(state: usize, idx: usize) -> f64 {
+        ((state >> idx) & 1) as f64
+    }
+}
+
+/// Observes a [`DiscreteSimulation`] as `N_STATES` inputs, all zero but the current state's.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct OneHot<D>(pub D);
+
+/// Observes a [`DiscreteSimulation`] through [`DiscreteSimulation::embed`].
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Embedded<D>(pub D);
+
+impl<D> Simulation for OneHot<D>
+where
+    D: DiscreteSimulation,
+{
+    const N_INPUTS: usize = D::N_STATES;
+    const N_ACTIONS: usize = D::N_ACTIONS;
+    const EPISODE_LENGTH: usize = D::EPISODE_LENGTH;
+
+    fn sample() -> Self {
+        OneHot(D::sample())
+    }
+
+    fn observe(&self, idx: usize) -> f64 {
+        (self.0.state() == idx) as usize as f64
+    }
+
+    fn step(&mut self, action: usize) -> (f64, bool) {
+        self.0.step(action)
+    }
+
+    fn succeeded(&self) -> bool {
+        self.0.succeeded()
+    }
+}
+
+impl<D> Simulation for Embedded<D>
+where
+    D: DiscreteSimulation,
+{
+    const N_INPUTS: usize = D::EMBEDDING_DIM;
+    const N_ACTIONS: usize = D::N_ACTIONS;
+    const EPISODE_LENGTH: usize = D::EPISODE_LENGTH;
+
+    fn sample() -> Self {
+        Embedded(D::sample())
+    }
+
+    fn observe(&self, idx: usize) -> f64 {
+        D::embed(self.0.state(), idx)
+    }
+
+    fn step(&mut self, action: usize) -> (f64, bool) {
+        self.0.step(action)
+    }
+
+    fn succeeded(&self) -> bool {
+        self.0.succeeded()
+    }
+}
+
+/// Rewrites the rewards of a [`Simulation`], e.g. to turn a sparse reward into a dense one.
+pub trait RewardShaper<S>: Clone + Send
+where
+    S: Simulation,
+{
+    /// Returns the reward of stepping from `before` to `after`, given the original `reward`.
+    fn shape(before: &S, action: usize, reward: f64, after: &S, done: bool) -> f64;
+}
+
+/// `S` with its rewards rewritten by `R`; observations, actions and termination are unchanged.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Shaped<S, R> {
+    pub simulation: S,
+    #[serde(skip)]
+    shaper: PhantomData<R>,
+}
+
+impl<S, R> Shaped<S, R> {
+    pub fn new(simulation: S) -> Self {
+        Shaped {
+            simulation,
+            shaper: PhantomData,
+        }
+    }
+}
+
+impl<S, R> Simulation for Shaped<S, R>
+where
+    S: Simulation,
+    R: RewardShaper<S>,
+{
+    const N_INPUTS: usize = S::N_INPUTS;
+    const N_ACTIONS: usize = S::N_ACTIONS;
+    const EPISODE_LENGTH: usize = S::EPISODE_LENGTH;
+    const SUCCESS_BONUS: f64 = S::SUCCESS_BONUS;
+    const CONTROL_EFFORT_LIMIT: f64 = S::CONTROL_EFFORT_LIMIT;
+
+    fn sample() -> Self {
+        Shaped::new(S::sample())
+    }
+
+    fn observe(&self, idx: usize) -> f64 {
+        self.simulation.observe(idx)
+    }
+
+    fn step(&mut self, action: usize) -> (f64, bool) {
+        let before = self.simulation.clone();
+        let (reward, done) = self.simulation.step(action);
+
+        (
+            R::shape(&before, action, reward, &self.simulation, done),
+            done,
+        )
+    }
+
+    fn succeeded(&self) -> bool {
+        self.simulation.succeeded()
+    }
+
+    fn control_effort(&self, action: usize) -> f64 {
+        self.simulation.control_effort(action)
+    }
+}
+
+/// Selects the observations of `S` a program gets to see, e.g. to hide velocities.
+pub trait ObservationMask<S>: Clone + Send
+where
+    S: Simulation,
+{
+    /// Indices of the observations of `S` which are kept, in order.
+    const INDICES: &'static [usize];
+}
+
+/// `S` observed through the mask `M`.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Masked<S, M> {
+    pub simulation: S,
+    #[serde(skip)]
+    mask: PhantomData<M>,
+}
+
+impl<S, M> Masked<S, M> {
+    pub fn new(simulation: S) -> Self {
+        Masked {
+            simulation,
+            mask: PhantomData,
+        }
+    }
+}
+
+impl<S, M> Simulation for Masked<S, M>
+where
+    S: Simulation,
+    M: ObservationMask<S>,
+{
+    const N_INPUTS: usize = M::INDICES.len();
+    const N_ACTIONS: usize = S::N_ACTIONS;
+    const EPISODE_LENGTH: usize = S::EPISODE_LENGTH;
+    const SUCCESS_BONUS: f64 = S::SUCCESS_BONUS;
+    const CONTROL_EFFORT_LIMIT: f64 = S::CONTROL_EFFORT_LIMIT;
+
+    fn sample() -> Self {
+        Masked::new(S::sample())
+    }
+
+    fn observe(&self, idx: usize) -> f64 {
+        self.simulation.observe(M::INDICES[idx])
+    }
+
+    fn step(&mut self, action: usize) -> (f64, bool) {
+        self.simulation.step(action)
+    }
+
+    fn succeeded(&self) -> bool {
+        self.simulation.succeeded()
+    }
+
+    fn control_effort(&self, action: usize) -> f64 {
+        self.simulation.control_effort(action)
+    }
+}
+
+/// `S` observed through its last `K` observations, most recent first, so programs can recover what a
+/// single observation does not show (e.g. velocities from successive positions). Before `K` steps have
+/// been taken, the missing observations repeat the initial one.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Stacked<S, const K: usize> {
+    pub simulation: S,
+    history: VecDeque<Vec<f64>>,
+}
+
+impl<S, const K: usize> Stacked<S, K>
+where
+    S: Simulation,
+{
+    pub fn new(simulation: S) -> Self {
+        let history = repeat(simulation.observation()).take(K.max(1)).collect();
+
+        Stacked {
+            simulation,
+            history,
+        }
+    }
+}
+
+impl<S, const K: usize> Simulation for Stacked<S, K>
+where
+    S: Simulation,
+{
+    const N_INPUTS: usize = S::N_INPUTS * K;
+    const N_ACTIONS: usize = S::N_ACTIONS;
+    const EPISODE_LENGTH: usize = S::EPISODE_LENGTH;
+    const SUCCESS_BONUS: f64 = S::SUCCESS_BONUS;
+    const CONTROL_EFFORT_LIMIT: f64 = S::CONTROL_EFFORT_LIMIT;
+
+    fn sample() -> Self {
+        Stacked::new(S::sample())
+    }
+
+    fn observe(&self, idx: usize) -> f64 {
+        self.history[idx / S::N_INPUTS][idx % S::N_INPUTS]
+    }
+
+    fn step(&mut self, action: usize) -> (f64, bool) {
+        let outcome = self.simulation.step(action);
+
+        self.history.pop_back();
+        self.history.push_front(self.simulation.observation());
+
+        outcome
+    }
+
+    fn succeeded(&self) -> bool {
+        self.simulation.succeeded()
+    }
+
+    fn control_effort(&self, action: usize) -> f64 {
+        self.simulation.control_effort(action)
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct SimulationInput<S: Simulation> {
+    simulation: S,
+    initial_state: S,
+    terminated: bool,
+    truncated: bool,
+    succeeded: bool,
+    episode_idx: usize,
+    last_action: Option<usize>,
+    action_switches: usize,
+    control_effort: f64,
+    schedule: Vec<S>,
+}
+
+impl<S> SimulationInput<S>
+where
+    S: Simulation,
+{
+    /// A trial which starts generation `g` from `schedule[g % schedule.len()]`.
+    pub fn with_schedule(schedule: Vec<S>) -> Self {
+        assert!(!schedule.is_empty());
+
+        SimulationInput {
+            simulation: schedule[0].clone(),
+            initial_state: schedule[0].clone(),
+            terminated: false,
+            truncated: false,
+            succeeded: false,
+            episode_idx: 0,
+            last_action: None,
+            action_switches: 0,
+            control_effort: 0.,
+            schedule,
+        }
+    }
+
+    /// Fraction of the steps of the current episode on which the action changed, e.g. to penalize
+    /// policies which chatter between actions.
+    pub fn action_switch_frequency(&self) -> f64 {
+        self.action_switches as f64 / self.episode_idx.saturating_sub(1).max(1) as f64
+    }
+}
+
+impl<S> GenerationAware for SimulationInput<S>
+where
+    S: Simulation,
+{
+    fn on_generation(&mut self, generation: usize) {
+        if self.schedule.is_empty() {
+            return;
+        }
+
+        self.initial_state = self.schedule[generation % self.schedule.len()].clone();
+        ResetEngine::reset(self);
+    }
+}
+
+/// Episodes start from the initial state of the generation, taken from a schedule fixed for the run.
+impl<S> FreshState for SimulationInput<S>
+where
+    S: Simulation,
+{
+    /// The observation, the step, whether the episode was terminated, truncated or succeeded, the
+    /// actions taken so far and the control effort they spent.
+    type PerEvaluation = (Vec<f64>, usize, bool, bool, bool, Option<usize>, usize, f64);
+    type PerGeneration = Vec<f64>;
+    type PerRun = Vec<Vec<f64>>;
+
+    fn per_evaluation(&self) -> Self::PerEvaluation {
+        (
+            self.simulation.observation(),
+            self.episode_idx,
+            self.terminated,
+            self.truncated,
+            self.succeeded,
+            self.last_action,
+            self.action_switches,
+            self.control_effort,
+        )
+    }
+
+    fn fresh(&self) -> Self::PerEvaluation {
+        (
+            self.initial_state.observation(),
+            0,
+            false,
+            false,
+            false,
+            None,
+            0,
+            0.,
+        )
+    }
+
+    fn per_generation(&self) -> Vec<f64> {
+        self.initial_state.observation()
+    }
+
+    fn per_run(&self) -> Vec<Vec<f64>> {
+        self.schedule.iter().map(Simulation::observation).collect()
+    }
+}
+
+impl<S> SpaceInfo for SimulationInput<S>
+where
+    S: Simulation,
+{
+    fn n_inputs(&self) -> usize {
+        S::N_INPUTS
+    }
+
+    fn n_actions(&self) -> usize {
+        S::N_ACTIONS
+    }
+}
+
+impl<S> State for SimulationInput<S>
+where
+    S: Simulation,
+{
+    fn get_value(&self, idx: usize) -> f64 {
+        self.simulation.observe(idx)
+    }
+
+    fn execute_action(&mut self, action: usize) -> f64 {
+        self.control_effort += self.simulation.control_effort(action);
+        let (mut reward, done) = self.simulation.step(action);
+        self.episode_idx += 1;
+
+        if self
+            .last_action
+            .map_or(false, |last_action| last_action != action)
+        {
+            self.action_switches += 1;
+        }
+        self.last_action = Some(action);
+
+        if !self.succeeded && self.simulation.succeeded() {
+            self.succeeded = true;
+            reward += S::SUCCESS_BONUS;
+        }
+
+        self.truncated = !done && !self.succeeded && self.episode_idx >= S::EPISODE_LENGTH;
+        self.terminated = self.truncated || done || self.succeeded;
+        reward
+    }
+
+    fn get(&mut self) -> Option<&mut Self> {
+        if self.terminated {
+            return None;
+        }
+
+        Some(self)
+    }
+
+    fn succeeded(&self) -> bool {
+        self.succeeded
+    }
+
+    /// Control effort spent by the episode beyond [`Simulation::CONTROL_EFFORT_LIMIT`].
+    fn constraint_violation(&self) -> f64 {
+        (self.control_effort - S::CONTROL_EFFORT_LIMIT).max(0.)
+    }
+}
+
+impl<S> RlState for SimulationInput<S>
+where
+    S: Simulation,
+{
+    fn is_terminal(&mut self) -> bool {
+        self.terminated
+    }
+
+    fn get_initial_state(&self) -> Vec<f64> {
+        self.initial_state.observation()
+    }
+
+    fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    fn info(&self) -> StepInfo {
+        StepInfo::from([
+            ("episode_step".to_string(), self.episode_idx.into()),
+            ("succeeded".to_string(), self.succeeded.into()),
+        ])
+    }
+}
+
+impl<S> Reset<SimulationInput<S>> for ResetEngine
+where
+    S: Simulation,
+{
+    fn reset(item: &mut SimulationInput<S>) {
+        item.simulation = item.initial_state.clone();
+        item.terminated = false;
+        item.truncated = false;
+        item.succeeded = false;
+        item.episode_idx = 0;
+        item.last_action = None;
+        item.action_switches = 0;
+        item.control_effort = 0.;
+    }
+}
+
+impl<S> Generate<(), SimulationInput<S>> for GenerateEngine
+where
+    S: Simulation,
+{
+    fn generate(_using: ()) -> SimulationInput<S> {
+        let simulation = S::sample();
+
+        SimulationInput {
+            initial_state: simulation.clone(),
+            simulation,
+            terminated: false,
+            truncated: false,
+            succeeded: false,
+            episode_idx: 0,
+            last_action: None,
+            action_switches: 0,
+            control_effort: 0.,
+            schedule: vec![],
+        }
+    }
+}
+
+/// A point mass on a line which must be brought to rest at the origin.
+///
+/// Observations: `[position, velocity]`. Actions: `0` pushes left, `1` coasts, `2` pushes right.
+/// Each step costs `-1` until the goal is reached.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub struct Navigation {
+    pub position: f64,
+    pub velocity: f64,
+}
+
+impl Navigation {
+    pub const FORCE: f64 = 0.01;
+    pub const MAX_SPEED: f64 = 0.1;
+    pub const BOUND: f64 = 1.;
+    pub const GOAL_TOLERANCE: f64 = 0.05;
+}
+
+impl Simulation for Navigation {
+    const N_INPUTS: usize = 2;
+    const N_ACTIONS: usize = 3;
+    const EPISODE_LENGTH: usize = 200;
+
+    fn sample() -> Self {
+        Navigation {
+            position: generator().gen_range(-Self::BOUND..=Self::BOUND),
+            velocity: 0.,
+        }
+    }
+
+    fn observe(&self, idx: usize) -> f64 {
+        match idx {
+            0 => self.position,
+            1 => self.velocity,
+            _ => unreachable!(),
+        }
+    }
+
+    fn step(&mut self, action: usize) -> (f64, bool) {
+        let force = (action as f64 - 1.) * Self::FORCE;
+
+        self.velocity = (self.velocity + force).clamp(-Self::MAX_SPEED, Self::MAX_SPEED);
+        self.position += self.velocity;
+
+        if self.position.abs() > Self::BOUND {
+            self.position = self.position.clamp(-Self::BOUND, Self::BOUND);
+            self.velocity = 0.;
+        }
+
+        (-1., self.succeeded())
+    }
+
+    fn succeeded(&self) -> bool {
+        self.position.abs() < Self::GOAL_TOLERANCE && self.velocity.abs() < Self::FORCE
+    }
+
+    /// Pushing for half the episode.
+    const CONTROL_EFFORT_LIMIT: f64 = Self::EPISODE_LENGTH as f64 / 2.;
+
+    /// Pushing costs 1, coasting is free.
+    fn control_effort(&self, action: usize) -> f64 {
+        (action as f64 - 1.).abs()
+    }
+}
+
+/// Hides the velocity of [`Navigation`].
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct PositionOnly;
+
+impl ObservationMask<Navigation> for PositionOnly {
+    const INDICES: &'static [usize] = &[0];
+}
+
+/// [`Navigation`] where the velocity has to be inferred from the last two positions.
+pub type StackedPositionNavigation = Stacked<Masked<Navigation, PositionOnly>, 2>;
+
+#[derive(Clone)]
+pub struct CustomEngine<S>(PhantomData<S>);
+#[derive(Clone)]
+pub struct CustomQEngine<S>(PhantomData<S>);
+/// Evolves a mix of [`Program`] and [`QProgram`] individuals.
+#[derive(Clone)]
+pub struct CustomOrganismEngine<S>(PhantomData<S>);
+
+impl<S> Core for CustomEngine<S>
+where
+    S: Simulation,
+{
+    type Individual = Program;
+    type ProgramParameters = ProgramGeneratorParameters;
+    type State = SimulationInput<S>;
+    type FitnessMarker = UseRlFitness;
+    type Generate = GenerateEngine;
+    type Fitness = FitnessEngine;
+    type Reset = ResetEngine;
+    type Breed = BreedEngine;
+    type Mutate = MutateEngine;
+    type Status = StatusEngine;
+    type Freeze = FreezeEngine;
+
+    fn evaluate(
+        population: &mut Vec<Self::Individual>,
+        trials: &mut Vec<Self::State>,
+        params: &HyperParameters<Self>,
+        penalties: &[Penalty<Self::Individual, Self::State>],
+        pool: &mut TrialPool<Self::State>,
+    ) {
+        evaluate_with(population, trials, params, penalties, pool)
+    }
+}
+
+impl<S> Core for CustomQEngine<S>
+where
+    S: Simulation,
+{
+    type Individual = QProgram;
+    type ProgramParameters = QProgramGeneratorParameters;
+    type State = SimulationInput<S>;
+    type FitnessMarker = ();
+    type Generate = GenerateEngine;
+    type Fitness = FitnessEngine;
+    type Reset = ResetEngine;
+    type Breed = BreedEngine;
+    type Mutate = MutateEngine;
+    type Status = StatusEngine;
+    type Freeze = FreezeEngine;
+
+    fn evaluate(
+        population: &mut Vec<Self::Individual>,
+        trials: &mut Vec<Self::State>,
+        params: &HyperParameters<Self>,
+        penalties: &[Penalty<Self::Individual, Self::State>],
+        pool: &mut TrialPool<Self::State>,
+    ) {
+        evaluate_with(population, trials, params, penalties, pool)
+    }
+}
+
+impl<S> Core for CustomOrganismEngine<S>
+where
+    S: Simulation,
+{
+    type Individual = Organism;
+    type ProgramParameters = OrganismGeneratorParameters;
+    type State = SimulationInput<S>;
+    type FitnessMarker = ();
+    type Generate = GenerateEngine;
+    type Fitness = FitnessEngine;
+    type Reset = ResetEngine;
+    type Breed = BreedEngine;
+    type Mutate = MutateEngine;
+    type Status = StatusEngine;
+    type Freeze = FreezeEngine;
+
+    fn evaluate(
+        population: &mut Vec<Self::Individual>,
+        trials: &mut Vec<Self::State>,
+        params: &HyperParameters<Self>,
+        penalties: &[Penalty<Self::Individual, Self::State>],
+        pool: &mut TrialPool<Self::State>,
+    ) {
+        evaluate_with(population, trials, params, penalties, pool)
+    }
+}
+
+/// Fitness parameters of a [`Problem`] solved by a [`CustomEngine`]: the dimensions of `S`, and the
+/// fitness of a program which fails.
+pub fn simulation_fitness_parameters<S>(
+    params: &mut HyperParameters<CustomEngine<S>>,
+    default_fitness: f64,
+) where
+    S: Simulation,
+{
+    set_dimensions(
+        &mut params.program_parameters.instruction_generator_parameters,
+        S::N_INPUTS,
+        S::N_ACTIONS,
+    );
+    params.default_fitness = default_fitness;
+}
+
+/// Same as [`simulation_fitness_parameters`] for a [`CustomQEngine`], also resetting the Q-learning
+/// rates to their initial values.
+pub fn q_simulation_fitness_parameters<S>(
+    params: &mut HyperParameters<CustomQEngine<S>>,
+    default_fitness: f64,
+) where
+    S: Simulation,
+{
+    ResetEngine::reset(&mut params.program_parameters.consts);
+    set_dimensions(
+        &mut params
+            .program_parameters
+            .program_parameters
+            .instruction_generator_parameters,
+        S::N_INPUTS,
+        S::N_ACTIONS,
+    );
+    params.default_fitness = default_fitness;
+}
+
+impl Problem for CustomEngine<Navigation> {
+    const NAME: &'static str = "navigation-lgp";
+
+    fn default_hyper_parameters() -> HyperParameters<Self> {
+        hyper_parameters(program_parameters(
+            Navigation::N_INPUTS,
+            Navigation::N_ACTIONS,
+        ))
+    }
+
+    fn build_fitness_parameters(params: &mut HyperParameters<Self>) {
+        simulation_fitness_parameters(params, -(Navigation::EPISODE_LENGTH as f64))
+    }
+
+    fn plot_range() -> (f64, f64) {
+        (-(Navigation::EPISODE_LENGTH as f64), 0.)
+    }
+}
+
+impl Problem for CustomQEngine<Navigation> {
+    const NAME: &'static str = "navigation-q";
+
+    fn default_hyper_parameters() -> HyperParameters<Self> {
+        hyper_parameters(q_program_parameters(
+            Navigation::N_INPUTS,
+            Navigation::N_ACTIONS,
+        ))
+    }
+
+    fn build_fitness_parameters(params: &mut HyperParameters<Self>) {
+        q_simulation_fitness_parameters(params, -(Navigation::EPISODE_LENGTH as f64))
+    }
+
+    fn plot_range() -> (f64, f64) {
+        (-(Navigation::EPISODE_LENGTH as f64), 0.)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    };
+    use std::time::Duration;
+
+    use itertools::Itertools;
+
+    use super::*;
+    use crate::core::characteristics::{Load, Save};
+    use crate::core::engines::core_engine::{
+        Checkpoint, CoreIter, HyperParametersBuilder, Schedule, StagnationPolicyBuilder,
+        StagnationResponse,
+    };
+    use crate::core::engines::fitness_engine::{FitnessMode, Objective, Penalty};
+    use crate::core::engines::status_engine::Status;
+    use crate::core::environment::StepResult;
+    use crate::extensions::ensemble::Ensemble;
+    use crate::extensions::organism::OrganismGeneratorParametersBuilder;
+    use crate::extensions::q_learning::QProgramGeneratorParametersBuilder;
+    use crate::utils::benchmark_tools::load_and_run_ensemble;
+    use crate::utils::misc::VoidResultAnyError;
+    use crate::utils::telemetry::{take_counters, take_penalties};
+    use crate::utils::test::{navigation_parameters, program_parameters_of_length, temp_dir};
+
+    #[test]
+    fn given_navigation_problem_when_overridden_then_fitness_parameters_are_restored() {
+        let mut params = CustomQEngine::<Navigation>::default_hyper_parameters();
+        let instruction_parameters = params
+            .program_parameters
+            .program_parameters
+            .instruction_generator_parameters;
+
+        assert_eq!(instruction_parameters.n_inputs, Navigation::N_INPUTS);
+        assert_eq!(instruction_parameters.n_actions, Navigation::N_ACTIONS);
+
+        params.default_fitness = 0.;
+        params
+            .program_parameters
+            .program_parameters
+            .instruction_generator_parameters
+            .n_inputs = 1;
+        CustomQEngine::<Navigation>::build_fitness_parameters(&mut params);
+
+        assert_eq!(params.default_fitness, -(Navigation::EPISODE_LENGTH as f64));
+        assert_eq!(
+            params
+                .program_parameters
+                .program_parameters
+                .instruction_generator_parameters
+                .n_inputs,
+            Navigation::N_INPUTS
+        );
+    }
+
+    #[test]
+    fn given_navigation_when_coasting_then_episode_is_truncated() {
+        let mut input: SimulationInput<Navigation> = GenerateEngine::generate(());
+        input.initial_state = Navigation {
+            position: 0.5,
+            velocity: 0.,
+        };
+        ResetEngine::reset(&mut input);
+
+        let mut score = 0.;
+        while let Some(state) = input.get() {
+            score += state.execute_action(1);
+        }
+
+        assert_eq!(score, -(Navigation::EPISODE_LENGTH as f64));
+        assert_eq!(input.get_initial_state(), vec![0.5, 0.]);
+    }
+
+    #[test]
+    fn given_navigation_when_stepped_then_truncation_is_distinguished_from_termination() {
+        let mut input: SimulationInput<Navigation> = GenerateEngine::generate(());
+        input.initial_state = Navigation {
+            position: 0.5,
+            velocity: 0.,
+        };
+        ResetEngine::reset(&mut input);
+
+        let mut last = StepResult::default();
+        while let Some(state) = input.get() {
+            last = state.step(1);
+        }
+
+        assert!(last.truncated());
+        assert_eq!(
+            last.info["episode_step"],
+            serde_json::Value::from(Navigation::EPISODE_LENGTH)
+        );
+
+        input.initial_state = Navigation {
+            position: 0.,
+            velocity: 0.,
+        };
+        ResetEngine::reset(&mut input);
+
+        let step = input.step(1);
+
+        assert!(step.terminated());
+        assert_eq!(step.info["succeeded"], serde_json::Value::from(true));
+    }
+
+    #[test]
+    fn given_stacked_positions_when_stepped_then_previous_position_is_observed() {
+        let mut navigation = StackedPositionNavigation::new(Masked::new(Navigation {
+            position: 0.5,
+            velocity: 0.,
+        }));
+
+        assert_eq!(StackedPositionNavigation::N_INPUTS, 2);
+        assert_eq!(navigation.observation(), vec![0.5, 0.5]);
+
+        navigation.step(2);
+
+        assert_eq!(navigation.observation(), vec![0.5 + Navigation::FORCE, 0.5]);
+    }
+
+    #[test]
+    fn given_frame_skip_when_evaluated_then_actions_are_repeated() -> VoidResultAnyError {
+        let mut program_parameters =
+            program_parameters_of_length(Navigation::N_INPUTS, Navigation::N_ACTIONS, 20);
+        program_parameters.frame_skip = 4;
+
+        let mut program: Program = GenerateEngine::generate(program_parameters);
+        let mut trial: SimulationInput<Navigation> = GenerateEngine::generate(());
+        trial.initial_state = Navigation {
+            position: 0.9,
+            velocity: 0.,
+        };
+        let mut trials = vec![trial];
+
+        take_counters();
+        CustomEngine::<Navigation>::eval_individual(&mut program, &mut trials, 0.);
+        let (environment_steps, program_executions) = take_counters();
+
+        assert!(program_executions < environment_steps);
+        assert!(program_executions * 4 >= environment_steps);
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_success_rate_mode_when_evaluated_then_fitness_counts_solved_trials(
+    ) -> VoidResultAnyError {
+        let program_parameters =
+            program_parameters_of_length(Navigation::N_INPUTS, Navigation::N_ACTIONS, 20);
+
+        let mut program: Program = GenerateEngine::generate(program_parameters);
+        let mut trials = [0., Navigation::BOUND]
+            .into_iter()
+            .map(|position| {
+                let mut trial: SimulationInput<Navigation> = GenerateEngine::generate(());
+                trial.initial_state = Navigation {
+                    position,
+                    velocity: 0.,
+                };
+                trial
+            })
+            .collect_vec();
+
+        CustomEngine::<Navigation>::eval_individual_with(
+            &mut program,
+            &mut trials,
+            -(Navigation::EPISODE_LENGTH as f64),
+            FitnessMode::SuccessRate,
+        );
+
+        let fitness = StatusEngine::get_fitness(&program);
+        let n_successes = trials.iter().filter(|trial| trial.succeeded()).count();
+
+        assert!((0. ..1.).contains(&fitness));
+        assert_eq!(
+            (fitness * (trials.len() + 1) as f64).floor() as usize,
+            n_successes
+        );
+
+        let metadata = StatusEngine::get_metadata(&program).unwrap();
+        assert_eq!(metadata.n_trials, trials.len());
+        assert_eq!(metadata.n_successes, n_successes);
+        assert!(metadata.environment_steps > 0);
+
+        // Metadata describes the current fitness only.
+        StatusEngine::set_fitness(&mut program, fitness);
+        assert_eq!(StatusEngine::get_metadata(&program), None);
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_penalties_when_evaluated_then_they_are_taken_off_the_fitness_and_reported(
+    ) -> VoidResultAnyError {
+        let program_parameters =
+            program_parameters_of_length(Navigation::N_INPUTS, Navigation::N_ACTIONS, 20);
+
+        let mut program: Program = GenerateEngine::generate(program_parameters);
+        let mut trials = vec![GenerateEngine::generate(())];
+
+        CustomEngine::<Navigation>::eval_individual_penalized(
+            &mut program,
+            &mut trials,
+            0.,
+            FitnessMode::CumulativeReward,
+            1.,
+            Objective::Maximize,
+            &[],
+        );
+        let unpenalized = StatusEngine::get_fitness(&program);
+
+        let penalties: Vec<Penalty<Program, SimulationInput<Navigation>>> = vec![
+            Penalty {
+                name: "constant".to_string(),
+                weight: 2.,
+                measure: Box::new(|_, _| 1.),
+            },
+            Penalty {
+                name: "action switches".to_string(),
+                weight: 1.,
+                measure: Box::new(|_, trial| trial.action_switch_frequency()),
+            },
+        ];
+
+        take_penalties();
+        CustomEngine::<Navigation>::eval_individual_penalized(
+            &mut program,
+            &mut trials,
+            0.,
+            FitnessMode::CumulativeReward,
+            1.,
+            Objective::Maximize,
+            &penalties,
+        );
+        let switches = trials[0].action_switch_frequency();
+
+        let metadata = StatusEngine::get_metadata(&program).unwrap();
+        assert_eq!(metadata.penalty, 2. + switches);
+        assert_eq!(
+            StatusEngine::get_fitness(&program),
+            unpenalized - (2. + switches)
+        );
+        assert_eq!(take_penalties(), vec![2., switches]);
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_actions_when_executed_then_switches_are_counted_until_reset() {
+        let mut input: SimulationInput<Navigation> = GenerateEngine::generate(());
+
+        for action in [0, 0, 1, 0] {
+            input.execute_action(action);
+        }
+        assert_eq!(input.action_switch_frequency(), 2. / 3.);
+
+        ResetEngine::reset(&mut input);
+        assert_eq!(input.action_switch_frequency(), 0.);
+    }
+
+    #[test]
+    fn given_pushes_beyond_effort_limit_when_executed_then_excess_is_the_constraint_violation() {
+        let mut input: SimulationInput<Navigation> = GenerateEngine::generate(());
+        input.initial_state = Navigation {
+            position: 0.5,
+            velocity: 0.,
+        };
+        ResetEngine::reset(&mut input);
+
+        for _ in 0..100 {
+            input.execute_action(2);
+        }
+        assert_eq!(input.constraint_violation(), 0.);
+
+        for action in repeat(0).take(20).chain(repeat(1).take(10)) {
+            input.execute_action(action);
+        }
+        assert_eq!(input.constraint_violation(), 20.);
+
+        ResetEngine::reset(&mut input);
+        assert_eq!(input.constraint_violation(), 0.);
+    }
+
+    #[test]
+    fn given_navigation_at_goal_when_coasting_then_trial_succeeds_early() -> VoidResultAnyError {
+        let mut input: SimulationInput<Navigation> = GenerateEngine::generate(());
+        input.initial_state = Navigation {
+            position: 0.,
+            velocity: 0.,
+        };
+        ResetEngine::reset(&mut input);
+
+        assert_eq!(input.execute_action(1), -1.);
+        assert!(input.is_terminal());
+        assert!(input.succeeded());
+
+        ResetEngine::reset(&mut input);
+        assert!(!input.succeeded());
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_validation_trials_when_evolved_then_best_is_validated_every_generation(
+    ) -> VoidResultAnyError {
+        let parameters = navigation_parameters(10)
+            .population_size(10)
+            .n_generations(3)
+            .n_trials(2)
+            .validation_trials(3)
+            .seed(Some(5))
+            .build()?;
+
+        let mut engine = parameters.build_engine();
+        let populations = engine.by_ref().collect_vec();
+
+        assert_eq!(engine.validation_history().len(), populations.len());
+        assert!(engine
+            .validation_history()
+            .iter()
+            .all(|fitness| fitness.is_finite()));
+
+        assert!(populations
+            .iter()
+            .all(|population| population.iter().all(StatusEngine::evaluated)));
+        assert_eq!(engine.summary().n_generations, populations.len());
+
+        Ok(())
+    }
+
+    #[test]
+    fn navigation_lgp() -> VoidResultAnyError {
+        let parameters = navigation_parameters(20)
+            .population_size(20)
+            .n_generations(10)
+            .n_trials(5)
+            .seed(Some(42))
+            .build()?;
+
+        let populations = parameters
+            .build_engine()
+            .take(parameters.n_generations)
+            .collect_vec();
+
+        let best_fitness = populations
+            .iter()
+            .map(|population| StatusEngine::get_fitness(population.first().unwrap()))
+            .collect_vec();
+
+        assert!(best_fitness.iter().all(|fitness| fitness.is_finite()));
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_final_population_when_ensembled_then_it_is_evaluated_like_a_program(
+    ) -> VoidResultAnyError {
+        let parameters = navigation_parameters(20)
+            .population_size(10)
+            .n_generations(3)
+            .n_trials(2)
+            .seed(Some(3))
+            .build()?;
+
+        let population = parameters.build_engine().last().unwrap();
+        let ensemble = Ensemble::from_population(&population, 3);
+
+        let path = temp_dir("navigation-ensemble").join("ensemble.json");
+        ensemble.save(path.to_str().unwrap())?;
+
+        let (original_fitness, new_fitness) = load_and_run_ensemble::<CustomEngine<Navigation>>(
+            path,
+            parameters.n_trials,
+            parameters.default_fitness,
+        )?;
+
+        assert_eq!(original_fitness, StatusEngine::get_fitness(&population[0]));
+        assert!(new_fitness >= parameters.default_fitness);
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_step_budget_when_evolving_then_unevaluated_programs_are_deferred() -> VoidResultAnyError
+    {
+        let parameters = navigation_parameters(20)
+            .population_size(20)
+            .n_generations(5)
+            .n_trials(4)
+            .step_budget(Some(10 * Navigation::EPISODE_LENGTH))
+            .seed(Some(42))
+            .build()?;
+
+        let populations = parameters
+            .build_engine()
+            .take(parameters.n_generations)
+            .collect_vec();
+
+        for population in populations {
+            assert!(!population.is_empty());
+            assert!(population.len() <= parameters.population_size);
+            assert!(population.iter().all(StatusEngine::evaluated));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_scheduled_trials_when_generations_pass_then_initial_states_rotate(
+    ) -> VoidResultAnyError {
+        let schedule = [-0.5, 0.5].map(|position| Navigation {
+            position,
+            velocity: 0.,
+        });
+        let mut input = SimulationInput::with_schedule(schedule.to_vec());
+
+        for generation in 0..4 {
+            input.on_generation(generation);
+            assert_eq!(input.initial_state, schedule[generation % 2]);
+            assert_eq!(input.simulation, schedule[generation % 2]);
+        }
+
+        let parameters = navigation_parameters(12)
+            .population_size(10)
+            .n_generations(3)
+            .seed(Some(3))
+            .build()?;
+
+        let populations = CoreIter::with_trials(parameters.clone(), vec![], vec![input])
+            .take(parameters.n_generations)
+            .collect_vec();
+
+        assert_eq!(populations.len(), parameters.n_generations);
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_mixed_population_when_evolving_then_both_kinds_compete() -> VoidResultAnyError {
+        let program_parameters =
+            program_parameters_of_length(Navigation::N_INPUTS, Navigation::N_ACTIONS, 20);
+        let q_program_parameters = QProgramGeneratorParametersBuilder::default()
+            .program_parameters(program_parameters)
+            .build()?;
+        let organism_parameters = OrganismGeneratorParametersBuilder::default()
+            .q_program_parameters(q_program_parameters)
+            .build()?;
+        let parameters = HyperParametersBuilder::<CustomOrganismEngine<Navigation>>::default()
+            .program_parameters(organism_parameters)
+            .default_fitness(-(Navigation::EPISODE_LENGTH as f64))
+            .population_size(20)
+            .n_generations(5)
+            .n_trials(2)
+            .seed(Some(11))
+            .build()?;
+
+        let populations = parameters
+            .build_engine()
+            .take(parameters.n_generations)
+            .collect_vec();
+
+        let first = populations.first().unwrap();
+        assert!(first.iter().any(Organism::is_q));
+        assert!(first.iter().any(|organism| !organism.is_q()));
+
+        for population in populations {
+            assert!(population.iter().all(StatusEngine::evaluated));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_local_search_when_evolving_then_offspring_are_evaluated_on_all_trials(
+    ) -> VoidResultAnyError {
+        let parameters = navigation_parameters(20)
+            .population_size(10)
+            .n_generations(3)
+            .n_trials(3)
+            .local_search_steps(3)
+            .seed(Some(5))
+            .build()?;
+
+        let populations = parameters
+            .build_engine()
+            .take(parameters.n_generations)
+            .collect_vec();
+
+        for population in populations {
+            assert_eq!(population.len(), parameters.population_size);
+            assert!(population.iter().all(StatusEngine::evaluated));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_minimize_objective_when_evolving_then_populations_are_ranked_lowest_first(
+    ) -> VoidResultAnyError {
+        let parameters = navigation_parameters(20)
+            .default_fitness(0.)
+            .objective(Objective::Minimize)
+            .population_size(10)
+            .n_generations(3)
+            .n_trials(2)
+            .seed(Some(9))
+            .build()?;
+
+        let populations = parameters
+            .build_engine()
+            .take(parameters.n_generations)
+            .collect_vec();
+
+        for population in populations {
+            let fitnesses = population
+                .iter()
+                .map(StatusEngine::get_fitness)
+                .collect_vec();
+            assert!(fitnesses.windows(2).all(|w| w[0] <= w[1]));
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_restart_policy_when_best_fitness_stagnates_then_population_is_regenerated(
+    ) -> VoidResultAnyError {
+        let stagnation_policy = StagnationPolicyBuilder::default()
+            .stagnation_response(StagnationResponse::Restart)
+            .stagnation_generations(1)
+            .restart_elites(2)
+            .build()?;
+        let parameters = navigation_parameters(20)
+            .stagnation_policy(stagnation_policy)
+            .population_size(10)
+            .n_generations(4)
+            .n_trials(2)
+            .seed(Some(3))
+            .build()?;
+
+        let populations = parameters
+            .build_engine()
+            .take(parameters.n_generations)
+            .collect_vec();
+
+        let best_fitness = populations
+            .iter()
+            .map(|population| StatusEngine::get_fitness(population.first().unwrap()))
+            .collect_vec();
+
+        for population in populations {
+            assert_eq!(population.len(), parameters.population_size);
+        }
+        // Elites survive restarts.
+        assert!(best_fitness.windows(2).all(|w| w[0] <= w[1]));
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_elapsed_max_duration_when_evolving_then_run_stops_after_one_generation(
+    ) -> VoidResultAnyError {
+        let parameters = navigation_parameters(20)
+            .max_duration(Some(Duration::ZERO))
+            .population_size(10)
+            .n_generations(5)
+            .n_trials(2)
+            .seed(Some(4))
+            .build()?;
+
+        let populations = parameters.build_engine().collect_vec();
+
+        assert_eq!(populations.len(), 1);
+        assert!(populations[0].iter().all(StatusEngine::evaluated));
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_stop_flag_when_set_then_run_can_resume_from_checkpoint() -> VoidResultAnyError {
+        let parameters = navigation_parameters(20)
+            .population_size(10)
+            .n_generations(5)
+            .n_trials(2)
+            .seed(Some(6))
+            .build()?;
+
+        let stop = Arc::new(AtomicBool::new(false));
+        let mut engine = parameters.build_engine().stop_when(stop.clone());
+
+        assert!(engine.next().is_some());
+        stop.store(true, Ordering::SeqCst);
+        assert!(engine.next().is_none());
+
+        let checkpoint = engine.checkpoint();
+        assert_eq!(checkpoint.generation, 1);
+        assert_eq!(checkpoint.population.len(), parameters.population_size);
+
+        // Offspring are not evaluated yet, their fitness must survive the round trip through JSON.
+        assert!(checkpoint
+            .population
+            .iter()
+            .any(|program| !StatusEngine::evaluated(program)));
+        let checkpoint_path = temp_dir("checkpoint").join("checkpoint.json");
+        checkpoint.save(checkpoint_path.to_str().unwrap())?;
+        let loaded = Checkpoint::<CustomEngine<Navigation>>::try_load(&checkpoint_path)?;
+        assert_eq!(loaded.population.len(), parameters.population_size);
+
+        let resumed = CoreIter::resume(loaded).collect_vec();
+        assert_eq!(resumed.len(), parameters.n_generations);
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_linear_schedule_when_evolving_then_mutation_percent_is_annealed() -> VoidResultAnyError
+    {
+        let parameters = navigation_parameters(20)
+            .population_size(10)
+            .n_generations(4)
+            .n_trials(2)
+            .seed(Some(8))
+            .build()?;
+
+        let schedule = Schedule::Linear {
+            start: 0.9,
+            end: 0.1,
+            n_generations: 4,
+        };
+        let mut engine = parameters
+            .build_engine()
+            .with_schedule(|params| &mut params.mutation_percent, schedule)
+            .with_schedule_fn(
+                |params| &mut params.gap,
+                |generation| 0.5 + 0.1 * generation as f64,
+            );
+
+        engine.next();
+        engine.next();
+
+        let checkpoint = engine.checkpoint();
+        let params = checkpoint.params;
+        assert!((params.mutation_percent - 0.7).abs() < 1e-12);
+        assert!((params.gap - 0.6).abs() < 1e-12);
+        // Mutation leaves 30% of the offspring to crossover.
+        assert!((params.crossover_percent - 0.3).abs() < 1e-12);
+
+        // Only the schedule given as data can be resumed.
+        assert_eq!(checkpoint.schedules, vec![Some(schedule), None]);
+        assert!(CoreIter::resume_with_schedules(
+            checkpoint.clone(),
+            &[|params| &mut params.mutation_percent]
+        )
+        .is_err());
+
+        let mut checkpoint = checkpoint;
+        checkpoint.schedules.pop();
+        let mut resumed =
+            CoreIter::resume_with_schedules(checkpoint, &[|params| &mut params.mutation_percent])?;
+        resumed.next();
+        let params = resumed.checkpoint().params;
+        assert!((params.mutation_percent - schedule.value(2)).abs() < 1e-12);
+
+        Ok(())
+    }
+}
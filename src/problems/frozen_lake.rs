@@ -0,0 +1,258 @@
+//! FrozenLake: a discrete grid world, evolved through one-hot or embedded observations.
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    core::engines::core_engine::HyperParameters,
+    problems::{
+        custom::{
+            q_simulation_fitness_parameters, simulation_fitness_parameters, CustomEngine,
+            CustomQEngine, DiscreteSimulation, Embedded, OneHot, Simulation,
+        },
+        problem::{hyper_parameters, program_parameters, q_program_parameters, Problem},
+    },
+};
+
+/// Walking from the top left corner to the goal in the bottom right corner of a frozen lake, without
+/// falling through a hole (the deterministic, non-slippery, 4x4 map of Gym).
+///
+/// The state is the index `row * SIZE + column` of the current tile. Actions: `0` left, `1` down,
+/// `2` right, `3` up. Reaching the goal is rewarded with `1`, every other step with `0`; falling
+/// through a hole ends the episode.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct FrozenLake {
+    pub position: usize,
+}
+
+impl FrozenLake {
+    pub const SIZE: usize = 4;
+    pub const MAP: [&'static str; 4] = ["SFFF", "FHFH", "FFFH", "HFFG"];
+
+    pub fn tile(state: usize) -> u8 {
+        Self::MAP[state / Self::SIZE].as_bytes()[state % Self::SIZE]
+    }
+}
+
+impl DiscreteSimulation for FrozenLake {
+    const N_STATES: usize = Self::SIZE * Self::SIZE;
+    const N_ACTIONS: usize = 4;
+    const EPISODE_LENGTH: usize = 100;
+    const EMBEDDING_DIM: usize = 2;
+
+    fn sample() -> Self {
+        FrozenLake { position: 0 }
+    }
+
+    fn state(&self) -> usize {
+        self.position
+    }
+
+    fn step(&mut self, action: usize) -> (f64, bool) {
+        let (row, column) = (self.position / Self::SIZE, self.position % Self::SIZE);
+
+        let (row, column) = match action {
+            0 => (row, column.saturating_sub(1)),
+            1 => ((row + 1).min(Self::SIZE - 1), column),
+            2 => (row, (column + 1).min(Self::SIZE - 1)),
+            3 => (row.saturating_sub(1), column),
+            _ => unreachable!(),
+        };
+
+        self.position = row * Self::SIZE + column;
+
+        match Self::tile(self.position) {
+            b'G' => (1., true),
+            b'H' => (0., true),
+            _ => (0., false),
+        }
+    }
+
+    fn succeeded(&self) -> bool {
+        Self::tile(self.position) == b'G'
+    }
+
+    /// Normalized `(row, column)` coordinates of the tile.
+    fn embed(state: usize, idx: usize) -> f64 {
+        let coordinate = match idx {
+            0 => state / Self::SIZE,
+            1 => state % Self::SIZE,
+            _ => unreachable!(),
+        };
+
+        coordinate as f64 / (Self::SIZE - 1) as f64
+    }
+}
+
+pub type OneHotFrozenLake = OneHot<FrozenLake>;
+pub type EmbeddedFrozenLake = Embedded<FrozenLake>;
+
+impl Problem for CustomEngine<OneHotFrozenLake> {
+    const NAME: &'static str = "frozen-lake-lgp";
+
+    fn default_hyper_parameters() -> HyperParameters<Self> {
+        hyper_parameters(program_parameters(
+            OneHotFrozenLake::N_INPUTS,
+            OneHotFrozenLake::N_ACTIONS,
+        ))
+    }
+
+    fn build_fitness_parameters(params: &mut HyperParameters<Self>) {
+        simulation_fitness_parameters(params, 0.)
+    }
+
+    fn plot_range() -> (f64, f64) {
+        (0., 1.)
+    }
+}
+
+impl Problem for CustomQEngine<OneHotFrozenLake> {
+    const NAME: &'static str = "frozen-lake-q";
+
+    fn default_hyper_parameters() -> HyperParameters<Self> {
+        hyper_parameters(q_program_parameters(
+            OneHotFrozenLake::N_INPUTS,
+            OneHotFrozenLake::N_ACTIONS,
+        ))
+    }
+
+    fn build_fitness_parameters(params: &mut HyperParameters<Self>) {
+        q_simulation_fitness_parameters(params, 0.)
+    }
+
+    fn plot_range() -> (f64, f64) {
+        (0., 1.)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+    use rand::Rng;
+
+    use super::*;
+    use crate::core::engines::core_engine::HyperParametersBuilder;
+    use crate::core::engines::status_engine::{Status, StatusEngine};
+    use crate::extensions::q_learning::{QConsts, QProgramGeneratorParametersBuilder};
+
+    use crate::utils::float_ops::argmax;
+    use crate::utils::misc::VoidResultAnyError;
+    use crate::utils::random::{generator, update_seed};
+    use crate::utils::test::program_parameters_of_length;
+
+    /// Plain tabular Q-learning over the state indices, as a reference for the evolved policies.
+    fn tabular_q_learning(n_episodes: usize) -> Vec<Vec<f64>> {
+        let (alpha, gamma, epsilon) = (0.5, 0.95, 0.2);
+        let mut table = vec![vec![0.; FrozenLake::N_ACTIONS]; FrozenLake::N_STATES];
+
+        for _ in 0..n_episodes {
+            let mut lake = FrozenLake::sample();
+
+            for _ in 0..FrozenLake::EPISODE_LENGTH {
+                let state = lake.state();
+                let action = if generator().gen::<f64>() < epsilon {
+                    generator().gen_range(0..FrozenLake::N_ACTIONS)
+                } else {
+                    argmax(table[state].iter().copied()).unwrap()
+                };
+
+                let (reward, done) = lake.step(action);
+                let next_value = match done {
+                    true => 0.,
+                    false => table[lake.state()].iter().copied().fold(f64::MIN, f64::max),
+                };
+
+                table[state][action] +=
+                    alpha * (reward + gamma * next_value - table[state][action]);
+
+                if done {
+                    break;
+                }
+            }
+        }
+
+        table
+    }
+
+    fn greedy_return(table: &[Vec<f64>]) -> f64 {
+        let mut lake = FrozenLake::sample();
+
+        for _ in 0..FrozenLake::EPISODE_LENGTH {
+            let (reward, done) = lake.step(argmax(table[lake.state()].iter().copied()).unwrap());
+
+            if done {
+                return reward;
+            }
+        }
+
+        0.
+    }
+
+    #[test]
+    fn given_lake_when_encoded_then_observations_match_state() {
+        let mut lake = OneHotFrozenLake::sample();
+        lake.step(2);
+
+        assert_eq!(OneHotFrozenLake::N_INPUTS, FrozenLake::N_STATES);
+        assert_eq!(lake.observation()[1], 1.);
+        assert_eq!(lake.observation().iter().sum::<f64>(), 1.);
+
+        let mut lake = EmbeddedFrozenLake::sample();
+        lake.step(1);
+
+        assert_eq!(lake.observation(), vec![1. / 3., 0.]);
+    }
+
+    #[test]
+    fn given_hole_when_stepped_into_then_episode_ends_without_reward() {
+        let mut lake = FrozenLake { position: 1 };
+
+        assert_eq!(lake.step(1), (0., true));
+    }
+
+    #[test]
+    fn given_frozen_lake_when_evolved_then_scores_are_compared_with_tabular_q_learning(
+    ) -> VoidResultAnyError {
+        update_seed(Some(13));
+        assert_eq!(greedy_return(&tabular_q_learning(500)), 1.);
+
+        let program_parameters =
+            program_parameters_of_length(FrozenLake::N_STATES, FrozenLake::N_ACTIONS, 20);
+
+        let lgp = HyperParametersBuilder::<CustomEngine<OneHotFrozenLake>>::default()
+            .program_parameters(program_parameters)
+            .population_size(20)
+            .n_generations(5)
+            .n_trials(1)
+            .seed(Some(13))
+            .build()?;
+        let q = HyperParametersBuilder::<CustomQEngine<OneHotFrozenLake>>::default()
+            .program_parameters(
+                QProgramGeneratorParametersBuilder::default()
+                    .program_parameters(program_parameters)
+                    .consts(QConsts::new(0.5, 0.95, 0.05, 0.01, 0.001))
+                    .build()?,
+            )
+            .population_size(20)
+            .n_generations(5)
+            .n_trials(1)
+            .seed(Some(13))
+            .build()?;
+
+        let lgp_best = lgp
+            .build_engine()
+            .take(lgp.n_generations)
+            .map(|population| StatusEngine::get_fitness(population.first().unwrap()))
+            .collect_vec();
+        let q_best = q
+            .build_engine()
+            .take(q.n_generations)
+            .map(|population| StatusEngine::get_fitness(population.first().unwrap()))
+            .collect_vec();
+
+        // Returns are either 0 (hole or timeout) or 1 (goal), so averages lie in between.
+        for fitness in lgp_best.iter().chain(&q_best) {
+            assert!((0.0..=1.).contains(fitness));
+        }
+
+        Ok(())
+    }
+}
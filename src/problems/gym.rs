@@ -1,9 +1,15 @@
 use std::marker::PhantomData;
 
 use gym_rs::core::Env;
+use gym_rs::envs::classical_control::cartpole::CartPoleEnv;
+use gym_rs::envs::classical_control::mountain_car::MountainCarEnv;
+use rand::RngCore;
+use serde::Serialize;
+use serde_json::Value;
 
 use crate::core::engines::breed_engine::BreedEngine;
 use crate::core::engines::core_engine::Core;
+use crate::core::engines::core_engine::HyperParameters;
 use crate::core::engines::fitness_engine::FitnessEngine;
 use crate::core::engines::freeze_engine::FreezeEngine;
 use crate::core::engines::generate_engine::Generate;
@@ -12,25 +18,63 @@ use crate::core::engines::mutate_engine::MutateEngine;
 use crate::core::engines::reset_engine::Reset;
 use crate::core::engines::reset_engine::ResetEngine;
 use crate::core::engines::status_engine::StatusEngine;
+use crate::core::environment::FreshState;
+use crate::core::environment::GenerationAware;
 use crate::core::environment::RlState;
+use crate::core::environment::SpaceInfo;
 use crate::core::environment::State;
+use crate::core::environment::StepInfo;
 use crate::core::program::Program;
 use crate::core::program::ProgramGeneratorParameters;
 use crate::extensions::interactive::UseRlFitness;
+use crate::extensions::organism::Organism;
+use crate::extensions::organism::OrganismGeneratorParameters;
 use crate::extensions::q_learning::QProgram;
 use crate::extensions::q_learning::QProgramGeneratorParameters;
+use crate::problems::custom::Masked;
+use crate::problems::custom::ObservationMask;
+use crate::problems::custom::PositionOnly;
+use crate::problems::custom::Simulation;
+use crate::problems::custom::Stacked;
+use crate::problems::problem::hyper_parameters;
+use crate::problems::problem::program_parameters;
+use crate::problems::problem::q_program_parameters;
+use crate::problems::problem::set_dimensions;
+use crate::problems::problem::Problem;
+use crate::utils::random::generator;
+
+const CART_POLE_N_INPUTS: usize = 4;
+const CART_POLE_N_ACTIONS: usize = 2;
+const CART_POLE_DEFAULT_FITNESS: f64 = 500.;
+const MOUNTAIN_CAR_N_INPUTS: usize = 2;
+const MOUNTAIN_CAR_N_ACTIONS: usize = 3;
+const MOUNTAIN_CAR_DEFAULT_FITNESS: f64 = -200.;
 
 #[derive(Clone, Debug)]
 pub struct GymRsInput<E: Env> {
     environment: E,
     terminated: bool,
+    truncated: bool,
     episode_idx: usize,
     initial_state: E::Observation,
+    /// The `info` of the environment's last step.
+    info: StepInfo,
+}
+
+/// The `info` of a gym step as [`StepInfo`]: the fields of a map (or struct), or else any value
+/// under `"info"`.
+fn step_info(info: &impl Serialize) -> StepInfo {
+    match serde_json::to_value(info) {
+        Ok(Value::Object(fields)) => fields.into_iter().collect(),
+        Ok(Value::Null) | Err(_) => StepInfo::new(),
+        Ok(value) => StepInfo::from([("info".to_string(), value)]),
+    }
 }
 
 impl<E> State for GymRsInput<E>
 where
     E: Env,
+    E::Info: Serialize,
 {
     fn get_value(&self, idx: usize) -> f64 {
         self.environment.get_observation_property(idx)
@@ -39,7 +83,11 @@ where
     fn execute_action(&mut self, action: usize) -> f64 {
         let action_reward = self.environment.step(action);
         self.episode_idx += 1;
-        self.terminated = self.episode_idx >= E::episode_length() || action_reward.done;
+        // Environments may truncate their own episodes, otherwise they are cut at their length.
+        self.truncated = action_reward.truncated
+            || (!action_reward.done && self.episode_idx >= E::episode_length());
+        self.terminated = self.truncated || action_reward.done;
+        self.info = step_info(&action_reward.info);
         action_reward.reward
     }
 
@@ -55,6 +103,7 @@ where
 impl<T> RlState for GymRsInput<T>
 where
     T: Env,
+    T::Info: Serialize,
 {
     fn is_terminal(&mut self) -> bool {
         self.terminated
@@ -63,45 +112,204 @@ where
     fn get_initial_state(&self) -> Vec<f64> {
         self.initial_state.into()
     }
+
+    fn is_truncated(&self) -> bool {
+        self.truncated
+    }
+
+    /// The environment's own `info`, along with the step of the episode.
+    fn info(&self) -> StepInfo {
+        let mut info = self.info.clone();
+        info.insert("episode_step".to_string(), self.episode_idx.into());
+
+        info
+    }
+}
+
+impl<E> GenerationAware for GymRsInput<E>
+where
+    E: Env,
+    E::Info: Serialize,
+{
+}
+
+impl<E> FreshState for GymRsInput<E>
+where
+    E: Env,
+    E::Info: Serialize,
+{
+    /// The step, whether the episode was terminated or truncated, and the observation.
+    type PerEvaluation = (usize, bool, bool, Vec<f64>);
+    type PerGeneration = Vec<f64>;
+    type PerRun = ();
+
+    fn per_evaluation(&self) -> Self::PerEvaluation {
+        let n_inputs = self.get_initial_state().len();
+        let observation = (0..n_inputs).map(|idx| self.get_value(idx)).collect();
+
+        (
+            self.episode_idx,
+            self.terminated,
+            self.truncated,
+            observation,
+        )
+    }
+
+    fn fresh(&self) -> Self::PerEvaluation {
+        (0, false, false, self.get_initial_state())
+    }
+
+    fn per_generation(&self) -> Vec<f64> {
+        self.get_initial_state()
+    }
+
+    fn per_run(&self) {}
+}
+
+/// Bounds of the observation space of gym's `CartPole-v1`; velocities are unbounded.
+impl SpaceInfo for GymRsInput<CartPoleEnv> {
+    fn n_inputs(&self) -> usize {
+        CART_POLE_N_INPUTS
+    }
+
+    fn n_actions(&self) -> usize {
+        CART_POLE_N_ACTIONS
+    }
+
+    fn input_bounds(&self) -> Vec<(f64, f64)> {
+        vec![
+            (-4.8, 4.8),
+            (f64::NEG_INFINITY, f64::INFINITY),
+            (-0.418, 0.418),
+            (f64::NEG_INFINITY, f64::INFINITY),
+        ]
+    }
+}
+
+/// Bounds of the observation space of gym's `MountainCar-v0`: the position, then the velocity.
+impl SpaceInfo for GymRsInput<MountainCarEnv> {
+    fn n_inputs(&self) -> usize {
+        MOUNTAIN_CAR_N_INPUTS
+    }
+
+    fn n_actions(&self) -> usize {
+        MOUNTAIN_CAR_N_ACTIONS
+    }
+
+    fn input_bounds(&self) -> Vec<(f64, f64)> {
+        vec![(-1.2, 0.6), (-0.07, 0.07)]
+    }
 }
 
 impl<T> Reset<GymRsInput<T>> for ResetEngine
 where
     T: Env,
+    T::Info: Serialize,
 {
     fn reset(item: &mut GymRsInput<T>) {
         item.environment.reset(None, false, None);
         item.environment.set_observation(item.initial_state);
         item.terminated = false;
+        item.truncated = false;
         item.episode_idx = 0;
+        item.info = StepInfo::new();
     }
 }
 
 impl<T> Generate<(), GymRsInput<T>> for GenerateEngine
 where
     T: Env,
+    T::Info: Serialize,
 {
+    /// The environment is seeded from the generator, so seeded runs start from the same states.
     fn generate(_from: ()) -> GymRsInput<T> {
         let mut environment: T = Env::new();
-        let (initial_state, _) = environment.reset(None, false, None);
+        let (initial_state, _) = environment.reset(Some(generator().next_u64()), false, None);
 
         GymRsInput {
             environment,
             terminated: false,
+            truncated: false,
             episode_idx: 0,
             initial_state,
+            info: StepInfo::new(),
         }
     }
 }
 
+/// The dimensions of a gym environment, for [`GymSimulation`].
+pub trait GymTask: Env + Clone + Send {
+    const N_INPUTS: usize;
+    const N_ACTIONS: usize;
+    const EPISODE_LENGTH: usize;
+}
+
+impl GymTask for CartPoleEnv {
+    const N_INPUTS: usize = CART_POLE_N_INPUTS;
+    const N_ACTIONS: usize = CART_POLE_N_ACTIONS;
+    const EPISODE_LENGTH: usize = 500;
+}
+
+impl GymTask for MountainCarEnv {
+    const N_INPUTS: usize = MOUNTAIN_CAR_N_INPUTS;
+    const N_ACTIONS: usize = MOUNTAIN_CAR_N_ACTIONS;
+    const EPISODE_LENGTH: usize = 200;
+}
+
+/// A gym environment stepped as a [`Simulation`], so the wrappers of custom tasks apply to it (e.g.
+/// [`Masked`] and [`Stacked`] to hide velocities) and it is evolved through
+/// [`CustomEngine`](crate::problems::custom::CustomEngine).
+#[derive(Clone, Debug)]
+pub struct GymSimulation<E> {
+    pub environment: E,
+}
+
+impl<E> Simulation for GymSimulation<E>
+where
+    E: GymTask,
+{
+    const N_INPUTS: usize = E::N_INPUTS;
+    const N_ACTIONS: usize = E::N_ACTIONS;
+    const EPISODE_LENGTH: usize = E::EPISODE_LENGTH;
+
+    /// Seeded from the generator, so seeded runs start from the same states.
+    fn sample() -> Self {
+        let mut environment = E::new();
+        environment.reset(Some(generator().next_u64()), false, None);
+
+        GymSimulation { environment }
+    }
+
+    fn observe(&self, idx: usize) -> f64 {
+        self.environment.get_observation_property(idx)
+    }
+
+    fn step(&mut self, action: usize) -> (f64, bool) {
+        let action_reward = self.environment.step(action);
+
+        (action_reward.reward, action_reward.done)
+    }
+}
+
+impl ObservationMask<GymSimulation<MountainCarEnv>> for PositionOnly {
+    const INDICES: &'static [usize] = &[0];
+}
+
+/// `MountainCar-v0` where the velocity has to be inferred from the last two positions.
+pub type StackedPositionMountainCar =
+    Stacked<Masked<GymSimulation<MountainCarEnv>, PositionOnly>, 2>;
+
 #[derive(Clone)]
 pub struct GymRsQEngine<T>(PhantomData<T>);
 #[derive(Clone)]
 pub struct GymRsEngine<T>(PhantomData<T>);
+#[derive(Clone)]
+pub struct GymRsOrganismEngine<T>(PhantomData<T>);
 
 impl<T> Core for GymRsQEngine<T>
 where
     T: Env,
+    T::Info: Serialize,
 {
     type Individual = QProgram;
     type ProgramParameters = QProgramGeneratorParameters;
@@ -119,6 +327,7 @@ where
 impl<T> Core for GymRsEngine<T>
 where
     T: Env,
+    T::Info: Serialize,
 {
     type Individual = Program;
     type ProgramParameters = ProgramGeneratorParameters;
@@ -133,32 +342,195 @@ where
     type Freeze = FreezeEngine;
 }
 
+impl<T> Core for GymRsOrganismEngine<T>
+where
+    T: Env,
+    T::Info: Serialize,
+{
+    type Individual = Organism;
+    type ProgramParameters = OrganismGeneratorParameters;
+    type State = GymRsInput<T>;
+    type FitnessMarker = ();
+    type Generate = GenerateEngine;
+    type Fitness = FitnessEngine;
+    type Reset = ResetEngine;
+    type Breed = BreedEngine;
+    type Mutate = MutateEngine;
+    type Status = StatusEngine;
+    type Freeze = FreezeEngine;
+}
+
+impl Problem for GymRsEngine<CartPoleEnv> {
+    const NAME: &'static str = "cart-pole-lgp";
+
+    fn default_hyper_parameters() -> HyperParameters<Self> {
+        hyper_parameters(program_parameters(CART_POLE_N_INPUTS, CART_POLE_N_ACTIONS))
+    }
+
+    fn build_fitness_parameters(params: &mut HyperParameters<Self>) {
+        set_dimensions(
+            &mut params.program_parameters.instruction_generator_parameters,
+            CART_POLE_N_INPUTS,
+            CART_POLE_N_ACTIONS,
+        );
+        params.default_fitness = CART_POLE_DEFAULT_FITNESS;
+    }
+
+    fn plot_range() -> (f64, f64) {
+        (0., 500.)
+    }
+}
+
+impl Problem for GymRsQEngine<CartPoleEnv> {
+    const NAME: &'static str = "cart-pole-q";
+
+    fn default_hyper_parameters() -> HyperParameters<Self> {
+        hyper_parameters(q_program_parameters(
+            CART_POLE_N_INPUTS,
+            CART_POLE_N_ACTIONS,
+        ))
+    }
+
+    fn build_fitness_parameters(params: &mut HyperParameters<Self>) {
+        ResetEngine::reset(&mut params.program_parameters.consts);
+        set_dimensions(
+            &mut params
+                .program_parameters
+                .program_parameters
+                .instruction_generator_parameters,
+            CART_POLE_N_INPUTS,
+            CART_POLE_N_ACTIONS,
+        );
+        params.default_fitness = CART_POLE_DEFAULT_FITNESS;
+    }
+
+    fn plot_range() -> (f64, f64) {
+        (0., 500.)
+    }
+}
+
+impl Problem for GymRsEngine<MountainCarEnv> {
+    const NAME: &'static str = "mountain-car-lgp";
+
+    fn default_hyper_parameters() -> HyperParameters<Self> {
+        hyper_parameters(program_parameters(
+            MOUNTAIN_CAR_N_INPUTS,
+            MOUNTAIN_CAR_N_ACTIONS,
+        ))
+    }
+
+    fn build_fitness_parameters(params: &mut HyperParameters<Self>) {
+        set_dimensions(
+            &mut params.program_parameters.instruction_generator_parameters,
+            MOUNTAIN_CAR_N_INPUTS,
+            MOUNTAIN_CAR_N_ACTIONS,
+        );
+        params.default_fitness = MOUNTAIN_CAR_DEFAULT_FITNESS;
+    }
+
+    fn plot_range() -> (f64, f64) {
+        (-200., 0.)
+    }
+}
+
+impl Problem for GymRsQEngine<MountainCarEnv> {
+    const NAME: &'static str = "mountain-car-q";
+
+    fn default_hyper_parameters() -> HyperParameters<Self> {
+        hyper_parameters(q_program_parameters(
+            MOUNTAIN_CAR_N_INPUTS,
+            MOUNTAIN_CAR_N_ACTIONS,
+        ))
+    }
+
+    fn build_fitness_parameters(params: &mut HyperParameters<Self>) {
+        ResetEngine::reset(&mut params.program_parameters.consts);
+        set_dimensions(
+            &mut params
+                .program_parameters
+                .program_parameters
+                .instruction_generator_parameters,
+            MOUNTAIN_CAR_N_INPUTS,
+            MOUNTAIN_CAR_N_ACTIONS,
+        );
+        params.default_fitness = MOUNTAIN_CAR_DEFAULT_FITNESS;
+    }
+
+    fn plot_range() -> (f64, f64) {
+        (-200., 0.)
+    }
+}
+
 #[cfg(test)]
 mod tests {
-    use itertools::Itertools;
-
     use super::*;
     use crate::core::config::load_hyper_parameters;
-    use crate::core::engines::core_engine::HyperParameters;
+    use crate::core::environment::{EpisodeStatus, StepResult};
 
-    use crate::utils::benchmark_tools::save_experiment;
+    use crate::extensions::q_learning::{save_q_tables, CrossoverVariant};
+    use crate::utils::benchmark_tools::run_experiment;
     use crate::utils::misc::VoidResultAnyError;
 
     use gym_rs::envs::classical_control::cartpole::CartPoleEnv;
     use gym_rs::envs::classical_control::mountain_car::MountainCarEnv;
 
+    #[test]
+    fn given_position_only_mountain_car_when_stepped_then_last_two_positions_are_observed() {
+        let mut mountain_car = StackedPositionMountainCar::sample();
+        let position = mountain_car.observe(0);
+
+        assert_eq!(StackedPositionMountainCar::N_INPUTS, 2);
+        assert_eq!(mountain_car.observation(), vec![position; 2]);
+
+        mountain_car.step(2);
+
+        assert_eq!(mountain_car.observe(1), position);
+        assert_eq!(
+            mountain_car.observe(0),
+            mountain_car
+                .simulation
+                .simulation
+                .environment
+                .get_observation_property(0)
+        );
+    }
+
+    /// Plays `action` until the episode ends, returning the result of the last step.
+    fn play_constant<E>(action: usize) -> StepResult
+    where
+        E: Env,
+        E::Info: Serialize,
+    {
+        let mut input: GymRsInput<E> = GenerateEngine::generate(());
+        let mut last = StepResult::default();
+
+        while let Some(state) = input.get() {
+            last = state.step(action);
+        }
+
+        last
+    }
+
+    #[test]
+    fn given_gym_episodes_when_they_end_then_truncation_is_told_apart_and_info_is_passed() {
+        // Coasting never reaches the flag, pushing left always drops the pole.
+        let coasting = play_constant::<MountainCarEnv>(1);
+        let falling = play_constant::<CartPoleEnv>(0);
+
+        assert_eq!(coasting.status, EpisodeStatus::Truncated);
+        assert_eq!(coasting.info["episode_step"], Value::from(200));
+        assert_eq!(falling.status, EpisodeStatus::Terminated);
+        assert!(falling.info["episode_step"].as_u64().unwrap() < 500);
+    }
+
     #[test]
     fn cart_pole_q() -> VoidResultAnyError {
         let name = "cart_pole_q";
 
         let parameters: HyperParameters<GymRsQEngine<CartPoleEnv>> =
             load_hyper_parameters("assets/parameters/cart-pole-q.json")?;
-        let populations = parameters
-            .build_engine()
-            .take(parameters.n_generations)
-            .collect_vec();
-
-        save_experiment(&populations, &parameters, name)?;
+        let populations = run_experiment(&parameters, name)?;
+        save_q_tables(&populations, name)?;
 
         Ok(())
     }
@@ -170,12 +542,7 @@ mod tests {
         let parameters: HyperParameters<GymRsEngine<CartPoleEnv>> =
             load_hyper_parameters("assets/parameters/cart-pole-lgp.json")?;
 
-        let populations = parameters
-            .build_engine()
-            .take(parameters.n_generations)
-            .collect_vec();
-
-        save_experiment(&populations, &parameters, name)?;
+        run_experiment(&parameters, name)?;
 
         Ok(())
     }
@@ -186,12 +553,7 @@ mod tests {
 
         let parameters: HyperParameters<GymRsEngine<MountainCarEnv>> =
             load_hyper_parameters("assets/parameters/mountain-car-lgp.json")?;
-        let populations = parameters
-            .build_engine()
-            .take(parameters.n_generations)
-            .collect_vec();
-
-        save_experiment(&populations, &parameters, name)?;
+        run_experiment(&parameters, name)?;
 
         Ok(())
     }
@@ -202,12 +564,26 @@ mod tests {
 
         let parameters: HyperParameters<GymRsQEngine<MountainCarEnv>> =
             load_hyper_parameters("assets/parameters/mountain-car-q.json")?;
-        let populations = parameters
-            .build_engine()
-            .take(parameters.n_generations)
-            .collect_vec();
+        let populations = run_experiment(&parameters, name)?;
+        save_q_tables(&populations, name)?;
+
+        Ok(())
+    }
+    /// Same as `mountain_car_q`, but recombining with [`CrossoverVariant::Aligned`] so the convergence of
+    /// both variants can be compared from the saved experiments.
+    #[test]
+    fn mountain_car_q_aligned_crossover() -> VoidResultAnyError {
+        let name = "mountain_car_q_aligned_crossover";
+
+        let mut parameters: HyperParameters<GymRsQEngine<MountainCarEnv>> =
+            load_hyper_parameters("assets/parameters/mountain-car-q.json")?;
+        parameters.program_parameters.consts = parameters
+            .program_parameters
+            .consts
+            .with_crossover(CrossoverVariant::Aligned);
 
-        save_experiment(&populations, &parameters, name)?;
+        let populations = run_experiment(&parameters, name)?;
+        save_q_tables(&populations, name)?;
 
         Ok(())
     }
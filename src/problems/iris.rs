@@ -1,3 +1,4 @@
+use lazy_static::lazy_static;
 use rand::seq::SliceRandom;
 use serde::{Deserialize, Serialize};
 use strum::EnumCount;
@@ -7,7 +8,7 @@ use crate::{
     core::{
         engines::{
             breed_engine::BreedEngine,
-            core_engine::Core,
+            core_engine::{Core, HyperParameters},
             fitness_engine::FitnessEngine,
             freeze_engine::FreezeEngine,
             generate_engine::{Generate, GenerateEngine},
@@ -15,9 +16,14 @@ use crate::{
             reset_engine::{Reset, ResetEngine},
             status_engine::StatusEngine,
         },
-        environment::State,
+        environment::{column_bounds, FreshState, GenerationAware, SpaceInfo, State},
         program::{Program, ProgramGeneratorParameters},
     },
+    extensions::classification::Dataset,
+    problems::{
+        problem::{hyper_parameters, program_parameters, set_dimensions, Problem},
+        supervised::{SupervisedEpisode, SupervisedTask},
+    },
     utils::{loader::download_and_load_csv, random::generator},
 };
 
@@ -49,6 +55,31 @@ pub enum IrisClass {
 
 pub struct IrisLgp;
 
+lazy_static! {
+    static ref IRIS_DATASET: Dataset = {
+        let state: IrisState = GenerateEngine::generate(());
+        state.dataset()
+    };
+}
+
+/// Iris as a [`SupervisedTask`], classifying one flower per episode.
+#[derive(Clone, Debug)]
+pub struct IrisTask;
+
+impl SupervisedTask for IrisTask {
+    const N_INPUTS: usize = IRIS_N_INPUTS;
+    const N_CLASSES: usize = IrisClass::COUNT;
+
+    fn dataset() -> &'static Dataset {
+        &IRIS_DATASET
+    }
+}
+
+pub type IrisEpisode = SupervisedEpisode<IrisTask>;
+
+/// Sepal and petal lengths and widths.
+pub const IRIS_N_INPUTS: usize = 4;
+
 #[derive(Deserialize, Debug, Clone, PartialEq, PartialOrd, Serialize)]
 pub struct IrisInput {
     sepal_length: f64,
@@ -63,6 +94,46 @@ pub struct IrisState {
     idx: usize,
 }
 
+impl IrisState {
+    /// The samples as a [`Dataset`], e.g. to measure the [`Program::feature_importance`] of a classifier.
+    pub fn dataset(&self) -> Dataset {
+        let features = self
+            .data
+            .iter()
+            .map(|item| {
+                vec![
+                    item.sepal_length,
+                    item.sepal_width,
+                    item.petal_length,
+                    item.petal_width,
+                ]
+            })
+            .collect();
+        let labels = self.data.iter().map(|item| item.class as usize).collect();
+
+        Dataset::new(features, labels)
+    }
+}
+
+impl IrisState {
+    /// Holds out the last `validation_fraction` of the (shuffled) samples, returning the
+    /// (training, validation) states, e.g. for [`CoreIter::with_validation`](crate::core::engines::core_engine::CoreIter::with_validation).
+    pub fn split(self, validation_fraction: f64) -> (IrisState, IrisState) {
+        let mut data = self.data;
+        let n_validation =
+            ((data.len() as f64) * validation_fraction.clamp(0., 1.)).round() as usize;
+        let validation = data.split_off(data.len() - n_validation);
+
+        (
+            IrisState { data, idx: 0 },
+            IrisState {
+                data: validation,
+                idx: 0,
+            },
+        )
+    }
+}
+
 impl State for IrisState {
     fn get_value(&self, idx: usize) -> f64 {
         let item = &self.data[self.idx];
@@ -93,6 +164,55 @@ impl State for IrisState {
     }
 }
 
+impl GenerationAware for IrisState {}
+
+impl FreshState for IrisState {
+    type PerEvaluation = usize;
+    type PerGeneration = ();
+    type PerRun = usize;
+
+    fn per_evaluation(&self) -> usize {
+        self.idx
+    }
+
+    fn fresh(&self) -> usize {
+        0
+    }
+
+    fn per_generation(&self) {}
+
+    fn per_run(&self) -> usize {
+        self.data.len()
+    }
+}
+
+impl SpaceInfo for IrisState {
+    fn n_inputs(&self) -> usize {
+        4
+    }
+
+    fn n_actions(&self) -> usize {
+        3
+    }
+
+    fn input_bounds(&self) -> Vec<(f64, f64)> {
+        let rows = self
+            .data
+            .iter()
+            .map(|item| {
+                vec![
+                    item.sepal_length,
+                    item.sepal_width,
+                    item.petal_length,
+                    item.petal_width,
+                ]
+            })
+            .collect::<Vec<_>>();
+
+        column_bounds(&rows)
+    }
+}
+
 impl Reset<IrisState> for ResetEngine {
     fn reset(item: &mut IrisState) {
         item.idx = 0;
@@ -129,31 +249,41 @@ impl Core for IrisEngine {
     type Freeze = FreezeEngine;
 }
 
+impl Problem for IrisEngine {
+    const NAME: &'static str = "iris-lgp";
+
+    fn default_hyper_parameters() -> HyperParameters<Self> {
+        hyper_parameters(program_parameters(IRIS_N_INPUTS, IrisClass::COUNT))
+    }
+
+    fn build_fitness_parameters(params: &mut HyperParameters<Self>) {
+        set_dimensions(
+            &mut params.program_parameters.instruction_generator_parameters,
+            IRIS_N_INPUTS,
+            IrisClass::COUNT,
+        );
+    }
+
+    fn plot_range() -> (f64, f64) {
+        (0., 1.)
+    }
+}
+
 #[cfg(test)]
 mod test {
 
-    use itertools::Itertools;
-
     use crate::core::engines::core_engine::HyperParametersBuilder;
     use crate::core::engines::status_engine::Status;
-    use crate::core::instruction::InstructionGeneratorParametersBuilder;
-    use crate::core::program::ProgramGeneratorParametersBuilder;
-    use crate::utils::benchmark_tools::save_experiment;
+    use crate::utils::benchmark_tools::run_experiment;
     use crate::utils::misc::VoidResultAnyError;
 
     use super::*;
+    use crate::utils::test::program_parameters_of_length;
 
     #[test]
     fn baseline() -> VoidResultAnyError {
         let name = "iris_baseline";
-        let instruction_parameters = InstructionGeneratorParametersBuilder::default()
-            .n_actions(3)
-            .n_inputs(4)
-            .build()?;
-        let program_parameters = ProgramGeneratorParametersBuilder::default()
-            .max_instructions(100)
-            .instruction_generator_parameters(instruction_parameters)
-            .build()?;
+        let program_parameters = program_parameters_of_length(4, 3, 100);
         let parameters = HyperParametersBuilder::<IrisEngine>::default()
             .program_parameters(program_parameters)
             .n_trials(1)
@@ -162,12 +292,7 @@ mod test {
             .crossover_percent(0.)
             .build()?;
 
-        let populations = parameters
-            .build_engine()
-            .take(parameters.n_generations)
-            .collect_vec();
-
-        save_experiment(&populations, &parameters, name)?;
+        let populations = run_experiment(&parameters, name)?;
 
         let last_population = populations.last().unwrap();
         assert!(last_population
@@ -181,14 +306,7 @@ mod test {
     #[test]
     fn mutation() -> VoidResultAnyError {
         let name = "iris_mutation";
-        let instruction_parameters = InstructionGeneratorParametersBuilder::default()
-            .n_actions(3)
-            .n_inputs(4)
-            .build()?;
-        let program_parameters = ProgramGeneratorParametersBuilder::default()
-            .max_instructions(100)
-            .instruction_generator_parameters(instruction_parameters)
-            .build()?;
+        let program_parameters = program_parameters_of_length(4, 3, 100);
         let parameters = HyperParametersBuilder::<IrisEngine>::default()
             .program_parameters(program_parameters)
             .mutation_percent(1.0)
@@ -196,12 +314,7 @@ mod test {
             .n_trials(1)
             .build()?;
 
-        let populations = parameters
-            .build_engine()
-            .take(parameters.n_generations)
-            .collect_vec();
-
-        save_experiment(&populations, &parameters, name)?;
+        run_experiment(&parameters, name)?;
 
         Ok(())
     }
@@ -209,14 +322,7 @@ mod test {
     #[test]
     fn crossover() -> VoidResultAnyError {
         let name = "iris_crossover";
-        let instruction_parameters = InstructionGeneratorParametersBuilder::default()
-            .n_actions(3)
-            .n_inputs(4)
-            .build()?;
-        let program_parameters = ProgramGeneratorParametersBuilder::default()
-            .max_instructions(100)
-            .instruction_generator_parameters(instruction_parameters)
-            .build()?;
+        let program_parameters = program_parameters_of_length(4, 3, 100);
         let parameters = HyperParametersBuilder::<IrisEngine>::default()
             .program_parameters(program_parameters)
             .mutation_percent(0.)
@@ -224,12 +330,7 @@ mod test {
             .n_trials(1)
             .build()?;
 
-        let populations = parameters
-            .build_engine()
-            .take(parameters.n_generations)
-            .collect_vec();
-
-        save_experiment(&populations, &parameters, name)?;
+        run_experiment(&parameters, name)?;
 
         Ok(())
     }
@@ -238,14 +339,7 @@ mod test {
     fn full() -> VoidResultAnyError {
         let name = "iris_full";
 
-        let instruction_parameters = InstructionGeneratorParametersBuilder::default()
-            .n_actions(3)
-            .n_inputs(4)
-            .build()?;
-        let program_parameters = ProgramGeneratorParametersBuilder::default()
-            .max_instructions(100)
-            .instruction_generator_parameters(instruction_parameters)
-            .build()?;
+        let program_parameters = program_parameters_of_length(4, 3, 100);
         let parameters = HyperParametersBuilder::<IrisEngine>::default()
             .program_parameters(program_parameters)
             .mutation_percent(0.5)
@@ -253,12 +347,7 @@ mod test {
             .n_trials(1)
             .build()?;
 
-        let populations = parameters
-            .build_engine()
-            .take(parameters.n_generations)
-            .collect_vec();
-
-        save_experiment(&populations, &parameters, name)?;
+        run_experiment(&parameters, name)?;
 
         Ok(())
     }
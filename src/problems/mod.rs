@@ -1,2 +1,12 @@
+pub mod acrobot;
+pub mod custom;
+pub mod frozen_lake;
 pub mod gym;
 pub mod iris;
+#[cfg(feature = "openml")]
+pub mod openml;
+pub mod presets;
+pub mod problem;
+pub mod pursuit;
+pub mod supervised;
+pub mod symbolic;
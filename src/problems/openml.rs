@@ -0,0 +1,396 @@
+//! Tabular classification datasets fetched from [OpenML](https://www.openml.org) by id, so new
+//! supervised problems do not need a hand-written input struct.
+//!
+//! Numeric attributes become the inputs and the target attribute the labels; other attributes (nominal
+//! features, strings, dates) are dropped, as are samples with missing features.
+use std::error::Error;
+
+use serde::{Deserialize, Serialize};
+use tokio::runtime::Runtime;
+
+use crate::{
+    core::engines::core_engine::{CoreIter, HyperParameters, HyperParametersBuilder},
+    extensions::classification::{Dataset, DatasetEngine},
+    problems::problem::program_parameters,
+    utils::{
+        loader::{load_table, Column, CsvLoadOptions, LoadReport, RowError},
+        random::update_seed,
+    },
+};
+
+pub const OPENML_API_LINK: &'static str = "https://www.openml.org/api/v1/json/data";
+
+/// The type of an ARFF attribute, as far as loading inputs is concerned.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum AttributeType {
+    /// `numeric`, `real` or `integer`.
+    Numeric,
+    /// One of the listed values.
+    Nominal(Vec<String>),
+    /// `string`, `date`, ...
+    Other,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Attribute {
+    pub name: String,
+    pub kind: AttributeType,
+}
+
+/// Removes the quotes around an ARFF name or value.
+fn unquote(token: &str) -> String {
+    let token = token.trim();
+
+    for quote in ['\'', '"'] {
+        if token.len() >= 2 && token.starts_with(quote) && token.ends_with(quote) {
+            return token[1..token.len() - 1].to_owned();
+        }
+    }
+
+    token.to_owned()
+}
+
+fn parse_attribute(declaration: &str) -> Result<Attribute, Box<dyn Error>> {
+    let declaration = declaration.trim();
+
+    let (name, kind) = match declaration.chars().next() {
+        Some(quote @ ('\'' | '"')) => {
+            let end = declaration[1..]
+                .find(quote)
+                .ok_or_else(|| format!("Unterminated attribute name in `{}`.", declaration))?;
+
+            (&declaration[..end + 2], &declaration[end + 2..])
+        }
+        _ => declaration
+            .split_once(char::is_whitespace)
+            .ok_or_else(|| format!("Attribute `{}` has no type.", declaration))?,
+    };
+
+    let kind = kind.trim();
+    let kind = if kind.starts_with('{') {
+        let values = kind
+            .trim_start_matches('{')
+            .trim_end_matches('}')
+            .split(',')
+            .map(unquote)
+            .collect();
+
+        AttributeType::Nominal(values)
+    } else {
+        match kind.to_lowercase().as_str() {
+            "numeric" | "real" | "integer" => AttributeType::Numeric,
+            _ => AttributeType::Other,
+        }
+    };
+
+    Ok(Attribute {
+        name: unquote(name),
+        kind,
+    })
+}
+
+/// Splits a dense ARFF file into its attributes and its data section (CSV).
+pub fn parse_arff(content: &str) -> Result<(Vec<Attribute>, &str), Box<dyn Error>> {
+    let mut attributes = vec![];
+    let mut offset = 0;
+
+    for line in content.split_inclusive('\n') {
+        offset += line.len();
+
+        let trimmed = line.trim();
+        let keyword = trimmed
+            .split_whitespace()
+            .next()
+            .unwrap_or("")
+            .to_lowercase();
+
+        match keyword.as_str() {
+            "@attribute" => attributes.push(parse_attribute(&trimmed["@attribute".len()..])?),
+            "@data" => {
+                let data = &content[offset..];
+
+                if data.trim_start().starts_with('{') {
+                    return Err("Sparse ARFF files are not supported.".into());
+                }
+
+                return Ok((attributes, data));
+            }
+            _ => {}
+        }
+    }
+
+    Err("The ARFF file has no `@data` section.".into())
+}
+
+/// A classification dataset read from OpenML.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct OpenMlDataset {
+    pub id: u64,
+    pub name: String,
+    /// Names of the numeric attributes, in input order.
+    pub feature_names: Vec<String>,
+    /// Values of the target attribute, in label order.
+    pub classes: Vec<String>,
+    pub dataset: Dataset,
+    /// Rows which could not be read or have missing features; lines count the samples of the data
+    /// section.
+    pub report: LoadReport,
+}
+
+impl OpenMlDataset {
+    /// Reads the ARFF `content` of a dataset, classifying `target`.
+    ///
+    /// Classes follow the declaration order of a nominal target, the sorted values of any other.
+    pub fn from_arff(
+        id: u64,
+        name: &str,
+        content: &str,
+        target: &str,
+    ) -> Result<Self, Box<dyn Error>> {
+        let (attributes, data) = parse_arff(content)?;
+
+        let target_idx = attributes
+            .iter()
+            .position(|attribute| attribute.name == target)
+            .ok_or_else(|| format!("Unknown target attribute `{}`.", target))?;
+        let feature_indices = (0..attributes.len())
+            .filter(|idx| *idx != target_idx && attributes[*idx].kind == AttributeType::Numeric)
+            .collect::<Vec<_>>();
+
+        if feature_indices.is_empty() {
+            return Err(format!("Dataset {} has no numeric features.", id).into());
+        }
+
+        let options = CsvLoadOptions {
+            quote: b'\'',
+            feature_columns: feature_indices.iter().copied().map(Column::Index).collect(),
+            label_column: Some(Column::Index(target_idx)),
+            missing_values: vec!["?".to_owned()],
+            ..Default::default()
+        };
+
+        // Comments are the only lines of the data section which are not samples.
+        let data = data
+            .lines()
+            .filter(|line| !line.trim().is_empty() && !line.trim_start().starts_with('%'))
+            .collect::<Vec<_>>()
+            .join("\n");
+
+        let (table, mut report) = load_table(&data, usize::MAX, &options)?;
+
+        let classes = match &attributes[target_idx].kind {
+            AttributeType::Nominal(values) => values.clone(),
+            _ => {
+                let mut values = table
+                    .labels
+                    .iter()
+                    .map(|label| unquote(label))
+                    .collect::<Vec<_>>();
+                values.sort();
+                values.dedup();
+                values
+            }
+        };
+
+        let mut features = vec![];
+        let mut labels = vec![];
+
+        for (idx, (row, label)) in table.features.into_iter().zip(table.labels).enumerate() {
+            let label = unquote(&label);
+
+            let class = match classes.iter().position(|class| *class == label) {
+                Some(class) => class,
+                None => {
+                    report.errors.push(RowError {
+                        line: idx as u64 + 1,
+                        cause: format!("Unknown or missing class `{}`.", label),
+                    });
+                    continue;
+                }
+            };
+
+            if row.iter().any(|value| value.is_nan()) {
+                report.errors.push(RowError {
+                    line: idx as u64 + 1,
+                    cause: "Missing features.".to_owned(),
+                });
+                continue;
+            }
+
+            features.push(row);
+            labels.push(class);
+        }
+
+        report.n_loaded = labels.len();
+
+        Ok(OpenMlDataset {
+            id,
+            name: name.to_owned(),
+            feature_names: feature_indices
+                .iter()
+                .map(|idx| attributes[*idx].name.clone())
+                .collect(),
+            classes,
+            dataset: Dataset::new(features, labels),
+            report,
+        })
+    }
+
+    pub fn n_inputs(&self) -> usize {
+        self.feature_names.len()
+    }
+
+    pub fn n_classes(&self) -> usize {
+        self.classes.len()
+    }
+
+    /// Default hyperparameters sized to the dataset, with the last `validation_fraction` of the
+    /// (shuffled) samples held out.
+    pub fn classification_parameters(&self, validation_fraction: f64) -> ClassificationParameters {
+        let hyper_parameters = HyperParametersBuilder::<DatasetEngine>::default()
+            .program_parameters(program_parameters(self.n_inputs(), self.n_classes()))
+            .n_trials(1)
+            .build()
+            .unwrap();
+
+        let (training, validation) = self.dataset.shuffled().split(validation_fraction);
+
+        ClassificationParameters {
+            hyper_parameters,
+            training,
+            validation,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct DatasetDescription {
+    name: String,
+    url: String,
+    default_target_attribute: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct DescriptionResponse {
+    data_set_description: DatasetDescription,
+}
+
+/// Fetches dataset `id`, classifying its default target attribute.
+pub async fn fetch_openml(id: u64) -> Result<OpenMlDataset, Box<dyn Error>> {
+    let response = reqwest::get(format!("{}/{}", OPENML_API_LINK, id))
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+    let description = serde_json::from_str::<DescriptionResponse>(&response)?.data_set_description;
+
+    let target = description
+        .default_target_attribute
+        .ok_or_else(|| format!("Dataset {} has no default target attribute.", id))?;
+    let content = reqwest::get(&description.url)
+        .await?
+        .error_for_status()?
+        .text()
+        .await?;
+
+    OpenMlDataset::from_arff(id, &description.name, &content, &target)
+}
+
+/// Blocking counterpart of [`fetch_openml`].
+pub fn load_openml(id: u64) -> Result<OpenMlDataset, Box<dyn Error>> {
+    Runtime::new()?.block_on(fetch_openml(id))
+}
+
+/// Everything needed to evolve classifiers on a loaded dataset.
+#[derive(Clone, Serialize, Deserialize)]
+pub struct ClassificationParameters {
+    pub hyper_parameters: HyperParameters<DatasetEngine>,
+    pub training: Dataset,
+    /// Empty without a validation split.
+    pub validation: Dataset,
+}
+
+impl ClassificationParameters {
+    /// Evolves on the training samples, validating the best individual of every generation on the
+    /// validation samples.
+    pub fn build_engine(&self) -> CoreIter<DatasetEngine> {
+        update_seed(self.hyper_parameters.seed);
+
+        let engine = CoreIter::with_trials(
+            self.hyper_parameters.clone(),
+            vec![],
+            vec![self.training.clone()],
+        );
+
+        match self.validation.labels.is_empty() {
+            true => engine,
+            false => engine.with_validation(vec![self.validation.clone()]),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+
+    use super::*;
+    use crate::core::engines::status_engine::{Status, StatusEngine};
+    use crate::utils::misc::VoidResultAnyError;
+
+    const ARFF: &str = "% A tiny dataset.\n\
+                        @RELATION 'threshold'\n\
+                        \n\
+                        @ATTRIBUTE 'x value' NUMERIC\n\
+                        @ATTRIBUTE colour {red,blue}\n\
+                        @ATTRIBUTE y real\n\
+                        @ATTRIBUTE class {'low','high'}\n\
+                        \n\
+                        @DATA\n\
+                        0.1,red,1,'low'\n\
+                        % A comment.\n\
+                        0.2,blue,?,low\n\
+                        0.8,blue,3,high\n\
+                        0.9,red,4,'high'\n";
+
+    #[test]
+    fn given_arff_when_loaded_then_numeric_attributes_are_inputs() -> VoidResultAnyError {
+        let loaded = OpenMlDataset::from_arff(0, "threshold", ARFF, "class")?;
+
+        assert_eq!(loaded.feature_names, vec!["x value", "y"]);
+        assert_eq!(loaded.classes, vec!["low", "high"]);
+        assert_eq!(
+            loaded.dataset.features,
+            vec![vec![0.1, 1.], vec![0.8, 3.], vec![0.9, 4.]]
+        );
+        assert_eq!(loaded.dataset.labels, vec![0, 1, 1]);
+        assert_eq!(loaded.report.n_loaded, 3);
+        assert_eq!(loaded.report.errors.len(), 1);
+
+        assert!(OpenMlDataset::from_arff(0, "threshold", ARFF, "label").is_err());
+        assert!(parse_arff("@RELATION empty\n@ATTRIBUTE x NUMERIC\n").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_classification_parameters_when_engine_is_built_then_samples_are_classified(
+    ) -> VoidResultAnyError {
+        let loaded = OpenMlDataset::from_arff(0, "threshold", ARFF, "class")?;
+
+        let mut parameters = loaded.classification_parameters(1. / 3.);
+        parameters.hyper_parameters.population_size = 10;
+        parameters.hyper_parameters.n_generations = 2;
+
+        assert_eq!(parameters.training.labels.len(), 2);
+        assert_eq!(parameters.validation.labels.len(), 1);
+
+        let mut engine = parameters.build_engine();
+        let generations = engine.by_ref().take(2).collect_vec();
+
+        assert_eq!(generations.len(), 2);
+        assert!(generations.iter().flatten().all(StatusEngine::evaluated));
+        assert_eq!(engine.validation_history().len(), 2);
+
+        Ok(())
+    }
+}
@@ -0,0 +1,247 @@
+//! Configuration presets of the built-in problems, shipped as data in `assets/presets`.
+//!
+//! Every preset file holds `defaults` applied to every problem, then the overrides of each problem keyed
+//! by [`Problem::NAME`]. Both are applied over [`Problem::default_hyper_parameters`], objects field by
+//! field, so a preset only lists what it changes.
+use std::error::Error;
+
+use clap::Parser;
+use serde_json::{Map, Value};
+
+use crate::core::engines::core_engine::HyperParameters;
+
+use super::problem::Problem;
+
+/// Few small generations, to check a problem runs and get a first idea of how it fares.
+const FAST: &str = include_str!("../../assets/presets/fast.json");
+/// Large populations evolved for long, for benchmarks.
+const THOROUGH: &str = include_str!("../../assets/presets/thorough.json");
+
+pub const PRESET_NAMES: [&str; 2] = ["fast", "thorough"];
+
+fn source(name: &str) -> Result<&'static str, Box<dyn Error>> {
+    match name {
+        "fast" => Ok(FAST),
+        "thorough" => Ok(THOROUGH),
+        _ => Err(format!(
+            "Unknown preset `{}`, expected one of {:?}.",
+            name, PRESET_NAMES
+        )
+        .into()),
+    }
+}
+
+/// Writes the fields of `overrides` into `value`, rejecting the fields `value` does not have so a
+/// misspelled parameter is not silently ignored.
+fn merge(value: &mut Value, overrides: &Value, path: &str) -> Result<(), Box<dyn Error>> {
+    match (value, overrides) {
+        (Value::Object(fields), Value::Object(overrides)) => {
+            for (key, field_override) in overrides {
+                let path = format!("{}.{}", path, key);
+                let field = fields
+                    .get_mut(key)
+                    .ok_or_else(|| format!("Unknown parameter `{}`.", path))?;
+
+                merge(field, field_override, &path)?;
+            }
+
+            Ok(())
+        }
+        (value, overrides) => {
+            *value = overrides.clone();
+            Ok(())
+        }
+    }
+}
+
+/// The fields of `given` differing from those of `defaults`, `None` when none does.
+fn changed(given: &Value, defaults: &Value) -> Option<Value> {
+    match (given, defaults) {
+        (Value::Object(given), Value::Object(defaults)) => {
+            let fields = given
+                .iter()
+                .filter_map(|(key, field)| {
+                    let changed = match defaults.get(key) {
+                        Some(default) => changed(field, default),
+                        None => Some(field.clone()),
+                    };
+
+                    changed.map(|field| (key.clone(), field))
+                })
+                .collect::<Map<String, Value>>();
+
+            Some(Value::Object(fields)).filter(|fields| fields != &Value::Object(Map::new()))
+        }
+        (given, defaults) => Some(given.clone()).filter(|given| given != defaults),
+    }
+}
+
+/// Writes the hyperparameters of `given` (e.g. parsed from the command line) differing from the
+/// command line defaults over `preset`, so the flags given next to `--preset` override it. A flag set
+/// to its default value cannot be told from an absent one and leaves the preset as is.
+pub fn override_preset<P>(
+    preset: HyperParameters<P>,
+    given: &HyperParameters<P>,
+) -> Result<HyperParameters<P>, Box<dyn Error>>
+where
+    P: Problem,
+{
+    let defaults = HyperParameters::<P>::try_parse_from([P::NAME])?;
+    let mut parameters = serde_json::to_value(preset)?;
+
+    if let Some(overrides) = changed(
+        &serde_json::to_value(given)?,
+        &serde_json::to_value(defaults)?,
+    ) {
+        merge(&mut parameters, &overrides, "command line")?;
+    }
+
+    let mut parameters: HyperParameters<P> = serde_json::from_value(parameters)?;
+    P::build_fitness_parameters(&mut parameters);
+
+    Ok(parameters)
+}
+
+/// The hyperparameters of the `name` preset of `P`, with the parameters dictated by the problem
+/// applied (see [`Problem::build_fitness_parameters`]).
+pub fn preset<P>(name: &str) -> Result<HyperParameters<P>, Box<dyn Error>>
+where
+    P: Problem,
+{
+    let mut presets: Map<String, Value> = serde_json::from_str(source(name)?)?;
+    let defaults = presets
+        .remove("defaults")
+        .unwrap_or_else(|| Value::Object(Map::new()));
+    let overrides = presets
+        .get("problems")
+        .and_then(|problems| problems.get(P::NAME))
+        .ok_or_else(|| format!("The `{}` preset has no entry for {}.", name, P::NAME))?;
+
+    let mut parameters = serde_json::to_value(P::default_hyper_parameters())?;
+    merge(&mut parameters, &defaults, name)?;
+    merge(&mut parameters, overrides, &format!("{}.{}", name, P::NAME))?;
+
+    let mut parameters: HyperParameters<P> = serde_json::from_value(parameters)?;
+    P::build_fitness_parameters(&mut parameters);
+
+    Ok(parameters)
+}
+
+#[cfg(test)]
+mod tests {
+    use gym_rs::envs::classical_control::{cartpole::CartPoleEnv, mountain_car::MountainCarEnv};
+
+    use super::*;
+    use crate::extensions::regression::RegressionEngine;
+    use crate::problems::{
+        acrobot::{Acrobot, ShapedAcrobot},
+        custom::{CustomEngine, CustomQEngine, Navigation},
+        frozen_lake::OneHotFrozenLake,
+        gym::{GymRsEngine, GymRsQEngine},
+        iris::IrisEngine,
+        symbolic::{Koza1, Nguyen1, Nguyen3, Nguyen4, Nguyen5, Nguyen6, Nguyen7, Nguyen8},
+    };
+    use crate::utils::misc::VoidResultAnyError;
+
+    fn check<P>() -> VoidResultAnyError
+    where
+        P: Problem,
+    {
+        let defaults = P::default_hyper_parameters();
+
+        for name in PRESET_NAMES {
+            let parameters = P::preset(name)?;
+
+            // The parameters dictated by the problem are applied over the preset.
+            assert_eq!(
+                parameters.default_fitness,
+                defaults.default_fitness,
+                "{} {}",
+                P::NAME,
+                name
+            );
+            assert!(parameters.population_size > 0 && parameters.n_generations > 0);
+            assert!(parameters.n_trials > 0);
+        }
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_built_in_problems_when_presets_are_loaded_then_every_problem_has_them(
+    ) -> VoidResultAnyError {
+        check::<GymRsEngine<CartPoleEnv>>()?;
+        check::<GymRsQEngine<CartPoleEnv>>()?;
+        check::<GymRsEngine<MountainCarEnv>>()?;
+        check::<GymRsQEngine<MountainCarEnv>>()?;
+        check::<CustomEngine<Acrobot>>()?;
+        check::<CustomEngine<ShapedAcrobot>>()?;
+        check::<CustomEngine<Navigation>>()?;
+        check::<CustomQEngine<Navigation>>()?;
+        check::<CustomEngine<OneHotFrozenLake>>()?;
+        check::<CustomQEngine<OneHotFrozenLake>>()?;
+        check::<IrisEngine>()?;
+        check::<RegressionEngine<Koza1>>()?;
+        check::<RegressionEngine<Nguyen1>>()?;
+        check::<RegressionEngine<Nguyen3>>()?;
+        check::<RegressionEngine<Nguyen4>>()?;
+        check::<RegressionEngine<Nguyen5>>()?;
+        check::<RegressionEngine<Nguyen6>>()?;
+        check::<RegressionEngine<Nguyen7>>()?;
+        check::<RegressionEngine<Nguyen8>>()?;
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_preset_when_loaded_then_overrides_apply_over_defaults() -> VoidResultAnyError {
+        let fast = GymRsEngine::<MountainCarEnv>::preset("fast")?;
+        assert_eq!(fast.population_size, 50);
+        assert_eq!(fast.n_trials, 5);
+        assert_eq!(fast.program_parameters.max_instructions, 10);
+        assert_eq!(fast.default_fitness, -200.);
+
+        let thorough = RegressionEngine::<Koza1>::preset("thorough")?;
+        assert_eq!(thorough.population_size, 500);
+        assert_eq!(thorough.n_trials, 20);
+
+        assert!(CustomEngine::<Navigation>::preset("slow").is_err());
+
+        let mut parameters =
+            serde_json::to_value(CustomEngine::<Navigation>::default_hyper_parameters())?;
+        let typo = serde_json::json!({ "populaton_size": 10 });
+        assert!(merge(&mut parameters, &typo, "test").is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_flags_next_to_preset_when_overridden_then_only_the_flags_given_replace_the_preset(
+    ) -> VoidResultAnyError {
+        type Regression = RegressionEngine<Koza1>;
+
+        let given = HyperParameters::<Regression>::try_parse_from([
+            "koza-1-lgp",
+            "--population-size",
+            "7",
+            "--max-instructions",
+            "15",
+        ])?;
+        let parameters = override_preset(Regression::preset("thorough")?, &given)?;
+
+        assert_eq!(parameters.population_size, 7);
+        assert_eq!(parameters.program_parameters.max_instructions, 15);
+        assert_eq!(parameters.n_trials, 20);
+
+        let unchanged = override_preset(
+            Regression::preset("thorough")?,
+            &HyperParameters::<Regression>::try_parse_from(["koza-1-lgp"])?,
+        )?;
+        assert_eq!(
+            serde_json::to_value(unchanged)?,
+            serde_json::to_value(Regression::preset("thorough")?)?
+        );
+
+        Ok(())
+    }
+}
@@ -0,0 +1,78 @@
+//! A common interface to the benchmark problems, so running one only takes its engine type.
+use std::error::Error;
+
+use crate::{
+    core::{
+        engines::core_engine::{Core, HyperParameters, HyperParametersBuilder},
+        instruction::{InstructionGeneratorParameters, InstructionGeneratorParametersBuilder},
+        program::{ProgramGeneratorParameters, ProgramGeneratorParametersBuilder},
+    },
+    extensions::q_learning::{QProgramGeneratorParameters, QProgramGeneratorParametersBuilder},
+};
+
+use super::presets::preset;
+
+/// A benchmark problem, identified by the engine solving it.
+pub trait Problem: Core + Sized {
+    /// Name of the problem on the command line and in benchmark directories.
+    const NAME: &'static str;
+
+    /// Hyperparameters a run of the problem starts from.
+    fn default_hyper_parameters() -> HyperParameters<Self>;
+
+    /// Overwrites the parameters dictated by the problem itself, e.g. the number of inputs and actions
+    /// of the programs and the fitness of a program which fails.
+    fn build_fitness_parameters(params: &mut HyperParameters<Self>);
+
+    /// Range of fitness values a plot of the problem should cover (see `asset_generator.py --y-range`).
+    fn plot_range() -> (f64, f64);
+
+    /// Hyperparameters of a preset shipped for the problem, `"fast"` or `"thorough"` (see
+    /// [`presets`](super::presets)).
+    fn preset(name: &str) -> Result<HyperParameters<Self>, Box<dyn Error>> {
+        preset(name)
+    }
+}
+
+pub fn set_dimensions(
+    parameters: &mut InstructionGeneratorParameters,
+    n_inputs: usize,
+    n_actions: usize,
+) {
+    parameters.n_inputs = n_inputs;
+    parameters.n_actions = n_actions;
+}
+
+pub fn program_parameters(n_inputs: usize, n_actions: usize) -> ProgramGeneratorParameters {
+    let instruction_generator_parameters = InstructionGeneratorParametersBuilder::default()
+        .n_inputs(n_inputs)
+        .n_actions(n_actions)
+        .build()
+        .unwrap();
+
+    ProgramGeneratorParametersBuilder::default()
+        .instruction_generator_parameters(instruction_generator_parameters)
+        .build()
+        .unwrap()
+}
+
+pub fn q_program_parameters(n_inputs: usize, n_actions: usize) -> QProgramGeneratorParameters {
+    QProgramGeneratorParametersBuilder::default()
+        .program_parameters(program_parameters(n_inputs, n_actions))
+        .build()
+        .unwrap()
+}
+
+/// Default hyperparameters around `program_parameters`, with the problem's fitness parameters applied.
+pub fn hyper_parameters<P>(program_parameters: P::ProgramParameters) -> HyperParameters<P>
+where
+    P: Problem,
+{
+    let mut params = HyperParametersBuilder::<P>::default()
+        .program_parameters(program_parameters)
+        .build()
+        .unwrap();
+
+    P::build_fitness_parameters(&mut params);
+    params
+}
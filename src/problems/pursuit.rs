@@ -0,0 +1,140 @@
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    extensions::coevolution::{Arena, Role},
+    utils::random::generator,
+};
+
+/// A pursuer (first player) chasing an evader (second player) on the unit square.
+///
+/// Observations: `[own x, own y, opponent x - own x, opponent y - own y]`.
+/// Actions: `0` up, `1` down, `2` left, `3` right, `4` stay.
+/// The pursuer is rewarded with the negative distance between the players every step, and the evader
+/// with the distance; a capture ends the game with a bonus for the pursuer.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+pub struct PursuitEvasion {
+    pub pursuer: [f64; 2],
+    pub evader: [f64; 2],
+}
+
+impl PursuitEvasion {
+    pub const PURSUER_SPEED: f64 = 0.06;
+    pub const EVADER_SPEED: f64 = 0.05;
+    pub const CAPTURE_RADIUS: f64 = 0.1;
+    pub const CAPTURE_BONUS: f64 = 10.;
+
+    pub fn distance(&self) -> f64 {
+        let dx = self.evader[0] - self.pursuer[0];
+        let dy = self.evader[1] - self.pursuer[1];
+
+        (dx * dx + dy * dy).sqrt()
+    }
+
+    fn displace(position: &mut [f64; 2], action: usize, speed: f64) {
+        let (dx, dy) = match action {
+            0 => (0., speed),
+            1 => (0., -speed),
+            2 => (-speed, 0.),
+            3 => (speed, 0.),
+            _ => (0., 0.),
+        };
+
+        position[0] = (position[0] + dx).clamp(0., 1.);
+        position[1] = (position[1] + dy).clamp(0., 1.);
+    }
+}
+
+impl Arena for PursuitEvasion {
+    const N_INPUTS: usize = 4;
+    const N_ACTIONS: usize = 5;
+    const EPISODE_LENGTH: usize = 100;
+
+    fn sample() -> Self {
+        PursuitEvasion {
+            pursuer: [
+                generator().gen_range(0.0..=1.),
+                generator().gen_range(0.0..=1.),
+            ],
+            evader: [
+                generator().gen_range(0.0..=1.),
+                generator().gen_range(0.0..=1.),
+            ],
+        }
+    }
+
+    fn observation(&self, role: Role) -> Vec<f64> {
+        let (own, other) = match role {
+            Role::First => (self.pursuer, self.evader),
+            Role::Second => (self.evader, self.pursuer),
+        };
+
+        vec![own[0], own[1], other[0] - own[0], other[1] - own[1]]
+    }
+
+    fn step(&mut self, first_action: usize, second_action: usize) -> (f64, f64, bool) {
+        Self::displace(&mut self.pursuer, first_action, Self::PURSUER_SPEED);
+        Self::displace(&mut self.evader, second_action, Self::EVADER_SPEED);
+
+        let distance = self.distance();
+
+        if distance < Self::CAPTURE_RADIUS {
+            return (Self::CAPTURE_BONUS, -Self::CAPTURE_BONUS, true);
+        }
+
+        (-distance, distance, false)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+
+    use super::*;
+    use crate::core::engines::core_engine::HyperParametersBuilder;
+    use crate::core::engines::status_engine::{Status, StatusEngine};
+    use crate::extensions::coevolution::{ArenaEngine, CoevolutionIter};
+    use crate::utils::misc::VoidResultAnyError;
+    use crate::utils::test::program_parameters_of_length;
+
+    #[test]
+    fn given_adjacent_players_when_stepped_then_pursuer_captures_evader() {
+        let mut arena = PursuitEvasion {
+            pursuer: [0.5, 0.5],
+            evader: [0.6, 0.5],
+        };
+
+        let (pursuer_reward, evader_reward, done) = arena.step(3, 4);
+
+        assert!(done);
+        assert_eq!(pursuer_reward, PursuitEvasion::CAPTURE_BONUS);
+        assert_eq!(evader_reward, -PursuitEvasion::CAPTURE_BONUS);
+    }
+
+    #[test]
+    fn pursuit_evasion_coevolution() -> VoidResultAnyError {
+        let program_parameters =
+            program_parameters_of_length(PursuitEvasion::N_INPUTS, PursuitEvasion::N_ACTIONS, 20);
+        let parameters = HyperParametersBuilder::<ArenaEngine<PursuitEvasion>>::default()
+            .program_parameters(program_parameters)
+            .default_fitness(-(PursuitEvasion::EPISODE_LENGTH as f64))
+            .population_size(10)
+            .n_generations(5)
+            .n_trials(2)
+            .seed(Some(7))
+            .build()?;
+
+        let generations = CoevolutionIter::new(parameters.clone(), parameters.clone(), 3)
+            .take(parameters.n_generations)
+            .collect_vec();
+
+        assert_eq!(generations.len(), parameters.n_generations);
+
+        for (pursuers, evaders) in generations {
+            assert!(pursuers.iter().all(StatusEngine::evaluated));
+            assert!(evaders.iter().all(StatusEngine::evaluated));
+        }
+
+        Ok(())
+    }
+}
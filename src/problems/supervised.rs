@@ -0,0 +1,142 @@
+//! Classification datasets exposed as RL tasks, so RL-oriented extensions (e.g. Q-learning) can be
+//! compared on supervised problems.
+use std::marker::PhantomData;
+
+use rand::Rng;
+
+use crate::{
+    extensions::classification::Dataset, problems::custom::Simulation, utils::random::generator,
+};
+
+/// A labelled dataset with a fixed number of features and classes.
+pub trait SupervisedTask: Clone + Send {
+    const N_INPUTS: usize;
+    const N_CLASSES: usize;
+
+    /// The samples episodes are drawn from; loaded once, e.g. through `lazy_static`.
+    fn dataset() -> &'static Dataset;
+}
+
+/// A single-step episode over a random sample of `T`: the action is the predicted class and the
+/// reward is `1` when it is correct, `0` otherwise.
+#[derive(Clone, Debug)]
+pub struct SupervisedEpisode<T> {
+    pub sample: usize,
+    correct: bool,
+    task: PhantomData<T>,
+}
+
+impl<T> SupervisedEpisode<T> {
+    pub fn new(sample: usize) -> Self {
+        SupervisedEpisode {
+            sample,
+            correct: false,
+            task: PhantomData,
+        }
+    }
+}
+
+impl<T> Simulation for SupervisedEpisode<T>
+where
+    T: SupervisedTask,
+{
+    const N_INPUTS: usize = T::N_INPUTS;
+    const N_ACTIONS: usize = T::N_CLASSES;
+    const EPISODE_LENGTH: usize = 1;
+
+    fn sample() -> Self {
+        Self::new(generator().gen_range(0..T::dataset().labels.len()))
+    }
+
+    fn observe(&self, idx: usize) -> f64 {
+        T::dataset().features[self.sample][idx]
+    }
+
+    fn step(&mut self, action: usize) -> (f64, bool) {
+        self.correct = T::dataset().labels[self.sample] == action;
+
+        (self.correct as usize as f64, true)
+    }
+
+    fn succeeded(&self) -> bool {
+        self.correct
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+    use lazy_static::lazy_static;
+
+    use super::*;
+    use crate::core::engines::core_engine::HyperParametersBuilder;
+    use crate::core::engines::generate_engine::{Generate, GenerateEngine};
+    use crate::core::engines::reset_engine::{Reset, ResetEngine};
+    use crate::core::engines::status_engine::{Status, StatusEngine};
+    use crate::core::environment::{RlState, State};
+    use crate::extensions::q_learning::QProgramGeneratorParametersBuilder;
+    use crate::problems::custom::{CustomQEngine, SimulationInput};
+    use crate::utils::misc::VoidResultAnyError;
+    use crate::utils::test::program_parameters_of_length;
+
+    lazy_static! {
+        static ref THRESHOLD: Dataset = Dataset::new(
+            (0..10).map(|idx| vec![idx as f64 / 10.]).collect(),
+            (0..10).map(|idx| (idx >= 5) as usize).collect(),
+        );
+    }
+
+    /// Class `1` above `0.5`.
+    #[derive(Clone, Debug)]
+    struct Threshold;
+
+    impl SupervisedTask for Threshold {
+        const N_INPUTS: usize = 1;
+        const N_CLASSES: usize = 2;
+
+        fn dataset() -> &'static Dataset {
+            &THRESHOLD
+        }
+    }
+
+    #[test]
+    fn given_sample_when_classified_then_episode_ends_with_correctness_reward() {
+        let mut input: SimulationInput<SupervisedEpisode<Threshold>> = GenerateEngine::generate(());
+        input.initial_state = SupervisedEpisode::new(7);
+        ResetEngine::reset(&mut input);
+
+        assert_eq!(input.get_value(0), 0.7);
+        assert_eq!(input.execute_action(1), 1.);
+        assert!(input.is_terminal());
+        assert!(input.succeeded());
+
+        ResetEngine::reset(&mut input);
+        assert_eq!(input.execute_action(0), 0.);
+        assert!(!input.succeeded());
+    }
+
+    #[test]
+    fn given_supervised_task_when_q_learning_then_accuracy_is_fitness() -> VoidResultAnyError {
+        let program_parameters =
+            program_parameters_of_length(Threshold::N_INPUTS, Threshold::N_CLASSES, 10);
+        let parameters =
+            HyperParametersBuilder::<CustomQEngine<SupervisedEpisode<Threshold>>>::default()
+                .program_parameters(
+                    QProgramGeneratorParametersBuilder::default()
+                        .program_parameters(program_parameters)
+                        .build()?,
+                )
+                .population_size(10)
+                .n_generations(3)
+                .n_trials(10)
+                .seed(Some(9))
+                .build()?;
+
+        let populations = parameters.build_engine().collect_vec();
+        let best = StatusEngine::get_fitness(populations.last().unwrap().first().unwrap());
+
+        assert!((0. ..=1.).contains(&best));
+
+        Ok(())
+    }
+}
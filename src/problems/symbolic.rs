@@ -0,0 +1,252 @@
+//! Classic symbolic regression benchmarks: Koza-1 and the univariate Nguyen functions, sampled as in
+//! their original definitions (20 uniform points).
+use rand::Rng;
+
+use crate::{
+    core::{
+        engines::core_engine::HyperParameters, instruction::Modes,
+        program::ProgramGeneratorParameters,
+    },
+    extensions::regression::{RegressionEngine, RegressionTask},
+    problems::problem::{hyper_parameters, program_parameters, set_dimensions, Problem},
+    utils::random::generator,
+};
+
+fn uniform(low: f64, high: f64) -> Vec<f64> {
+    vec![generator().gen_range(low..=high)]
+}
+
+/// `x^4 + x^3 + x^2 + x` over `[-1, 1]`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Koza1;
+
+impl RegressionTask for Koza1 {
+    const NAME: &'static str = "koza-1-lgp";
+    const N_INPUTS: usize = 1;
+
+    fn sample_input() -> Vec<f64> {
+        uniform(-1., 1.)
+    }
+
+    fn target(input: &[f64]) -> f64 {
+        let x = input[0];
+        x.powi(4) + x.powi(3) + x.powi(2) + x
+    }
+}
+
+/// `x^3 + x^2 + x` over `[-1, 1]`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Nguyen1;
+
+impl RegressionTask for Nguyen1 {
+    const NAME: &'static str = "nguyen-1-lgp";
+    const N_INPUTS: usize = 1;
+
+    fn sample_input() -> Vec<f64> {
+        uniform(-1., 1.)
+    }
+
+    fn target(input: &[f64]) -> f64 {
+        let x = input[0];
+        x.powi(3) + x.powi(2) + x
+    }
+}
+
+/// `x^5 + x^4 + x^3 + x^2 + x` over `[-1, 1]`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Nguyen3;
+
+impl RegressionTask for Nguyen3 {
+    const NAME: &'static str = "nguyen-3-lgp";
+    const N_INPUTS: usize = 1;
+
+    fn sample_input() -> Vec<f64> {
+        uniform(-1., 1.)
+    }
+
+    fn target(input: &[f64]) -> f64 {
+        let x = input[0];
+        x.powi(5) + x.powi(4) + x.powi(3) + x.powi(2) + x
+    }
+}
+
+/// `x^6 + x^5 + x^4 + x^3 + x^2 + x` over `[-1, 1]`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Nguyen4;
+
+impl RegressionTask for Nguyen4 {
+    const NAME: &'static str = "nguyen-4-lgp";
+    const N_INPUTS: usize = 1;
+
+    fn sample_input() -> Vec<f64> {
+        uniform(-1., 1.)
+    }
+
+    fn target(input: &[f64]) -> f64 {
+        let x = input[0];
+        x.powi(6) + x.powi(5) + x.powi(4) + x.powi(3) + x.powi(2) + x
+    }
+}
+
+/// `sin(x^2) cos(x) - 1` over `[-1, 1]`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Nguyen5;
+
+impl RegressionTask for Nguyen5 {
+    const NAME: &'static str = "nguyen-5-lgp";
+    const N_INPUTS: usize = 1;
+
+    fn sample_input() -> Vec<f64> {
+        uniform(-1., 1.)
+    }
+
+    fn target(input: &[f64]) -> f64 {
+        let x = input[0];
+        x.powi(2).sin() * x.cos() - 1.
+    }
+}
+
+/// `sin(x) + sin(x + x^2)` over `[-1, 1]`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Nguyen6;
+
+impl RegressionTask for Nguyen6 {
+    const NAME: &'static str = "nguyen-6-lgp";
+    const N_INPUTS: usize = 1;
+
+    fn sample_input() -> Vec<f64> {
+        uniform(-1., 1.)
+    }
+
+    fn target(input: &[f64]) -> f64 {
+        let x = input[0];
+        x.sin() + (x + x.powi(2)).sin()
+    }
+}
+
+/// `ln(x + 1) + ln(x^2 + 1)` over `[0, 2]`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Nguyen7;
+
+impl RegressionTask for Nguyen7 {
+    const NAME: &'static str = "nguyen-7-lgp";
+    const N_INPUTS: usize = 1;
+
+    fn sample_input() -> Vec<f64> {
+        uniform(0., 2.)
+    }
+
+    fn target(input: &[f64]) -> f64 {
+        let x = input[0];
+        (x + 1.).ln() + (x.powi(2) + 1.).ln()
+    }
+}
+
+/// `sqrt(x)` over `[0, 4]`.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct Nguyen8;
+
+impl RegressionTask for Nguyen8 {
+    const NAME: &'static str = "nguyen-8-lgp";
+    const N_INPUTS: usize = 1;
+
+    fn sample_input() -> Vec<f64> {
+        uniform(0., 4.)
+    }
+
+    fn target(input: &[f64]) -> f64 {
+        input[0].sqrt()
+    }
+}
+
+/// A single output register, fed by inputs taken as is and by constants in `[-1, 1]`.
+pub fn regression_program_parameters(n_inputs: usize) -> ProgramGeneratorParameters {
+    let mut parameters = program_parameters(n_inputs, 1);
+    parameters.instruction_generator_parameters.external_factor = 1.;
+    parameters.instruction_generator_parameters.modes = Modes::Immediate;
+
+    parameters
+}
+
+impl<T> Problem for RegressionEngine<T>
+where
+    T: RegressionTask,
+{
+    const NAME: &'static str = T::NAME;
+
+    fn default_hyper_parameters() -> HyperParameters<Self> {
+        hyper_parameters(regression_program_parameters(T::N_INPUTS))
+    }
+
+    fn build_fitness_parameters(params: &mut HyperParameters<Self>) {
+        set_dimensions(
+            &mut params.program_parameters.instruction_generator_parameters,
+            T::N_INPUTS,
+            1,
+        );
+        params.default_fitness = T::DEFAULT_FITNESS;
+    }
+
+    fn plot_range() -> (f64, f64) {
+        (T::DEFAULT_FITNESS, 0.)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+
+    use super::*;
+    use crate::core::engines::generate_engine::{Generate, GenerateEngine};
+    use crate::core::engines::status_engine::{Status, StatusEngine};
+    use crate::extensions::regression::RegressionInput;
+    use crate::utils::misc::VoidResultAnyError;
+    use crate::utils::random::update_seed;
+
+    fn approximate<T>(seed: u64) -> VoidResultAnyError
+    where
+        T: RegressionTask,
+    {
+        let mut parameters = RegressionEngine::<T>::default_hyper_parameters();
+        parameters.population_size = 100;
+        parameters.n_generations = 50;
+        parameters.seed = Some(seed);
+
+        let populations = parameters
+            .build_engine()
+            .take(parameters.n_generations)
+            .collect_vec();
+
+        let best = populations.last().unwrap().first().unwrap();
+        let fitness = populations
+            .iter()
+            .map(|population| StatusEngine::get_fitness(population.first().unwrap()))
+            .collect_vec();
+
+        // Survivors are kept as is, so the best fitness never decreases.
+        assert!(fitness.windows(2).all(|pair| pair[1] >= pair[0]));
+
+        update_seed(Some(seed + 1));
+        let held_out: RegressionInput<T> = GenerateEngine::generate(());
+
+        // Beats the best constant by a wide margin on points it was not evolved on.
+        assert!(best.mean_squared_error(&held_out) < 0.5 * held_out.variance());
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_koza_1_when_evolved_then_programs_approximate_the_target() -> VoidResultAnyError {
+        approximate::<Koza1>(11)
+    }
+
+    #[test]
+    fn given_nguyen_1_when_evolved_then_programs_approximate_the_target() -> VoidResultAnyError {
+        approximate::<Nguyen1>(12)
+    }
+
+    #[test]
+    fn given_nguyen_7_when_evolved_then_programs_approximate_the_target() -> VoidResultAnyError {
+        approximate::<Nguyen7>(13)
+    }
+}
@@ -6,17 +6,28 @@ use std::{
     path::{Path, PathBuf},
 };
 
-use crate::core::{
-    characteristics::{Load, Save},
-    engines::generate_engine::Generate,
-    engines::{
-        core_engine::{Core, HyperParameters},
-        freeze_engine::Freeze,
-        status_engine::Status,
+use crate::{
+    core::{
+        characteristics::{Load, Save},
+        engines::generate_engine::Generate,
+        engines::{
+            core_engine::{Core, HyperParameters},
+            fitness_engine::{Fitness, FitnessEngine},
+            freeze_engine::Freeze,
+            reset_engine::{Reset, ResetEngine},
+            status_engine::Status,
+        },
+        program::AsProgram,
     },
+    extensions::ensemble::Ensemble,
 };
 
-use super::misc::VoidResultAnyError;
+use super::{
+    misc::VoidResultAnyError,
+    pareto::{pareto_front, save_pareto_csv},
+    stats::PopulationStats,
+    usage::{save_usage_csv, usage_per_generation},
+};
 
 pub fn benchmark_prefix() -> String {
     env::var("BENCHMARK_PREFIX").expect("BENCHMARK_PREFIX must be set")
@@ -51,6 +62,7 @@ pub fn save_experiment<C>(
 ) -> VoidResultAnyError
 where
     C: Core,
+    C::Individual: AsProgram,
 {
     let best_path = create_path(
         Path::new(&benchmark_prefix())
@@ -97,6 +109,15 @@ where
         true,
     )?;
 
+    let stats_path = create_path(
+        Path::new(&benchmark_prefix())
+            .join(test_name)
+            .join("stats.json")
+            .to_str()
+            .unwrap(),
+        true,
+    )?;
+
     let last_population = populations.last().unwrap();
 
     let (mut worst, mut median, mut best) = populations
@@ -119,6 +140,67 @@ where
     best.save(best_path.to_str().unwrap())?;
     params.save(params_path.to_str().unwrap())?;
     populations.save(plot_path.to_str().unwrap())?;
+    populations
+        .iter()
+        .map(|population| PopulationStats::with_defaults::<C>(population, params.objective))
+        .collect_vec()
+        .save(stats_path.to_str().unwrap())?;
+    save_usage_csv(
+        &usage_per_generation(populations),
+        Path::new(&benchmark_prefix())
+            .join(test_name)
+            .join("usage.csv")
+            .to_str()
+            .unwrap(),
+    )?;
+    save_pareto_csv(
+        &pareto_front::<C>(populations, params.objective),
+        Path::new(&benchmark_prefix())
+            .join(test_name)
+            .join("pareto.csv")
+            .to_str()
+            .unwrap(),
+    )?;
+
+    Ok(())
+}
+
+/// Evolves `params.n_generations` generations of a run of `params`, saving them as the experiment
+/// named `test_name` along with their validation fitness when the run is validated.
+pub fn run_experiment<C>(
+    params: &HyperParameters<C>,
+    test_name: &str,
+) -> Result<Vec<Vec<C::Individual>>, Box<dyn Error>>
+where
+    C: Core,
+    C::Individual: AsProgram,
+{
+    let mut engine = params.build_engine();
+    let populations = engine.by_ref().take(params.n_generations).collect_vec();
+
+    save_experiment(&populations, params, test_name)?;
+    if !engine.validation_history().is_empty() {
+        save_validation(engine.validation_history(), test_name)?;
+    }
+
+    Ok(populations)
+}
+
+/// Saves the validation fitness of every generation next to the experiment named `test_name`, where
+/// it is plotted alongside the training fitness.
+pub fn save_validation(validation_history: &[f64], test_name: &str) -> VoidResultAnyError {
+    let validation_path = create_path(
+        Path::new(&benchmark_prefix())
+            .join(test_name)
+            .join("validation.json")
+            .to_str()
+            .unwrap(),
+        true,
+    )?;
+
+    validation_history
+        .to_vec()
+        .save(validation_path.to_str().unwrap())?;
 
     Ok(())
 }
@@ -145,3 +227,39 @@ where
 
     Ok((original_fitness, new_fitness))
 }
+
+/// Same as [`load_and_run_program`] for a saved [`Ensemble`], evaluated as the individuals of `C`.
+pub fn load_and_run_ensemble<C>(
+    ensemble_path: impl Into<PathBuf> + Clone,
+    n_trials: usize,
+    default_fitness: f64,
+) -> Result<(f64, f64), Box<dyn Error>>
+where
+    C: Core,
+    FitnessEngine: Fitness<Ensemble, C::State, C::FitnessMarker>,
+{
+    let mut ensemble = Ensemble::try_load(ensemble_path)?;
+    let original_fitness = ensemble.best_fitness();
+
+    let scores = repeat_with(|| C::Generate::generate(()))
+        .take(n_trials)
+        .map(|mut trial: C::State| {
+            ResetEngine::reset(&mut ensemble);
+            C::Reset::reset(&mut trial);
+
+            let score =
+                <FitnessEngine as Fitness<Ensemble, C::State, C::FitnessMarker>>::eval_fitness(
+                    &mut ensemble,
+                    &mut trial,
+                );
+            match score.is_finite() {
+                true => score,
+                false => default_fitness,
+            }
+        })
+        .collect_vec();
+
+    let new_fitness = scores.iter().sum::<f64>() / scores.len() as f64;
+
+    Ok((original_fitness, new_fitness))
+}
@@ -0,0 +1,522 @@
+//! Compares two runs, each repeated over several seeds: whether their difference is significant is
+//! judged on the final generation of every seed, and their progress is reported generation by
+//! generation.
+use std::{
+    error::Error,
+    path::{Path, PathBuf},
+    process::Command,
+};
+
+use csv::Writer;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    core::characteristics::Load,
+    utils::{benchmark_tools::create_path, stats::PopulationStats},
+};
+
+/// Up to this many (non-zero) differences, p-values are computed from the exact distribution of the
+/// signed-rank statistic rather than its normal approximation.
+pub const EXACT_MAX_N: usize = 30;
+
+/// The asset generator, whose `compare` subcommand overlays the fitness curves of two runs (see
+/// `scripts/requirements.txt` for the packages it needs).
+pub const ASSET_GENERATOR: &str =
+    concat!(env!("CARGO_MANIFEST_DIR"), "/scripts/asset_generator.py");
+
+/// Result of a two-sided Wilcoxon signed-rank test.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct WilcoxonTest {
+    /// Number of non-zero differences the test was ran on.
+    pub n: usize,
+    /// Sum of the ranks of the positive differences.
+    pub w_plus: f64,
+    /// Sum of the ranks of the negative differences.
+    pub w_minus: f64,
+    pub p_value: f64,
+    /// Matched-pairs rank-biserial correlation, within `[-1, 1]`.
+    pub rank_biserial: f64,
+}
+
+/// One-based ranks of `values`, ties sharing the average of their ranks.
+fn average_ranks(values: &[f64]) -> Vec<f64> {
+    let order = (0..values.len())
+        .sorted_by(|a, b| values[*a].total_cmp(&values[*b]))
+        .collect_vec();
+    let mut ranks = vec![0.; values.len()];
+
+    let mut start = 0;
+    while start < order.len() {
+        let mut end = start + 1;
+        while end < order.len() && values[order[end]] == values[order[start]] {
+            end += 1;
+        }
+
+        // Ranks `start + 1..=end` are shared.
+        let rank = (start + 1 + end) as f64 / 2.;
+        for idx in &order[start..end] {
+            ranks[*idx] = rank;
+        }
+
+        start = end;
+    }
+
+    ranks
+}
+
+/// Complementary error function (Abramowitz and Stegun 7.1.26, absolute error below `1.5e-7`).
+fn erfc(x: f64) -> f64 {
+    let z = x.abs();
+    let t = 1. / (1. + 0.3275911 * z);
+    let polynomial = t
+        * (0.254829592
+            + t * (-0.284496736 + t * (1.421413741 + t * (-1.453152027 + t * 1.061405429))));
+    let value = polynomial * (-z * z).exp();
+
+    if x >= 0. {
+        value
+    } else {
+        2. - value
+    }
+}
+
+fn normal_cdf(z: f64) -> f64 {
+    0.5 * erfc(-z / std::f64::consts::SQRT_2)
+}
+
+/// Two-sided Wilcoxon signed-rank test of whether `differences` are centered on zero. Zero and
+/// non-finite differences are dropped.
+pub fn wilcoxon_signed_rank(differences: &[f64]) -> WilcoxonTest {
+    let differences = differences
+        .iter()
+        .copied()
+        .filter(|difference| *difference != 0. && difference.is_finite())
+        .collect_vec();
+    let n = differences.len();
+
+    if n == 0 {
+        return WilcoxonTest {
+            n,
+            w_plus: 0.,
+            w_minus: 0.,
+            p_value: 1.,
+            rank_biserial: 0.,
+        };
+    }
+
+    let ranks = average_ranks(&differences.iter().map(|d| d.abs()).collect_vec());
+    let w_plus: f64 = differences
+        .iter()
+        .zip(&ranks)
+        .filter(|(difference, _)| **difference > 0.)
+        .map(|(_, rank)| rank)
+        .sum();
+    let total = (n * (n + 1)) as f64 / 2.;
+    let w_minus = total - w_plus;
+    let statistic = w_plus.min(w_minus);
+
+    let p_value = if n <= EXACT_MAX_N {
+        // Average ranks are multiples of one half, doubling them makes every subset sum an integer.
+        let doubled = ranks.iter().map(|rank| (rank * 2.) as usize).collect_vec();
+        let max_sum = doubled.iter().sum::<usize>();
+        let mut counts = vec![0f64; max_sum + 1];
+        counts[0] = 1.;
+
+        for rank in doubled {
+            for sum in (rank..=max_sum).rev() {
+                counts[sum] += counts[sum - rank];
+            }
+        }
+
+        let at_most = counts[..=((statistic * 2.) as usize)].iter().sum::<f64>();
+        2. * at_most / 2f64.powi(n as i32)
+    } else {
+        let n = n as f64;
+        let ties = ranks
+            .iter()
+            .counts_by(|rank| rank.to_bits())
+            .values()
+            .map(|t| (t.pow(3) - t) as f64)
+            .sum::<f64>();
+        let mean = n * (n + 1.) / 4.;
+        let variance = n * (n + 1.) * (2. * n + 1.) / 24. - ties / 48.;
+        let z = (statistic - mean + 0.5) / variance.sqrt();
+
+        2. * normal_cdf(z.min(0.))
+    };
+
+    WilcoxonTest {
+        n,
+        w_plus,
+        w_minus,
+        p_value: p_value.min(1.),
+        rank_biserial: (w_plus - w_minus) / total,
+    }
+}
+
+/// The statistics of both runs for one generation, averaged over their seeds.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GenerationComparison {
+    pub generation: usize,
+    pub best_a: f64,
+    pub best_b: f64,
+    pub median_a: f64,
+    pub median_b: f64,
+    pub mean_a: f64,
+    pub mean_b: f64,
+}
+
+/// How a statistic of the final generation of run B differs from that of run A, over their seeds.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct MetricComparison {
+    pub metric: String,
+    pub mean_a: f64,
+    pub mean_b: f64,
+    /// Mean of `b - a`.
+    pub mean_difference: f64,
+    /// Mean difference over the standard deviation of the differences (paired Cohen's d).
+    pub cohens_d: f64,
+    pub test: WilcoxonTest,
+}
+
+impl MetricComparison {
+    /// Compares the finite `(a, b)` pairs, failing with fewer than two of them.
+    fn new(metric: &str, pairs: &[(f64, f64)]) -> Result<Self, Box<dyn Error>> {
+        let pairs = pairs
+            .iter()
+            .copied()
+            .filter(|(a, b)| a.is_finite() && b.is_finite())
+            .collect_vec();
+
+        if pairs.len() < 2 {
+            return Err(format!(
+                "Comparing the {} fitness needs at least two seeds with a finite value, got {}.",
+                metric,
+                pairs.len()
+            )
+            .into());
+        }
+
+        let n = pairs.len() as f64;
+
+        let differences = pairs.iter().map(|(a, b)| b - a).collect_vec();
+        let mean_difference = differences.iter().sum::<f64>() / n;
+        let std = (differences
+            .iter()
+            .map(|difference| (difference - mean_difference).powi(2))
+            .sum::<f64>()
+            / (n - 1.))
+            .sqrt();
+
+        Ok(MetricComparison {
+            metric: metric.to_string(),
+            mean_a: pairs.iter().map(|(a, _)| a).sum::<f64>() / n,
+            mean_b: pairs.iter().map(|(_, b)| b).sum::<f64>() / n,
+            mean_difference,
+            cohens_d: mean_difference / std,
+            test: wilcoxon_signed_rank(&differences),
+        })
+    }
+}
+
+/// Two runs, the seeds of one paired with those of the other in order.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct RunComparison {
+    /// Number of seed pairs compared; extra seeds of either run are ignored.
+    pub n_seeds: usize,
+    /// The generations every seed went through.
+    pub generations: Vec<GenerationComparison>,
+    pub metrics: Vec<MetricComparison>,
+}
+
+/// Mean of `statistic` over the `generation` of every seed.
+fn mean_over_seeds(
+    seeds: &[Vec<PopulationStats>],
+    generation: usize,
+    statistic: fn(&PopulationStats) -> f64,
+) -> f64 {
+    seeds
+        .iter()
+        .map(|stats| statistic(&stats[generation]))
+        .sum::<f64>()
+        / seeds.len() as f64
+}
+
+impl RunComparison {
+    /// Compares the final generation of the seeds of run A with that of the seeds of run B, each seed
+    /// being the statistics of every generation of one run. Fails with fewer than two seed pairs, as a
+    /// single pair says nothing of the variance between seeds.
+    pub fn new(
+        run_a: &[Vec<PopulationStats>],
+        run_b: &[Vec<PopulationStats>],
+    ) -> Result<Self, Box<dyn Error>> {
+        let n_seeds = run_a.len().min(run_b.len());
+        if n_seeds < 2 {
+            return Err(format!(
+                "Comparing runs needs at least two seeds of each, got {} and {}.",
+                run_a.len(),
+                run_b.len()
+            )
+            .into());
+        }
+
+        let (run_a, run_b) = (&run_a[..n_seeds], &run_b[..n_seeds]);
+        if run_a.iter().chain(run_b).any(Vec::is_empty) {
+            return Err("Every seed needs at least one generation.".into());
+        }
+
+        let n_generations = run_a.iter().chain(run_b).map(Vec::len).min().unwrap();
+        let generations = (0..n_generations)
+            .map(|generation| GenerationComparison {
+                generation,
+                best_a: mean_over_seeds(run_a, generation, |stats| stats.best),
+                best_b: mean_over_seeds(run_b, generation, |stats| stats.best),
+                median_a: mean_over_seeds(run_a, generation, |stats| stats.median),
+                median_b: mean_over_seeds(run_b, generation, |stats| stats.median),
+                mean_a: mean_over_seeds(run_a, generation, |stats| stats.mean),
+                mean_b: mean_over_seeds(run_b, generation, |stats| stats.mean),
+            })
+            .collect_vec();
+
+        let metric = |name: &str, statistic: fn(&PopulationStats) -> f64| {
+            let pairs = run_a
+                .iter()
+                .zip(run_b)
+                .map(|(a, b)| (statistic(a.last().unwrap()), statistic(b.last().unwrap())))
+                .collect_vec();
+
+            MetricComparison::new(name, &pairs)
+        };
+        let metrics = vec![
+            metric("best", |stats| stats.best)?,
+            metric("median", |stats| stats.median)?,
+            metric("mean", |stats| stats.mean)?,
+        ];
+
+        Ok(RunComparison {
+            n_seeds,
+            generations,
+            metrics,
+        })
+    }
+
+    /// The `stats.json` of every seed of the run at `dir`: each subdirectory holding one, in name
+    /// order, or else `dir` itself.
+    pub fn load_seeds(dir: &Path) -> Result<Vec<Vec<PopulationStats>>, Box<dyn Error>> {
+        let mut seed_dirs = std::fs::read_dir(dir)?
+            .map(|entry| entry.map(|entry| entry.path()))
+            .collect::<Result<Vec<_>, _>>()?
+            .into_iter()
+            .filter(|path| path.join("stats.json").is_file())
+            .collect_vec();
+        seed_dirs.sort();
+
+        if seed_dirs.is_empty() {
+            seed_dirs.push(dir.to_path_buf());
+        }
+
+        seed_dirs
+            .iter()
+            .map(|seed_dir| Vec::<PopulationStats>::try_load(seed_dir.join("stats.json")))
+            .collect()
+    }
+
+    /// Compares two run directories written by `save_experiment` (see [`RunComparison::load_seeds`]).
+    pub fn load(run_a: &Path, run_b: &Path) -> Result<Self, Box<dyn Error>> {
+        RunComparison::new(
+            &RunComparison::load_seeds(run_a)?,
+            &RunComparison::load_seeds(run_b)?,
+        )
+    }
+
+    pub fn to_markdown(&self, label_a: &str, label_b: &str) -> String {
+        let mut report = format!(
+            "# {} vs {}\n\n{} seeds compared on their final generation ({} aligned generations), \
+             differences are B - A.\n\n",
+            label_a,
+            label_b,
+            self.n_seeds,
+            self.generations.len()
+        );
+
+        report.push_str("| Metric | Mean A | Mean B | Mean difference | Cohen's d | Rank-biserial | W+ | W- | p-value |\n");
+        report.push_str("|---|---|---|---|---|---|---|---|---|\n");
+
+        for metric in &self.metrics {
+            report.push_str(&format!(
+                "| {} | {:.4} | {:.4} | {:.4} | {:.3} | {:.3} | {} | {} | {:.4} |\n",
+                metric.metric,
+                metric.mean_a,
+                metric.mean_b,
+                metric.mean_difference,
+                metric.cohens_d,
+                metric.test.rank_biserial,
+                metric.test.w_plus,
+                metric.test.w_minus,
+                metric.test.p_value
+            ));
+        }
+
+        report
+    }
+
+    /// Writes the aligned statistics of every generation, one row per generation.
+    pub fn save_csv(&self, path: &str) -> Result<(), Box<dyn Error>> {
+        create_path(path, true)?;
+
+        let mut writer = Writer::from_path(path)?;
+
+        for generation in &self.generations {
+            writer.serialize(generation)?;
+        }
+
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Writes `compare.csv` to `dir`, then overlays the best and median fitness of both runs from it
+    /// with the asset generator, returning the path of the figure (`<name of dir>_compare.svg`, next
+    /// to the table).
+    pub fn save_plot(&self, dir: &Path) -> Result<PathBuf, Box<dyn Error>> {
+        self.save_csv(dir.join("compare.csv").to_str().unwrap())?;
+
+        let status = Command::new("python3")
+            .arg(ASSET_GENERATOR)
+            .arg("--input")
+            .arg(dir)
+            .arg("--output")
+            .arg(dir)
+            .args(["--format", "svg", "compare"])
+            .status()
+            .map_err(|error| format!("Could not run `{}`: {}.", ASSET_GENERATOR, error))?;
+
+        if !status.success() {
+            return Err(format!(
+                "`{}` could not plot `{}` ({}).",
+                ASSET_GENERATOR,
+                dir.display(),
+                status
+            )
+            .into());
+        }
+
+        let name = dir
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default();
+
+        Ok(dir.join(format!("{}_compare.svg", name)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::misc::VoidResultAnyError;
+    use crate::utils::stats::Histogram;
+    use crate::utils::test::temp_dir;
+
+    #[test]
+    fn given_tied_values_when_ranked_then_ties_share_the_average_rank() {
+        assert_eq!(average_ranks(&[3., 1., 3., 2.]), vec![3.5, 1., 3.5, 2.]);
+    }
+
+    #[test]
+    fn given_differences_when_wilcoxon_then_exact_p_value_is_computed() {
+        // Every difference is positive: the most extreme of the 2^8 sign assignments on either side.
+        let test = wilcoxon_signed_rank(&[1., 2., 3., 4., 5., 6., 7., 8.]);
+
+        assert_eq!(test.n, 8);
+        assert_eq!(test.w_plus, 36.);
+        assert_eq!(test.w_minus, 0.);
+        assert_eq!(test.rank_biserial, 1.);
+        assert!((test.p_value - 2. / 256.).abs() < 1e-12);
+
+        let balanced = wilcoxon_signed_rank(&[1., -1., 2., -2., 0.]);
+        assert_eq!(balanced.n, 4);
+        assert_eq!(balanced.p_value, 1.);
+    }
+
+    #[test]
+    fn given_many_differences_when_wilcoxon_then_normal_approximation_is_used() {
+        let differences = (1..=40).map(|d| d as f64).collect_vec();
+        let test = wilcoxon_signed_rank(&differences);
+
+        assert!(test.n > EXACT_MAX_N);
+        assert!(test.p_value < 1e-6);
+        assert!((normal_cdf(0.) - 0.5).abs() < 1e-7);
+        assert!((normal_cdf(1.96) - 0.975).abs() < 1e-4);
+    }
+
+    fn stats(best: f64) -> PopulationStats {
+        PopulationStats {
+            size: 1,
+            n_valid: 1,
+            best,
+            median: best,
+            worst: best,
+            mean: best,
+            std: 0.,
+            quantiles: vec![],
+            histogram: Histogram::new(&[best], 1),
+            mean_length: 1.,
+            mean_effective_length: 1.,
+            diversity: 1.,
+        }
+    }
+
+    #[test]
+    fn given_seeds_of_two_runs_when_compared_then_final_generations_are_paired(
+    ) -> VoidResultAnyError {
+        // Three seeds each, B ending 1, 2 and 3 above A; the longer seed still ends on its own final
+        // generation, but is cut to two generations when aligned.
+        let run_a = vec![
+            vec![stats(0.), stats(1.)],
+            vec![stats(0.), stats(1.), stats(2.)],
+            vec![stats(0.), stats(3.)],
+        ];
+        let run_b = vec![
+            vec![stats(0.), stats(2.)],
+            vec![stats(1.), stats(4.)],
+            vec![stats(2.), stats(6.)],
+        ];
+
+        let comparison = RunComparison::new(&run_a, &run_b)?;
+
+        assert_eq!(comparison.n_seeds, 3);
+        assert_eq!(comparison.generations.len(), 2);
+        assert_eq!(comparison.generations[0].best_b, 1.);
+        assert_eq!(comparison.generations[1].best_b, 4.);
+
+        let best = &comparison.metrics[0];
+        assert_eq!(
+            (best.mean_a, best.mean_b, best.mean_difference),
+            (2., 4., 2.)
+        );
+        assert_eq!(best.test.n, 3);
+        assert_eq!(best.test.w_minus, 0.);
+        assert!(comparison.to_markdown("a", "b").contains("3 seeds"));
+
+        assert!(RunComparison::new(&run_a[..1], &run_b).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_compared_runs_when_plot_is_saved_then_the_overlay_is_written_next_to_the_table(
+    ) -> VoidResultAnyError {
+        let run_a = vec![vec![stats(0.), stats(1.)], vec![stats(0.), stats(3.)]];
+        let run_b = vec![vec![stats(0.), stats(2.)], vec![stats(2.), stats(6.)]];
+        let dir = temp_dir("compare");
+
+        let figure = RunComparison::new(&run_a, &run_b)?.save_plot(&dir)?;
+
+        assert!(dir.join("compare.csv").is_file());
+        assert_eq!(figure.parent(), Some(dir.as_path()));
+        assert!(std::fs::read_to_string(figure)?.contains("<svg"));
+
+        Ok(())
+    }
+}
@@ -0,0 +1,164 @@
+//! A live terminal dashboard for babysitting long runs, drawn on stderr so the stdout protocol (one best
+//! fitness per generation) is left untouched.
+use std::{
+    io::{self, Stderr},
+    time::Instant,
+};
+
+use crossterm::{
+    cursor::{Hide, Show},
+    execute,
+    terminal::{EnterAlternateScreen, LeaveAlternateScreen},
+};
+use ratatui::{
+    backend::CrosstermBackend,
+    layout::{Constraint, Direction, Layout},
+    widgets::{Block, Borders, Paragraph, Sparkline},
+    Terminal,
+};
+
+use crate::{
+    core::{
+        batch::Row,
+        engines::{
+            core_engine::Core,
+            reset_engine::{Reset, ResetEngine},
+            status_engine::Status,
+        },
+        program::AsProgram,
+    },
+    utils::stats::structural_diversity,
+};
+
+/// Height of the sparkline, in the resolution of the bars.
+const SPARKLINE_RESOLUTION: f64 = 100.;
+
+pub struct Dashboard {
+    terminal: Terminal<CrosstermBackend<Stderr>>,
+    best_fitness: Vec<f64>,
+    started: Instant,
+    trace_input: Option<Vec<f64>>,
+}
+
+impl Dashboard {
+    pub fn new() -> io::Result<Self> {
+        let mut stderr = io::stderr();
+        execute!(stderr, EnterAlternateScreen, Hide)?;
+
+        Ok(Dashboard {
+            terminal: Terminal::new(CrosstermBackend::new(stderr))?,
+            best_fitness: vec![],
+            started: Instant::now(),
+            trace_input: None,
+        })
+    }
+
+    /// Shows the registers after every instruction of the best individual when it runs over `input`,
+    /// instead of its bare instructions.
+    pub fn with_trace_input(mut self, input: Option<Vec<f64>>) -> Self {
+        self.trace_input = input;
+        self
+    }
+
+    /// Records the next generation's ranked population and redraws the dashboard.
+    pub fn update<C>(&mut self, population: &[C::Individual]) -> io::Result<()>
+    where
+        C: Core,
+        C::Individual: AsProgram,
+    {
+        let best = match population.first() {
+            Some(best) => best,
+            None => return Ok(()),
+        };
+
+        self.best_fitness.push(C::Status::get_fitness(best));
+
+        let finite = self
+            .best_fitness
+            .iter()
+            .copied()
+            .filter(|fitness| fitness.is_finite());
+        let lower = finite.clone().fold(f64::INFINITY, f64::min);
+        let upper = finite.fold(f64::NEG_INFINITY, f64::max);
+        let range = if upper > lower { upper - lower } else { 1. };
+
+        let bars = self
+            .best_fitness
+            .iter()
+            .map(|fitness| match fitness.is_finite() {
+                true => ((fitness - lower) / range * SPARKLINE_RESOLUTION) as u64,
+                false => 0,
+            })
+            .collect::<Vec<_>>();
+
+        let measured = match C::Status::get_metadata(best) {
+            Some(metadata) => format!(
+                " (± {:.4} over {} trials, {} successes)",
+                metadata.std(),
+                metadata.n_trials,
+                metadata.n_successes
+            ),
+            None => String::new(),
+        };
+        let summary = format!(
+            "generation: {}    best: {:.4}{}    diversity: {:.2}    elapsed: {:.0?}",
+            self.best_fitness.len() - 1,
+            C::Status::get_fitness(best),
+            measured,
+            structural_diversity(population),
+            self.started.elapsed()
+        );
+        let disassembly = match &self.trace_input {
+            Some(input) if input.len() >= best.as_program().n_inputs_read() => {
+                let mut program = best.as_program().clone();
+                ResetEngine::reset(&mut program.registers);
+
+                program
+                    .exec_traced(&Row(input))
+                    .iter()
+                    .map(ToString::to_string)
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            }
+            _ => best.as_program().to_string(),
+        };
+
+        self.terminal.draw(|frame| {
+            let areas = Layout::default()
+                .direction(Direction::Vertical)
+                .constraints([
+                    Constraint::Length(3),
+                    Constraint::Length(8),
+                    Constraint::Min(0),
+                ])
+                .split(frame.size());
+
+            frame.render_widget(
+                Paragraph::new(summary).block(Block::default().title("Run").borders(Borders::ALL)),
+                areas[0],
+            );
+            frame.render_widget(
+                Sparkline::default()
+                    .block(Block::default().title("Best fitness").borders(Borders::ALL))
+                    .data(&bars[bars.len().saturating_sub(areas[1].width as usize)..]),
+                areas[1],
+            );
+            frame.render_widget(
+                Paragraph::new(disassembly).block(
+                    Block::default()
+                        .title("Best individual")
+                        .borders(Borders::ALL),
+                ),
+                areas[2],
+            );
+        })?;
+
+        Ok(())
+    }
+}
+
+impl Drop for Dashboard {
+    fn drop(&mut self) {
+        let _ = execute!(self.terminal.backend_mut(), LeaveAlternateScreen, Show);
+    }
+}
@@ -0,0 +1,216 @@
+//! Differences between two snapshots of a population (e.g. generations `N` and `N + 1`), telling apart
+//! survivors, clones and offspring through the ids and origins of the programs.
+use std::{collections::HashSet, error::Error, fmt};
+
+use itertools::Itertools;
+use uuid::Uuid;
+
+use crate::{
+    core::{
+        population::Population,
+        program::{AsProgram, Origin, Program},
+    },
+    extensions::{organism::Organism, q_learning::QProgram},
+};
+
+/// What happened between two snapshots: every individual of the later one is accounted for once.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PopulationDiff {
+    /// Kept as is.
+    pub survived: Vec<Uuid>,
+    /// Copies of an individual which also survived.
+    pub cloned: Vec<Uuid>,
+    /// `(child, parent)` pairs.
+    pub mutated: Vec<(Uuid, Uuid)>,
+    /// `(child, (first parent, second parent))` pairs.
+    pub crossed_over: Vec<(Uuid, (Uuid, Uuid))>,
+    /// Generated from scratch, e.g. by a restart.
+    pub generated: Vec<Uuid>,
+    /// Individuals of the earlier snapshot missing from the later one.
+    pub dropped: Vec<Uuid>,
+}
+
+impl PopulationDiff {
+    pub fn new<I>(before: &[I], after: &[I]) -> Self
+    where
+        I: AsProgram,
+    {
+        let before_ids = before
+            .iter()
+            .map(|individual| individual.as_program().id)
+            .collect::<HashSet<_>>();
+        let after_ids = after
+            .iter()
+            .map(|individual| individual.as_program().id)
+            .collect::<HashSet<_>>();
+
+        let mut diff = PopulationDiff::default();
+        let mut seen = HashSet::new();
+
+        for program in after.iter().map(AsProgram::as_program) {
+            let id = program.id;
+
+            if before_ids.contains(&id) {
+                match seen.insert(id) {
+                    true => diff.survived.push(id),
+                    false => diff.cloned.push(id),
+                }
+                continue;
+            }
+
+            match program.origin {
+                Origin::Generated => diff.generated.push(id),
+                Origin::Mutation { parent } => diff.mutated.push((id, parent)),
+                Origin::Crossover { parents } => diff.crossed_over.push((id, parents)),
+            }
+        }
+
+        diff.dropped = before
+            .iter()
+            .map(|individual| individual.as_program().id)
+            .filter(|id| !after_ids.contains(id))
+            .unique()
+            .collect();
+
+        diff
+    }
+}
+
+impl fmt::Display for PopulationDiff {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "survived: {}", self.survived.len())?;
+        writeln!(f, "cloned: {}", self.cloned.len())?;
+        writeln!(f, "mutated: {}", self.mutated.len())?;
+        writeln!(f, "crossed over: {}", self.crossed_over.len())?;
+        writeln!(f, "generated: {}", self.generated.len())?;
+        writeln!(f, "dropped: {}", self.dropped.len())?;
+
+        for (child, parent) in &self.mutated {
+            writeln!(f, "{} <- mutation of {}", child, parent)?;
+        }
+
+        for (child, (first, second)) in &self.crossed_over {
+            writeln!(f, "{} <- crossover of {} and {}", child, first, second)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// A line diff of the instructions of two programs: kept lines start with two spaces, removed lines
+/// with `- ` and added lines with `+ `.
+pub fn instruction_diff(before: &Program, after: &Program) -> String {
+    let before = before.to_string();
+    let after = after.to_string();
+    let before = before.lines().collect_vec();
+    let after = after.lines().collect_vec();
+
+    // Longest common subsequence of the lines, from the end of both programs.
+    let mut common = vec![vec![0usize; after.len() + 1]; before.len() + 1];
+    for i in (0..before.len()).rev() {
+        for j in (0..after.len()).rev() {
+            common[i][j] = match before[i] == after[j] {
+                true => common[i + 1][j + 1] + 1,
+                false => common[i + 1][j].max(common[i][j + 1]),
+            };
+        }
+    }
+
+    let mut lines = vec![];
+    let (mut i, mut j) = (0, 0);
+    while i < before.len() || j < after.len() {
+        if i < before.len() && j < after.len() && before[i] == after[j] {
+            lines.push(format!("  {}", before[i]));
+            i += 1;
+            j += 1;
+        } else if i < before.len() && (j == after.len() || common[i + 1][j] >= common[i][j + 1]) {
+            lines.push(format!("- {}", before[i]));
+            i += 1;
+        } else {
+            lines.push(format!("+ {}", after[j]));
+            j += 1;
+        }
+    }
+
+    lines.join("\n")
+}
+
+/// The programs of a saved population of [`Program`]s, [`QProgram`]s or [`Organism`]s.
+pub fn load_programs(path: &str) -> Result<Population<Program>, Box<dyn Error>> {
+    if let Ok(population) = Population::<Program>::load(path) {
+        return Ok(population);
+    }
+
+    let (generation, programs) = match Population::<QProgram>::load(path) {
+        Ok(population) => (
+            population.generation,
+            population
+                .individuals
+                .into_iter()
+                .map(|individual| individual.program)
+                .collect(),
+        ),
+        Err(_) => {
+            let population = Population::<Organism>::load(path)?;
+            (
+                population.generation,
+                population
+                    .individuals
+                    .iter()
+                    .map(|individual| individual.as_program().clone())
+                    .collect(),
+            )
+        }
+    };
+
+    Ok(Population::new(generation, programs))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::engines::breed_engine::{Breed, BreedEngine};
+    use crate::core::engines::mutate_engine::{Mutate, MutateEngine};
+    use crate::problems::problem::program_parameters;
+    use crate::utils::misc::VoidResultAnyError;
+
+    #[test]
+    fn given_consecutive_generations_when_diffed_then_offspring_are_traced_to_their_parents(
+    ) -> VoidResultAnyError {
+        let program_parameters = program_parameters(1, 2);
+
+        let first = Program::parse("r0 = r0 + in0; r1 = r1 - in0", program_parameters)?;
+        let second = Program::parse("r1 = r1 * in0; r0 = r0 / in0", program_parameters)?;
+
+        let mut mutated = first.clone();
+        MutateEngine::mutate(&mut mutated, program_parameters);
+        let (child, _) = BreedEngine::two_point_crossover(&first, &second);
+
+        let before = vec![first.clone(), second.clone()];
+        let after = vec![first.clone(), first.clone(), mutated.clone(), child.clone()];
+        let diff = PopulationDiff::new(&before, &after);
+
+        assert_eq!(diff.survived, vec![first.id]);
+        assert_eq!(diff.cloned, vec![first.id]);
+        assert_eq!(diff.mutated, vec![(mutated.id, first.id)]);
+        assert_eq!(diff.crossed_over, vec![(child.id, (first.id, second.id))]);
+        assert_eq!(diff.dropped, vec![second.id]);
+
+        let edited = Program::parse("r0 = r0 + in0; r1 = r1 * in0", program_parameters)?;
+        let lines = instruction_diff(&first, &edited);
+        assert_eq!(
+            lines.lines().filter(|line| line.starts_with("  ")).count(),
+            1
+        );
+        assert_eq!(
+            lines.lines().filter(|line| line.starts_with("- ")).count(),
+            1
+        );
+        assert_eq!(
+            lines.lines().filter(|line| line.starts_with("+ ")).count(),
+            1
+        );
+
+        Ok(())
+    }
+}
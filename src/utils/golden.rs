@@ -0,0 +1,240 @@
+//! Golden episodes: the actions of a program on fixed, seeded episodes, recorded once and replayed after
+//! a refactor to catch silent changes in the semantics of the interpreter (or of the environment).
+//! The golden files checked by the tests are committed under `assets/fixtures/golden`.
+use std::{env, error::Error, fmt, path::Path};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    core::{
+        characteristics::{Load, Save},
+        engines::{core_engine::Core, generate_engine::Generate, reset_engine::Reset},
+        environment::RlState,
+        program::Program,
+        registers::TieBreak,
+    },
+    extensions::interactive::repeat_action,
+    utils::random::update_seed,
+};
+
+/// When set, [`check_golden`] re-records golden files instead of checking them.
+pub const UPDATE_GOLDEN_VAR: &str = "UPDATE_GOLDEN";
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GoldenEpisode {
+    /// Seed the initial state (and any randomness of the episode) is generated from.
+    pub seed: u64,
+    /// The action taken at every decision; the episode stops early if the registers overflow.
+    pub actions: Vec<usize>,
+    pub score: f64,
+}
+
+/// The first decision at which a replayed episode departs from its golden recording, `None` standing
+/// for an episode which already ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Divergence {
+    pub seed: u64,
+    pub step: usize,
+    pub expected: Option<usize>,
+    pub actual: Option<usize>,
+}
+
+impl fmt::Display for Divergence {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "episode {} diverged at step {}: expected {:?}, found {:?}",
+            self.seed, self.step, self.expected, self.actual
+        )
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GoldenEpisodes {
+    pub program: Program,
+    pub episodes: Vec<GoldenEpisode>,
+}
+
+fn play<C>(program: &mut Program, mut state: C::State) -> (Vec<usize>, f64)
+where
+    C: Core<Individual = Program>,
+    C::State: RlState,
+{
+    <C::Reset as Reset<Program>>::reset(program);
+    <C::Reset as Reset<C::State>>::reset(&mut state);
+
+    let mut actions = vec![];
+    let mut score = 0.;
+
+    while let Some(state) = state.get() {
+        program.run(state);
+
+        let action = match program.select_action(TieBreak::First) {
+            Some(action) => action,
+            None => break,
+        };
+
+        actions.push(action);
+        score += repeat_action(state, action, program.frame_skip).reward;
+    }
+
+    (actions, score)
+}
+
+impl GoldenEpisodes {
+    /// Plays `program` on the episodes generated from each of `seeds`.
+    ///
+    /// Reseeds the generator of the current thread before every episode.
+    pub fn record<C>(program: &Program, seeds: &[u64]) -> Self
+    where
+        C: Core<Individual = Program>,
+        C::State: RlState,
+    {
+        let episodes = seeds
+            .iter()
+            .map(|seed| {
+                update_seed(Some(*seed));
+                let state = C::Generate::generate(());
+                let (actions, score) = play::<C>(&mut program.clone(), state);
+
+                GoldenEpisode {
+                    seed: *seed,
+                    actions,
+                    score,
+                }
+            })
+            .collect();
+
+        GoldenEpisodes {
+            program: program.clone(),
+            episodes,
+        }
+    }
+
+    /// Replays the recorded program on the recorded episodes, returning where each episode
+    /// diverged (if it did).
+    pub fn replay<C>(&self) -> Vec<Divergence>
+    where
+        C: Core<Individual = Program>,
+        C::State: RlState,
+    {
+        let seeds = self
+            .episodes
+            .iter()
+            .map(|episode| episode.seed)
+            .collect::<Vec<_>>();
+        let replayed = GoldenEpisodes::record::<C>(&self.program, &seeds);
+
+        self.episodes
+            .iter()
+            .zip(replayed.episodes)
+            .filter_map(|(expected, actual)| {
+                let n_steps = expected.actions.len().max(actual.actions.len());
+
+                (0..n_steps)
+                    .map(|step| Divergence {
+                        seed: expected.seed,
+                        step,
+                        expected: expected.actions.get(step).copied(),
+                        actual: actual.actions.get(step).copied(),
+                    })
+                    .find(|divergence| divergence.expected != divergence.actual)
+            })
+            .collect()
+    }
+}
+
+/// Checks that `program` still behaves as recorded in the golden file at `path`, recording it
+/// first if the file does not exist (or [`UPDATE_GOLDEN_VAR`] is set).
+///
+/// Once recorded, the program stored in the file is replayed, so the check tracks the interpreter
+/// rather than `program`.
+pub fn check_golden<C>(path: &Path, program: &Program, seeds: &[u64]) -> Result<(), Box<dyn Error>>
+where
+    C: Core<Individual = Program>,
+    C::State: RlState,
+{
+    if !path.exists() || env::var_os(UPDATE_GOLDEN_VAR).is_some() {
+        GoldenEpisodes::record::<C>(program, seeds).save(path.to_str().unwrap())?;
+        return Ok(());
+    }
+
+    let divergences = GoldenEpisodes::try_load(path)?.replay::<C>();
+
+    if divergences.is_empty() {
+        return Ok(());
+    }
+
+    let report = divergences
+        .iter()
+        .map(Divergence::to_string)
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    Err(format!(
+        "{} of the golden episodes in {} changed (set {} to re-record):\n{}",
+        divergences.len(),
+        path.display(),
+        UPDATE_GOLDEN_VAR,
+        report
+    )
+    .into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::problems::custom::{CustomEngine, Navigation, Simulation};
+    use crate::problems::problem::program_parameters;
+    use crate::utils::misc::VoidResultAnyError;
+    use crate::utils::test::temp_dir;
+
+    /// Golden episodes committed with the crate.
+    const FIXTURES: &str = "assets/fixtures/golden";
+
+    #[test]
+    fn given_committed_golden_episodes_when_checked_then_the_interpreter_still_matches(
+    ) -> VoidResultAnyError {
+        // Pushes right whatever the velocity it accumulates in r0, so the start position drawn from
+        // the seed never changes the 200 actions of an episode.
+        let path = Path::new(FIXTURES).join("navigation.json");
+        let golden = GoldenEpisodes::try_load(&path)?;
+
+        assert!(golden
+            .episodes
+            .iter()
+            .all(|episode| episode.actions == vec![2; Navigation::EPISODE_LENGTH]));
+
+        check_golden::<CustomEngine<Navigation>>(&path, &golden.program, &[1, 2, 3])
+    }
+
+    #[test]
+    fn given_golden_episodes_when_program_changes_then_divergences_are_reported(
+    ) -> VoidResultAnyError {
+        let program_parameters = program_parameters(Navigation::N_INPUTS, Navigation::N_ACTIONS);
+
+        // Always pushes right.
+        let program = Program::parse("r2 = r2 + 1", program_parameters)?;
+        let seeds = [1, 2, 3];
+
+        let path = temp_dir("golden").join("navigation.json");
+
+        check_golden::<CustomEngine<Navigation>>(&path, &program, &seeds)?;
+        check_golden::<CustomEngine<Navigation>>(&path, &program, &seeds)?;
+
+        let mut golden = GoldenEpisodes::try_load(&path)?;
+        assert_eq!(golden.episodes.len(), seeds.len());
+        assert!(golden.replay::<CustomEngine<Navigation>>().is_empty());
+
+        // Simulates a change in semantics: the recorded program now pushes left.
+        golden.program = Program::parse("r0 = r0 + 1", program_parameters)?;
+        let divergences = golden.replay::<CustomEngine<Navigation>>();
+
+        assert_eq!(divergences.len(), seeds.len());
+        assert!(divergences.iter().all(|divergence| divergence.step == 0
+            && divergence.expected == Some(2)
+            && divergence.actual == Some(0)));
+
+        Ok(())
+    }
+}
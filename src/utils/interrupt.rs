@@ -0,0 +1,29 @@
+//! Ctrl-C handling for long runs: the first interrupt asks iterators watching the returned flag to stop
+//! after the current generation, a second one exits immediately.
+use std::sync::{
+    atomic::{AtomicBool, Ordering},
+    Arc,
+};
+
+use lazy_static::lazy_static;
+
+lazy_static! {
+    static ref INTERRUPTED: Arc<AtomicBool> = Arc::new(AtomicBool::new(false));
+}
+
+/// Installs the process-wide SIGINT handler; can only be called once per process.
+pub fn install_interrupt_handler() -> Result<Arc<AtomicBool>, ctrlc::Error> {
+    let interrupted = INTERRUPTED.clone();
+
+    ctrlc::set_handler(move || {
+        if INTERRUPTED.swap(true, Ordering::SeqCst) {
+            std::process::exit(130);
+        }
+    })?;
+
+    Ok(interrupted)
+}
+
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::SeqCst)
+}
@@ -0,0 +1,264 @@
+//! A record of every fitness evaluation of a run, keyed by genome.
+//!
+//! Individuals surviving several generations are recorded once per generation they are evaluated in,
+//! so the ledger traces how the estimate of each genome evolved. Kept in a JSON lines file, it grows
+//! across runs: evaluations of earlier runs are replayed when it is opened.
+//!
+//! Genomes are keyed like [`EvaluationStrategy::Keyed`] caches them, by [`Status::genome_hash`]: for
+//! programs [`Program::genome_hash`], which hashes the three-address instructions along with every
+//! other field deciding what the program does (input mask, frame skip, ...) with a hasher stable
+//! across Rust versions. Individuals without a genome hash, such as Q-learning programs whose tables
+//! keep learning, are not recorded. Ledgers written before instructions moved to that layout (format
+//! version 2), before the hasher was made stable or before the key covered the whole program key the
+//! same genomes differently, start a new ledger rather than growing them. Runs flush the ledger every
+//! generation, so an interrupted run keeps the evaluations made so far.
+//!
+//! [`EvaluationStrategy::Keyed`]: crate::core::engines::fitness_engine::EvaluationStrategy::Keyed
+use std::{
+    collections::{HashMap, HashSet},
+    error::Error,
+    fs::{read_to_string, OpenOptions},
+    io::Write,
+    path::PathBuf,
+};
+
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::core::{
+    engines::{core_engine::Core, fitness_engine::Objective, status_engine::Status},
+    program::Program,
+};
+
+use super::benchmark_tools::create_path;
+
+/// Hashes `program` (see [`Program::genome_hash`]), so identical genomes share a key whatever their id.
+pub fn genome_hash(program: &Program) -> u64 {
+    program.genome_hash()
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct Evaluation {
+    /// Index of the run the evaluation belongs to, counting the runs already in the ledger.
+    pub run: usize,
+    pub generation: usize,
+    pub genome: u64,
+    pub fitness: f64,
+}
+
+/// What the ledger knows about a single genome.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct GenomeRecord {
+    pub genome: u64,
+    pub n_evaluations: usize,
+    pub best_fitness: f64,
+    pub last_fitness: f64,
+}
+
+#[derive(Debug, Clone)]
+pub struct EvaluationLedger {
+    objective: Objective,
+    run: usize,
+    evaluations: Vec<Evaluation>,
+    genomes: HashMap<u64, GenomeRecord>,
+    recorded: HashSet<(usize, usize, u64)>,
+    path: Option<PathBuf>,
+    n_persisted: usize,
+}
+
+impl EvaluationLedger {
+    /// An in-memory ledger, ranking genomes according to `objective`.
+    pub fn new(objective: Objective) -> Self {
+        EvaluationLedger {
+            objective,
+            run: 0,
+            evaluations: vec![],
+            genomes: HashMap::new(),
+            recorded: HashSet::new(),
+            path: None,
+            n_persisted: 0,
+        }
+    }
+
+    /// Opens (or creates on the first [`EvaluationLedger::flush`]) the ledger at `path`, replaying the
+    /// evaluations of earlier runs; new evaluations belong to the next run.
+    pub fn open(path: impl Into<PathBuf>, objective: Objective) -> Result<Self, Box<dyn Error>> {
+        let path = path.into();
+        let mut ledger = Self::new(objective);
+
+        if path.exists() {
+            for line in read_to_string(&path)?
+                .lines()
+                .filter(|line| !line.is_empty())
+            {
+                let evaluation: Evaluation = serde_json::from_str(line)?;
+                ledger.run = ledger.run.max(evaluation.run + 1);
+                ledger.insert(evaluation);
+            }
+        }
+
+        ledger.n_persisted = ledger.evaluations.len();
+        ledger.path = Some(path);
+
+        Ok(ledger)
+    }
+
+    fn insert(&mut self, evaluation: Evaluation) -> bool {
+        if !self
+            .recorded
+            .insert((evaluation.run, evaluation.generation, evaluation.genome))
+        {
+            return false;
+        }
+
+        let objective = self.objective;
+        self.genomes
+            .entry(evaluation.genome)
+            .and_modify(|record| {
+                record.n_evaluations += 1;
+                record.last_fitness = evaluation.fitness;
+
+                if objective.is_better(evaluation.fitness, record.best_fitness) {
+                    record.best_fitness = evaluation.fitness;
+                }
+            })
+            .or_insert(GenomeRecord {
+                genome: evaluation.genome,
+                n_evaluations: 1,
+                best_fitness: evaluation.fitness,
+                last_fitness: evaluation.fitness,
+            });
+        self.evaluations.push(evaluation);
+
+        true
+    }
+
+    /// Records the fitness of `genome` in `generation` of the current run, returning `false` when it
+    /// was already recorded for that generation or the fitness is not finite.
+    pub fn record(&mut self, generation: usize, genome: u64, fitness: f64) -> bool {
+        if !fitness.is_finite() {
+            return false;
+        }
+
+        self.insert(Evaluation {
+            run: self.run,
+            generation,
+            genome,
+            fitness,
+        })
+    }
+
+    /// Records every evaluated individual of `population` which has a genome hash.
+    pub fn record_population<C>(&mut self, generation: usize, population: &[C::Individual])
+    where
+        C: Core,
+    {
+        for individual in population {
+            if let Some(genome) = C::Status::genome_hash(individual) {
+                self.record(generation, genome, C::Status::get_fitness(individual));
+            }
+        }
+    }
+
+    pub fn get(&self, genome: u64) -> Option<&GenomeRecord> {
+        self.genomes.get(&genome)
+    }
+
+    pub fn evaluations(&self) -> &[Evaluation] {
+        &self.evaluations
+    }
+
+    /// The `k` genomes with the best fitness ever recorded, best first.
+    pub fn hall_of_fame(&self, k: usize) -> Vec<GenomeRecord> {
+        self.genomes
+            .values()
+            .copied()
+            .sorted_by(|a, b| {
+                self.objective
+                    .compare(b.best_fitness, a.best_fitness)
+                    .then(a.genome.cmp(&b.genome))
+            })
+            .take(k)
+            .collect()
+    }
+
+    /// Appends the evaluations recorded since the last flush to the ledger's file, if it has one.
+    pub fn flush(&mut self) -> Result<(), Box<dyn Error>> {
+        let path = match &self.path {
+            Some(path) => path,
+            None => return Ok(()),
+        };
+
+        if !path.exists() {
+            create_path(path.to_str().unwrap(), true)?;
+        }
+
+        let mut file = OpenOptions::new().append(true).open(path)?;
+
+        for evaluation in &self.evaluations[self.n_persisted..] {
+            writeln!(file, "{}", serde_json::to_string(evaluation)?)?;
+        }
+
+        self.n_persisted = self.evaluations.len();
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::engines::status_engine::StatusEngine;
+    use crate::problems::iris::IrisEngine;
+    use crate::problems::problem::program_parameters;
+    use crate::utils::misc::VoidResultAnyError;
+    use crate::utils::test::temp_dir;
+
+    #[test]
+    fn given_surviving_genomes_when_recorded_then_ledger_deduplicates_and_persists(
+    ) -> VoidResultAnyError {
+        let program_parameters = program_parameters(2, 2);
+
+        let mut first = Program::parse("r0 = r0 + in0", program_parameters)?;
+        let mut clone = Program::parse("r0 = r0 + in0", program_parameters)?;
+        let mut second = Program::parse("r1 = r1 + in1", program_parameters)?;
+        StatusEngine::set_fitness(&mut first, 0.5);
+        StatusEngine::set_fitness(&mut clone, 0.5);
+        StatusEngine::set_fitness(&mut second, 0.7);
+
+        assert_eq!(genome_hash(&first), genome_hash(&clone));
+        assert_ne!(genome_hash(&first), genome_hash(&second));
+        assert_eq!(Some(genome_hash(&first)), StatusEngine::genome_hash(&first));
+
+        // The same instructions behave differently with another input mask or frame skip.
+        let mut masked = first.clone();
+        masked.input_mask = Some(vec![false, true]);
+        let mut skipping = first.clone();
+        skipping.frame_skip = 4;
+        assert_ne!(genome_hash(&first), genome_hash(&masked));
+        assert_ne!(genome_hash(&first), genome_hash(&skipping));
+        assert_ne!(genome_hash(&masked), genome_hash(&skipping));
+
+        let path = temp_dir("ledger").join("ledger.jsonl");
+        let mut ledger = EvaluationLedger::open(&path, Objective::Maximize)?;
+
+        ledger.record_population::<IrisEngine>(0, &[first.clone(), clone, second.clone()]);
+        StatusEngine::set_fitness(&mut first, 0.9);
+        ledger.record_population::<IrisEngine>(1, &[first.clone(), second]);
+
+        assert_eq!(ledger.evaluations().len(), 4);
+        let record = ledger.get(genome_hash(&first)).unwrap();
+        assert_eq!((record.n_evaluations, record.best_fitness), (2, 0.9));
+        assert_eq!(ledger.hall_of_fame(1)[0].genome, genome_hash(&first));
+
+        ledger.flush()?;
+
+        let reopened = EvaluationLedger::open(&path, Objective::Maximize)?;
+        assert_eq!(reopened.evaluations(), ledger.evaluations());
+        assert_eq!(reopened.run, 1);
+
+        std::fs::remove_file(path)?;
+
+        Ok(())
+    }
+}
@@ -1,25 +1,448 @@
 use std::error::Error;
 
 use csv::ReaderBuilder;
+use derive_builder::Builder;
 use reqwest::get;
-use serde::de::DeserializeOwned;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+
+/// A column, either by position or by header name.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum Column {
+    Index(usize),
+    Name(String),
+}
+
+/// How a CSV (or TSV, ...) file is read.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Builder)]
+pub struct CsvLoadOptions {
+    #[builder(default = "false")]
+    pub has_headers: bool,
+    #[builder(default = "b','")]
+    pub delimiter: u8,
+    /// When disabled, quote characters are read as regular data.
+    #[builder(default = "true")]
+    pub quoting: bool,
+    #[builder(default = "b'\"'")]
+    pub quote: u8,
+    /// Columns read as features by [`load_table`]; every column but the label when empty.
+    #[builder(default)]
+    pub feature_columns: Vec<Column>,
+    #[builder(default)]
+    pub label_column: Option<Column>,
+    /// Tokens read as missing (`NaN`) features, e.g. `""`, `"NA"` or `"?"`.
+    #[builder(default)]
+    #[serde(default)]
+    pub missing_values: Vec<String>,
+}
+
+impl Default for CsvLoadOptions {
+    fn default() -> Self {
+        CsvLoadOptionsBuilder::default().build().unwrap()
+    }
+}
+
+impl CsvLoadOptions {
+    pub fn tsv() -> Self {
+        CsvLoadOptions {
+            delimiter: b'\t',
+            ..Default::default()
+        }
+    }
+
+    pub fn reader_builder(&self) -> ReaderBuilder {
+        let mut builder = ReaderBuilder::new();
+
+        builder
+            .has_headers(self.has_headers)
+            .delimiter(self.delimiter)
+            .quoting(self.quoting)
+            .quote(self.quote);
+
+        builder
+    }
+
+    /// Parses a feature, mapping any of the `missing_values` tokens to `NaN`.
+    pub fn parse_feature(&self, value: &str) -> Result<f64, std::num::ParseFloatError> {
+        let value = value.trim();
+
+        if self.missing_values.iter().any(|missing| missing == value) {
+            return Ok(f64::NAN);
+        }
+
+        value.parse()
+    }
+}
 
 pub async fn download_and_load_csv<T>(url: &str) -> Result<Vec<T>, Box<dyn Error>>
+where
+    T: DeserializeOwned + Send,
+{
+    download_and_load_csv_with_options(url, &CsvLoadOptions::default()).await
+}
+
+pub async fn download_and_load_csv_with_options<T>(
+    url: &str,
+    options: &CsvLoadOptions,
+) -> Result<Vec<T>, Box<dyn Error>>
 where
     T: DeserializeOwned + Send,
 {
     let response = get(url).await?;
     let content = response.text().await?;
 
-    let mut csv_reader = ReaderBuilder::new()
-        .has_headers(false)
-        .from_reader(content.as_bytes());
+    load_csv(&content, options)
+}
+
+/// Deserializes every row of `content` into `T`, failing on the first malformed row.
+pub fn load_csv<T>(content: &str, options: &CsvLoadOptions) -> Result<Vec<T>, Box<dyn Error>>
+where
+    T: DeserializeOwned,
+{
+    let mut csv_reader = options.reader_builder().from_reader(content.as_bytes());
 
-    let inputs: Result<Vec<T>, _> = csv_reader
-        .deserialize()
-        .into_iter()
-        .map(|input| input)
-        .collect();
+    let inputs: Result<Vec<T>, _> = csv_reader.deserialize().collect();
 
     Ok(inputs?)
 }
+
+/// A row which could not be read.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RowError {
+    /// 1-based line of the row in the file.
+    pub line: u64,
+    pub cause: String,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LoadReport {
+    pub n_rows: usize,
+    pub n_loaded: usize,
+    pub errors: Vec<RowError>,
+}
+
+/// Loads every row of `content`, skipping (and reporting) malformed rows.
+///
+/// Fails once more than `max_errors` rows are malformed, so a file with the wrong layout is not silently
+/// reduced to a handful of rows.
+pub fn load_csv_lenient<T>(
+    content: &str,
+    max_errors: usize,
+    options: &CsvLoadOptions,
+) -> Result<(Vec<T>, LoadReport), Box<dyn Error>>
+where
+    T: DeserializeOwned,
+{
+    let mut csv_reader = options.reader_builder().from_reader(content.as_bytes());
+
+    let mut inputs = vec![];
+    let mut report = LoadReport::default();
+
+    for (idx, input) in csv_reader.deserialize::<T>().enumerate() {
+        report.n_rows += 1;
+
+        match input {
+            Ok(input) => inputs.push(input),
+            Err(error) => {
+                let line = error
+                    .position()
+                    .map(|position| position.line())
+                    .unwrap_or(idx as u64 + 1);
+
+                report.errors.push(RowError {
+                    line,
+                    cause: error.to_string(),
+                });
+
+                if report.errors.len() > max_errors {
+                    return Err(format!(
+                        "More than {} malformed rows, last at line {}: {}",
+                        max_errors, line, error
+                    )
+                    .into());
+                }
+            }
+        }
+    }
+
+    report.n_loaded = inputs.len();
+
+    Ok((inputs, report))
+}
+
+/// Lenient counterpart of [`download_and_load_csv`], see [`load_csv_lenient`].
+pub async fn download_and_load_csv_lenient<T>(
+    url: &str,
+    max_errors: usize,
+    options: &CsvLoadOptions,
+) -> Result<(Vec<T>, LoadReport), Box<dyn Error>>
+where
+    T: DeserializeOwned + Send,
+{
+    let response = get(url).await?;
+    let content = response.text().await?;
+
+    load_csv_lenient(&content, max_errors, options)
+}
+
+/// Untyped rows: the selected feature columns parsed as numbers, and the label column kept as text.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct Table {
+    pub feature_names: Vec<String>,
+    pub features: Vec<Vec<f64>>,
+    /// Empty when no label column was selected.
+    pub labels: Vec<String>,
+}
+
+pub(crate) fn resolve_column(
+    column: &Column,
+    headers: &Option<Vec<String>>,
+) -> Result<usize, Box<dyn Error>> {
+    match (column, headers) {
+        (Column::Index(idx), Some(headers)) if *idx >= headers.len() => Err(format!(
+            "Column {} is out of range, the header row has {} columns.",
+            idx,
+            headers.len()
+        )
+        .into()),
+        (Column::Index(idx), _) => Ok(*idx),
+        (Column::Name(name), Some(headers)) => headers
+            .iter()
+            .position(|header| header == name)
+            .ok_or_else(|| format!("Unknown column `{}`.", name).into()),
+        (Column::Name(name), None) => Err(format!(
+            "Column `{}` is selected by name but the file has no headers.",
+            name
+        )
+        .into()),
+    }
+}
+
+/// Raw rows as text, along with the headers when the file has them.
+pub type Records = (Option<Vec<String>>, Vec<Vec<String>>);
+
+/// Reads every row of `content` as text, without interpreting any column.
+pub fn load_records(content: &str, options: &CsvLoadOptions) -> Result<Records, Box<dyn Error>> {
+    let mut csv_reader = options.reader_builder().from_reader(content.as_bytes());
+
+    let headers = if options.has_headers {
+        Some(csv_reader.headers()?.iter().map(str::to_owned).collect())
+    } else {
+        None
+    };
+
+    let rows = csv_reader
+        .records()
+        .map(|record| Ok(record?.iter().map(str::to_owned).collect()))
+        .collect::<Result<Vec<_>, csv::Error>>()?;
+
+    Ok((headers, rows))
+}
+
+/// Loads the columns selected by `options`, skipping (and reporting) up to `max_errors` malformed rows.
+pub fn load_table(
+    content: &str,
+    max_errors: usize,
+    options: &CsvLoadOptions,
+) -> Result<(Table, LoadReport), Box<dyn Error>> {
+    let mut csv_reader = options.reader_builder().from_reader(content.as_bytes());
+
+    let headers = if options.has_headers {
+        Some(
+            csv_reader
+                .headers()?
+                .iter()
+                .map(str::to_owned)
+                .collect::<Vec<_>>(),
+        )
+    } else {
+        None
+    };
+
+    let label_idx = options
+        .label_column
+        .as_ref()
+        .map(|column| resolve_column(column, &headers))
+        .transpose()?;
+    let mut feature_indices = options
+        .feature_columns
+        .iter()
+        .map(|column| resolve_column(column, &headers))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let mut table = Table::default();
+    let mut report = LoadReport::default();
+
+    for (idx, record) in csv_reader.records().enumerate() {
+        report.n_rows += 1;
+
+        let row = record
+            .map_err(|error| error.to_string())
+            .and_then(|record| {
+                if options.feature_columns.is_empty() && table.feature_names.is_empty() {
+                    feature_indices = (0..record.len())
+                        .filter(|column| Some(*column) != label_idx)
+                        .collect();
+                }
+
+                let features = feature_indices
+                    .iter()
+                    .map(|column| match record.get(*column) {
+                        Some(value) => options
+                            .parse_feature(value)
+                            .map_err(|error| format!("Column {}: {}", column, error)),
+                        None => Err(format!("Missing column {}.", column)),
+                    })
+                    .collect::<Result<Vec<_>, _>>()?;
+
+                let label = match label_idx {
+                    Some(column) => Some(
+                        record
+                            .get(column)
+                            .ok_or_else(|| format!("Missing label column {}.", column))?
+                            .to_owned(),
+                    ),
+                    None => None,
+                };
+
+                Ok((features, label))
+            });
+
+        match row {
+            Ok((features, label)) => {
+                if table.feature_names.is_empty() {
+                    table.feature_names = feature_indices
+                        .iter()
+                        .map(|column| match &headers {
+                            Some(headers) => headers.get(*column).cloned().ok_or_else(|| {
+                                format!(
+                                    "Column {} has no header, the header row has {} columns.",
+                                    column,
+                                    headers.len()
+                                )
+                            }),
+                            None => Ok(column.to_string()),
+                        })
+                        .collect::<Result<_, _>>()?;
+                }
+
+                table.features.push(features);
+                table.labels.extend(label);
+            }
+            Err(cause) => {
+                // Assumes single-line records, as quoted line breaks are rare in numeric data.
+                let line = idx as u64 + 1 + options.has_headers as u64;
+                report.errors.push(RowError { line, cause });
+
+                if report.errors.len() > max_errors {
+                    return Err(format!("More than {} malformed rows.", max_errors).into());
+                }
+            }
+        }
+    }
+
+    report.n_loaded = table.features.len();
+
+    Ok((table, report))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::misc::VoidResultAnyError;
+
+    #[derive(Debug, Deserialize, PartialEq)]
+    struct Row {
+        a: f64,
+        b: f64,
+    }
+
+    const CONTENT: &str = "1.0,2.0\nnot,a number\n3.0,4.0\n5.0\n";
+
+    #[test]
+    fn given_malformed_rows_when_loaded_leniently_then_rows_are_skipped_and_reported(
+    ) -> VoidResultAnyError {
+        let (rows, report) = load_csv_lenient::<Row>(CONTENT, 2, &CsvLoadOptions::default())?;
+
+        assert_eq!(rows, vec![Row { a: 1., b: 2. }, Row { a: 3., b: 4. }]);
+        assert_eq!(report.n_rows, 4);
+        assert_eq!(report.n_loaded, 2);
+        assert_eq!(
+            report
+                .errors
+                .iter()
+                .map(|error| error.line)
+                .collect::<Vec<_>>(),
+            vec![2, 4]
+        );
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_too_many_malformed_rows_when_loaded_leniently_then_loading_fails() {
+        assert!(load_csv_lenient::<Row>(CONTENT, 1, &CsvLoadOptions::default()).is_err());
+    }
+
+    #[test]
+    fn given_tsv_with_headers_when_table_is_loaded_then_columns_are_selected_by_name(
+    ) -> VoidResultAnyError {
+        let content = "id\twidth\theight\tspecies\n\
+                       1\t0.5\t1.5\tsetosa\n\
+                       2\t0.7\tbad\tvirginica\n\
+                       3\t0.9\t2.5\t\"versi\tcolor\"\n";
+
+        let options = CsvLoadOptions {
+            has_headers: true,
+            feature_columns: vec![
+                Column::Name("height".to_owned()),
+                Column::Name("width".to_owned()),
+            ],
+            label_column: Some(Column::Name("species".to_owned())),
+            ..CsvLoadOptions::tsv()
+        };
+
+        let (table, report) = load_table(content, 1, &options)?;
+
+        assert_eq!(table.feature_names, vec!["height", "width"]);
+        assert_eq!(table.features, vec![vec![1.5, 0.5], vec![2.5, 0.9]]);
+        assert_eq!(table.labels, vec!["setosa", "versi\tcolor"]);
+        assert_eq!(report.errors.len(), 1);
+        assert_eq!(report.errors[0].line, 3);
+
+        let out_of_range = CsvLoadOptions {
+            feature_columns: vec![Column::Index(4)],
+            ..options
+        };
+        assert!(load_table(content, 1, &out_of_range).is_err());
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_headerless_csv_when_table_is_loaded_then_non_label_columns_are_features(
+    ) -> VoidResultAnyError {
+        let options = CsvLoadOptions {
+            label_column: Some(Column::Index(0)),
+            ..Default::default()
+        };
+
+        let (table, _) = load_table("a,1,2\nb,3,4\n", 0, &options)?;
+
+        assert_eq!(table.feature_names, vec!["1", "2"]);
+        assert_eq!(table.features, vec![vec![1., 2.], vec![3., 4.]]);
+        assert_eq!(table.labels, vec!["a", "b"]);
+
+        assert!(load_table(
+            "a,1,2\n",
+            0,
+            &CsvLoadOptions {
+                label_column: Some(Column::Name("label".to_owned())),
+                ..Default::default()
+            }
+        )
+        .is_err());
+
+        Ok(())
+    }
+}
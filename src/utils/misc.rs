@@ -1,11 +1,76 @@
-use std::error::Error;
+use std::{error::Error, hash::Hasher, time::Duration};
 
-use crate::core::engines::reset_engine::{Reset, ResetEngine};
+use crate::{
+    core::engines::reset_engine::{Reset, ResetEngine},
+    utils::random::random_id,
+};
 
 pub type VoidResultAnyError = Result<(), Box<dyn Error>>;
 
 impl Reset<uuid::Uuid> for ResetEngine {
     fn reset(item: &mut uuid::Uuid) {
-        *item = uuid::Uuid::new_v4();
+        *item = random_id();
+    }
+}
+
+/// 64-bit FNV-1a. Unlike [`DefaultHasher`](std::collections::hash_map::DefaultHasher), hashes the
+/// same bytes to the same value whatever the Rust version or platform, for hashes kept on disk.
+/// Integers are hashed as little-endian bytes.
+#[derive(Debug, Clone, Copy)]
+pub struct StableHasher(u64);
+
+impl Default for StableHasher {
+    fn default() -> Self {
+        StableHasher(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Hasher for StableHasher {
+    fn write(&mut self, bytes: &[u8]) {
+        for byte in bytes {
+            self.0 = (self.0 ^ *byte as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+
+    fn write_u64(&mut self, value: u64) {
+        self.write(&value.to_le_bytes());
+    }
+
+    fn finish(&self) -> u64 {
+        self.0
+    }
+}
+
+/// Parses a (possibly fractional) number of seconds, as accepted by duration command line arguments.
+pub fn parse_duration(value: &str) -> Result<Duration, String> {
+    let seconds: f64 = value
+        .parse()
+        .map_err(|error| format!("invalid number of seconds `{value}`: {error}"))?;
+
+    Duration::try_from_secs_f64(seconds).map_err(|error| error.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn given_seconds_when_parsed_then_duration_is_returned() {
+        assert_eq!(parse_duration("1.5"), Ok(Duration::from_millis(1500)));
+        assert!(parse_duration("-1").is_err());
+        assert!(parse_duration("soon").is_err());
+    }
+
+    #[test]
+    fn given_bytes_when_hashed_then_fnv_1a_reference_values_are_returned() {
+        let hash = |bytes: &[u8]| {
+            let mut hasher = StableHasher::default();
+            hasher.write(bytes);
+            hasher.finish()
+        };
+
+        assert_eq!(hash(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(hash(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(hash(b"foobar"), 0x8594_4171_f739_67e8);
     }
 }
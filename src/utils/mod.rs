@@ -1,6 +1,21 @@
 pub mod benchmark_tools;
+pub mod compare;
+#[cfg(feature = "tui")]
+pub mod dashboard;
+pub mod diff;
 pub mod float_ops;
+pub mod golden;
+pub mod interrupt;
+pub mod ledger;
 pub mod loader;
 pub mod misc;
+pub mod pareto;
 pub mod random;
+pub mod report;
+pub mod robustness;
+pub mod stats;
+pub mod telemetry;
 pub mod test;
+pub mod tournament;
+pub mod transfer;
+pub mod usage;
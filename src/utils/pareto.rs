@@ -0,0 +1,151 @@
+//! The trade-off between fitness and program size over an entire run.
+//!
+//! Every evaluated individual of every generation is a candidate; the non-dominated ones form the Pareto
+//! front, from which a compact program can be picked over the single fittest one.
+use std::{collections::HashSet, error::Error};
+
+use csv::Writer;
+use serde::{Deserialize, Serialize};
+
+use crate::core::{
+    engines::{core_engine::Core, fitness_engine::Objective, status_engine::Status},
+    program::AsProgram,
+};
+
+use super::benchmark_tools::create_path;
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ParetoPoint {
+    /// First generation the individual appeared in.
+    pub generation: usize,
+    pub fitness: f64,
+    pub effective_length: usize,
+    /// The effective instructions, one per line.
+    pub program: String,
+}
+
+impl ParetoPoint {
+    /// Whether `self` is at least as good as `other` on both criteria, and better on one.
+    pub fn dominates(&self, other: &ParetoPoint, objective: Objective) -> bool {
+        let fitness = objective.compare(self.fitness, other.fitness);
+
+        fitness.is_ge()
+            && self.effective_length <= other.effective_length
+            && (fitness.is_gt() || self.effective_length < other.effective_length)
+    }
+}
+
+/// Extracts the individuals of `populations` no other individual dominates, ordered by effective length.
+/// Individuals without a finite fitness are ignored, as are repeated occurrences of the same program.
+pub fn pareto_front<C>(populations: &[Vec<C::Individual>], objective: Objective) -> Vec<ParetoPoint>
+where
+    C: Core,
+    C::Individual: AsProgram,
+{
+    let mut seen = HashSet::new();
+    let mut candidates = vec![];
+
+    for (generation, population) in populations.iter().enumerate() {
+        for individual in population {
+            let fitness = C::Status::get_fitness(individual);
+            let program = individual.as_program();
+
+            if !fitness.is_finite() || !seen.insert(program.id) {
+                continue;
+            }
+
+            let effective = program
+                .effective_instructions()
+                .iter()
+                .map(ToString::to_string)
+                .collect::<Vec<_>>();
+
+            candidates.push(ParetoPoint {
+                generation,
+                fitness,
+                effective_length: effective.len(),
+                program: effective.join("\n"),
+            });
+        }
+    }
+
+    let mut front = candidates
+        .iter()
+        .filter(|candidate| {
+            !candidates
+                .iter()
+                .any(|other| other.dominates(candidate, objective))
+        })
+        .cloned()
+        .collect::<Vec<_>>();
+
+    front.sort_by(|a, b| {
+        a.effective_length
+            .cmp(&b.effective_length)
+            .then(objective.compare(b.fitness, a.fitness))
+    });
+    front
+}
+
+/// Writes the front as a CSV with the columns `generation,fitness,effective_length,program`.
+pub fn save_pareto_csv(front: &[ParetoPoint], path: &str) -> Result<(), Box<dyn Error>> {
+    create_path(path, true)?;
+
+    let mut writer = Writer::from_path(path)?;
+
+    for point in front {
+        writer.serialize(point)?;
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{engines::status_engine::StatusEngine, program::Program};
+    use crate::problems::iris::IrisEngine;
+    use crate::problems::problem::program_parameters;
+    use crate::utils::misc::VoidResultAnyError;
+
+    #[test]
+    fn given_programs_of_several_sizes_when_front_is_extracted_then_dominated_ones_are_dropped(
+    ) -> VoidResultAnyError {
+        let program_parameters = program_parameters(2, 2);
+
+        let program = |text: &str, fitness: f64| -> Result<Program, Box<dyn Error>> {
+            let mut program = Program::parse(text, program_parameters)?;
+            StatusEngine::set_fitness(&mut program, fitness);
+            Ok(program)
+        };
+
+        let small = program("r0 = r0 + in0", 0.5)?;
+        let large = program("r0 = r0 + in0; r1 = r1 + in1", 0.9)?;
+        // As large as `large`, but worse.
+        let dominated = program("r0 = r0 + in1; r1 = r1 - in0", 0.7)?;
+        let invalid = program("r0 = r0 + in1", f64::NAN)?;
+
+        let populations = vec![
+            vec![large.clone(), small.clone(), dominated],
+            vec![large, small, invalid],
+        ];
+
+        let front = pareto_front::<IrisEngine>(&populations, Objective::Maximize);
+
+        assert_eq!(
+            front
+                .iter()
+                .map(|point| (point.generation, point.effective_length, point.fitness))
+                .collect::<Vec<_>>(),
+            vec![(0, 1, 0.5), (0, 2, 0.9)]
+        );
+
+        let minimized = pareto_front::<IrisEngine>(&populations, Objective::Minimize);
+        assert_eq!(minimized.len(), 1);
+        assert_eq!(minimized[0].fitness, 0.5);
+
+        Ok(())
+    }
+}
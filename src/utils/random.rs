@@ -1,7 +1,13 @@
-use std::{cell::UnsafeCell, sync::Arc};
+use std::{
+    cell::{Cell, RefCell, UnsafeCell},
+    collections::BTreeMap,
+    sync::Arc,
+};
 
 use rand::{RngCore, SeedableRng};
 use rand_xoshiro::Xoshiro256PlusPlus;
+use serde::{Deserialize, Serialize};
+use uuid::{Builder, Uuid};
 
 type InternalGenerator = Arc<UnsafeCell<Xoshiro256PlusPlus>>;
 
@@ -10,14 +16,66 @@ pub struct Random {
     rng: InternalGenerator,
 }
 
+/// Draws of the generator, grouped by the stream they were made in (see [`in_stream`]).
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RngRecording {
+    pub draws: BTreeMap<String, Vec<u64>>,
+}
+
+enum Tape {
+    Off,
+    Recording(RngRecording),
+    /// The recording, along with the number of draws already replayed from each stream.
+    Replaying(RngRecording, BTreeMap<String, usize>),
+}
+
+pub const DEFAULT_STREAM: &str = "default";
+
 thread_local! {
     static GENERATOR: InternalGenerator = {
         let prng = Xoshiro256PlusPlus::from_entropy();
 
         Arc::new(UnsafeCell::new(prng))
+    };
+    static STREAM: Cell<&'static str> = Cell::new(DEFAULT_STREAM);
+    static TAPE: RefCell<Tape> = RefCell::new(Tape::Off);
+}
+
+/// Attributes the draws `f` makes on this thread to the stream `label` (e.g. `"variation"`), so they
+/// can be recorded and replayed apart from the draws of other subsystems.
+pub fn in_stream<T>(label: &'static str, f: impl FnOnce() -> T) -> T {
+    let previous = STREAM.with(|stream| stream.replace(label));
+    let result = f();
+    STREAM.with(|stream| stream.set(previous));
+
+    result
+}
+
+/// Starts logging every draw made on this thread, discarding any recording or replay in progress.
+pub fn start_recording() {
+    TAPE.with(|tape| *tape.borrow_mut() = Tape::Recording(RngRecording::default()));
+}
+
+/// Stops recording, returning the draws made since [`start_recording`] (none when not recording).
+pub fn stop_recording() -> RngRecording {
+    match TAPE.with(|tape| tape.replace(Tape::Off)) {
+        Tape::Recording(recording) => recording,
+        Tape::Off | Tape::Replaying(..) => RngRecording::default(),
     }
 }
 
+/// Makes draws on this thread return those of `recording` instead, stream by stream, so a run (or a
+/// single generation) takes the exact same random decisions whatever the seed. Drawing past the end
+/// of a stream panics.
+pub fn replay(recording: RngRecording) {
+    TAPE.with(|tape| *tape.borrow_mut() = Tape::Replaying(recording, BTreeMap::new()));
+}
+
+/// Returns to drawing from the generator.
+pub fn stop_replay() {
+    TAPE.with(|tape| *tape.borrow_mut() = Tape::Off);
+}
+
 /// This function should only be called once and at the top level of a program.
 pub fn update_seed(seed: Option<u64>) {
     let prng = match seed {
@@ -31,6 +89,18 @@ pub fn update_seed(seed: Option<u64>) {
     });
 }
 
+/// Runs `f` with the generator of this thread seeded from `seed`, then restores the generator. Work
+/// handed to other threads (e.g. by rayon) would otherwise draw from their unseeded generators; drawing
+/// its seed from the caller's generator keeps seeded runs reproducible.
+pub fn with_seed<T>(seed: u64, f: impl FnOnce() -> T) -> T {
+    let seeded = Xoshiro256PlusPlus::seed_from_u64(seed);
+    let previous = GENERATOR.with(|t| std::mem::replace(unsafe { &mut *t.get() }, seeded));
+    let result = f();
+    GENERATOR.with(|t| *unsafe { &mut *t.get() } = previous);
+
+    result
+}
+
 pub fn generator() -> Random {
     let rng = GENERATOR.with(|t| t.clone());
     Random { rng }
@@ -42,24 +112,181 @@ impl Default for Random {
     }
 }
 
+impl Random {
+    /// Draws through `draw`, unless the draw is being replayed.
+    fn draw(&mut self, draw: impl FnOnce(&mut Xoshiro256PlusPlus) -> u64) -> u64 {
+        let rng = unsafe { &mut *self.rng.get() };
+
+        TAPE.with(|tape| match &mut *tape.borrow_mut() {
+            Tape::Off => draw(rng),
+            Tape::Recording(recording) => {
+                let value = draw(rng);
+                let stream = STREAM.with(Cell::get);
+                recording
+                    .draws
+                    .entry(stream.to_owned())
+                    .or_default()
+                    .push(value);
+                value
+            }
+            Tape::Replaying(recording, cursors) => {
+                let stream = STREAM.with(Cell::get);
+                let cursor = cursors.entry(stream.to_owned()).or_default();
+                let value = recording
+                    .draws
+                    .get(stream)
+                    .and_then(|draws| draws.get(*cursor))
+                    .copied()
+                    .unwrap_or_else(|| panic!("No more recorded draws in stream `{}`.", stream));
+                *cursor += 1;
+                value
+            }
+        })
+    }
+
+    fn taping() -> bool {
+        TAPE.with(|tape| !matches!(*tape.borrow(), Tape::Off))
+    }
+}
+
 impl RngCore for Random {
     fn next_u32(&mut self) -> u32 {
-        let rng = unsafe { &mut *self.rng.get() };
-        rng.next_u32()
+        self.draw(|rng| rng.next_u32() as u64) as u32
     }
 
     fn next_u64(&mut self) -> u64 {
-        let rng = unsafe { &mut *self.rng.get() };
-        rng.next_u64()
+        self.draw(|rng| rng.next_u64())
     }
 
     fn fill_bytes(&mut self, dest: &mut [u8]) {
-        let rng = unsafe { &mut *self.rng.get() };
-        rng.fill_bytes(dest)
+        if !Self::taping() {
+            let rng = unsafe { &mut *self.rng.get() };
+            return rng.fill_bytes(dest);
+        }
+
+        // Taped 8 bytes at a time, so every chunk is a single draw.
+        for chunk in dest.chunks_mut(8) {
+            let bytes = self.next_u64().to_le_bytes();
+            chunk.copy_from_slice(&bytes[..chunk.len()]);
+        }
     }
 
     fn try_fill_bytes(&mut self, dest: &mut [u8]) -> Result<(), rand::Error> {
-        let rng = unsafe { &mut *self.rng.get() };
-        rng.try_fill_bytes(dest)
+        self.fill_bytes(dest);
+        Ok(())
+    }
+}
+
+/// A random (version 4) id drawn from the generator, so seeded runs assign the same ids.
+pub fn random_id() -> Uuid {
+    use rand::Rng;
+
+    Builder::from_random_bytes(generator().gen()).into_uuid()
+}
+
+/// Draws from the standard normal distribution (Box-Muller transform).
+pub fn standard_normal() -> f64 {
+    use rand::Rng;
+
+    // `1 - u` lies in `(0, 1]`, keeping the logarithm finite.
+    let u: f64 = 1. - generator().gen::<f64>();
+    let v: f64 = generator().gen();
+
+    (-2. * u.ln()).sqrt() * (2. * std::f64::consts::PI * v).cos()
+}
+
+#[cfg(test)]
+mod tests {
+    use itertools::Itertools;
+    use rand::Rng;
+
+    use super::*;
+    use crate::core::engines::core_engine::HyperParameters;
+    use crate::extensions::regression::RegressionEngine;
+    use crate::problems::{problem::Problem, symbolic::Koza1};
+
+    #[test]
+    fn given_seed_when_drawing_on_another_thread_then_draws_repeat_and_generator_is_restored() {
+        let draw = |seed| {
+            std::thread::spawn(move || with_seed(seed, || generator().gen::<u64>()))
+                .join()
+                .unwrap()
+        };
+        assert_eq!(draw(3), draw(3));
+
+        update_seed(Some(1));
+        let expected = generator().gen::<u64>();
+        update_seed(Some(1));
+        with_seed(2, || generator().gen::<u64>());
+        assert_eq!(generator().gen::<u64>(), expected);
+    }
+
+    #[test]
+    fn given_recorded_streams_when_replayed_then_draws_repeat_whatever_the_seed() {
+        update_seed(Some(1));
+        start_recording();
+        let variation = in_stream("variation", || generator().gen::<[u64; 3]>());
+        let exploration = in_stream("exploration", || generator().gen::<f64>());
+        let id = random_id();
+        let recording = stop_recording();
+
+        assert_eq!(recording.draws["variation"].len(), 3);
+        assert_eq!(recording.draws.len(), 3);
+
+        // Streams are replayed independently of the order they are drawn from.
+        update_seed(Some(2));
+        replay(recording);
+        assert_eq!(
+            in_stream("exploration", || generator().gen::<f64>()),
+            exploration
+        );
+        assert_eq!(random_id(), id);
+        assert_eq!(
+            in_stream("variation", || generator().gen::<[u64; 3]>()),
+            variation
+        );
+        stop_replay();
+
+        assert_ne!(
+            in_stream("variation", || generator().gen::<[u64; 3]>()),
+            variation
+        );
+    }
+
+    #[test]
+    fn given_recorded_generation_when_replayed_then_the_engine_evolves_the_same_population() {
+        type Regression = RegressionEngine<Koza1>;
+
+        let mut params = Regression::default_hyper_parameters();
+        Regression::build_fitness_parameters(&mut params);
+        params.population_size = 10;
+        params.n_trials = 2;
+        params.seed = Some(5);
+
+        // The initial population and one full generation: evaluation, selection and variation.
+        let run = |params: &HyperParameters<Regression>| {
+            params
+                .build_engine()
+                .take(2)
+                .flatten()
+                .map(|program| (program.to_string(), program.fitness.to_bits()))
+                .collect_vec()
+        };
+
+        start_recording();
+        let recorded = run(&params);
+        let recording = stop_recording();
+
+        for stream in ["generation", "variation"] {
+            assert!(recording.draws.contains_key(stream), "{}", stream);
+        }
+
+        // Unseeded, so only the recording can make the run repeat.
+        params.seed = None;
+        replay(recording);
+        let replayed = run(&params);
+        stop_replay();
+
+        assert_eq!(replayed, recorded);
     }
 }
@@ -0,0 +1,548 @@
+//! A single Markdown or HTML document summarizing a run directory written by `save_experiment`: its
+//! configuration, final metrics, figures, best program and, for Q-learning runs, the best Q-table.
+//! Classification runs also show the per-class breakdown exported by `evaluate-iris --report-dir`.
+use std::{
+    error::Error,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use clap::ValueEnum;
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+
+use crate::{
+    core::{
+        characteristics::Load,
+        engines::fitness_engine::Objective,
+        program::{AsProgram, Program},
+    },
+    extensions::{
+        classification::ClassificationReport,
+        organism::Organism,
+        q_learning::{QProgram, QTableSnapshot},
+    },
+    utils::stats::PopulationStats,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize, ValueEnum)]
+pub enum ReportFormat {
+    #[default]
+    Markdown,
+    Html,
+}
+
+impl ReportFormat {
+    pub fn file_name(self) -> &'static str {
+        match self {
+            ReportFormat::Markdown => "report.md",
+            ReportFormat::Html => "report.html",
+        }
+    }
+}
+
+/// Extensions of the figures embedded in reports.
+pub const FIGURE_EXTENSIONS: [&str; 2] = ["png", "svg"];
+
+/// Loads a best individual saved by any engine.
+fn load_best(path: &Path) -> Result<Organism, Box<dyn Error>> {
+    Organism::try_load(path)
+        .or_else(|_| Program::try_load(path).map(Organism::Lgp))
+        .or_else(|_| QProgram::try_load(path).map(Organism::Q))
+}
+
+/// Figures in `run_dir` and its `figures` directory, relative to `run_dir`.
+fn find_figures(run_dir: &Path) -> Vec<PathBuf> {
+    [run_dir.to_owned(), run_dir.join("figures")]
+        .iter()
+        .filter_map(|directory| fs::read_dir(directory).ok())
+        .flatten()
+        .filter_map(Result::ok)
+        .map(|entry| entry.path())
+        .filter(|path| {
+            path.extension()
+                .and_then(|extension| extension.to_str())
+                .map_or(false, |extension| FIGURE_EXTENSIONS.contains(&extension))
+        })
+        .filter_map(|path| path.strip_prefix(run_dir).ok().map(Path::to_owned))
+        .sorted()
+        .collect()
+}
+
+fn escape_html(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Blue for the lowest values of a table, red for the highest.
+fn heat_colour(value: f64, lower: f64, upper: f64) -> String {
+    let t = match upper > lower {
+        true => ((value - lower) / (upper - lower)).clamp(0., 1.),
+        false => 0.5,
+    };
+
+    let red = (59. + t * (180. - 59.)) as u8;
+    let green = (76. + (1. - (2. * t - 1.).abs()) * (221. - 76.)) as u8;
+    let blue = (192. - t * (192. - 38.)) as u8;
+
+    format!("#{:02x}{:02x}{:02x}", red, green, blue)
+}
+
+/// Index of the single best action of a register, if there is one.
+fn best_action(actions: &[f64]) -> Option<usize> {
+    let max = actions.iter().copied().fold(f64::NEG_INFINITY, f64::max);
+
+    match actions.iter().filter(|value| **value == max).count() {
+        1 => actions.iter().position(|value| *value == max),
+        _ => None,
+    }
+}
+
+/// Everything a report shows about a run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct RunReport {
+    pub name: String,
+    /// The hyperparameters of the run, as saved in `params.json`.
+    pub config: Value,
+    /// Statistics of every generation, from `stats.json`.
+    pub stats: Vec<PopulationStats>,
+    /// Validation fitness of every generation, empty without `validation.json`.
+    pub validation: Vec<f64>,
+    /// The objective of the run from `params.json`, deciding which validation fitness is best.
+    #[serde(default)]
+    pub objective: Objective,
+    pub best: Option<Organism>,
+    /// Q-tables of the best individual of every generation, empty without `q_tables.json`.
+    pub q_tables: Vec<QTableSnapshot>,
+    /// Figures relative to the run directory.
+    pub figures: Vec<PathBuf>,
+    /// Breakdown of the best classifier by class, from `classification.json`.
+    #[serde(default)]
+    pub classification: Option<ClassificationReport>,
+}
+
+impl RunReport {
+    /// Reads the artifacts of `run_dir`; only `params.json` and `stats.json` are required.
+    pub fn load(run_dir: &Path) -> Result<Self, Box<dyn Error>> {
+        let optional = |file: &str| Some(run_dir.join(file)).filter(|path| path.exists());
+
+        let validation = match optional("validation.json") {
+            Some(path) => Vec::<f64>::try_load(path)?,
+            None => vec![],
+        };
+        let best = match optional("best.json") {
+            Some(path) => Some(load_best(&path)?),
+            None => None,
+        };
+        let q_tables = match optional("q_tables.json") {
+            Some(path) => Vec::<QTableSnapshot>::try_load(path)?,
+            None => vec![],
+        };
+        let classification = match optional("classification.json") {
+            Some(path) => Some(ClassificationReport::try_load(path)?),
+            None => None,
+        };
+
+        let config = Value::try_load(run_dir.join("params.json"))?;
+        let objective = match config.get("objective") {
+            Some(objective) => serde_json::from_value(objective.clone())?,
+            None => Objective::default(),
+        };
+
+        Ok(RunReport {
+            name: run_dir
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            config,
+            stats: Vec::<PopulationStats>::try_load(run_dir.join("stats.json"))?,
+            validation,
+            objective,
+            best,
+            q_tables,
+            figures: find_figures(run_dir),
+            classification,
+        })
+    }
+
+    /// `(metric, value)` pairs describing the last generation.
+    pub fn final_metrics(&self) -> Vec<(&'static str, String)> {
+        let mut metrics = vec![
+            ("generations", self.stats.len().to_string()),
+            ("objective", format!("{:?}", self.objective)),
+        ];
+
+        if let Some(last) = self.stats.last() {
+            metrics.extend([
+                ("best", format!("{:.4}", last.best)),
+                ("median", format!("{:.4}", last.median)),
+                ("mean", format!("{:.4}", last.mean)),
+                ("worst", format!("{:.4}", last.worst)),
+                ("std", format!("{:.4}", last.std)),
+                (
+                    "valid individuals",
+                    format!("{}/{}", last.n_valid, last.size),
+                ),
+                ("mean length", format!("{:.2}", last.mean_length)),
+                (
+                    "mean effective length",
+                    format!("{:.2}", last.mean_effective_length),
+                ),
+                ("diversity", format!("{:.2}", last.diversity)),
+            ]);
+        }
+
+        if let Some(validation) = self.validation.last() {
+            metrics.push(("validation", format!("{:.4}", validation)));
+        }
+
+        if let Some(best_validation) = self
+            .validation
+            .iter()
+            .copied()
+            .max_by(|a, b| self.objective.compare(*a, *b))
+        {
+            metrics.push(("best validation", format!("{:.4}", best_validation)));
+        }
+
+        if let Some(metadata) = self.best.as_ref().and_then(Organism::fitness_metadata) {
+            metrics.extend([
+                ("best trials", metadata.n_trials.to_string()),
+                ("best trial std", format!("{:.4}", metadata.std())),
+                ("best successes", metadata.n_successes.to_string()),
+            ]);
+        }
+
+        metrics
+    }
+
+    /// The Q-table of the best individual, indexed by register then action.
+    fn q_table(&self) -> Option<&[Vec<f64>]> {
+        match &self.best {
+            Some(Organism::Q(q_program)) => Some(q_program.q_table.values()),
+            _ => None,
+        }
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut report = format!("# Report: {}\n\n", self.name);
+
+        report.push_str("## Configuration\n\n```json\n");
+        report.push_str(&serde_json::to_string_pretty(&self.config).unwrap());
+        report.push_str("\n```\n\n## Final metrics\n\n| Metric | Value |\n|---|---|\n");
+
+        for (metric, value) in self.final_metrics() {
+            report.push_str(&format!("| {} | {} |\n", metric, value));
+        }
+
+        if !self.figures.is_empty() {
+            report.push_str("\n## Figures\n\n");
+
+            for figure in &self.figures {
+                report.push_str(&format!(
+                    "![{}]({})\n\n",
+                    figure.display(),
+                    figure.display()
+                ));
+            }
+        }
+
+        if let Some(program) = self.best.as_ref().map(AsProgram::as_program) {
+            report.push_str(&format!(
+                "\n## Best program\n\nFitness: {:.4}\n\n```text\n{}```\n",
+                program.fitness, program
+            ));
+        }
+
+        if let Some(table) = self.q_table() {
+            let n_actions = table.first().map_or(0, Vec::len);
+
+            report.push_str(
+                "\n## Q-table\n\nThe single best action of each register is in bold.\n\n",
+            );
+            report.push_str(&format!(
+                "| Register | {} |\n|---|{}\n",
+                (0..n_actions)
+                    .map(|action| format!("a{}", action))
+                    .join(" | "),
+                "---|".repeat(n_actions)
+            ));
+
+            for (register, actions) in table.iter().enumerate() {
+                let best = best_action(actions);
+                let cells = actions
+                    .iter()
+                    .enumerate()
+                    .map(|(action, value)| match Some(action) == best {
+                        true => format!("**{:.4}**", value),
+                        false => format!("{:.4}", value),
+                    })
+                    .join(" | ");
+
+                report.push_str(&format!("| r{} | {} |\n", register, cells));
+            }
+        }
+
+        if let (Some(first), Some(last)) = (self.q_tables.first(), self.q_tables.last()) {
+            report.push_str(&format!(
+                "\nDecided registers went from {} in generation {} to {} in generation {}.\n",
+                first.n_decided_registers,
+                first.generation,
+                last.n_decided_registers,
+                last.generation
+            ));
+        }
+
+        if let Some(classification) = &self.classification {
+            let n_classes = classification.confusion.len();
+
+            report.push_str(&format!(
+                "\n## Classification\n\nAccuracy: {:.4}\n\n\
+                 | Class | Precision | Recall | Support |\n|---|---|---|---|\n",
+                classification.accuracy
+            ));
+            for class in &classification.classes {
+                report.push_str(&format!(
+                    "| {} | {:.4} | {:.4} | {} |\n",
+                    class.class, class.precision, class.recall, class.support
+                ));
+            }
+
+            report.push_str(&format!(
+                "\nRows are actual classes, columns predicted ones.\n\n\
+                 | Actual | {} | Unclassified |\n|---|{}---|\n",
+                (0..n_classes).join(" | "),
+                "---|".repeat(n_classes)
+            ));
+            for (actual, row) in classification.confusion.iter().enumerate() {
+                report.push_str(&format!(
+                    "| {} | {} | {} |\n",
+                    actual,
+                    row.iter().join(" | "),
+                    classification.unclassified[actual]
+                ));
+            }
+        }
+
+        report
+    }
+
+    pub fn to_html(&self) -> String {
+        let mut body = format!("<h1>Report: {}</h1>\n", escape_html(&self.name));
+
+        body.push_str(&format!(
+            "<h2>Configuration</h2>\n<pre>{}</pre>\n",
+            escape_html(&serde_json::to_string_pretty(&self.config).unwrap())
+        ));
+
+        body.push_str("<h2>Final metrics</h2>\n<table>\n<tr><th>Metric</th><th>Value</th></tr>\n");
+        for (metric, value) in self.final_metrics() {
+            body.push_str(&format!(
+                "<tr><td>{}</td><td>{}</td></tr>\n",
+                metric,
+                escape_html(&value)
+            ));
+        }
+        body.push_str("</table>\n");
+
+        if !self.figures.is_empty() {
+            body.push_str("<h2>Figures</h2>\n");
+
+            for figure in &self.figures {
+                let figure = escape_html(&figure.display().to_string());
+                body.push_str(&format!(
+                    "<figure><img src=\"{}\" alt=\"{}\"><figcaption>{}</figcaption></figure>\n",
+                    figure, figure, figure
+                ));
+            }
+        }
+
+        if let Some(program) = self.best.as_ref().map(AsProgram::as_program) {
+            body.push_str(&format!(
+                "<h2>Best program</h2>\n<p>Fitness: {:.4}</p>\n<pre>{}</pre>\n",
+                program.fitness,
+                escape_html(&program.to_string())
+            ));
+        }
+
+        if let Some(table) = self.q_table() {
+            let values = table.iter().flatten().copied().filter(|v| v.is_finite());
+            let lower = values.clone().fold(f64::INFINITY, f64::min);
+            let upper = values.fold(f64::NEG_INFINITY, f64::max);
+            let n_actions = table.first().map_or(0, Vec::len);
+
+            body.push_str("<h2>Q-table</h2>\n<table>\n<tr><th>Register</th>");
+            for action in 0..n_actions {
+                body.push_str(&format!("<th>a{}</th>", action));
+            }
+            body.push_str("</tr>\n");
+
+            for (register, actions) in table.iter().enumerate() {
+                let best = best_action(actions);
+                body.push_str(&format!("<tr><th>r{}</th>", register));
+
+                for (action, value) in actions.iter().enumerate() {
+                    body.push_str(&format!(
+                        "<td style=\"background: {}\">{}</td>",
+                        heat_colour(*value, lower, upper),
+                        match Some(action) == best {
+                            true => format!("<b>{:.4}</b>", value),
+                            false => format!("{:.4}", value),
+                        }
+                    ));
+                }
+
+                body.push_str("</tr>\n");
+            }
+
+            body.push_str("</table>\n");
+        }
+
+        if let Some(classification) = &self.classification {
+            let n_classes = classification.confusion.len();
+
+            body.push_str(&format!(
+                "<h2>Classification</h2>\n<p>Accuracy: {:.4}</p>\n<table>\n\
+                 <tr><th>Class</th><th>Precision</th><th>Recall</th><th>Support</th></tr>\n",
+                classification.accuracy
+            ));
+            for class in &classification.classes {
+                body.push_str(&format!(
+                    "<tr><td>{}</td><td>{:.4}</td><td>{:.4}</td><td>{}</td></tr>\n",
+                    class.class, class.precision, class.recall, class.support
+                ));
+            }
+            body.push_str("</table>\n");
+
+            body.push_str(
+                "<p>Rows are actual classes, columns predicted ones.</p>\n<table>\n<tr><th>Actual</th>",
+            );
+            for class in 0..n_classes {
+                body.push_str(&format!("<th>{}</th>", class));
+            }
+            body.push_str("<th>Unclassified</th></tr>\n");
+
+            for (actual, row) in classification.confusion.iter().enumerate() {
+                body.push_str(&format!("<tr><th>{}</th>", actual));
+                for (predicted, count) in row.iter().enumerate() {
+                    let correct = predicted == actual;
+                    body.push_str(&match correct {
+                        true => format!("<td><b>{}</b></td>", count),
+                        false => format!("<td>{}</td>", count),
+                    });
+                }
+                body.push_str(&format!(
+                    "<td>{}</td></tr>\n",
+                    classification.unclassified[actual]
+                ));
+            }
+            body.push_str("</table>\n");
+        }
+
+        format!(
+            "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>Report: {}</title>\n\
+             <style>body {{ font-family: sans-serif; }} table {{ border-collapse: collapse; }} \
+             td, th {{ border: 1px solid #ccc; padding: 4px 8px; }} img {{ max-width: 100%; }}</style>\n\
+             </head>\n<body>\n{}</body>\n</html>\n",
+            escape_html(&self.name),
+            body
+        )
+    }
+
+    pub fn render(&self, format: ReportFormat) -> String {
+        match format {
+            ReportFormat::Markdown => self.to_markdown(),
+            ReportFormat::Html => self.to_html(),
+        }
+    }
+
+    /// Writes the report to `run_dir`, returning its path.
+    pub fn save(&self, run_dir: &Path, format: ReportFormat) -> Result<PathBuf, Box<dyn Error>> {
+        let path = run_dir.join(format.file_name());
+        fs::write(&path, self.render(format))?;
+
+        Ok(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::characteristics::Save;
+    use crate::core::engines::fitness_engine::Objective;
+    use crate::core::engines::generate_engine::{Generate, GenerateEngine};
+    use crate::core::engines::status_engine::{Status, StatusEngine};
+    use crate::extensions::q_learning::QProgramGeneratorParametersBuilder;
+    use crate::problems::iris::IrisEngine;
+    use crate::utils::misc::VoidResultAnyError;
+    use crate::utils::test::{program_parameters_of_length, temp_dir};
+
+    #[test]
+    fn given_run_directory_when_reported_then_every_section_is_rendered() -> VoidResultAnyError {
+        let program_parameters = program_parameters_of_length(2, 2, 10);
+        let parameters = QProgramGeneratorParametersBuilder::default()
+            .program_parameters(program_parameters)
+            .build()?;
+
+        let mut best: QProgram = GenerateEngine::generate(parameters);
+        StatusEngine::set_fitness(&mut best, 2.);
+
+        let run_dir = temp_dir("report");
+        let path = |file: &str| run_dir.join(file).to_str().unwrap().to_owned();
+
+        program_parameters.save(&path("params.json"))?;
+        vec![PopulationStats::with_defaults::<IrisEngine>(
+            &[best.program.clone()],
+            Objective::Maximize,
+        )]
+        .save(&path("stats.json"))?;
+        best.save(&path("best.json"))?;
+        vec![0.5, 0.9, 0.7].save(&path("validation.json"))?;
+        fs::write(run_dir.join("fitness.svg"), "<svg/>")?;
+        ClassificationReport::new(2, &[(0, Some(0)), (1, Some(0)), (1, None)])
+            .save(&path("classification.json"))?;
+
+        let report = RunReport::load(&run_dir)?;
+
+        assert_eq!(report.name, "lgp-report");
+        assert_eq!(report.figures, vec![PathBuf::from("fitness.svg")]);
+        assert!(matches!(report.best, Some(Organism::Q(_))));
+
+        let markdown = report.to_markdown();
+        for section in [
+            "## Configuration",
+            "## Final metrics",
+            "## Figures",
+            "## Best program",
+        ] {
+            assert!(markdown.contains(section), "missing {}", section);
+        }
+        assert!(markdown.contains("## Q-table"));
+        assert!(markdown.contains("| best | 2.0000 |"));
+        assert!(markdown.contains("| objective | Maximize |"));
+        assert!(markdown.contains("| best validation | 0.9000 |"));
+        assert!(markdown.contains("## Classification"));
+        assert!(markdown.contains("| 1 | 1 | 0 | 1 |"));
+
+        let html = report.to_html();
+        assert!(html.contains("<img src=\"fitness.svg\""));
+        assert!(html.contains("<h2>Q-table</h2>"));
+        assert!(html.contains("<h2>Classification</h2>"));
+
+        let saved = report.save(&run_dir, ReportFormat::Html)?;
+        assert_eq!(fs::read_to_string(saved)?, html);
+
+        let minimized = RunReport {
+            objective: Objective::Minimize,
+            ..report
+        };
+        assert!(minimized
+            .to_markdown()
+            .contains("| best validation | 0.5000 |"));
+
+        Ok(())
+    }
+}
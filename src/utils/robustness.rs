@@ -0,0 +1,121 @@
+//! Robustness of an evolved program: its fitness on the same trials with and without noise on what it
+//! observes and does.
+use serde::{Deserialize, Serialize};
+
+use crate::core::{
+    engines::{
+        core_engine::{Core, HyperParameters},
+        status_engine::Status,
+    },
+    environment::{with_noise, NoiseParameters},
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct RobustnessReport {
+    pub noise: NoiseParameters,
+    pub clean: f64,
+    pub noisy: f64,
+    /// Fitness lost to the noise, positive when the noise hurts whatever the objective.
+    pub degradation: f64,
+}
+
+/// Evaluates `individual` on `trials` as is, then under the test noise of `params` (see
+/// [`NoiseParameters::test`]).
+pub fn robustness<C>(
+    individual: &C::Individual,
+    trials: &mut [C::State],
+    params: &HyperParameters<C>,
+) -> RobustnessReport
+where
+    C: Core,
+{
+    let noise = params.noise.test();
+
+    let mut evaluate = |noise: NoiseParameters| {
+        let mut individual = individual.clone();
+        with_noise(noise, || {
+            C::eval_individual_penalized(
+                &mut individual,
+                trials,
+                params.default_fitness,
+                params.fitness_mode,
+                params.risk_aversion,
+                params.objective,
+                &[],
+            )
+        });
+
+        C::Status::get_fitness(&individual)
+    };
+
+    let clean = evaluate(NoiseParameters::CLEAN);
+    let noisy = evaluate(noise);
+
+    RobustnessReport {
+        noise,
+        clean,
+        noisy,
+        degradation: params.objective.improvement(clean, noisy),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::engines::core_engine::HyperParametersBuilder;
+    use crate::core::environment::{NoiseParametersBuilder, NoisePhase};
+    use crate::core::program::Program;
+    use crate::problems::custom::{CustomEngine, Navigation, Simulation, SimulationInput};
+    use crate::problems::problem::program_parameters;
+    use crate::utils::misc::VoidResultAnyError;
+    use crate::utils::random::update_seed;
+
+    #[test]
+    fn given_noise_when_best_program_is_tested_then_clean_and_noisy_fitness_are_compared(
+    ) -> VoidResultAnyError {
+        update_seed(Some(3));
+
+        let parameters = program_parameters(Navigation::N_INPUTS, Navigation::N_ACTIONS);
+        // Pushes towards the origin: right of it, r0 (push left) wins, left of it r2 (push right).
+        let program = Program::parse("r0 = r0 + 1 * in0; r2 = r2 - 1 * in0", parameters)?;
+        let mut trials = vec![
+            SimulationInput::with_schedule(vec![Navigation {
+                position: 0.5,
+                velocity: 0.,
+            }]),
+            SimulationInput::with_schedule(vec![Navigation {
+                position: -0.5,
+                velocity: 0.,
+            }]),
+        ];
+
+        let noise = NoiseParametersBuilder::default()
+            .observation_noise(1.)
+            .action_noise(0.5)
+            .build()?;
+        let params = HyperParametersBuilder::<CustomEngine<Navigation>>::default()
+            .program_parameters(parameters)
+            .noise(noise)
+            .build()?;
+
+        let report = robustness(&program, &mut trials, &params);
+        assert_eq!(report.noise, noise);
+        assert!(report.clean.is_finite() && report.noisy.is_finite());
+        assert_eq!(report.degradation, report.clean - report.noisy);
+        assert!(report.noisy <= report.clean);
+
+        // Training-only noise leaves tests clean.
+        let training_only = HyperParametersBuilder::<CustomEngine<Navigation>>::default()
+            .program_parameters(parameters)
+            .noise(NoiseParameters {
+                noise_phase: NoisePhase::Training,
+                ..noise
+            })
+            .build()?;
+        let report = robustness(&program, &mut trials, &training_only);
+        assert!(report.noise.is_clean());
+        assert_eq!(report.clean, report.noisy);
+
+        Ok(())
+    }
+}
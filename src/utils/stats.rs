@@ -0,0 +1,258 @@
+//! Summary statistics of a population's fitness and structure.
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::core::{
+    engines::{core_engine::Core, fitness_engine::Objective, status_engine::Status},
+    program::AsProgram,
+};
+
+pub const DEFAULT_QUANTILES: [f64; 5] = [0.05, 0.25, 0.5, 0.75, 0.95];
+pub const DEFAULT_N_BINS: usize = 10;
+
+/// Returns the `q`-quantile of `sorted` (ascending), interpolating linearly between ranks.
+pub fn quantile(sorted: &[f64], q: f64) -> f64 {
+    if sorted.is_empty() {
+        return f64::NAN;
+    }
+
+    let rank = q.clamp(0., 1.) * (sorted.len() - 1) as f64;
+    let lower = rank.floor() as usize;
+    let upper = rank.ceil() as usize;
+
+    sorted[lower] + (sorted[upper] - sorted[lower]) * (rank - lower as f64)
+}
+
+/// Fraction of individuals whose instructions differ from every other individual's (1 when all programs
+/// are distinct, `1 / len` when the population has converged to a single program).
+pub fn structural_diversity<I>(population: &[I]) -> f64
+where
+    I: AsProgram,
+{
+    if population.is_empty() {
+        return f64::NAN;
+    }
+
+    let n_distinct = population
+        .iter()
+        .map(|individual| individual.as_program().structural_hash())
+        .unique()
+        .count();
+
+    n_distinct as f64 / population.len() as f64
+}
+
+/// Equal-width bins spanning `[lower, upper]`; the last bin includes `upper`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Histogram {
+    pub lower: f64,
+    pub upper: f64,
+    pub counts: Vec<usize>,
+}
+
+impl Histogram {
+    pub fn new(values: &[f64], n_bins: usize) -> Self {
+        let (lower, upper) = match values.iter().copied().minmax().into_option() {
+            Some(bounds) => bounds,
+            None => {
+                return Histogram {
+                    lower: f64::NAN,
+                    upper: f64::NAN,
+                    counts: vec![],
+                }
+            }
+        };
+
+        let n_bins = n_bins.max(1);
+        let mut counts = vec![0; n_bins];
+        let width = (upper - lower) / n_bins as f64;
+
+        for value in values {
+            let bin = if width > 0. {
+                (((value - lower) / width) as usize).min(n_bins - 1)
+            } else {
+                0
+            };
+
+            counts[bin] += 1;
+        }
+
+        Histogram {
+            lower,
+            upper,
+            counts,
+        }
+    }
+
+    pub fn bin_width(&self) -> f64 {
+        (self.upper - self.lower) / self.counts.len() as f64
+    }
+}
+
+/// Statistics over the valid (finite) fitness values of a population, along with structural statistics
+/// over every individual. `best` and `worst` follow the objective the population was ranked by, the
+/// quantiles are ascending whatever the objective.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct PopulationStats {
+    pub size: usize,
+    pub n_valid: usize,
+    pub best: f64,
+    pub median: f64,
+    pub worst: f64,
+    pub mean: f64,
+    pub std: f64,
+    /// `(q, value)` pairs.
+    pub quantiles: Vec<(f64, f64)>,
+    pub histogram: Histogram,
+    pub mean_length: f64,
+    pub mean_effective_length: f64,
+    pub diversity: f64,
+}
+
+impl PopulationStats {
+    pub fn new<C>(
+        population: &[C::Individual],
+        objective: Objective,
+        quantiles: &[f64],
+        n_bins: usize,
+    ) -> Self
+    where
+        C: Core,
+        C::Individual: AsProgram,
+    {
+        let fitness = population
+            .iter()
+            .map(C::Status::get_fitness)
+            .filter(|fitness| fitness.is_finite())
+            .sorted_by(f64::total_cmp)
+            .collect_vec();
+
+        let n_valid = fitness.len();
+        let mean = fitness.iter().sum::<f64>() / n_valid as f64;
+        let std = (fitness
+            .iter()
+            .map(|value| (value - mean).powi(2))
+            .sum::<f64>()
+            / n_valid as f64)
+            .sqrt();
+
+        let size = population.len();
+        let mean_length =
+            population.iter().map(AsProgram::length).sum::<usize>() as f64 / size as f64;
+        let mean_effective_length = population
+            .iter()
+            .map(AsProgram::effective_length)
+            .sum::<usize>() as f64
+            / size as f64;
+
+        PopulationStats {
+            size,
+            n_valid,
+            best: fitness
+                .iter()
+                .copied()
+                .max_by(|a, b| objective.compare(*a, *b))
+                .unwrap_or(f64::NAN),
+            median: quantile(&fitness, 0.5),
+            worst: fitness
+                .iter()
+                .copied()
+                .min_by(|a, b| objective.compare(*a, *b))
+                .unwrap_or(f64::NAN),
+            mean,
+            std,
+            quantiles: quantiles
+                .iter()
+                .map(|q| (*q, quantile(&fitness, *q)))
+                .collect(),
+            histogram: Histogram::new(&fitness, n_bins),
+            mean_length,
+            mean_effective_length,
+            diversity: structural_diversity(population),
+        }
+    }
+
+    pub fn with_defaults<C>(population: &[C::Individual], objective: Objective) -> Self
+    where
+        C: Core,
+        C::Individual: AsProgram,
+    {
+        Self::new::<C>(population, objective, &DEFAULT_QUANTILES, DEFAULT_N_BINS)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::{
+        engines::{
+            generate_engine::{Generate, GenerateEngine},
+            status_engine::StatusEngine,
+        },
+        program::Program,
+    };
+    use crate::problems::iris::IrisEngine;
+    use crate::utils::misc::VoidResultAnyError;
+    use crate::utils::test::program_parameters_of_length;
+
+    #[test]
+    fn given_sorted_values_when_quantile_then_ranks_are_interpolated() {
+        let values = [1., 2., 3., 4.];
+
+        assert_eq!(quantile(&values, 0.), 1.);
+        assert_eq!(quantile(&values, 0.5), 2.5);
+        assert_eq!(quantile(&values, 1.), 4.);
+    }
+
+    #[test]
+    fn given_values_when_histogram_then_every_value_is_binned() {
+        let histogram = Histogram::new(&[0., 0.1, 0.5, 0.9, 1.], 2);
+
+        assert_eq!(histogram.counts, vec![2, 3]);
+        assert_eq!(histogram.bin_width(), 0.5);
+    }
+
+    #[test]
+    fn given_population_when_stats_then_invalid_individuals_are_ignored() -> VoidResultAnyError {
+        let program_parameters = program_parameters_of_length(4, 3, 10);
+
+        let population = [1., 2., 3., f64::NEG_INFINITY]
+            .into_iter()
+            .map(|fitness| {
+                let mut program: Program = GenerateEngine::generate(program_parameters);
+                StatusEngine::set_fitness(&mut program, fitness);
+                program
+            })
+            .collect::<Vec<_>>();
+
+        let stats = PopulationStats::with_defaults::<IrisEngine>(&population, Objective::Maximize);
+
+        assert_eq!(stats.size, 4);
+        assert_eq!(stats.n_valid, 3);
+        assert_eq!(stats.best, 3.);
+        assert_eq!(stats.worst, 1.);
+        assert_eq!(stats.mean, 2.);
+        assert!(stats.mean_effective_length <= stats.mean_length);
+        assert!(stats.mean_length >= 1.);
+
+        let minimized =
+            PopulationStats::with_defaults::<IrisEngine>(&population, Objective::Minimize);
+        assert_eq!((minimized.best, minimized.worst), (1., 3.));
+        assert_eq!(minimized.quantiles, stats.quantiles);
+
+        Ok(())
+    }
+
+    #[test]
+    fn given_cloned_programs_when_diversity_then_clones_count_once() -> VoidResultAnyError {
+        let program_parameters = program_parameters_of_length(4, 3, 10);
+
+        let program: Program = GenerateEngine::generate(program_parameters);
+        let converged = vec![program.clone(), program.clone()];
+
+        assert_eq!(structural_diversity(&converged), 0.5);
+        assert!(structural_diversity::<Program>(&[]).is_nan());
+
+        Ok(())
+    }
+}
@@ -0,0 +1,276 @@
+use std::{
+    cell::{Cell, RefCell},
+    time::Duration,
+};
+
+use serde::{Deserialize, Serialize};
+
+thread_local! {
+    static ENVIRONMENT_STEPS: Cell<usize> = Cell::new(0);
+    static PROGRAM_EXECUTIONS: Cell<usize> = Cell::new(0);
+    static TRIAL_PROGRAM_EXECUTIONS: Cell<usize> = Cell::new(0);
+    static EPISODES: Cell<usize> = Cell::new(0);
+    static SUCCESSES: Cell<usize> = Cell::new(0);
+    static PENALTIES: RefCell<(Vec<f64>, usize)> = RefCell::new((vec![], 0));
+}
+
+/// Called every time an action is executed against a state.
+pub fn record_environment_step() {
+    ENVIRONMENT_STEPS.with(|steps| steps.set(steps.get() + 1));
+}
+
+/// Called every time a program is ran over a state.
+pub fn record_program_execution() {
+    PROGRAM_EXECUTIONS.with(|executions| executions.set(executions.get() + 1));
+}
+
+/// Called at the end of every RL episode.
+pub fn record_episode(succeeded: bool) {
+    EPISODES.with(|episodes| episodes.set(episodes.get() + 1));
+
+    if succeeded {
+        SUCCESSES.with(|successes| successes.set(successes.get() + 1));
+    }
+}
+
+/// Returns the (episodes, successful episodes) recorded on this thread since the last call, and resets
+/// both counters.
+pub fn take_episode_counters() -> (usize, usize) {
+    let episodes = EPISODES.with(|episodes| episodes.replace(0));
+    let successes = SUCCESSES.with(|successes| successes.replace(0));
+
+    (episodes, successes)
+}
+
+/// Called after every trial with the weighted value of each penalty the individual incurred on it.
+pub fn record_penalties(penalties: &[f64]) {
+    PENALTIES.with(|recorded| {
+        let (totals, n_trials) = &mut *recorded.borrow_mut();
+        totals.resize(totals.len().max(penalties.len()), 0.);
+
+        for (total, penalty) in totals.iter_mut().zip(penalties) {
+            *total += penalty;
+        }
+        *n_trials += 1;
+    })
+}
+
+/// Returns the mean of each penalty over the trials recorded on this thread since the last call, and
+/// resets them.
+pub fn take_penalties() -> Vec<f64> {
+    let (totals, n_trials) = PENALTIES.with(|recorded| recorded.take());
+
+    totals
+        .into_iter()
+        .map(|total| total / n_trials as f64)
+        .collect()
+}
+
+/// Returns the number of environment steps recorded on this thread since the counters were last taken.
+pub fn environment_steps() -> usize {
+    ENVIRONMENT_STEPS.with(|steps| steps.get())
+}
+
+/// Returns the number of program executions recorded on this thread since the counters were last taken.
+pub fn program_executions() -> usize {
+    PROGRAM_EXECUTIONS.with(|executions| executions.get())
+}
+
+/// Called after every trial with the number of times programs were ran over it.
+pub fn record_trial_program_executions(executions: usize) {
+    TRIAL_PROGRAM_EXECUTIONS.with(|recorded| recorded.set(executions));
+}
+
+/// Returns the program executions of the trial last evaluated on this thread, e.g. for penalties on
+/// what running a program cost over a whole trial.
+pub fn trial_program_executions() -> usize {
+    TRIAL_PROGRAM_EXECUTIONS.with(|executions| executions.get())
+}
+
+/// Returns the (environment steps, program executions) recorded on this thread since the last call,
+/// and resets both counters.
+pub fn take_counters() -> (usize, usize) {
+    let steps = ENVIRONMENT_STEPS.with(|steps| steps.replace(0));
+    let executions = PROGRAM_EXECUTIONS.with(|executions| executions.replace(0));
+
+    (steps, executions)
+}
+
+/// Everything recorded on a thread, so work handed to other threads (e.g. parallel evaluation) can be
+/// reported on the calling one.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct ThreadCounters {
+    pub environment_steps: usize,
+    pub program_executions: usize,
+    pub episodes: usize,
+    pub successes: usize,
+    penalty_totals: Vec<f64>,
+    penalty_trials: usize,
+}
+
+/// Returns everything recorded on this thread since the counters were last taken, and resets them.
+pub fn take_thread_counters() -> ThreadCounters {
+    let (environment_steps, program_executions) = take_counters();
+    let (episodes, successes) = take_episode_counters();
+    let (penalty_totals, penalty_trials) = PENALTIES.with(|recorded| recorded.take());
+
+    ThreadCounters {
+        environment_steps,
+        program_executions,
+        episodes,
+        successes,
+        penalty_totals,
+        penalty_trials,
+    }
+}
+
+/// Adds `counters`, taken on another thread, to the counters of this thread.
+pub fn add_thread_counters(counters: &ThreadCounters) {
+    ENVIRONMENT_STEPS.with(|steps| steps.set(steps.get() + counters.environment_steps));
+    PROGRAM_EXECUTIONS
+        .with(|executions| executions.set(executions.get() + counters.program_executions));
+    EPISODES.with(|episodes| episodes.set(episodes.get() + counters.episodes));
+    SUCCESSES.with(|successes| successes.set(successes.get() + counters.successes));
+    PENALTIES.with(|recorded| {
+        let (totals, n_trials) = &mut *recorded.borrow_mut();
+        totals.resize(totals.len().max(counters.penalty_totals.len()), 0.);
+
+        for (total, penalty) in totals.iter_mut().zip(&counters.penalty_totals) {
+            *total += penalty;
+        }
+        *n_trials += counters.penalty_trials;
+    });
+}
+
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub struct GenerationMetrics {
+    pub generation: usize,
+    pub eval_time: Duration,
+    pub rank_time: Duration,
+    pub survive_time: Duration,
+    pub variation_time: Duration,
+    pub environment_steps: usize,
+    pub program_executions: usize,
+    #[serde(default)]
+    pub episodes: usize,
+    #[serde(default)]
+    pub successes: usize,
+    /// Fitness of the best individual on the validation states, if any.
+    #[serde(default)]
+    pub validation_fitness: Option<f64>,
+    /// Index of the curriculum task the generation was evaluated on, if any.
+    #[serde(default)]
+    pub task: Option<usize>,
+}
+
+impl GenerationMetrics {
+    pub fn total_time(&self) -> Duration {
+        self.eval_time + self.rank_time + self.survive_time + self.variation_time
+    }
+
+    /// Fraction of episodes which met their task's success criterion (`NaN` without episodes).
+    pub fn success_rate(&self) -> f64 {
+        self.successes as f64 / self.episodes as f64
+    }
+}
+
+/// Accumulates generation metrics over an entire run.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize, PartialEq)]
+pub struct RunSummary {
+    pub n_generations: usize,
+    pub eval_time: Duration,
+    pub rank_time: Duration,
+    pub survive_time: Duration,
+    pub variation_time: Duration,
+    pub environment_steps: usize,
+    pub program_executions: usize,
+    #[serde(default)]
+    pub episodes: usize,
+    #[serde(default)]
+    pub successes: usize,
+}
+
+impl RunSummary {
+    pub fn record(&mut self, metrics: &GenerationMetrics) {
+        self.n_generations += 1;
+        self.eval_time += metrics.eval_time;
+        self.rank_time += metrics.rank_time;
+        self.survive_time += metrics.survive_time;
+        self.variation_time += metrics.variation_time;
+        self.environment_steps += metrics.environment_steps;
+        self.program_executions += metrics.program_executions;
+        self.episodes += metrics.episodes;
+        self.successes += metrics.successes;
+    }
+
+    pub fn success_rate(&self) -> f64 {
+        self.successes as f64 / self.episodes as f64
+    }
+
+    pub fn total_time(&self) -> Duration {
+        self.eval_time + self.rank_time + self.survive_time + self.variation_time
+    }
+
+    pub fn mean_generation_time(&self) -> Duration {
+        if self.n_generations == 0 {
+            return Duration::ZERO;
+        }
+
+        self.total_time() / self.n_generations as u32
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn given_recorded_events_when_counters_are_taken_then_counters_are_reset() {
+        take_counters();
+
+        record_environment_step();
+        record_environment_step();
+        record_program_execution();
+
+        assert_eq!(take_counters(), (2, 1));
+        assert_eq!(take_counters(), (0, 0));
+    }
+
+    #[test]
+    fn given_recorded_penalties_when_taken_then_means_are_returned_and_reset() {
+        take_penalties();
+
+        record_penalties(&[1., 4.]);
+        record_penalties(&[3., 0.]);
+
+        assert_eq!(take_penalties(), vec![2., 2.]);
+        assert!(take_penalties().is_empty());
+    }
+
+    #[test]
+    fn given_generation_metrics_when_recorded_then_summary_accumulates() {
+        let metrics = GenerationMetrics {
+            generation: 0,
+            eval_time: Duration::from_millis(4),
+            rank_time: Duration::from_millis(1),
+            survive_time: Duration::from_millis(1),
+            variation_time: Duration::from_millis(2),
+            environment_steps: 10,
+            program_executions: 10,
+            episodes: 4,
+            successes: 1,
+            validation_fitness: None,
+            task: None,
+        };
+
+        let mut summary = RunSummary::default();
+        summary.record(&metrics);
+        summary.record(&metrics);
+
+        assert_eq!(summary.n_generations, 2);
+        assert_eq!(summary.environment_steps, 20);
+        assert_eq!(summary.total_time(), Duration::from_millis(16));
+        assert_eq!(summary.mean_generation_time(), Duration::from_millis(8));
+        assert_eq!(summary.success_rate(), 0.25);
+    }
+}
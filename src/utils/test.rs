@@ -1,12 +1,24 @@
 // For testing purposes only (binary classification).
 
+#[cfg(test)]
+use std::path::PathBuf;
+
+#[cfg(test)]
+use crate::{
+    core::{engines::core_engine::HyperParametersBuilder, program::ProgramGeneratorParameters},
+    problems::{
+        custom::{CustomEngine, Navigation, Simulation},
+        problem::program_parameters,
+    },
+};
+
 use rand::{distributions::Standard, prelude::Distribution};
 use serde::{Deserialize, Serialize};
 use strum::EnumCount;
 
 use crate::core::{
     engines::reset_engine::{Reset, ResetEngine},
-    environment::State,
+    environment::{GenerationAware, State},
 };
 
 #[derive(
@@ -59,6 +71,8 @@ impl State for TestInput {
     }
 }
 
+impl GenerationAware for TestInput {}
+
 impl Reset<TestInput> for ResetEngine {
     fn reset(item: &mut TestInput) {
         item.idx = 0;
@@ -80,6 +94,46 @@ impl Default for TestInput {
     }
 }
 
+/// A directory under the system temporary directory unique to the caller, so tests running in
+/// parallel never write to the same files.
+#[cfg(test)]
+pub fn temp_dir(name: &str) -> PathBuf {
+    std::env::temp_dir().join(format!("lgp-{}-{}", name, uuid::Uuid::new_v4()))
+}
+
+/// The default parameters of programs over `n_inputs` inputs and `n_actions` actions (see
+/// [`program_parameters`]), programs being up to `max_instructions` instructions long.
+#[cfg(test)]
+pub fn program_parameters_of_length(
+    n_inputs: usize,
+    n_actions: usize,
+    max_instructions: usize,
+) -> ProgramGeneratorParameters {
+    let mut parameters = program_parameters(n_inputs, n_actions);
+    parameters.max_instructions = max_instructions;
+    parameters
+}
+
+/// Hyperparameters evolving [`Navigation`] with programs of up to `max_instructions` instructions,
+/// failing programs scoring as many penalties as an episode has steps. Tests set their own budget.
+#[cfg(test)]
+pub fn navigation_parameters(
+    max_instructions: usize,
+) -> HyperParametersBuilder<CustomEngine<Navigation>> {
+    let program_parameters = program_parameters_of_length(
+        Navigation::N_INPUTS,
+        Navigation::N_ACTIONS,
+        max_instructions,
+    );
+
+    let mut parameters = HyperParametersBuilder::default();
+    parameters
+        .program_parameters(program_parameters)
+        .default_fitness(-(Navigation::EPISODE_LENGTH as f64));
+
+    parameters
+}
+
 impl Distribution<SingleInput> for Standard {
     fn sample<R: rand::Rng + ?Sized>(&self, rng: &mut R) -> SingleInput {
         let data: [f64; 4] = [0.0; 4].map(|_| rng.gen_range(0.0..=1.0));
@@ -0,0 +1,317 @@
+//! Head-to-head tournaments between saved runs, e.g. to compare variants of an operator: the final
+//! populations of the runs are re-evaluated on the same trials, every run is scored against every
+//! other, and the runs are merged into a single population of their best individuals.
+use std::{cmp::Ordering, error::Error, path::Path};
+
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::core::{
+    characteristics::Load,
+    engines::{
+        core_engine::{Checkpoint, Core, HyperParameters},
+        fitness_engine::Objective,
+        status_engine::Status,
+    },
+};
+
+/// The hyperparameters and last population of a saved run: the `checkpoint.json` of a run ran with
+/// `--checkpoint-dir`, or else the `params.json` and `population.json` written by `save_experiment`.
+pub fn load_run<C>(
+    run_dir: &Path,
+) -> Result<(HyperParameters<C>, Vec<C::Individual>), Box<dyn Error>>
+where
+    C: Core,
+{
+    let checkpoint_path = run_dir.join("checkpoint.json");
+
+    if checkpoint_path.exists() {
+        let checkpoint = Checkpoint::<C>::try_load(checkpoint_path)?;
+        return Ok((checkpoint.params, checkpoint.population));
+    }
+
+    let params = HyperParameters::<C>::try_load(run_dir.join("params.json"))?;
+    let population = Vec::<Vec<C::Individual>>::try_load(run_dir.join("population.json"))?
+        .pop()
+        .ok_or_else(|| format!("{} holds no population.", run_dir.display()))?;
+
+    Ok((params, population))
+}
+
+/// How the individuals of a run fared in a [`Tournament`].
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Standing {
+    pub run: String,
+    pub n_individuals: usize,
+    pub best_fitness: f64,
+    pub mean_fitness: f64,
+    /// Probability an individual of the run beats one of the other runs, ties counting half.
+    pub win_rate: f64,
+    /// Fraction of the merged population coming from the run.
+    pub merged_share: f64,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Tournament {
+    /// Number of trials every individual was re-evaluated on.
+    pub n_trials: usize,
+    pub standings: Vec<Standing>,
+    /// `head_to_head[a][b]` is the probability an individual of run `a` beats one of run `b`, ties
+    /// counting half, so `head_to_head[a][b] + head_to_head[b][a] == 1`.
+    pub head_to_head: Vec<Vec<f64>>,
+}
+
+/// Probability a fitness of `a` is better than one of `b`, ties counting half.
+fn beats(a: &[f64], b: &[f64], objective: Objective) -> f64 {
+    let score: f64 = a
+        .iter()
+        .cartesian_product(b)
+        .map(|(a, b)| match objective.compare(*a, *b) {
+            Ordering::Greater => 1.,
+            Ordering::Equal => 0.5,
+            Ordering::Less => 0.,
+        })
+        .sum();
+
+    score / (a.len() * b.len()) as f64
+}
+
+/// Re-evaluates the individuals of every `(name, individuals)` run on `trials` under the fitness
+/// settings of `params` (penalties aside), then scores the runs head to head.
+///
+/// Returns the tournament along with the merged population: the best `params.population_size`
+/// individuals of all runs, ranked best first.
+pub fn tournament<C>(
+    runs: Vec<(String, Vec<C::Individual>)>,
+    params: &HyperParameters<C>,
+    trials: &mut [C::State],
+) -> Result<(Tournament, Vec<C::Individual>), Box<dyn Error>>
+where
+    C: Core,
+{
+    if runs.len() < 2 {
+        return Err("A tournament needs at least two runs.".into());
+    }
+
+    if let Some((name, _)) = runs.iter().find(|(_, individuals)| individuals.is_empty()) {
+        return Err(format!("Run {} has no individuals.", name).into());
+    }
+
+    let mut runs = runs;
+    for (_, individuals) in runs.iter_mut() {
+        for individual in individuals.iter_mut() {
+            C::eval_individual_penalized(
+                individual,
+                trials,
+                params.default_fitness,
+                params.fitness_mode,
+                params.risk_aversion,
+                params.objective,
+                &[],
+            );
+        }
+    }
+
+    let names = runs.iter().map(|(name, _)| name.clone()).collect_vec();
+    let fitnesses = runs
+        .iter()
+        .map(|(_, individuals)| individuals.iter().map(C::Status::get_fitness).collect_vec())
+        .collect_vec();
+
+    let head_to_head = fitnesses
+        .iter()
+        .map(|a| {
+            fitnesses
+                .iter()
+                .map(|b| beats(a, b, params.objective))
+                .collect_vec()
+        })
+        .collect_vec();
+
+    let mut merged = runs
+        .into_iter()
+        .enumerate()
+        .flat_map(|(run, (_, individuals))| {
+            individuals
+                .into_iter()
+                .map(move |individual| (run, individual))
+        })
+        .collect_vec();
+    merged.sort_by(|(_, a), (_, b)| {
+        params
+            .objective
+            .compare(C::Status::get_fitness(b), C::Status::get_fitness(a))
+    });
+    merged.truncate(params.population_size.max(1));
+
+    let standings = fitnesses
+        .iter()
+        .enumerate()
+        .map(|(run, fitness)| {
+            let others = (0..fitnesses.len()).filter(|other| *other != run);
+            let n_opponents: usize = others.clone().map(|other| fitnesses[other].len()).sum();
+            let wins: f64 = others
+                .map(|other| head_to_head[run][other] * fitnesses[other].len() as f64)
+                .sum();
+
+            Standing {
+                run: names[run].clone(),
+                n_individuals: fitness.len(),
+                best_fitness: fitness
+                    .iter()
+                    .copied()
+                    .max_by(|a, b| params.objective.compare(*a, *b))
+                    .unwrap(),
+                mean_fitness: fitness.iter().sum::<f64>() / fitness.len() as f64,
+                win_rate: wins / n_opponents as f64,
+                merged_share: merged.iter().filter(|(source, _)| *source == run).count() as f64
+                    / merged.len() as f64,
+            }
+        })
+        .collect_vec();
+
+    let tournament = Tournament {
+        n_trials: trials.len(),
+        standings,
+        head_to_head,
+    };
+    let merged = merged
+        .into_iter()
+        .map(|(_, individual)| individual)
+        .collect_vec();
+
+    Ok((tournament, merged))
+}
+
+impl Tournament {
+    /// The run whose individuals beat those of the other runs most often.
+    pub fn winner(&self) -> &Standing {
+        self.standings
+            .iter()
+            .max_by(|a, b| a.win_rate.total_cmp(&b.win_rate))
+            .unwrap()
+    }
+
+    pub fn to_markdown(&self) -> String {
+        let mut report = format!(
+            "# Tournament\n\n{} runs re-evaluated on {} common trials, {} dominates.\n\n",
+            self.standings.len(),
+            self.n_trials,
+            self.winner().run
+        );
+
+        report.push_str("| Run | Individuals | Best | Mean | Win rate | Merged share |\n");
+        report.push_str("|---|---|---|---|---|---|\n");
+
+        for standing in &self.standings {
+            report.push_str(&format!(
+                "| {} | {} | {:.4} | {:.4} | {:.3} | {:.3} |\n",
+                standing.run,
+                standing.n_individuals,
+                standing.best_fitness,
+                standing.mean_fitness,
+                standing.win_rate,
+                standing.merged_share
+            ));
+        }
+
+        report.push_str(
+            "\n## Head to head\n\nProbability an individual of the row's run beats one of the \
+             column's run, ties counting half.\n\n",
+        );
+        report.push_str(&format!(
+            "| | {} |\n|---|{}\n",
+            self.standings
+                .iter()
+                .map(|standing| &standing.run)
+                .join(" | "),
+            "---|".repeat(self.standings.len())
+        ));
+
+        for (standing, row) in self.standings.iter().zip(&self.head_to_head) {
+            report.push_str(&format!(
+                "| {} | {} |\n",
+                standing.run,
+                row.iter().map(|rate| format!("{:.3}", rate)).join(" | ")
+            ));
+        }
+
+        report
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::characteristics::Save;
+    use crate::core::engines::generate_engine::{Generate, GenerateEngine};
+    use crate::core::engines::status_engine::StatusEngine;
+    use crate::extensions::regression::{RegressionEngine, RegressionInput};
+    use crate::problems::{problem::Problem, symbolic::Koza1};
+    use crate::utils::misc::VoidResultAnyError;
+    use crate::utils::random::update_seed;
+    use crate::utils::test::temp_dir;
+
+    type Regression = RegressionEngine<Koza1>;
+
+    #[test]
+    fn given_stronger_run_when_tournament_is_held_then_it_dominates_and_fills_the_merged_population(
+    ) -> VoidResultAnyError {
+        let mut params = Regression::default_hyper_parameters();
+        Regression::build_fitness_parameters(&mut params);
+        params.population_size = 20;
+        params.n_trials = 2;
+        params.seed = Some(3);
+
+        // The runs write their checkpoints as `--checkpoint-dir` does: one after a few generations,
+        // the other before its first.
+        let strong_dir = temp_dir("tournament-strong");
+        let mut engine = params.build_engine();
+        for _ in 0..5 {
+            engine.next();
+        }
+        engine
+            .checkpoint()
+            .save(strong_dir.join("checkpoint.json").to_str().unwrap())?;
+
+        let weak_dir = temp_dir("tournament-weak");
+        params
+            .build_engine()
+            .checkpoint()
+            .save(weak_dir.join("checkpoint.json").to_str().unwrap())?;
+
+        let (loaded_params, strong) = load_run::<Regression>(&strong_dir)?;
+        let (_, weak) = load_run::<Regression>(&weak_dir)?;
+        assert_eq!(loaded_params.population_size, params.population_size);
+        // Offspring of the last generation are not evaluated yet.
+        assert!(strong
+            .iter()
+            .any(|program| !StatusEngine::evaluated(program)));
+
+        update_seed(params.seed);
+        let mut trials: Vec<RegressionInput<Koza1>> =
+            (0..2).map(|_| GenerateEngine::generate(())).collect_vec();
+
+        let runs = vec![("strong".to_string(), strong), ("weak".to_string(), weak)];
+        let (tournament, merged) = tournament::<Regression>(runs, &params, &mut trials)?;
+
+        assert_eq!(tournament.winner().run, "strong");
+        assert!(tournament.head_to_head[0][1] > 0.5);
+        assert_eq!(
+            tournament.head_to_head[0][1] + tournament.head_to_head[1][0],
+            1.
+        );
+        assert_eq!(tournament.head_to_head[0][0], 0.5);
+        assert_eq!(
+            tournament.standings[0].win_rate,
+            tournament.head_to_head[0][1]
+        );
+        assert!(tournament.standings[0].merged_share >= 0.5);
+        assert_eq!(merged.len(), params.population_size);
+        assert!(tournament.to_markdown().contains("strong dominates"));
+
+        assert!(tournament::<Regression>(vec![], &params, &mut trials).is_err());
+
+        Ok(())
+    }
+}
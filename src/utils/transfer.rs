@@ -0,0 +1,228 @@
+//! Transfer learning: a population evolved on one problem is evaluated as is on another problem with
+//! the same register layout (zero-shot), then evolved further on it.
+use std::error::Error;
+
+use itertools::Itertools;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    core::{
+        engines::{
+            core_engine::{Core, CoreIter, HyperParameters},
+            fitness_engine::Objective,
+            generate_engine::Generate,
+            status_engine::Status,
+        },
+        environment::SpaceInfo,
+        program::{AsProgram, Program},
+    },
+    utils::random::update_seed,
+};
+
+/// Checks that `program` runs as intended on a problem whose programs are laid out like `reference`
+/// and which provides `n_inputs` inputs: the registers, actions and outputs must match, and the
+/// program may not read an input the problem does not provide.
+pub fn check_layout(
+    program: &Program,
+    reference: &Program,
+    n_inputs: usize,
+) -> Result<(), Box<dyn Error>> {
+    let (registers, expected) = (&program.registers, &reference.registers);
+
+    if registers.len() != expected.len() {
+        return Err(format!(
+            "The program has {} registers, the target problem {}.",
+            registers.len(),
+            expected.len()
+        )
+        .into());
+    }
+
+    if registers.n_actions() != expected.n_actions()
+        || registers.output_registers() != expected.output_registers()
+        || registers.readout() != expected.readout()
+    {
+        return Err(format!(
+            "The program reads {} actions from registers {:?}, the target problem {} actions from \
+             registers {:?}.",
+            registers.n_actions(),
+            registers.output_registers(),
+            expected.n_actions(),
+            expected.output_registers()
+        )
+        .into());
+    }
+
+    if program.n_inputs_read() > n_inputs {
+        return Err(format!(
+            "The program reads {} inputs, the target problem provides {}.",
+            program.n_inputs_read(),
+            n_inputs
+        )
+        .into());
+    }
+
+    Ok(())
+}
+
+/// How a transferred population fared on its new problem.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct TransferReport {
+    /// The objective of the run on the new problem, deciding which fitness is best.
+    #[serde(default)]
+    pub objective: Objective,
+    /// Fitness of every transferred individual on the new problem before any evolution, in the order
+    /// they were given.
+    pub zero_shot: Vec<f64>,
+    /// Best fitness of every generation evolved on the new problem.
+    pub best: Vec<f64>,
+    /// Median fitness of every generation evolved on the new problem.
+    pub median: Vec<f64>,
+}
+
+impl TransferReport {
+    /// The best zero-shot fitness, `NaN` without individuals.
+    pub fn best_zero_shot(&self) -> f64 {
+        self.zero_shot
+            .iter()
+            .copied()
+            .max_by(|a, b| self.objective.compare(*a, *b))
+            .unwrap_or(f64::NAN)
+    }
+}
+
+/// Evaluates `population` on the problem of `C` as is, then seeds a run of `params` with it,
+/// returning the report alongside the last generation. Fails before evaluating anything when an
+/// individual is not laid out like the programs of `C` (see [`check_layout`]).
+pub fn transfer<C>(
+    population: Vec<C::Individual>,
+    params: &HyperParameters<C>,
+) -> Result<(TransferReport, Vec<C::Individual>), Box<dyn Error>>
+where
+    C: Core,
+    C::Individual: AsProgram,
+    C::State: SpaceInfo,
+{
+    update_seed(params.seed);
+
+    let reference: C::Individual = C::Generate::generate(params.program_parameters);
+    let state: C::State = C::Generate::generate(());
+
+    for individual in population.iter() {
+        check_layout(
+            individual.as_program(),
+            reference.as_program(),
+            state.n_inputs(),
+        )?;
+    }
+
+    let mut population = population;
+    for individual in population.iter_mut() {
+        C::Status::set_fitness(individual, f64::NAN);
+    }
+
+    let mut engine = CoreIter::<C>::with_seeds(params.clone(), population.clone());
+
+    let zero_shot = population
+        .iter_mut()
+        .map(|individual| {
+            C::eval_individual_penalized(
+                individual,
+                engine.trials_mut(),
+                params.default_fitness,
+                params.fitness_mode,
+                params.risk_aversion,
+                params.objective,
+                &[],
+            );
+            C::Status::get_fitness(individual)
+        })
+        .collect_vec();
+
+    let generations = engine.by_ref().collect_vec();
+    let best = generations
+        .iter()
+        .filter_map(|generation| generation.first())
+        .map(C::Status::get_fitness)
+        .collect();
+    let median = generations
+        .iter()
+        .filter_map(|generation| generation.get(generation.len() / 2))
+        .map(C::Status::get_fitness)
+        .collect();
+
+    let report = TransferReport {
+        objective: params.objective,
+        zero_shot,
+        best,
+        median,
+    };
+
+    Ok((report, generations.into_iter().last().unwrap_or_default()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::engines::core_engine::HyperParametersBuilder;
+    use crate::core::engines::generate_engine::GenerateEngine;
+    use crate::problems::custom::{
+        CustomEngine, Masked, Navigation, PositionOnly, Simulation, StackedPositionNavigation,
+    };
+    use crate::problems::problem::program_parameters;
+    use crate::utils::misc::VoidResultAnyError;
+
+    #[test]
+    fn given_population_when_transferred_then_layouts_are_checked_and_adaptation_is_reported(
+    ) -> VoidResultAnyError {
+        let source_parameters = program_parameters(Navigation::N_INPUTS, Navigation::N_ACTIONS);
+        // Reads the velocity, which the position-only problem does not provide.
+        let population = vec![
+            Program::parse("r0 = r0 + 1 * in1", source_parameters)?,
+            GenerateEngine::generate(source_parameters),
+        ];
+
+        let masked =
+            HyperParametersBuilder::<CustomEngine<Masked<Navigation, PositionOnly>>>::default()
+                .program_parameters(program_parameters(1, Navigation::N_ACTIONS))
+                .build()?;
+        assert!(transfer(population.clone(), &masked).is_err());
+
+        let more_actions = HyperParametersBuilder::<CustomEngine<Navigation>>::default()
+            .program_parameters(program_parameters(
+                Navigation::N_INPUTS,
+                Navigation::N_ACTIONS + 1,
+            ))
+            .build()?;
+        assert!(transfer(population.clone(), &more_actions).is_err());
+
+        let stacked = HyperParametersBuilder::<CustomEngine<StackedPositionNavigation>>::default()
+            .program_parameters(program_parameters(
+                StackedPositionNavigation::N_INPUTS,
+                StackedPositionNavigation::N_ACTIONS,
+            ))
+            .population_size(4)
+            .n_generations(2)
+            .n_trials(2)
+            .seed(Some(7))
+            .build()?;
+        let (report, last) = transfer(population, &stacked)?;
+
+        assert_eq!(report.zero_shot.len(), 2);
+        assert!(report.zero_shot.iter().all(|fitness| fitness.is_finite()));
+        assert_eq!(report.best.len(), 3);
+        assert_eq!(report.median.len(), 3);
+        assert!(report.best_zero_shot() <= report.best[0]);
+        assert_eq!(last.len(), 4);
+
+        let costs = TransferReport {
+            objective: Objective::Minimize,
+            zero_shot: vec![3., f64::NAN, 1., 2.],
+            best: vec![],
+            median: vec![],
+        };
+        assert_eq!(costs.best_zero_shot(), 1.);
+
+        Ok(())
+    }
+}
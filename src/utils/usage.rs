@@ -0,0 +1,154 @@
+//! Which operations and operands the effective code of a population relies on.
+//!
+//! Aggregated per generation, the counts form a heatmap (generation x operand) that shows whether parts of
+//! the instruction set, or some inputs, are never selected for a problem.
+use std::{collections::BTreeMap, error::Error};
+
+use csv::Writer;
+use serde::{Deserialize, Serialize};
+
+use crate::core::{instruction::Operand, program::AsProgram};
+
+use super::benchmark_tools::create_path;
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct InstructionUsage {
+    pub generation: usize,
+    /// Uses of each operation, keyed by its symbol.
+    pub operations: BTreeMap<String, usize>,
+    /// Writes to each register.
+    pub destinations: BTreeMap<usize, usize>,
+    /// Reads of each register as an (internal) operand.
+    pub registers: BTreeMap<usize, usize>,
+    /// Reads of each input as an (external) operand.
+    pub inputs: BTreeMap<usize, usize>,
+}
+
+impl InstructionUsage {
+    pub fn new<I>(generation: usize, population: &[I]) -> Self
+    where
+        I: AsProgram,
+    {
+        let mut usage = InstructionUsage {
+            generation,
+            ..Default::default()
+        };
+
+        for individual in population {
+            let program = individual.as_program();
+
+            for idx in program.effective_instruction_indices(&individual.output_registers()) {
+                let instruction = &program.instructions[idx];
+
+                *usage
+                    .operations
+                    .entry(instruction.op().to_string())
+                    .or_default() += 1;
+                *usage.destinations.entry(instruction.dest()).or_default() += 1;
+
+                let (operands, operand) = match instruction.src2() {
+                    Operand::Register(register) => (&mut usage.registers, register),
+                    Operand::Input(input) => (&mut usage.inputs, input),
+                    Operand::Immediate(_) => continue,
+                };
+                *operands.entry(operand).or_default() += 1;
+            }
+        }
+
+        usage
+    }
+
+    /// `(category, operand, count)` triples, e.g. `("input", "in2", 14)`.
+    pub fn rows(&self) -> Vec<(&'static str, String, usize)> {
+        let operations = self
+            .operations
+            .iter()
+            .map(|(op, count)| ("operation", op.clone(), *count));
+        let destinations = self
+            .destinations
+            .iter()
+            .map(|(idx, count)| ("destination", format!("r{}", idx), *count));
+        let registers = self
+            .registers
+            .iter()
+            .map(|(idx, count)| ("register", format!("r{}", idx), *count));
+        let inputs = self
+            .inputs
+            .iter()
+            .map(|(idx, count)| ("input", format!("in{}", idx), *count));
+
+        operations
+            .chain(destinations)
+            .chain(registers)
+            .chain(inputs)
+            .collect()
+    }
+}
+
+/// Computes the usage of every generation.
+pub fn usage_per_generation<I>(populations: &[Vec<I>]) -> Vec<InstructionUsage>
+where
+    I: AsProgram,
+{
+    populations
+        .iter()
+        .enumerate()
+        .map(|(generation, population)| InstructionUsage::new(generation, population))
+        .collect()
+}
+
+/// Writes the usages as a long-format CSV with the columns `generation,category,operand,count`.
+pub fn save_usage_csv(usages: &[InstructionUsage], path: &str) -> Result<(), Box<dyn Error>> {
+    create_path(path, true)?;
+
+    let mut writer = Writer::from_path(path)?;
+    writer.write_record(["generation", "category", "operand", "count"])?;
+
+    for usage in usages {
+        for (category, operand, count) in usage.rows() {
+            writer.write_record([
+                usage.generation.to_string(),
+                category.to_owned(),
+                operand,
+                count.to_string(),
+            ])?;
+        }
+    }
+
+    writer.flush()?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::core::program::Program;
+    use crate::problems::problem::program_parameters;
+    use crate::utils::misc::VoidResultAnyError;
+
+    #[test]
+    fn given_programs_when_usage_is_computed_then_only_effective_code_is_counted(
+    ) -> VoidResultAnyError {
+        let program_parameters = program_parameters(2, 2);
+
+        // `r2 = r2 - in0` is an intron: r2 is never read by an output register afterwards.
+        let program = Program::parse(
+            "r2 = r2 + in1\nr0 = r0 * r2\nr1 = r1 + in0\nr2 = r2 - in0",
+            program_parameters,
+        )?;
+
+        let usage = InstructionUsage::new(3, &[program.clone(), program]);
+
+        assert_eq!(usage.generation, 3);
+        assert_eq!(usage.operations.get("+"), Some(&4));
+        assert_eq!(usage.operations.get("*"), Some(&2));
+        assert_eq!(usage.operations.get("-"), None);
+        assert_eq!(usage.registers.get(&2), Some(&2));
+        assert_eq!(usage.inputs.get(&0), Some(&2));
+        assert_eq!(usage.inputs.get(&1), Some(&2));
+        assert_eq!(usage.rows().len(), 2 + 3 + 1 + 2);
+
+        Ok(())
+    }
+}
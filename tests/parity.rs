@@ -0,0 +1,148 @@
+//! End-to-end regression protection for algorithm changes: the tuned gym benchmarks are ran with fixed
+//! seeds and the best fitness of the population must reach a floor at every milestone generation.
+//!
+//! These runs take minutes, run them with `cargo test --release --features expensive-tests --test parity`.
+//! Adding the `f32-registers` feature checks the same floors are reached with `f32` registers.
+#![cfg(feature = "expensive-tests")]
+
+use gym_rs::envs::classical_control::{cartpole::CartPoleEnv, mountain_car::MountainCarEnv};
+use itertools::Itertools;
+use lgp::{
+    core::{
+        config::load_hyper_parameters,
+        engines::{
+            core_engine::HyperParameters,
+            status_engine::{Status, StatusEngine},
+        },
+    },
+    extensions::q_learning::CrossoverVariant,
+    problems::{
+        gym::{GymRsEngine, GymRsQEngine},
+        problem::Problem,
+    },
+    utils::misc::VoidResultAnyError,
+};
+
+const SEED: u64 = 42;
+const N_GENERATIONS: usize = 50;
+const N_TRIALS: usize = 10;
+
+/// The best fitness of every generation of `P` ran with `parameters` and `seed`.
+fn best_fitness<P>(mut parameters: HyperParameters<P>, seed: u64) -> Vec<f64>
+where
+    P: Problem,
+{
+    P::build_fitness_parameters(&mut parameters);
+    parameters.seed = Some(seed);
+    parameters.n_generations = N_GENERATIONS;
+    parameters.n_trials = N_TRIALS;
+
+    parameters
+        .build_engine()
+        .take(N_GENERATIONS)
+        .map(|population| {
+            population
+                .first()
+                .map(|best| StatusEngine::get_fitness(best))
+                .unwrap_or(f64::NEG_INFINITY)
+        })
+        .collect_vec()
+}
+
+/// Runs `P` with the parameters tuned in `path` and checks the best fitness of every milestone
+/// `(generation, minimum best fitness)`.
+fn assert_parity<P>(path: &str, milestones: &[(usize, f64)]) -> VoidResultAnyError
+where
+    P: Problem,
+{
+    let best = best_fitness(load_hyper_parameters::<P>(path)?, SEED);
+
+    for (generation, minimum) in milestones {
+        let fitness = best[*generation];
+
+        assert!(
+            fitness >= *minimum,
+            "{}: best fitness {} at generation {} is below the expected {}",
+            P::NAME,
+            fitness,
+            generation,
+            minimum
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn cart_pole_lgp_parity() -> VoidResultAnyError {
+    assert_parity::<GymRsEngine<CartPoleEnv>>(
+        "assets/parameters/cart-pole-lgp.json",
+        &[(0, 9.), (24, 100.), (49, 200.)],
+    )
+}
+
+#[test]
+fn cart_pole_q_parity() -> VoidResultAnyError {
+    assert_parity::<GymRsQEngine<CartPoleEnv>>(
+        "assets/parameters/cart-pole-q.json",
+        &[(0, 9.), (24, 75.), (49, 150.)],
+    )
+}
+
+#[test]
+fn mountain_car_lgp_parity() -> VoidResultAnyError {
+    assert_parity::<GymRsEngine<MountainCarEnv>>(
+        "assets/parameters/mountain-car-lgp.json",
+        &[(0, -200.), (24, -195.), (49, -180.)],
+    )
+}
+
+#[test]
+fn mountain_car_q_parity() -> VoidResultAnyError {
+    assert_parity::<GymRsQEngine<MountainCarEnv>>(
+        "assets/parameters/mountain-car-q.json",
+        &[(0, -200.), (24, -198.), (49, -190.)],
+    )
+}
+
+/// Measures the effect of [`CrossoverVariant::Aligned`] on the convergence of Q-learning on mountain car:
+/// the mean final best fitness over a few seeds must not fall behind two-point crossover by more than
+/// `TOLERANCE` (a few steps per episode).
+#[test]
+fn mountain_car_q_aligned_crossover_parity() -> VoidResultAnyError {
+    const SEEDS: [u64; 3] = [SEED, SEED + 1, SEED + 2];
+    const TOLERANCE: f64 = 5.;
+
+    let parameters: HyperParameters<GymRsQEngine<MountainCarEnv>> =
+        load_hyper_parameters("assets/parameters/mountain-car-q.json")?;
+
+    let mean_final_best = |crossover: CrossoverVariant| {
+        let mut parameters = parameters;
+        parameters.program_parameters.consts = parameters
+            .program_parameters
+            .consts
+            .with_crossover(crossover);
+
+        let total: f64 = SEEDS
+            .iter()
+            .map(|seed| best_fitness(parameters, *seed)[N_GENERATIONS - 1])
+            .sum();
+        total / SEEDS.len() as f64
+    };
+
+    let two_point = mean_final_best(CrossoverVariant::TwoPoint);
+    let aligned = mean_final_best(CrossoverVariant::Aligned);
+    println!(
+        "mountain car q: mean final best fitness {} with two-point crossover, {} with aligned crossover",
+        two_point, aligned
+    );
+
+    assert!(
+        aligned >= two_point - TOLERANCE,
+        "aligned crossover converged to {}, two-point crossover to {}",
+        aligned,
+        two_point
+    );
+
+    Ok(())
+}
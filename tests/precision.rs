@@ -0,0 +1,93 @@
+//! Short trainings of the standard benchmarks, checking they still train with the precision of the
+//! registers: the best fitness must stay finite, never regress under elitism and reach the same floors
+//! with `f64` and `f32` registers.
+//!
+//! Run them with `cargo test --test precision`, then again with `--features f32-registers`.
+use gym_rs::envs::classical_control::{cartpole::CartPoleEnv, mountain_car::MountainCarEnv};
+use itertools::Itertools;
+use lgp::{
+    core::engines::status_engine::Status,
+    extensions::regression::{RegressionEngine, RegressionTask},
+    problems::{
+        gym::{GymRsEngine, GymRsQEngine},
+        problem::Problem,
+        symbolic::Koza1,
+    },
+    utils::misc::VoidResultAnyError,
+};
+
+const SEED: u64 = 5;
+const POPULATION_SIZE: usize = 10;
+const N_GENERATIONS: usize = 5;
+const N_TRIALS: usize = 2;
+
+/// Trains the `fast` preset of `P` for a few generations, checking the best fitness of every generation
+/// is finite, never regresses when `elitist` (the Q-tables of Q-learning keep learning) and ends better
+/// than `floor`, if any.
+fn assert_trains<P>(elitist: bool, floor: Option<f64>) -> VoidResultAnyError
+where
+    P: Problem,
+{
+    let mut parameters = P::preset("fast")?;
+    parameters.population_size = POPULATION_SIZE;
+    parameters.n_generations = N_GENERATIONS;
+    parameters.n_trials = N_TRIALS;
+    parameters.seed = Some(SEED);
+
+    let best = parameters
+        .build_engine()
+        .take(N_GENERATIONS)
+        .map(|population| P::Status::get_fitness(population.first().unwrap()))
+        .collect_vec();
+
+    assert!(
+        best.iter().all(|fitness| fitness.is_finite()),
+        "{}: {:?}",
+        P::NAME,
+        best
+    );
+    assert!(
+        !elitist
+            || best
+                .windows(2)
+                .all(|pair| !parameters.objective.is_better(pair[0], pair[1])),
+        "{}: {:?}",
+        P::NAME,
+        best
+    );
+
+    if let Some(floor) = floor {
+        let last = *best.last().unwrap();
+
+        assert!(
+            parameters.objective.is_better(last, floor),
+            "{}: best fitness {} is not better than {}",
+            P::NAME,
+            last,
+            floor
+        );
+    }
+
+    Ok(())
+}
+
+#[test]
+fn cart_pole_trains() -> VoidResultAnyError {
+    // Pushing the cart one way only topples the pole within about 9 steps.
+    assert_trains::<GymRsEngine<CartPoleEnv>>(true, Some(8.))?;
+    assert_trains::<GymRsQEngine<CartPoleEnv>>(false, Some(8.))
+}
+
+#[test]
+fn mountain_car_trains() -> VoidResultAnyError {
+    // Reaching the flag may take more than a few generations, so there is no floor above the `-200`
+    // of failed episodes.
+    assert_trains::<GymRsEngine<MountainCarEnv>>(true, None)?;
+    assert_trains::<GymRsQEngine<MountainCarEnv>>(false, None)
+}
+
+#[test]
+fn regression_trains() -> VoidResultAnyError {
+    // Registers overflowing to infinity or `NaN` score the default fitness.
+    assert_trains::<RegressionEngine<Koza1>>(true, Some(Koza1::DEFAULT_FITNESS))
+}
@@ -0,0 +1,123 @@
+//! Reduced-budget end-to-end runs of every extension: tiny populations evolved for a few generations
+//! from fixed seeds. Every run must end with a finite best fitness and take the exact same course when
+//! ran again, so functional regressions are caught in seconds instead of by the parity suite.
+//!
+//! Run them with `cargo test --test smoke`.
+use gym_rs::envs::classical_control::{cartpole::CartPoleEnv, mountain_car::MountainCarEnv};
+use itertools::Itertools;
+use lgp::{
+    core::engines::{
+        core_engine::{Core, CoreIter, HyperParameters, HyperParametersBuilder},
+        status_engine::Status,
+    },
+    extensions::{
+        classification::{Dataset, DatasetEngine},
+        regression::RegressionEngine,
+    },
+    problems::{
+        custom::{CustomEngine, CustomQEngine, Navigation},
+        gym::{GymRsEngine, GymRsQEngine},
+        problem::{program_parameters, Problem},
+        symbolic::Koza1,
+    },
+    utils::random::{standard_normal, update_seed},
+};
+
+const SEED: u64 = 7;
+const POPULATION_SIZE: usize = 8;
+const N_GENERATIONS: usize = 3;
+const N_TRIALS: usize = 2;
+
+/// The `fast` preset of `P`, shrunk further to a CI-sized budget.
+fn parameters<P>() -> HyperParameters<P>
+where
+    P: Problem,
+{
+    let mut parameters = P::preset("fast").unwrap();
+    parameters.population_size = POPULATION_SIZE;
+    parameters.n_generations = N_GENERATIONS;
+    parameters.n_trials = N_TRIALS;
+    parameters.seed = Some(SEED);
+
+    parameters
+}
+
+/// The best fitness of every generation.
+fn best_fitness<C>(engine: CoreIter<C>) -> Vec<f64>
+where
+    C: Core,
+{
+    engine
+        .map(|population| C::Status::get_fitness(population.first().unwrap()))
+        .collect_vec()
+}
+
+/// Runs `run` twice, checking both runs evolve every generation to the same finite best fitness.
+fn assert_reproducible(name: &str, run: impl Fn() -> Vec<f64>) {
+    let first = run();
+
+    assert_eq!(first.len(), N_GENERATIONS + 1, "{}", name);
+    assert!(
+        first.iter().all(|fitness| fitness.is_finite()),
+        "{}: {:?}",
+        name,
+        first
+    );
+    assert_eq!(first, run(), "{} is not reproducible", name);
+}
+
+fn assert_problem_reproducible<P>()
+where
+    P: Problem,
+{
+    assert_reproducible(P::NAME, || best_fitness(parameters::<P>().build_engine()));
+}
+
+#[test]
+fn interactive_runs_are_reproducible() {
+    assert_problem_reproducible::<CustomEngine<Navigation>>();
+    assert_problem_reproducible::<GymRsEngine<CartPoleEnv>>();
+}
+
+#[test]
+fn q_learning_runs_are_reproducible() {
+    assert_problem_reproducible::<CustomQEngine<Navigation>>();
+    assert_problem_reproducible::<GymRsQEngine<MountainCarEnv>>();
+}
+
+#[test]
+fn classification_runs_are_reproducible() {
+    assert_reproducible("classification", || {
+        // Two gaussian blobs, one per class; the iris dataset would need the network.
+        update_seed(Some(SEED));
+        let (features, labels): (Vec<Vec<f64>>, Vec<usize>) = (0..40)
+            .map(|idx| {
+                let class = idx % 2;
+                let center = class as f64 * 2. - 1.;
+                let sample = vec![center + standard_normal(), center + standard_normal()];
+
+                (sample, class)
+            })
+            .unzip();
+
+        let parameters = HyperParametersBuilder::<DatasetEngine>::default()
+            .program_parameters(program_parameters(2, 2))
+            .population_size(POPULATION_SIZE)
+            .n_generations(N_GENERATIONS)
+            .seed(Some(SEED))
+            .build()
+            .unwrap();
+
+        update_seed(parameters.seed);
+        best_fitness(CoreIter::with_trials(
+            parameters,
+            vec![],
+            vec![Dataset::new(features, labels)],
+        ))
+    });
+}
+
+#[test]
+fn regression_runs_are_reproducible() {
+    assert_problem_reproducible::<RegressionEngine<Koza1>>();
+}